@@ -0,0 +1,139 @@
+//! Extraction of federal profile and search pages and the name normalization, over pages the
+//! size of the registry's largest, `cargo bench -p dump-core`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use dump_core::{
+    extract::{self, Options},
+    models::CorporationSection,
+    normalize, search,
+};
+
+const PROFILE: &str = include_str!("../tests/fixtures/profile.html");
+
+// Results of a search page, as many as the registry lists on one
+const SEARCH_ROWS: usize = 500;
+// Directors, filing years and former names of a long-lived corporation's profile
+const DIRECTORS: usize = 200;
+const FILING_YEARS: usize = 100;
+const FORMER_NAMES: usize = 100;
+
+// The fixture profile with its repeated sections grown to `DIRECTORS`, `FILING_YEARS` and
+// `FORMER_NAMES` entries
+fn large_profile() -> String {
+    let directors: String = (0..DIRECTORS)
+        .map(|i| {
+            format!(
+                r#"<li class="full-width">DIRECTOR NUMBER {}<br>{} Elm Street<br>Toronto ON M5V 1A1<br>Canada</li>"#,
+                i, i
+            )
+        })
+        .collect();
+    let filings: String = (0..FILING_YEARS)
+        .map(|i| format!("<li>{} - Filed</li>", 2024 - i))
+        .collect();
+    let history: String = (0..FORMER_NAMES)
+        .map(|i| {
+            format!(
+                "<tr><td>{}-01-01 to {}-01-01</td><td>ACME NAME {} INC.</td></tr>",
+                1900 + i,
+                1901 + i,
+                i
+            )
+        })
+        .collect();
+
+    PROFILE
+        .replacen(
+            "<ul>\n      <li class=\"full-width\">",
+            &format!("<ul>\n      {}<li class=\"full-width\">", directors),
+            1,
+        )
+        .replacen(
+            "<ul><li>2024 - Filed</li>",
+            &format!("<ul>{}<li>2024 - Filed</li>", filings),
+            1,
+        )
+        .replacen("<tbody>", &format!("<tbody>{}", history), 1)
+}
+
+// A search page of `SEARCH_ROWS` results with a next page
+fn large_search_page() -> String {
+    let rows: String = (0..SEARCH_ROWS)
+        .map(|i| {
+            format!(
+                r#"<div class="row"><div class="col-md-11">
+                <span><a href="/cc/lgcy/fdrlCrpDtls.html?corpId={i:07}">ACME {i} HOLDINGS INC.</a></span>
+                <span>Status: Active</span>
+                <span>Corporation number: {i:06}-7</span>
+                <span>Business Number: {i:09}RC0001</span>
+                </div></div>"#
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html><html lang="en"><body><main>{}<nav><a rel="next" href="?p=1">Next</a></nav></main></body></html>"#,
+        rows
+    )
+}
+
+fn profile(c: &mut Criterion) {
+    let profile = large_profile();
+    // the fixture's own two directors and the added ones, or the fixture changed under the bench
+    let data =
+        extract::corporation(&profile, &CorporationSection::ALL, Options::default()).unwrap();
+    assert_eq!(data.directors().unwrap().current.len(), DIRECTORS + 2);
+
+    c.bench_function("profile_every_section", |b| {
+        b.iter(|| {
+            extract::corporation(
+                black_box(&profile),
+                &CorporationSection::ALL,
+                Options::default(),
+            )
+            .unwrap()
+        })
+    });
+    c.bench_function("profile_corp_details", |b| {
+        b.iter(|| {
+            extract::corporation(
+                black_box(&profile),
+                &[CorporationSection::Corp],
+                Options::default(),
+            )
+            .unwrap()
+        })
+    });
+    let bilingual = Options {
+        bilingual_labels: true,
+    };
+    c.bench_function("profile_bilingual_labels", |b| {
+        b.iter(|| {
+            extract::corporation(black_box(&profile), &CorporationSection::ALL, bilingual).unwrap()
+        })
+    });
+}
+
+fn search_page(c: &mut Criterion) {
+    let page = large_search_page();
+    assert_eq!(search::parse_search_page(&page).rows.len(), SEARCH_ROWS);
+
+    c.bench_function("search_page", |b| {
+        b.iter(|| search::parse_search_page(black_box(&page)))
+    });
+}
+
+fn normalize_names(c: &mut Criterion) {
+    let names = [
+        "ACME WIDGETS INC.",
+        "Les Entreprises Élégantes Ltée",
+        "  o'brien   & sons\u{a0}limited ",
+    ];
+
+    c.bench_function("normalize_names", |b| {
+        b.iter(|| black_box(names).map(normalize::normalize_name))
+    });
+}
+
+criterion_group!(benches, profile, search_page, normalize_names);
+criterion_main!(benches);
@@ -0,0 +1,182 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::{enrich::city_and_province, models::CorporationData};
+
+/// A director as listed in the directors section.
+#[derive(Serialize, Debug, Clone)]
+pub struct Director {
+    pub name: String,
+    pub address: Option<String>,
+}
+
+/// The directors section of a corporation, read from the `director_details` maps.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Directors {
+    pub minimum: Option<u32>,
+    pub maximum: Option<u32>,
+    pub current: Vec<Director>,
+}
+
+// Leading number of a count, bounds the registry doesn't state as a number stay unknown
+fn count(value: &str) -> Option<u32> {
+    let digits: String = value
+        .trim()
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+impl Directors {
+    pub fn from_details(details: &HashMap<String, Vec<HashMap<String, String>>>) -> Directors {
+        let mut directors = Directors::default();
+        for (label, value) in details
+            .get("director_count")
+            .into_iter()
+            .flatten()
+            .flatten()
+        {
+            let label = label.to_lowercase();
+            if label.contains("minimum") {
+                directors.minimum = count(value);
+            } else if label.contains("maximum") {
+                directors.maximum = count(value);
+            }
+        }
+
+        directors.current = details
+            .get("director_personal_data")
+            .into_iter()
+            .flatten()
+            .filter_map(|director| {
+                Some(Director {
+                    name: director.get("name")?.clone(),
+                    address: director
+                        .get("address")
+                        .filter(|address| !address.is_empty())
+                        .cloned(),
+                })
+            })
+            .collect();
+
+        directors
+    }
+}
+
+impl CorporationData {
+    /// `None` when the directors section wasn't scraped.
+    pub fn directors(&self) -> Option<Directors> {
+        self.director_details.as_ref().map(Directors::from_details)
+    }
+
+    /// Reduces the directors' residential addresses to their city and province.
+    pub fn minimize_pii(&mut self) {
+        let directors = self
+            .director_details
+            .iter_mut()
+            .filter_map(|details| details.get_mut("director_personal_data"))
+            .flatten();
+        for director in directors {
+            if let Some(address) = director.get_mut("address") {
+                *address = city_and_province(address).unwrap_or_default();
+            }
+        }
+    }
+}
+
+impl Director {
+    pub fn minimize_pii(&mut self) {
+        self.address = self.address.as_deref().and_then(city_and_province);
+    }
+}
+
+/// An address more than one director gives for service.
+#[derive(Serialize, Debug)]
+pub struct SharedAddress {
+    pub address: String,
+    pub directors: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BoardStats {
+    pub count: usize,
+    // whether `count` is within the minimum and maximum, unknown without either
+    pub within_limits: Option<bool>,
+    pub shared_addresses: Vec<SharedAddress>,
+}
+
+/// Board composition of a corporation, the `/api/corporation/:id/board` response.
+#[derive(Serialize, Debug)]
+pub struct Board {
+    pub min_directors: Option<u32>,
+    pub max_directors: Option<u32>,
+    pub directors: Vec<Director>,
+    pub stats: BoardStats,
+}
+
+// Addresses are compared ignoring case, punctuation and spacing, "100 King St. W" and
+// "100 KING ST W" are the same office
+fn address_key(address: &str) -> String {
+    address
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_uppercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl From<Directors> for Board {
+    fn from(directors: Directors) -> Board {
+        let count = directors.current.len();
+        let within_limits = match (directors.minimum, directors.maximum) {
+            (None, None) => None,
+            (minimum, maximum) => Some(
+                minimum.is_none_or(|min| count >= min as usize)
+                    && maximum.is_none_or(|max| count <= max as usize),
+            ),
+        };
+
+        // keyed by the normalized address, shown as first listed
+        let mut by_address: BTreeMap<String, SharedAddress> = BTreeMap::new();
+        for director in &directors.current {
+            let Some(address) = &director.address else {
+                continue;
+            };
+            by_address
+                .entry(address_key(address))
+                .or_insert_with(|| SharedAddress {
+                    address: address.clone(),
+                    directors: vec![],
+                })
+                .directors
+                .push(director.name.clone());
+        }
+        let shared_addresses = by_address
+            .into_values()
+            .filter(|shared| shared.directors.len() > 1)
+            .collect();
+
+        Board {
+            min_directors: directors.minimum,
+            max_directors: directors.maximum,
+            directors: directors.current,
+            stats: BoardStats {
+                count,
+                within_limits,
+                shared_addresses,
+            },
+        }
+    }
+}
+
+impl Board {
+    /// Like `CorporationData::minimize_pii`, shared addresses are still found on the full ones.
+    pub fn minimize_pii(&mut self) {
+        self.directors.iter_mut().for_each(Director::minimize_pii);
+        for shared in &mut self.stats.shared_addresses {
+            shared.address = city_and_province(&shared.address).unwrap_or_default();
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{CorporationData, CORPORATION_NUMBER_LABEL},
+    patterns::FEDERAL_NUMBER,
+};
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Jurisdiction {
+    Federal,
+    Alberta,
+    BritishColumbia,
+    Manitoba,
+    NewBrunswick,
+    NewfoundlandAndLabrador,
+    NovaScotia,
+    Ontario,
+    PrinceEdwardIsland,
+    Quebec,
+    Saskatchewan,
+    NorthwestTerritories,
+    Nunavut,
+    Yukon,
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityType {
+    BusinessCorporation,
+    NotForProfit,
+    Cooperative,
+    BoardOfTrade,
+    Unknown,
+}
+
+const PROVINCES: [(&str, &str, Jurisdiction); 13] = [
+    ("AB", "ALBERTA", Jurisdiction::Alberta),
+    ("BC", "BRITISH COLUMBIA", Jurisdiction::BritishColumbia),
+    ("MB", "MANITOBA", Jurisdiction::Manitoba),
+    ("NB", "NEW BRUNSWICK", Jurisdiction::NewBrunswick),
+    ("NL", "NEWFOUNDLAND", Jurisdiction::NewfoundlandAndLabrador),
+    ("NS", "NOVA SCOTIA", Jurisdiction::NovaScotia),
+    ("ON", "ONTARIO", Jurisdiction::Ontario),
+    (
+        "PE",
+        "PRINCE EDWARD ISLAND",
+        Jurisdiction::PrinceEdwardIsland,
+    ),
+    ("QC", "QUEBEC", Jurisdiction::Quebec),
+    ("SK", "SASKATCHEWAN", Jurisdiction::Saskatchewan),
+    (
+        "NT",
+        "NORTHWEST TERRITORIES",
+        Jurisdiction::NorthwestTerritories,
+    ),
+    ("NU", "NUNAVUT", Jurisdiction::Nunavut),
+    ("YT", "YUKON", Jurisdiction::Yukon),
+];
+
+fn detail<'a>(data: &'a CorporationData, key: &str) -> Option<&'a str> {
+    data.corp_details
+        .iter()
+        .flatten()
+        .find_map(|row| row.get(key))
+        .map(String::as_str)
+}
+
+fn governing_act(data: &CorporationData) -> Option<String> {
+    ["Governing Legislation", "Governing Act", "Corporation Type"]
+        .iter()
+        .find_map(|key| detail(data, key))
+        .map(str::to_uppercase)
+}
+
+/// Province of the registered office, from the last province code or name in the address.
+fn office_province(address: &str) -> Option<Jurisdiction> {
+    let address = address.to_uppercase();
+    let words: Vec<&str> = address
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let by_code = words.iter().rev().find_map(|word| {
+        PROVINCES
+            .iter()
+            .find(|(code, _, _)| code == word)
+            .map(|(_, _, jurisdiction)| *jurisdiction)
+    });
+
+    by_code.or_else(|| {
+        PROVINCES
+            .iter()
+            .filter_map(|(_, name, jurisdiction)| {
+                address.rfind(name).map(|index| (index, *jurisdiction))
+            })
+            .max_by_key(|(index, _)| *index)
+            .map(|(_, jurisdiction)| jurisdiction)
+    })
+}
+
+/// Only the city and province code of an address, e.g. "Toronto, ON" of "12 Elm St, Unit 4,
+/// Toronto ON M5V 2T6". The city is what precedes the province in its part of the address, or
+/// the part before. None when no province is found.
+pub fn city_and_province(address: &str) -> Option<String> {
+    let parts: Vec<&str> = address
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    for (i, part) in parts.iter().enumerate().rev() {
+        let words: Vec<&str> = part.split_whitespace().collect();
+        // the last province named, "Quebec City QC" is in QC
+        let found = (0..words.len()).rev().find_map(|at| {
+            let rest = words[at..].join(" ").to_uppercase();
+            PROVINCES.iter().find_map(|(code, name, _)| {
+                let first = rest.split(|c: char| !c.is_alphanumeric()).next()?;
+                (first == *code || rest.starts_with(name)).then_some((at, *code))
+            })
+        });
+        let Some((at, code)) = found else {
+            continue;
+        };
+
+        let city = match at {
+            0 => i.checked_sub(1).map(|previous| parts[previous].to_string()),
+            at => Some(words[..at].join(" ")),
+        };
+        return Some(match city {
+            Some(city) => format!("{}, {}", city, code),
+            None => code.to_string(),
+        });
+    }
+
+    None
+}
+
+fn infer_jurisdiction(data: &CorporationData, act: Option<&str>) -> Jurisdiction {
+    if let Some(act) = act {
+        if act.starts_with("CANADA ") || act.contains("BOARDS OF TRADE") {
+            return Jurisdiction::Federal;
+        }
+        if act.contains("ONTARIO") {
+            return Jurisdiction::Ontario;
+        }
+    }
+    if detail(data, "Ontario Corporation Number").is_some() {
+        return Jurisdiction::Ontario;
+    }
+    if detail(data, CORPORATION_NUMBER_LABEL).is_some_and(|number| FEDERAL_NUMBER.is_match(number))
+    {
+        return Jurisdiction::Federal;
+    }
+
+    data.address_details
+        .as_deref()
+        .and_then(office_province)
+        .unwrap_or(Jurisdiction::Unknown)
+}
+
+fn infer_entity_type(act: Option<&str>) -> EntityType {
+    let Some(act) = act else {
+        return EntityType::Unknown;
+    };
+
+    if act.contains("NOT-FOR-PROFIT")
+        || act.contains("NOT FOR PROFIT")
+        || act.contains("NON-PROFIT")
+    {
+        EntityType::NotForProfit
+    } else if act.contains("COOPERATIVE") || act.contains("CO-OPERATIVE") {
+        EntityType::Cooperative
+    } else if act.contains("BOARDS OF TRADE") {
+        EntityType::BoardOfTrade
+    } else if act.contains("BUSINESS CORPORATION") {
+        EntityType::BusinessCorporation
+    } else {
+        EntityType::Unknown
+    }
+}
+
+/// Fills `jurisdiction` and `entity_type` from the scraped details and registered office.
+/// Left untouched when neither section was extracted.
+pub fn enrich(data: &mut CorporationData) {
+    if data.corp_details.is_none() && data.address_details.is_none() {
+        return;
+    }
+
+    let act = governing_act(data);
+    data.jurisdiction = Some(infer_jurisdiction(data, act.as_deref()));
+    data.entity_type = Some(infer_entity_type(act.as_deref()));
+}
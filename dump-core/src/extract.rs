@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use scraper::{ElementRef, Html, Selector};
+
+use crate::{
+    enrich::enrich,
+    labels,
+    models::{
+        AnnualFilings, CorporationData, CorporationSection, FilingStatus, ShareClass,
+        ADDRESS_LABEL, ANNIVERSARY_DATE_LABEL, ANNUAL_FILINGS_LABEL, CORPORATE_HISTORY_LABEL,
+        CORPORATE_NAME_LABEL, CORPORATION_NUMBER_LABEL, DIRECTORS_LABEL, FILING_STATUS_LABEL,
+        SHARE_RESTRICTIONS_LABEL, SHARE_STRUCTURE_LABEL,
+    },
+    normalize,
+    patterns::*,
+};
+
+/// How the extracted data is labelled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    // English labels whichever language the page is in, see `labels::canonical`
+    pub bilingual_labels: bool,
+}
+
+impl Options {
+    fn label(self, label: &str) -> String {
+        if self.bilingual_labels {
+            labels::canonical(label)
+        } else {
+            label.to_string()
+        }
+    }
+}
+
+/// Profile page of the federal corporation `corporation_id`.
+pub fn profile_url(corporation_id: &str) -> String {
+    format!(
+        "https://redacted/cc/lgcy/fdrlCrpDtls.html?p=0&corpId={corporation_id}&V_TOKEN=null&crpNm=Tech&crpNmbr=&bsNmbr=&cProv=&cStatus=&cAct=",
+        corporation_id = corporation_id
+    )
+}
+
+/// Extracts `sections` of a federal corporation profile page, with `jurisdiction` and
+/// `entity_type` inferred from them. Fails on pages that aren't a profile, e.g. after a redesign.
+/// The share sections only some profiles have are left out when the page has none.
+pub fn corporation(
+    html: &str,
+    sections: &[CorporationSection],
+    options: Options,
+) -> Result<CorporationData> {
+    use CorporationSection::*;
+
+    let document = Html::parse_document(html);
+    // only parse what was asked for, the history tables in particular are slow
+    let mut data = CorporationData {
+        corp_details: sections
+            .contains(&Corp)
+            .then(|| corp_details(&document, options))
+            .transpose()?,
+        address_details: sections
+            .contains(&Address)
+            .then(|| address_details(&document))
+            .transpose()?,
+        director_details: sections
+            .contains(&Directors)
+            .then(|| director_details(&document, options))
+            .transpose()?,
+        annual_filings_details: sections
+            .contains(&AnnualFilings)
+            .then(|| annual_filings_details(&document, options))
+            .transpose()?,
+        corp_history_details: sections
+            .contains(&CorpHistory)
+            .then(|| corp_history_details(&document, options))
+            .transpose()?,
+        share_structure: sections
+            .contains(&ShareStructure)
+            .then(|| share_structure(&document))
+            .flatten(),
+        share_restrictions: sections
+            .contains(&ShareRestrictions)
+            .then(|| share_restrictions(&document))
+            .flatten(),
+        jurisdiction: None,
+        entity_type: None,
+    };
+    enrich(&mut data);
+
+    Ok(data)
+}
+
+// The first element under `element` matching `selector`, `what` naming it when there's none
+fn first<'a>(element: ElementRef<'a>, selector: &Selector, what: &str) -> Result<ElementRef<'a>> {
+    element
+        .select(selector)
+        .next()
+        .with_context(|| format!("the profile page has no {}", what))
+}
+
+fn corp_details(html_data: &Html, options: Options) -> Result<Vec<HashMap<String, String>>> {
+    let block = rows_section(html_data, CORPORATION_NUMBER_LABEL)
+        .context("the profile page has no corporation details")?;
+    let rows = block.select(&DATA_DISPLAY_GROUP).collect_vec();
+    let mut data: Vec<HashMap<String, String>> = Vec::new();
+
+    for row in rows {
+        let key = options.label(&normalize::clean_html(
+            &first(row, &BOLD, "corporation detail label")?.inner_html(),
+        ));
+
+        let text = first(row, &COL_SM_8, "corporation detail value")?
+            .text()
+            .map(normalize::clean)
+            .join("");
+        let value = if labels::canonical(&key) == CORPORATE_NAME_LABEL {
+            text.split("<br>").next().unwrap_or_default().to_string()
+        } else {
+            text
+        };
+
+        let mut row_data: HashMap<String, String> = HashMap::new();
+        row_data.insert(key.trim().to_string(), value.trim().to_string());
+        data.push(row_data);
+    }
+
+    Ok(data)
+}
+
+fn address_details(html_data: &Html) -> Result<String> {
+    let block = section(html_data, ADDRESS_LABEL).context("the profile page has no address")?;
+    let address = first(block, &DIV, "address")?.text().collect_vec();
+
+    Ok(address
+        .iter()
+        .map(|s| normalize::clean(s))
+        .filter(|s| !s.is_empty())
+        .join(", "))
+}
+
+fn director_details(
+    html_data: &Html,
+    options: Options,
+) -> Result<HashMap<String, Vec<HashMap<String, String>>>> {
+    let html_data =
+        section(html_data, DIRECTORS_LABEL).context("the profile page has no directors")?;
+
+    let director_count = first(html_data, &INLINE_GROUP, "director count")?;
+    let mut director_count_data: Vec<HashMap<String, String>> = Vec::new();
+    for row in director_count.select(&DIV) {
+        if let Some(key) = row.select(&BOLD).next() {
+            let value = normalize::clean_html(&first(row, &SPAN, "director count")?.inner_html());
+            let mut row_data: HashMap<String, String> = HashMap::new();
+            row_data.insert(
+                options.label(&normalize::clean_html(&key.inner_html())),
+                value,
+            );
+            director_count_data.push(row_data);
+        }
+    }
+
+    let directors_lists = html_data.select(&FULL_WIDTH_ITEM).collect_vec();
+
+    let mut directors_personal_data: Vec<HashMap<String, String>> = Vec::new();
+
+    for row in directors_lists {
+        let director_p = row.text().map(normalize::clean).collect_vec();
+        let Some((name, address)) = director_p.split_first() else {
+            continue;
+        };
+        let name = name.to_string();
+        let address = address.join(", ");
+        let mut row_data: HashMap<String, String> = HashMap::new();
+        row_data.insert("name".to_string(), name);
+        row_data.insert("address".to_string(), address);
+        directors_personal_data.push(row_data);
+    }
+
+    let mut directors_final_data: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
+    directors_final_data.insert("director_count".to_string(), director_count_data.to_vec());
+    directors_final_data.insert(
+        "director_personal_data".to_string(),
+        directors_personal_data.to_vec(),
+    );
+
+    Ok(directors_final_data)
+}
+
+fn annual_filings_details(html_data: &Html, options: Options) -> Result<AnnualFilings> {
+    let rows = section(html_data, ANNUAL_FILINGS_LABEL)
+        .context("the profile page has no annual filings")?;
+    let mut filings = AnnualFilings::default();
+
+    for row in rows.select(&DATA_DISPLAY_GROUP) {
+        let key = first(row, &BOLD, "annual filing label")?
+            .text()
+            .map(normalize::clean)
+            .join("");
+        let key = options.label(key.trim());
+        let key = key.as_str();
+        let value_div = first(row, &COL_SM_9, "annual filing value")?;
+
+        if key == FILING_STATUS_LABEL {
+            // one `2023 - Filed` item per year
+            filings.status_by_year = value_div
+                .select(&LI)
+                .filter_map(|item| {
+                    let text = item.text().map(normalize::clean).join("");
+                    let (year, status) = text.split_once('-')?;
+                    Some(FilingStatus {
+                        year: year.trim().parse().ok()?,
+                        status: status.trim().to_string(),
+                    })
+                })
+                .collect();
+            continue;
+        }
+
+        let value = normalize::clean(&value_div.text().collect::<String>());
+        if key == ANNIVERSARY_DATE_LABEL {
+            filings.anniversary_date = Some(value);
+        } else {
+            filings.other.insert(key.to_string(), value);
+        }
+    }
+
+    filings.last_filed = filings.latest_filed();
+
+    Ok(filings)
+}
+
+fn corp_history_details(
+    html_data: &Html,
+    options: Options,
+) -> Result<HashMap<String, Vec<HashMap<String, String>>>> {
+    let html_data = section(html_data, CORPORATE_HISTORY_LABEL)
+        .context("the profile page has no corporate history")?;
+
+    let table_data = first(html_data, &TABLE, "name history")?;
+    let heading = first(table_data, &THEAD, "name history heading")?
+        .text()
+        .map(normalize::clean)
+        .join("");
+    let heading = options.label(&heading);
+    let td_data = table_data.select(&TD).collect_vec();
+    let table_info = td_data
+        .iter()
+        .map(|data| {
+            let row_val = data
+                .text()
+                .map(normalize::clean)
+                .filter(|s| !s.is_empty())
+                .collect_vec()
+                .join(" ");
+            row_val
+        })
+        .collect_vec();
+
+    let name_history_data = table_info
+        .chunks_exact(2)
+        .map(|data| {
+            let key = data[0].to_string();
+            let value = data[1].to_string();
+            let mut row_data: HashMap<String, String> = HashMap::new();
+            row_data.insert(key, value);
+            row_data
+        })
+        .collect_vec();
+
+    let section = first(html_data, &PANEL_INFO, "certificates panel")?;
+    let section_header = first(section, &HEADER, "certificates heading")?
+        .text()
+        .map(normalize::clean)
+        .join("");
+    let section_header = options.label(&section_header);
+
+    let panel_body = first(section, &PANEL_BODY, "certificates")?;
+
+    let rows = panel_body.select(&DATA_DISPLAY_GROUP).collect_vec();
+    let mut panel_data: Vec<HashMap<String, String>> = Vec::new();
+    for row in rows {
+        let key = first(row, &BOLD, "certificate label")?
+            .text()
+            .map(normalize::clean)
+            .join("");
+        let value = first(row, &COL_SM_6, "certificate value")?
+            .text()
+            .map(normalize::clean)
+            .join("");
+        let mut row_data: HashMap<String, String> = HashMap::new();
+        row_data.insert(options.label(key.trim()), value.trim().to_string());
+        panel_data.push(row_data);
+    }
+
+    let mut data: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
+    data.insert(heading, name_history_data);
+    data.insert(section_header, panel_data);
+
+    Ok(data)
+}
+
+// The block of the section headed `label`, in either language. The optional sections move the
+// blocks after them, so sections are found by their heading rather than by position. The heading
+// is either in the block with the section's rows or in a block of its own right before them.
+fn section<'a>(html_data: &'a Html, label: &str) -> Option<ElementRef<'a>> {
+    let blocks = html_data.select(&COL_SM_12).collect_vec();
+    let headed = blocks.iter().position(|block| {
+        // blocks wrapping other sections have more than one heading
+        let headings = block.select(&HEADING).collect_vec();
+        headings.len() == 1
+            && labels::canonical(&headings[0].text().map(normalize::clean).join(" "))
+                .eq_ignore_ascii_case(label)
+    })?;
+
+    let block = blocks[headed];
+    let has_content = block.select(&DATA_DISPLAY_GROUP).next().is_some()
+        || block.select(&LI).next().is_some()
+        || block.select(&PARAGRAPH).next().is_some();
+    if has_content {
+        Some(block)
+    } else {
+        blocks.get(headed + 1).copied()
+    }
+}
+
+// The block of rows holding the one labelled `label`, for the details at the top of the profile,
+// which have no heading of their own
+fn rows_section<'a>(html_data: &'a Html, label: &str) -> Option<ElementRef<'a>> {
+    html_data
+        .select(&COL_SM_12)
+        .filter(|block| {
+            // only the innermost block, not the ones wrapping the whole profile
+            block.select(&COL_SM_12).nth(1).is_none()
+        })
+        .find(|block| {
+            block.select(&DATA_DISPLAY_GROUP).any(|row| {
+                row.select(&BOLD).next().is_some_and(|bold| {
+                    labels::canonical(&normalize::clean_html(&bold.inner_html()))
+                        .eq_ignore_ascii_case(label)
+                })
+            })
+        })
+}
+
+fn share_structure(html_data: &Html) -> Option<Vec<ShareClass>> {
+    let block = section(html_data, SHARE_STRUCTURE_LABEL)?;
+
+    // a `Common: Unlimited` row per class, or a list of them
+    let rows = block.select(&DATA_DISPLAY_GROUP).collect_vec();
+    let classes = if rows.is_empty() {
+        block
+            .select(&LI)
+            .filter_map(|item| {
+                let text = item.text().map(normalize::clean).join(" ");
+                let (name, authorized) = text.split_once(':').or_else(|| text.split_once(" - "))?;
+                Some(ShareClass {
+                    name: name.trim().to_string(),
+                    authorized: authorized.trim().to_string(),
+                })
+            })
+            .collect_vec()
+    } else {
+        rows.into_iter()
+            .filter_map(|row| {
+                let name = normalize::clean_html(&row.select(&BOLD).next()?.inner_html());
+                let text = normalize::clean(&row.text().collect::<String>());
+                let authorized = text.strip_prefix(name.as_str()).unwrap_or(&text);
+                Some(ShareClass {
+                    name: name.trim_end_matches(':').trim().to_string(),
+                    authorized: authorized.trim_start_matches(':').trim().to_string(),
+                })
+            })
+            .collect_vec()
+    };
+
+    Some(classes)
+}
+
+fn share_restrictions(html_data: &Html) -> Option<Vec<String>> {
+    let block = section(html_data, SHARE_RESTRICTIONS_LABEL)?;
+
+    let paragraphs = block
+        .select(&PARAGRAPH)
+        .chain(block.select(&LI))
+        .map(|paragraph| normalize::clean(&paragraph.text().collect::<String>()))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect_vec();
+
+    Some(paragraphs)
+}
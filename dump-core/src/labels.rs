@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::models::CorporationData;
+
+/// Language of the federal corporation pages and of the labels in the response.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    En,
+    Fr,
+}
+
+impl Lang {
+    pub fn accept_language(self) -> &'static str {
+        match self {
+            Lang::En => "en-CA,en;q=0.9",
+            Lang::Fr => "fr-CA,fr;q=0.9",
+        }
+    }
+}
+
+// Labels of the federal corporation page, English then French
+const LABELS: [(&str, &str); 23] = [
+    ("Corporate Name", "Dénomination sociale"),
+    ("Corporation Number", "Numéro de société"),
+    ("Business Number (BN)", "Numéro d'entreprise (NE)"),
+    ("Governing Legislation", "Loi régissante"),
+    ("Status", "Statut"),
+    ("Registered Office Address", "Adresse du siège social"),
+    ("Directors", "Administrateurs"),
+    (
+        "Minimum Number of Directors",
+        "Nombre minimal d'administrateurs",
+    ),
+    (
+        "Maximum Number of Directors",
+        "Nombre maximal d'administrateurs",
+    ),
+    ("Annual Filings", "Dépôts annuels"),
+    ("Anniversary Date (MM-DD)", "Date anniversaire (MM-JJ)"),
+    (
+        "Date of Last Annual Meeting",
+        "Date de la dernière assemblée annuelle",
+    ),
+    (
+        "Annual Filing Period (MM-DD)",
+        "Période de dépôt annuel (MM-JJ)",
+    ),
+    ("Type of Corporation", "Type de société"),
+    ("Status of Annual Filings", "Statut des dépôts annuels"),
+    ("Corporate History", "Historique de la société"),
+    (
+        "Corporate Name History",
+        "Historique de la dénomination sociale",
+    ),
+    ("Certificates and Filings", "Certificats et dépôts"),
+    ("Amalgamation and Continuance", "Fusion et prorogation"),
+    ("Date of Incorporation", "Date de constitution"),
+    ("Date of Dissolution", "Date de dissolution"),
+    ("Share Structure", "Structure du capital-actions"),
+    ("Share Restrictions", "Restrictions sur les actions"),
+];
+
+/// The English label of `label`, which may be either language. The registry isn't consistent
+/// about case and apostrophes, so those are ignored. Unknown labels are returned as they are.
+pub fn canonical(label: &str) -> String {
+    let wanted = label.replace('\u{2019}', "'").to_lowercase();
+    LABELS
+        .iter()
+        .find(|(en, fr)| en.to_lowercase() == wanted || fr.to_lowercase() == wanted)
+        .map_or_else(|| label.to_string(), |(en, _)| en.to_string())
+}
+
+/// `label`, a canonical English one, in `lang`.
+pub fn localized(label: &str, lang: Lang) -> String {
+    match lang {
+        Lang::En => label.to_string(),
+        Lang::Fr => LABELS
+            .iter()
+            .find(|(en, _)| *en == label)
+            .map_or_else(|| label.to_string(), |(_, fr)| fr.to_string()),
+    }
+}
+
+fn localize_rows(rows: &mut [HashMap<String, String>], lang: Lang) {
+    for row in rows {
+        *row = row
+            .drain()
+            .map(|(label, value)| (localized(&label, lang), value))
+            .collect();
+    }
+}
+
+/// Relabels the page sections of `data` in `lang`. Extraction stores English labels whatever
+/// the page language, so this only runs on responses.
+pub fn localize(data: &mut CorporationData, lang: Lang) {
+    if lang == Lang::En {
+        return;
+    }
+
+    if let Some(rows) = &mut data.corp_details {
+        localize_rows(rows, lang);
+    }
+    // the section keys of these are the API's own, only the rows hold page labels
+    if let Some(rows) = data
+        .director_details
+        .as_mut()
+        .and_then(|details| details.get_mut("director_count"))
+    {
+        localize_rows(rows, lang);
+    }
+    if let Some(filings) = &mut data.annual_filings_details {
+        filings.other = std::mem::take(&mut filings.other)
+            .into_iter()
+            .map(|(label, value)| (localized(&label, lang), value))
+            .collect();
+    }
+    if let Some(history) = data.corp_history_details.take() {
+        data.corp_history_details = Some(
+            history
+                .into_iter()
+                .map(|(heading, mut rows)| {
+                    localize_rows(&mut rows, lang);
+                    (localized(&heading, lang), rows)
+                })
+                .collect(),
+        );
+    }
+}
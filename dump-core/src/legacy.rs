@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::models::{AnnualFilings, FilingStatus, ANNIVERSARY_DATE_LABEL, FILING_STATUS_LABEL};
+
+// Shape annual filings had before `AnnualFilings`: one single-entry map per row of the section,
+// the filing status of every year under `Right` and any other field under `Left`
+#[derive(Serialize, Deserialize)]
+pub enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+pub type LegacyAnnualFilings = Vec<HashMap<String, Either<String, Vec<HashMap<String, String>>>>>;
+
+/// Reads annual filings in either the typed or the legacy shape.
+pub fn annual_filings<'de, D>(deserializer: D) -> Result<Option<AnnualFilings>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Typed(AnnualFilings),
+        Legacy(LegacyAnnualFilings),
+    }
+
+    let filings = Option::<Stored>::deserialize(deserializer)?.map(|stored| match stored {
+        Stored::Typed(filings) => filings,
+        Stored::Legacy(rows) => from_legacy(rows),
+    });
+
+    Ok(filings)
+}
+
+fn from_legacy(rows: LegacyAnnualFilings) -> AnnualFilings {
+    let mut filings = AnnualFilings::default();
+    for (label, value) in rows.into_iter().flatten() {
+        match value {
+            Either::Right(years) => {
+                filings.status_by_year = years
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(year, status)| {
+                        Some(FilingStatus {
+                            year: year.trim().parse().ok()?,
+                            status: status.trim().to_string(),
+                        })
+                    })
+                    .collect();
+            }
+            Either::Left(value) if label == ANNIVERSARY_DATE_LABEL => {
+                filings.anniversary_date = Some(value)
+            }
+            Either::Left(value) => {
+                filings.other.insert(label, value);
+            }
+        }
+    }
+    filings.last_filed = filings.latest_filed();
+
+    filings
+}
+
+/// `filings` in the shape they had before `AnnualFilings`.
+pub fn to_legacy(filings: AnnualFilings) -> LegacyAnnualFilings {
+    let mut rows = vec![];
+    if let Some(date) = filings.anniversary_date {
+        rows.push(HashMap::from([(
+            ANNIVERSARY_DATE_LABEL.to_string(),
+            Either::Left(date),
+        )]));
+    }
+    for (label, value) in filings.other {
+        rows.push(HashMap::from([(label, Either::Left(value))]));
+    }
+    let years = filings
+        .status_by_year
+        .into_iter()
+        .map(|filing| HashMap::from([(filing.year.to_string(), filing.status)]))
+        .collect();
+    rows.push(HashMap::from([(
+        FILING_STATUS_LABEL.to_string(),
+        Either::Right(years),
+    )]));
+
+    rows
+}
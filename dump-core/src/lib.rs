@@ -0,0 +1,26 @@
+//! Scraping core of the registry service, without the HTTP API around it: the corporation models,
+//! the parsers of federal profile and search pages and of Ontario Profile Reports, and the name
+//! normalization. Services embed it to scrape and compare registry data in-process.
+//!
+//! Fetching stays with the caller, e.g. with its own politeness and retries: get
+//! `extract::profile_url` and hand the page to `extract::corporation`, hand search pages to
+//! `search::parse_search_page` and the text of a report PDF to `report::parse_profile_report`.
+//!
+//! Not moved here yet, as they still read the service's configuration and run on its browser
+//! sessions:
+//! - the browser pool (`pool`, `session`), which needs the WebDriver settings passed in instead of
+//!   read from the CLI config;
+//! - the provider registries (`products`, `popups`, `maintenance`), which load their overrides from
+//!   the files the config names;
+//! - the browser flows built from them (`flow`).
+
+pub mod board;
+pub mod enrich;
+pub mod extract;
+pub mod labels;
+pub mod legacy;
+pub mod models;
+pub mod normalize;
+pub mod patterns;
+pub mod report;
+pub mod search;
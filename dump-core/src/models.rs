@@ -0,0 +1,223 @@
+use std::collections::{BTreeMap, HashMap};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    enrich::{EntityType, Jurisdiction},
+    legacy,
+};
+
+pub const CORPORATE_NAME_LABEL: &str = "Corporate Name";
+pub const CORPORATION_NUMBER_LABEL: &str = "Corporation Number";
+pub const ADDRESS_LABEL: &str = "Registered Office Address";
+pub const DIRECTORS_LABEL: &str = "Directors";
+pub const ANNUAL_FILINGS_LABEL: &str = "Annual Filings";
+pub const CORPORATE_HISTORY_LABEL: &str = "Corporate History";
+pub const ANNIVERSARY_DATE_LABEL: &str = "Anniversary Date (MM-DD)";
+pub const FILING_STATUS_LABEL: &str = "Status of Annual Filings";
+pub const SHARE_STRUCTURE_LABEL: &str = "Share Structure";
+pub const SHARE_RESTRICTIONS_LABEL: &str = "Share Restrictions";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FilingStatus {
+    pub year: u16,
+    // as shown by the registry, e.g. "Filed" or "Overdue"
+    pub status: String,
+}
+
+/// Annual return filings of a federal corporation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct AnnualFilings {
+    // MM-DD
+    pub anniversary_date: Option<String>,
+    // latest year with a "Filed" status
+    pub last_filed: Option<u16>,
+    pub status_by_year: Vec<FilingStatus>,
+    // remaining fields of the section by label, e.g. the date of the last annual meeting
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub other: BTreeMap<String, String>,
+}
+
+impl AnnualFilings {
+    pub fn latest_filed(&self) -> Option<u16> {
+        self.status_by_year
+            .iter()
+            .filter(|filing| filing.status.eq_ignore_ascii_case("filed"))
+            .map(|filing| filing.year)
+            .max()
+    }
+}
+
+/// A class of shares the corporation may issue.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ShareClass {
+    // e.g. "Common" or "Class A Preferred"
+    pub name: String,
+    // number authorized as shown by the registry, e.g. "Unlimited"
+    pub authorized: String,
+}
+
+// Sections are omitted from the response when not requested
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct CorporationData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub corp_details: Option<Vec<HashMap<String, String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address_details: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub director_details: Option<HashMap<String, Vec<HashMap<String, String>>>>,
+    // records stored before the typed model are read in their old shape too
+    #[serde(
+        default,
+        deserialize_with = "legacy::annual_filings",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub annual_filings_details: Option<AnnualFilings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub corp_history_details: Option<HashMap<String, Vec<HashMap<String, String>>>>,
+    // only some profiles have these, they're omitted when the page doesn't
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub share_structure: Option<Vec<ShareClass>>,
+    // restrictions on share transfers and other provisions, a paragraph each
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub share_restrictions: Option<Vec<String>>,
+    // inferred from the details and registered office, see `enrich`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jurisdiction: Option<Jurisdiction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_type: Option<EntityType>,
+}
+
+impl CorporationData {
+    pub fn has(&self, section: CorporationSection) -> bool {
+        match section {
+            CorporationSection::Corp => self.corp_details.is_some(),
+            CorporationSection::Address => self.address_details.is_some(),
+            CorporationSection::Directors => self.director_details.is_some(),
+            CorporationSection::AnnualFilings => self.annual_filings_details.is_some(),
+            CorporationSection::CorpHistory => self.corp_history_details.is_some(),
+            CorporationSection::ShareStructure => self.share_structure.is_some(),
+            CorporationSection::ShareRestrictions => self.share_restrictions.is_some(),
+        }
+    }
+
+    /// The value of the details row labelled `label`, e.g. `CORPORATE_NAME_LABEL`.
+    pub fn detail(&self, label: &str) -> Option<&str> {
+        self.corp_details
+            .iter()
+            .flatten()
+            .find_map(|row| row.get(label))
+            .map(String::as_str)
+    }
+
+    /// The name in the details, whichever registry it came from.
+    pub fn corporate_name(&self) -> Option<&str> {
+        self.detail(CORPORATE_NAME_LABEL)
+    }
+
+    /// Copies `sections` over from `other`.
+    pub fn fill(&mut self, other: &CorporationData, sections: &[CorporationSection]) {
+        use CorporationSection::*;
+
+        for section in sections {
+            match section {
+                Corp => self.corp_details = other.corp_details.clone(),
+                Address => self.address_details = other.address_details.clone(),
+                Directors => self.director_details = other.director_details.clone(),
+                AnnualFilings => self.annual_filings_details = other.annual_filings_details.clone(),
+                CorpHistory => self.corp_history_details = other.corp_history_details.clone(),
+                ShareStructure => self.share_structure = other.share_structure.clone(),
+                ShareRestrictions => self.share_restrictions = other.share_restrictions.clone(),
+            }
+        }
+    }
+
+    /// Asked for the details but got none, the page isn't the profile it should be.
+    pub fn lacks_details(&self, sections: &[CorporationSection]) -> bool {
+        sections.contains(&CorporationSection::Corp)
+            && self.corp_details.as_ref().is_none_or(Vec::is_empty)
+    }
+
+    /// Drops the sections other than `sections`.
+    pub fn retain(&mut self, sections: &[CorporationSection]) {
+        use CorporationSection::*;
+
+        if !sections.contains(&Corp) {
+            self.corp_details = None;
+        }
+        if !sections.contains(&Address) {
+            self.address_details = None;
+        }
+        if !sections.contains(&Directors) {
+            self.director_details = None;
+        }
+        if !sections.contains(&AnnualFilings) {
+            self.annual_filings_details = None;
+        }
+        if !sections.contains(&CorpHistory) {
+            self.corp_history_details = None;
+        }
+        if !sections.contains(&ShareStructure) {
+            self.share_structure = None;
+        }
+        if !sections.contains(&ShareRestrictions) {
+            self.share_restrictions = None;
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorporationSection {
+    #[serde(rename = "corp_details")]
+    Corp,
+    #[serde(rename = "address_details", alias = "address")]
+    Address,
+    #[serde(rename = "director_details", alias = "directors")]
+    Directors,
+    #[serde(
+        rename = "annual_filings_details",
+        alias = "annual_filings",
+        alias = "filings"
+    )]
+    AnnualFilings,
+    #[serde(
+        rename = "corp_history_details",
+        alias = "corp_history",
+        alias = "history"
+    )]
+    CorpHistory,
+    #[serde(rename = "share_structure", alias = "shares")]
+    ShareStructure,
+    #[serde(rename = "share_restrictions", alias = "restrictions")]
+    ShareRestrictions,
+}
+
+impl CorporationSection {
+    pub const ALL: [CorporationSection; 7] = [
+        CorporationSection::Corp,
+        CorporationSection::Address,
+        CorporationSection::Directors,
+        CorporationSection::AnnualFilings,
+        CorporationSection::CorpHistory,
+        CorporationSection::ShareStructure,
+        CorporationSection::ShareRestrictions,
+    ];
+
+    /// Sections that change from one scrape to the next: the details, which hold the status, and
+    /// the annual filings. A differential scrape always fetches them.
+    pub const VOLATILE: [CorporationSection; 2] =
+        [CorporationSection::Corp, CorporationSection::AnnualFilings];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CorporationSection::Corp => "corp_details",
+            CorporationSection::Address => "address_details",
+            CorporationSection::Directors => "director_details",
+            CorporationSection::AnnualFilings => "annual_filings_details",
+            CorporationSection::CorpHistory => "corp_history_details",
+            CorporationSection::ShareStructure => "share_structure",
+            CorporationSection::ShareRestrictions => "share_restrictions",
+        }
+    }
+}
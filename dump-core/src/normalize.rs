@@ -0,0 +1,166 @@
+use scraper::Html;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+// Legal form designations dropped from the end of a name (already accent-free and uppercased)
+const LEGAL_SUFFIXES: [&str; 13] = [
+    "INC",
+    "INCORPORATED",
+    "INCORPOREE",
+    "LTD",
+    "LIMITED",
+    "LTEE",
+    "LIMITEE",
+    "CORP",
+    "CORPORATION",
+    "CO",
+    "COMPANY",
+    "ULC",
+    "LLP",
+];
+
+/// Canonical form of a company name used for matching: accents removed, uppercased, `&` spelled
+/// out, apostrophes dropped, other punctuation collapsed to single spaces and trailing legal
+/// suffixes ("Inc.", "Ltée", ...) stripped.
+pub fn normalize_name(name: &str) -> String {
+    let folded: String = name
+        .nfkd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_uppercase()
+        .replace('&', " AND ")
+        .replace(['\'', '\u{2019}'], "");
+
+    let mut words: Vec<&str> = folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    // keep at least one word so "Limited Inc." doesn't normalize to nothing
+    while words.len() > 1
+        && words
+            .last()
+            .is_some_and(|word| LEGAL_SUFFIXES.contains(word))
+    {
+        words.pop();
+    }
+
+    words.join(" ")
+}
+
+/// Whether `word`, of a normalized name, is a legal form designation like "INC", all that's left of
+/// a name like "Inc." once normalized.
+pub fn is_legal_suffix(word: &str) -> bool {
+    LEGAL_SUFFIXES.contains(&word)
+}
+
+// Character pairs of each word, sorted, a lone letter paired with a space
+fn bigrams(name: &str) -> Vec<(char, char)> {
+    let mut bigrams: Vec<(char, char)> = name
+        .split(' ')
+        .flat_map(|word| {
+            let chars: Vec<char> = word.chars().collect();
+            match chars.as_slice() {
+                [letter] => vec![(*letter, ' ')],
+                chars => chars.windows(2).map(|pair| (pair[0], pair[1])).collect(),
+            }
+        })
+        .collect();
+    bigrams.sort_unstable();
+
+    bigrams
+}
+
+/// How alike two `normalize_name`d names are, from 0 to 1: the Dice coefficient of the character
+/// pairs of their words, so neither word order nor a letter or two of difference count for much.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    // pairs both have, as often as both have them
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    2.0 * shared as f64 / (a.len() + b.len()) as f64
+}
+
+// Invisible characters the registry pages leave inside names
+const ZERO_WIDTH: [char; 4] = ['\u{200B}', '\u{200C}', '\u{200D}', '\u{FEFF}'];
+
+/// Scraped text as stored: NFC normalized, zero-width characters dropped and runs of whitespace,
+/// non-breaking spaces included, collapsed to single spaces.
+pub fn clean(text: &str) -> String {
+    text.nfc()
+        .filter(|c| !ZERO_WIDTH.contains(c))
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `clean` for markup taken with `inner_html`, e.g. `Smith &amp; Sons&nbsp;Ltd.`: entities are
+/// decoded and tags dropped first.
+pub fn clean_html(fragment: &str) -> String {
+    let fragment = Html::parse_fragment(fragment);
+    clean(&fragment.root_element().text().collect::<Vec<_>>().join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean_collapses_whitespace() {
+        assert_eq!(clean("  ACME \n\t WIDGETS   INC. "), "ACME WIDGETS INC.");
+        assert_eq!(clean(" \n "), "");
+    }
+
+    #[test]
+    fn clean_treats_non_breaking_spaces_as_whitespace() {
+        assert_eq!(
+            clean("ACME\u{a0}\u{a0}WIDGETS\u{202f}INC."),
+            "ACME WIDGETS INC."
+        );
+    }
+
+    #[test]
+    fn clean_drops_zero_width_characters() {
+        assert_eq!(clean("AC\u{200b}ME\u{feff} INC."), "ACME INC.");
+    }
+
+    #[test]
+    fn clean_composes_accents() {
+        assert_eq!(clean("Lte\u{301}e"), "Ltée");
+    }
+
+    #[test]
+    fn clean_html_decodes_entities() {
+        assert_eq!(
+            clean_html("Smith &amp; Sons&nbsp;Ltd. &quot;East&quot; &#39;99"),
+            "Smith & Sons Ltd. \"East\" '99"
+        );
+    }
+
+    #[test]
+    fn clean_html_drops_tags() {
+        assert_eq!(
+            clean_html("100 Main Street<br>Ottawa <b>ON</b>"),
+            "100 Main Street Ottawa ON"
+        );
+        assert_eq!(clean_html("<span>\n  </span>"), "");
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use scraper::Selector;
+
+// Declares each pattern as a static compiled on first use, and records its source for `check`
+macro_rules! selectors {
+    ($($name:ident = $css:expr;)*) => {
+        $(pub static $name: Lazy<Selector> = Lazy::new(|| Selector::parse($css).expect($css));)*
+        const SELECTORS: &[(&str, &str)] = &[$((stringify!($name), $css)),*];
+    };
+}
+
+macro_rules! regexes {
+    ($($name:ident = $re:expr;)*) => {
+        $(pub static $name: Lazy<Regex> = Lazy::new(|| Regex::new($re).expect($re));)*
+        const REGEXES: &[(&str, &str)] = &[$((stringify!($name), $re)),*];
+    };
+}
+
+selectors! {
+    DIV = "div";
+    SPAN = "span";
+    BOLD = "b";
+    LINK = "a";
+    LI = "li";
+    TD = "td";
+    TABLE = "table";
+    THEAD = "thead";
+    HEADER = "header";
+    HEADING = "h2, h3";
+    PARAGRAPH = "p";
+    COL_SM_6 = "div.col-sm-6";
+    COL_SM_8 = "div.col-sm-8";
+    COL_SM_9 = "div.col-sm-9";
+    COL_SM_12 = "div.col-sm-12";
+    COL_MD_11 = "div.col-md-11";
+    DATA_DISPLAY_GROUP = "div.data-display-group";
+    INLINE_GROUP = "div.inline-group";
+    FULL_WIDTH_ITEM = "li.full-width";
+    PANEL_INFO = "section.panel-info";
+    PANEL_BODY = "div.panel-body";
+    NEXT_PAGE_LINK = "a[rel=\"next\"]";
+}
+
+regexes! {
+    // Month Day, Year
+    DATE_INPUT = r"^([A-Z][a-z]+) (\d{1,2}), (\d{4})$";
+    // Federal corporation numbers are shown as six digits and a check digit, e.g. 123456-7
+    FEDERAL_NUMBER = r"^\d{6,7}-\d$";
+    // Language tag of a page, e.g. <html lang="fr-CA">
+    HTML_LANG = r#"(?i)<html[^>]*\slang\s*=\s*["']?([a-z]{2,3}(?:-[a-z0-9]+)*)"#;
+}
+
+/// Compiles every selector and regex so a broken pattern stops startup rather than panicking in
+/// the middle of a request.
+pub fn check() -> Result<()> {
+    let selectors = SELECTORS.iter().filter_map(|(name, css)| {
+        Selector::parse(css)
+            .err()
+            .map(|err| format!("{} ({}): {:?}", name, css, err))
+    });
+    let regexes = REGEXES.iter().filter_map(|(name, re)| {
+        Regex::new(re)
+            .err()
+            .map(|err| format!("{} ({}): {}", name, re, err))
+    });
+
+    let broken: Vec<String> = selectors.chain(regexes).collect();
+    anyhow::ensure!(broken.is_empty(), "invalid patterns: {}", broken.join("; "));
+
+    Ok(())
+}
+
+/// Every selector and regex by name, with its source.
+pub fn sources() -> impl Iterator<Item = &'static (&'static str, &'static str)> {
+    SELECTORS.iter().chain(REGEXES)
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use crate::{
+    enrich::enrich,
+    models::{CorporationData, CORPORATE_NAME_LABEL},
+    normalize,
+};
+
+// The report's label of the name, stored under `CORPORATE_NAME_LABEL` like the federal one
+const NAME_LABEL: &str = "Corporation Name";
+// Header fields copied into `corp_details`
+const DETAIL_LABELS: [&str; 9] = [
+    NAME_LABEL,
+    "Ontario Corporation Number",
+    "Corporation Type",
+    "Corporation Status",
+    "Date of Incorporation",
+    "Date of Amalgamation",
+    "Jurisdiction",
+    "Governing Legislation",
+    "Business Number",
+];
+const ADDRESS_LABEL: &str = "Registered or Head Office Address";
+const DIRECTOR_COUNT_LABELS: [&str; 2] =
+    ["Minimum Number of Directors", "Maximum Number of Directors"];
+const DIRECTOR_LABELS: [&str; 4] = [
+    "Name",
+    "Address for Service",
+    "Resident Canadian",
+    "Date Began",
+];
+
+const DIRECTORS_SECTION: &str = "Active Director(s)";
+// Sections after the directors we don't parse (yet)
+const OTHER_SECTIONS: [&str; 5] = [
+    "Active Officer(s)",
+    "Corporate Name History",
+    "Active Business Names",
+    "Expired or Cancelled Business Names",
+    "Document List",
+];
+
+fn is_label(line: &str) -> bool {
+    DETAIL_LABELS
+        .iter()
+        .chain(DIRECTOR_COUNT_LABELS.iter())
+        .chain(DIRECTOR_LABELS.iter())
+        .chain([ADDRESS_LABEL, DIRECTORS_SECTION].iter())
+        .chain(OTHER_SECTIONS.iter())
+        .any(|label| strip_label(line, label).is_some())
+}
+
+// `Label: value` / `Label value` / bare `Label`, the value being on the following line(s)
+fn strip_label<'a>(line: &'a str, label: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(label)?;
+    if !rest.is_empty() && !rest.starts_with([' ', ':', '\t']) {
+        return None;
+    }
+
+    Some(rest.trim_start_matches([' ', ':', '\t']).trim())
+}
+
+// Value of the label at `lines[i]`, following continuation lines until the next label when
+// `multiline`. Returns the value and the index of the last line consumed.
+fn value_at(lines: &[&str], i: usize, label: &str, multiline: bool) -> (String, usize) {
+    let mut parts = vec![];
+    let inline = strip_label(lines[i], label).unwrap_or_default();
+    if !inline.is_empty() {
+        parts.push(inline.to_string());
+    }
+
+    let mut end = i;
+    while end + 1 < lines.len() && !is_label(lines[end + 1]) && (multiline || parts.is_empty()) {
+        end += 1;
+        parts.push(lines[end].to_string());
+    }
+
+    (parts.join(", "), end)
+}
+
+/// Parses the text of an Ontario Profile Report into the same model `corporation_get` returns.
+/// Only the header, registered office and director sections are mapped.
+pub fn parse_profile_report(text: &str) -> CorporationData {
+    let lines: Vec<String> = text
+        .lines()
+        .map(normalize::clean)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+
+    let mut corp_details: Vec<HashMap<String, String>> = vec![];
+    let mut address = None;
+    let mut director_count: Vec<HashMap<String, String>> = vec![];
+    let mut directors: Vec<HashMap<String, String>> = vec![];
+    let mut in_directors = false;
+
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+
+        if line == DIRECTORS_SECTION {
+            in_directors = true;
+        } else if OTHER_SECTIONS.contains(&line) {
+            in_directors = false;
+        } else if let Some(label) = DIRECTOR_COUNT_LABELS
+            .iter()
+            .find(|label| strip_label(line, label).is_some())
+        {
+            let (value, end) = value_at(&lines, i, label, false);
+            director_count.push(HashMap::from([(label.to_string(), value)]));
+            i = end;
+        } else if in_directors {
+            if let Some(label) = DIRECTOR_LABELS
+                .iter()
+                .find(|label| strip_label(line, label).is_some())
+            {
+                if *label == "Name" {
+                    directors.push(HashMap::new());
+                }
+                let (value, end) = value_at(&lines, i, label, *label == "Address for Service");
+                if let Some(director) = directors.last_mut() {
+                    let key = match *label {
+                        "Name" => "name".to_string(),
+                        "Address for Service" => "address".to_string(),
+                        other => other.to_string(),
+                    };
+                    director.insert(key, value);
+                }
+                i = end;
+            }
+        } else if strip_label(line, ADDRESS_LABEL).is_some() {
+            let (value, end) = value_at(&lines, i, ADDRESS_LABEL, true);
+            address = Some(value);
+            i = end;
+        } else if let Some(label) = DETAIL_LABELS
+            .iter()
+            .find(|label| strip_label(line, label).is_some())
+        {
+            let (value, end) = value_at(&lines, i, label, false);
+            let key = if *label == NAME_LABEL {
+                CORPORATE_NAME_LABEL
+            } else {
+                label
+            };
+            corp_details.push(HashMap::from([(key.to_string(), value)]));
+            i = end;
+        }
+
+        i += 1;
+    }
+
+    let mut data = CorporationData {
+        corp_details: Some(corp_details),
+        address_details: address,
+        director_details: Some(HashMap::from([
+            ("director_count".to_string(), director_count),
+            ("director_personal_data".to_string(), directors),
+        ])),
+        annual_filings_details: None,
+        corp_history_details: None,
+        share_structure: None,
+        share_restrictions: None,
+        jurisdiction: None,
+        entity_type: None,
+    };
+    enrich(&mut data);
+
+    data
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use scraper::{ElementRef, Html};
+
+use crate::{
+    normalize,
+    patterns::{COL_MD_11, LINK, NEXT_PAGE_LINK, SPAN},
+};
+
+/// One page of federal corporation search results.
+#[derive(Debug, Default)]
+pub struct SearchPage {
+    // `business_name`, `status`, `corporation_number` (without its dash) and `business_number`
+    pub rows: Vec<HashMap<String, String>>,
+    // rows left out as they don't have the expected name link and fields, so one changed row
+    // doesn't fail the search
+    pub skipped: usize,
+    pub has_next: bool,
+}
+
+/// Parses a page of the federal search by name, see `FEDERAL_SEARCH_URL` in the service.
+pub fn parse_search_page(html: &str) -> SearchPage {
+    let document = Html::parse_document(html);
+    let mut page = SearchPage::default();
+
+    for row in document.select(&COL_MD_11) {
+        match search_row(row) {
+            Some(row) => page.rows.push(row),
+            None => page.skipped += 1,
+        }
+    }
+    page.has_next = document.select(&NEXT_PAGE_LINK).next().is_some();
+
+    page
+}
+
+// The name link, then `Status: ..`, `Corporation number: ..` and `Business Number: ..`
+fn search_row(row: ElementRef) -> Option<HashMap<String, String>> {
+    let spans = row.select(&SPAN).collect::<Vec<_>>();
+    let [name, status, corporation_number, business_number, ..] = spans.as_slice() else {
+        return None;
+    };
+    let value = |span: &ElementRef| {
+        let text = normalize::clean_html(&span.inner_html());
+        Some(text.split_once(':')?.1.trim().to_string())
+    };
+
+    let mut row_data: HashMap<String, String> = HashMap::new();
+    row_data.insert(
+        "business_name".to_string(),
+        normalize::clean_html(&name.select(&LINK).next()?.inner_html()),
+    );
+    row_data.insert("status".to_string(), value(status)?);
+    row_data.insert(
+        "corporation_number".to_string(),
+        value(corporation_number)?.replace('-', ""),
+    );
+    row_data.insert("business_number".to_string(), value(business_number)?);
+
+    Some(row_data)
+}
@@ -0,0 +1,127 @@
+use dump_core::{
+    extract::{self, Options},
+    models::{AnnualFilings, CorporationSection},
+};
+
+const PROFILE: &str = include_str!("fixtures/profile.html");
+
+fn annual_filings(html: &str) -> AnnualFilings {
+    extract::corporation(
+        html,
+        &[CorporationSection::AnnualFilings],
+        Options::default(),
+    )
+    .unwrap()
+    .annual_filings_details
+    .unwrap()
+}
+
+// A profile page with only an annual filings section holding `rows`
+fn page(rows: &str) -> String {
+    format!(
+        r#"<html><body><div class="col-sm-12"><h3>Annual Filings</h3>{}</div></body></html>"#,
+        rows
+    )
+}
+
+fn row(label: &str, value: &str) -> String {
+    format!(
+        r#"<div class="data-display-group"><div class="col-sm-3"><b>{}</b></div><div class="col-sm-9">{}</div></div>"#,
+        label, value
+    )
+}
+
+#[test]
+fn parses_the_filings_of_a_profile() {
+    let filings = annual_filings(PROFILE);
+
+    assert_eq!(filings.anniversary_date.as_deref(), Some("03-05"));
+    assert_eq!(filings.last_filed, Some(2024));
+    let statuses: Vec<(u16, &str)> = filings
+        .status_by_year
+        .iter()
+        .map(|filing| (filing.year, filing.status.as_str()))
+        .collect();
+    assert_eq!(
+        statuses,
+        [(2024, "Filed"), (2023, "Filed"), (2022, "Overdue")]
+    );
+    assert_eq!(
+        filings
+            .other
+            .get("Date of Last Annual Meeting")
+            .map(String::as_str),
+        Some("2024-06-01")
+    );
+}
+
+#[test]
+fn last_filed_is_the_latest_filed_year_not_the_latest_year() {
+    let filings = annual_filings(&page(&row(
+        "Status of Annual Filings",
+        "<ul><li>2024 - Overdue</li><li>2022 - Filed</li><li>2023 - filed</li></ul>",
+    )));
+
+    assert_eq!(filings.last_filed, Some(2023));
+}
+
+#[test]
+fn nothing_filed_leaves_last_filed_unset() {
+    let filings = annual_filings(&page(&row(
+        "Status of Annual Filings",
+        "<ul><li>2024 - Overdue</li></ul>",
+    )));
+
+    assert_eq!(filings.last_filed, None);
+    assert_eq!(filings.status_by_year.len(), 1);
+}
+
+#[test]
+fn skips_status_items_without_a_year() {
+    let filings = annual_filings(&page(&row(
+        "Status of Annual Filings",
+        "<ul><li>Not yet due</li><li>n/a - Filed</li><li>2021 - Filed</li></ul>",
+    )));
+
+    assert_eq!(filings.status_by_year.len(), 1);
+    assert_eq!(filings.status_by_year[0].year, 2021);
+}
+
+#[test]
+fn reads_french_labels_with_bilingual_labels() {
+    let html = page(&row("Date anniversaire (MM-JJ)", "11-30"));
+    let extract = |bilingual_labels| {
+        extract::corporation(
+            &html,
+            &[CorporationSection::AnnualFilings],
+            Options { bilingual_labels },
+        )
+        .unwrap()
+        .annual_filings_details
+        .unwrap()
+    };
+
+    assert_eq!(extract(true).anniversary_date.as_deref(), Some("11-30"));
+    let filings = extract(false);
+    assert_eq!(filings.anniversary_date, None);
+    assert_eq!(
+        filings
+            .other
+            .get("Date anniversaire (MM-JJ)")
+            .map(String::as_str),
+        Some("11-30")
+    );
+}
+
+#[test]
+fn a_page_without_the_section_is_an_error() {
+    let html =
+        r#"<html><body><div class="col-sm-12"><h3>Directors</h3><p>None</p></div></body></html>"#;
+
+    assert!(extract::corporation(
+        html,
+        &[CorporationSection::AnnualFilings],
+        Options::default()
+    )
+    .is_err());
+}
@@ -0,0 +1,35 @@
+use dump_core::search::parse_search_page;
+
+const SEARCH_PAGE: &str = include_str!("fixtures/search.html");
+
+#[test]
+fn search_page_skips_rows_missing_fields() {
+    let page = parse_search_page(SEARCH_PAGE);
+
+    let names: Vec<&str> = page
+        .rows
+        .iter()
+        .map(|row| row["business_name"].as_str())
+        .collect();
+    assert_eq!(names, ["ACME HOLDINGS INC.", "ACME VENTURES LTD."]);
+    assert_eq!(page.skipped, 2);
+    assert!(page.has_next);
+}
+
+#[test]
+fn search_row_reads_labelled_fields() {
+    let page = parse_search_page(SEARCH_PAGE);
+
+    assert_eq!(page.rows[0]["status"], "Active");
+    assert_eq!(page.rows[0]["corporation_number"], "1234567");
+    assert_eq!(page.rows[0]["business_number"], "123456789RC0001");
+}
+
+#[test]
+fn search_page_without_rows_is_empty() {
+    let page = parse_search_page("<html><body></body></html>");
+
+    assert!(page.rows.is_empty());
+    assert_eq!(page.skipped, 0);
+    assert!(!page.has_next);
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use once_cell::sync::Lazy;
+use serde_json::json;
+
+use crate::config::CONFIG;
+
+#[derive(Default)]
+struct Window {
+    // (when, failed)
+    outcomes: VecDeque<(Instant, bool)>,
+    last_alert: Option<Instant>,
+}
+
+static WINDOWS: Lazy<Mutex<HashMap<String, Window>>> = Lazy::new(Default::default);
+
+/// Records the outcome of every routed request (5xx counts as a failure) and raises a Slack
+/// alert when a route's failure ratio over the alert window crosses the threshold.
+pub async fn track(req: Request, next: Next) -> Response {
+    let route = match req.extensions().get::<MatchedPath>() {
+        Some(path) => format!("{} {}", req.method(), path.as_str()),
+        None => format!("{} {}", req.method(), req.uri().path()),
+    };
+
+    let response = next.run(req).await;
+    record(route, response.status().is_server_error());
+
+    response
+}
+
+fn record(route: String, failed: bool) {
+    if CONFIG.slack_webhook_url.is_none() {
+        return;
+    }
+
+    let window_size = Duration::from_secs(CONFIG.alert_window_secs);
+    let now = Instant::now();
+
+    let mut windows = WINDOWS.lock().unwrap();
+    let window = windows.entry(route.clone()).or_default();
+    window.outcomes.push_back((now, failed));
+    while let Some((at, _)) = window.outcomes.front() {
+        if now.duration_since(*at) <= window_size {
+            break;
+        }
+        window.outcomes.pop_front();
+    }
+
+    let total = window.outcomes.len();
+    let failures = window.outcomes.iter().filter(|(_, failed)| *failed).count();
+    let cooling_down = window
+        .last_alert
+        .is_some_and(|at| now.duration_since(at) < window_size);
+    if cooling_down
+        || total < CONFIG.alert_min_requests
+        || (failures as f64) < CONFIG.alert_failure_ratio * total as f64
+    {
+        return;
+    }
+    window.last_alert = Some(now);
+
+    let text = format!(
+        "{} failing {}/{} times in {} min",
+        route,
+        failures,
+        total,
+        window_size.as_secs() / 60
+    );
+    send(text);
+}
+
+/// Posts `text` to `SLACK_WEBHOOK_URL` in the background, a no-op without one.
+pub fn send(text: String) {
+    let Some(webhook_url) = CONFIG.slack_webhook_url.clone() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        tracing::warn!("alert: {}", text);
+        let sent = reqwest::Client::new()
+            .post(&webhook_url)
+            .json(&json!({ "text": text }))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(err) = sent {
+            tracing::error!("failed to send slack alert: {}", err);
+        }
+    });
+}
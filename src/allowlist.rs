@@ -0,0 +1,45 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::config::CONFIG;
+
+// Address of the caller: the peer, or the last X-Forwarded-For hop when a trusted load balancer
+// sits in front (ALB appends the address it saw)
+fn client_ip(req: &Request) -> Option<IpAddr> {
+    if CONFIG.trust_forwarded_for {
+        return req
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+    }
+
+    req.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+/// Rejects callers outside `ALLOWED_CIDRS` with 403. Every caller is allowed when the list is
+/// empty.
+pub async fn check(req: Request, next: Next) -> Result<Response, StatusCode> {
+    if CONFIG.allowed_cidrs.is_empty() {
+        return Ok(next.run(req).await);
+    }
+
+    match client_ip(&req) {
+        Some(ip) if CONFIG.allowed_cidrs.iter().any(|net| net.contains(&ip)) => {
+            Ok(next.run(req).await)
+        }
+        ip => {
+            tracing::warn!("rejected {:?} outside the allowlist", ip);
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use aws_sdk_s3::{presigning::PresigningConfig, primitives::ByteStream};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use sha2::Sha256;
+use tokio::sync::OnceCell;
+
+use crate::config::{ArtifactBackend, CONFIG};
+
+/// Blob store for files produced or fetched while scraping (documents, screenshots, pages).
+#[async_trait]
+pub trait ArtifactStore: Send + Sync {
+    /// Stores `bytes` under `key` and returns where it ended up.
+    async fn put(&self, key: &str, bytes: &[u8], content_type: &str) -> Result<String>;
+    /// Link clients can download `key` from for the next `expires_in`.
+    async fn url(&self, key: &str, expires_in: Duration) -> Result<String>;
+    /// Contents of `key`, none when nothing is stored under it.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// Deletes `key`, nothing to do when nothing is stored under it.
+    async fn delete(&self, key: &str) -> Result<()>;
+    /// Deletes the artifacts under `prefix` stored before `before`, returning how many.
+    async fn purge(&self, prefix: &str, before: DateTime<Utc>) -> Result<usize>;
+}
+
+static LINK_KEY: Lazy<Vec<u8>> = Lazy::new(|| match &CONFIG.artifact_link_key {
+    Some(key) => key.as_bytes().to_vec(),
+    None => {
+        let mut key = vec![0; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        key
+    }
+});
+
+// Over `{key}.{expires}` under `ARTIFACT_LINK_KEY`
+fn link_mac(key: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&LINK_KEY).expect("HMAC takes keys of any size");
+    mac.update(format!("{}.{}", key, expires).as_bytes());
+    mac
+}
+
+/// Whether `signature` is the one `FilesystemArtifacts::url` gave the link to `key`, compared in
+/// constant time.
+pub fn verify_link(key: &str, expires: i64, signature: &str) -> bool {
+    let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+        return false;
+    };
+    link_mac(key, expires).verify_slice(&signature).is_ok()
+}
+
+pub struct FilesystemArtifacts {
+    root: PathBuf,
+}
+
+impl FilesystemArtifacts {
+    /// The file of `key`, `None` for keys reaching outside the artifact directory.
+    pub fn path(&self, key: &str) -> Option<PathBuf> {
+        let escapes = key
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..");
+        (!escapes).then(|| self.root.join(key))
+    }
+
+    fn checked_path(&self, key: &str) -> Result<PathBuf> {
+        self.path(key)
+            .with_context(|| format!("invalid artifact key {}", key))
+    }
+}
+
+#[async_trait]
+impl ArtifactStore for FilesystemArtifacts {
+    async fn put(&self, key: &str, bytes: &[u8], _content_type: &str) -> Result<String> {
+        let path = self.checked_path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, bytes).await?;
+
+        Ok(path.display().to_string())
+    }
+
+    // Served by /api/artifacts, which checks the signature and the expiry
+    async fn url(&self, key: &str, expires_in: Duration) -> Result<String> {
+        let expires = Utc::now().timestamp() + expires_in.as_secs() as i64;
+        let signature = URL_SAFE_NO_PAD.encode(link_mac(key, expires).finalize().into_bytes());
+
+        Ok(format!(
+            "/api/artifacts/{}?expires={}&signature={}",
+            key, expires, signature
+        ))
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let Some(path) = self.path(key) else {
+            return Ok(None);
+        };
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.checked_path(key)?).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn purge(&self, prefix: &str, before: DateTime<Utc>) -> Result<usize> {
+        let mut purged = 0;
+        // prefixes are directories, e.g. `jobs/`
+        let mut dirs = vec![self.checked_path(prefix.trim_end_matches('/'))?];
+        while let Some(dir) = dirs.pop() {
+            let mut entries = match tokio::fs::read_dir(&dir).await {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            while let Some(entry) = entries.next_entry().await? {
+                let metadata = entry.metadata().await?;
+                if metadata.is_dir() {
+                    dirs.push(entry.path());
+                } else if DateTime::<Utc>::from(metadata.modified()?) < before {
+                    tokio::fs::remove_file(entry.path()).await?;
+                    purged += 1;
+                }
+            }
+        }
+
+        Ok(purged)
+    }
+}
+
+pub struct S3Artifacts {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+impl S3Artifacts {
+    pub async fn new(bucket: &str) -> Self {
+        let config = aws_config::load_from_env().await;
+
+        S3Artifacts {
+            client: aws_sdk_s3::Client::new(&config),
+            bucket: bucket.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ArtifactStore for S3Artifacts {
+    async fn put(&self, key: &str, bytes: &[u8], content_type: &str) -> Result<String> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .body(ByteStream::from(bytes.to_vec()))
+            .send()
+            .await
+            .with_context(|| format!("failed to upload {} to {}", key, self.bucket))?;
+
+        Ok(format!("s3://{}/{}", self.bucket, key))
+    }
+
+    // Presigned, so clients need no AWS credentials
+    async fn url(&self, key: &str, expires_in: Duration) -> Result<String> {
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .presigned(PresigningConfig::expires_in(expires_in)?)
+            .await?;
+
+        Ok(request.uri().to_string())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let output = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await;
+        let output = match output {
+            Ok(output) => output,
+            Err(err)
+                if err
+                    .as_service_error()
+                    .is_some_and(|err| err.is_no_such_key()) =>
+            {
+                return Ok(None)
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to download {}", key));
+            }
+        };
+
+        Ok(Some(output.body.collect().await?.into_bytes().to_vec()))
+    }
+
+    // S3 doesn't fail deletes of missing keys
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .with_context(|| format!("failed to delete {} from {}", key, self.bucket))?;
+
+        Ok(())
+    }
+
+    async fn purge(&self, prefix: &str, before: DateTime<Utc>) -> Result<usize> {
+        let mut purged = 0;
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            for object in page?.contents() {
+                let modified = object
+                    .last_modified()
+                    .map_or(i64::MAX, |modified| modified.secs());
+                let Some(key) = object.key() else {
+                    continue;
+                };
+                if modified < before.timestamp() {
+                    self.client
+                        .delete_object()
+                        .bucket(&self.bucket)
+                        .key(key)
+                        .send()
+                        .await?;
+                    purged += 1;
+                }
+            }
+        }
+
+        Ok(purged)
+    }
+}
+
+static ARTIFACTS: OnceCell<Box<dyn ArtifactStore>> = OnceCell::const_new();
+
+pub async fn init() -> Result<()> {
+    let store: Box<dyn ArtifactStore> = match CONFIG.artifact_backend {
+        ArtifactBackend::Filesystem => Box::new(filesystem()),
+        ArtifactBackend::S3 => {
+            let bucket = CONFIG
+                .artifact_bucket
+                .as_deref()
+                .context("the s3 artifact backend needs ARTIFACT_BUCKET")?;
+            Box::new(S3Artifacts::new(bucket).await)
+        }
+    };
+    ARTIFACTS
+        .set(store)
+        .map_err(|_| anyhow::anyhow!("artifact store already initialized"))
+}
+
+pub fn filesystem() -> FilesystemArtifacts {
+    FilesystemArtifacts {
+        root: CONFIG.artifact_dir.clone(),
+    }
+}
+
+pub fn artifacts() -> &'static dyn ArtifactStore {
+    ARTIFACTS
+        .get()
+        .expect("artifact store not initialized")
+        .as_ref()
+}
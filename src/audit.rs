@@ -0,0 +1,180 @@
+use std::{collections::BTreeMap, time::Instant};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::Request,
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    cards,
+    config::{AuditSink, CONFIG},
+    response, storage,
+};
+
+const COLLECTION: &str = "audit";
+
+// Headers that authenticate the client, never kept
+const HIDDEN_HEADERS: [header::HeaderName; 3] =
+    [header::AUTHORIZATION, header::COOKIE, header::SET_COOKIE];
+
+// Fields whose values are scrubbed wherever they are in a JSON body
+const SCRUBBED_FIELDS: [&str; 5] = ["card", "cvv", "password", "secret", "token"];
+
+/// A request and the answer it got, as the client sent and received them.
+#[derive(Serialize, Debug)]
+pub struct Exchange {
+    pub request_id: Option<Uuid>,
+    pub at: DateTime<Utc>,
+    pub method: String,
+    pub uri: String,
+    pub request_headers: BTreeMap<String, String>,
+    pub request_body: Captured,
+    pub status: u16,
+    pub response_body: Captured,
+    pub duration_ms: u64,
+}
+
+/// A body up to `AUDIT_MAX_BODY_BYTES`, larger and streamed ones are only described.
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Captured {
+    Empty,
+    Json { json: Value },
+    Text { text: String },
+    Binary { bytes: usize },
+    TooLarge { bytes: u64 },
+    Streamed,
+}
+
+/// Replaces the values of card, password, secret and token fields.
+pub fn scrub(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (name, value) in fields {
+                let name = name.to_lowercase();
+                if SCRUBBED_FIELDS.iter().any(|field| name.contains(field)) {
+                    *value = Value::String("[scrubbed]".to_string());
+                } else {
+                    scrub(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(scrub),
+        _ => {}
+    }
+}
+
+fn capture(bytes: &Bytes) -> Captured {
+    if bytes.is_empty() {
+        return Captured::Empty;
+    }
+    if let Ok(mut json) = serde_json::from_slice::<Value>(bytes) {
+        scrub(&mut json);
+        // the loaded card and account can still show up under other names
+        let json = serde_json::from_str(&cards::redact(&json.to_string())).unwrap_or(json);
+        return Captured::Json { json };
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Captured::Text {
+            text: cards::redact(text),
+        },
+        Err(_) => Captured::Binary { bytes: bytes.len() },
+    }
+}
+
+// Only bodies of known size within the limit are read, the rest pass through untouched
+async fn buffer(body: Body) -> Result<(Body, Captured), axum::Error> {
+    let limit = CONFIG.audit_max_body_bytes as u64;
+    match body.size_hint().exact() {
+        Some(0) => Ok((body, Captured::Empty)),
+        Some(size) if size <= limit => {
+            let bytes = axum::body::to_bytes(body, usize::MAX).await?;
+            let captured = capture(&bytes);
+            Ok((Body::from(bytes), captured))
+        }
+        Some(size) => Ok((body, Captured::TooLarge { bytes: size })),
+        None => Ok((body, Captured::Streamed)),
+    }
+}
+
+fn headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| !HIDDEN_HEADERS.contains(name))
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            (name.to_string(), cards::redact(&value))
+        })
+        .collect()
+}
+
+async fn persist(exchange: Exchange) {
+    match CONFIG.audit_sink {
+        AuditSink::Log => match serde_json::to_string(&exchange) {
+            Ok(json) => tracing::info!(target: "audit", "{}", json),
+            Err(err) => tracing::warn!("failed to serialize an audited request: {}", err),
+        },
+        AuditSink::Storage => {
+            let id = exchange.request_id.unwrap_or_else(Uuid::new_v4).to_string();
+            if let Err(err) = storage::save(COLLECTION, &id, &exchange).await {
+                tracing::warn!("failed to store audited request {}: {}", id, err);
+            }
+        }
+    }
+}
+
+/// Records `AUDIT_SAMPLE_RATE` of the requests with their responses to `AUDIT_SINK`, so what a
+/// client sent and got back can be looked up later. Credentials are left out and card details
+/// scrubbed, see `Captured` for how bodies are kept.
+pub async fn capture_exchange(req: Request, next: Next) -> Response {
+    if CONFIG.audit_sample_rate <= 0.0
+        || rand::thread_rng().gen::<f64>() >= CONFIG.audit_sample_rate
+    {
+        return next.run(req).await;
+    }
+
+    let started = Instant::now();
+    let at = Utc::now();
+    let (parts, body) = req.into_parts();
+    let (body, request_body) = match buffer(body).await {
+        Ok(buffered) => buffered,
+        // the handler would fail to read it just the same
+        Err(_) => (Body::empty(), Captured::Streamed),
+    };
+    let method = parts.method.to_string();
+    let uri = parts.uri.to_string();
+    let request_headers = headers(&parts.headers);
+
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let (parts, body) = response.into_parts();
+    let (body, response_body) = match buffer(body).await {
+        Ok(buffered) => buffered,
+        Err(err) => {
+            tracing::warn!("failed to read the response of {}: {}", uri, err);
+            (Body::empty(), Captured::Streamed)
+        }
+    };
+    let exchange = Exchange {
+        request_id: response::request_id(),
+        at,
+        method,
+        uri,
+        request_headers,
+        request_body,
+        status: parts.status.as_u16(),
+        response_body,
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+    tokio::spawn(persist(exchange));
+
+    Response::from_parts(parts, body)
+}
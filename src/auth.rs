@@ -0,0 +1,212 @@
+use std::{
+    collections::HashSet,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::Request,
+    http::{self, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::config::CONFIG;
+
+// Keys are refetched after this long, or sooner when a token names an unknown key
+const JWKS_MAX_AGE: Duration = Duration::from_secs(3600);
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+static JWKS: Lazy<RwLock<Option<(Instant, JwkSet)>>> = Lazy::new(Default::default);
+
+/// Who made the request, available to handlers as a request extension.
+#[derive(Clone, Debug)]
+pub struct Principal {
+    pub subject: String,
+    pub roles: HashSet<String>,
+}
+
+impl Principal {
+    pub fn is_admin(&self) -> bool {
+        self.roles.contains("admin")
+    }
+
+    /// Whether responses may carry full director addresses under `MINIMIZE_PII`.
+    pub fn sees_pii(&self) -> bool {
+        self.is_admin() || self.roles.contains("pii")
+    }
+
+    // The static token is trusted with everything
+    fn token() -> Self {
+        Principal {
+            subject: "token".to_string(),
+            roles: HashSet::from(["admin".to_string()]),
+        }
+    }
+}
+
+/// Accepts the static `TOKEN`, or a bearer JWT signed by a key of `JWKS_URL` when configured.
+pub async fn auth(mut req: Request, next: Next) -> Result<Response, StatusCode> {
+    let auth_header = req
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok());
+
+    let principal = match auth_header {
+        Some(header) if header == CONFIG.token => Principal::token(),
+        Some(header) if CONFIG.jwks_url.is_some() => {
+            let Some(token) = header.strip_prefix("Bearer ") else {
+                return Err(StatusCode::UNAUTHORIZED);
+            };
+            verify(token).await.map_err(|err| {
+                tracing::info!("rejected bearer token: {:#}", err);
+                StatusCode::UNAUTHORIZED
+            })?
+        }
+        _ => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    tracing::debug!(
+        "authenticated {} with roles {:?}",
+        principal.subject,
+        principal.roles
+    );
+    req.extensions_mut().insert(principal);
+
+    Ok(next.run(req).await)
+}
+
+async fn verify(token: &str) -> Result<Principal> {
+    let header = decode_header(token)?;
+    let kid = header.kid.context("token has no key id")?;
+    let jwk = jwk(&kid).await?;
+    // the key decides the algorithm, a token can't pick a weaker one or HMAC with a public key
+    let algorithms = algorithms(&jwk)?;
+    anyhow::ensure!(
+        algorithms.contains(&header.alg),
+        "token is signed with {:?}, key {} is for {:?}",
+        header.alg,
+        kid,
+        algorithms
+    );
+    let key = DecodingKey::from_jwk(&jwk)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.algorithms = algorithms;
+    if let Some(issuer) = &CONFIG.jwt_issuer {
+        validation.set_issuer(&[issuer]);
+    }
+    match &CONFIG.jwt_audience {
+        Some(audience) => validation.set_audience(&[audience]),
+        None => validation.validate_aud = false,
+    }
+
+    let claims = decode::<Value>(token, &key, &validation)?.claims;
+    Ok(Principal {
+        subject: claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        roles: roles(&claims),
+    })
+}
+
+// `JWT_ROLES_CLAIM` holds either a list of roles or a space separated string (like `scope`),
+// a dotted path reaches nested claims, e.g. `realm_access.roles`
+fn roles(claims: &Value) -> HashSet<String> {
+    let claim = CONFIG
+        .jwt_roles_claim
+        .split('.')
+        .try_fold(claims, |value, key| value.get(key));
+
+    match claim {
+        Some(Value::Array(roles)) => roles
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        Some(Value::String(roles)) => roles.split_whitespace().map(str::to_string).collect(),
+        _ => HashSet::new(),
+    }
+}
+
+// The `alg` the key is published with, or the signature algorithms of its key type. Symmetric
+// keys are never accepted, the issuer's keys are public.
+fn algorithms(jwk: &Jwk) -> Result<Vec<Algorithm>> {
+    if let Some(algorithm) = jwk.common.key_algorithm {
+        let algorithm = Algorithm::from_str(&algorithm.to_string())
+            .with_context(|| format!("{} isn't a signature algorithm", algorithm))?;
+        anyhow::ensure!(
+            !matches!(
+                algorithm,
+                Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+            ),
+            "keys with {:?} aren't accepted",
+            algorithm
+        );
+        return Ok(vec![algorithm]);
+    }
+
+    let algorithms = match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => vec![
+            Algorithm::RS256,
+            Algorithm::RS384,
+            Algorithm::RS512,
+            Algorithm::PS256,
+            Algorithm::PS384,
+            Algorithm::PS512,
+        ],
+        AlgorithmParameters::EllipticCurve(key) => match key.curve {
+            EllipticCurve::P256 => vec![Algorithm::ES256],
+            EllipticCurve::P384 => vec![Algorithm::ES384],
+            _ => anyhow::bail!("unsupported curve {:?}", key.curve),
+        },
+        AlgorithmParameters::OctetKeyPair(_) => vec![Algorithm::EdDSA],
+        AlgorithmParameters::OctetKey(_) => anyhow::bail!("symmetric keys aren't accepted"),
+    };
+
+    Ok(algorithms)
+}
+
+async fn jwk(kid: &str) -> Result<Jwk> {
+    {
+        let jwks = JWKS.read().await;
+        if let Some((fetched, keys)) = jwks.as_ref() {
+            let key = keys.find(kid);
+            let fresh = fetched.elapsed() < JWKS_MAX_AGE;
+            // unknown keys only trigger a refetch once in a while, so bad tokens can't hammer the
+            // issuer
+            if let (Some(key), true) = (key, fresh) {
+                return Ok(key.clone());
+            }
+            if key.is_none() && fetched.elapsed() < JWKS_MIN_REFRESH {
+                anyhow::bail!("unknown key id {}", kid);
+            }
+        }
+    }
+
+    let url = CONFIG.jwks_url.as_deref().context("JWKS_URL is not set")?;
+    let keys: JwkSet = reqwest::get(url)
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+        .context("failed to fetch JWKS")?;
+    let key = keys
+        .find(kid)
+        .cloned()
+        .with_context(|| format!("unknown key id {}", kid));
+    *JWKS.write().await = Some((Instant::now(), keys));
+
+    key
+}
@@ -0,0 +1,517 @@
+use std::{
+    fmt,
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use dump_core::normalize;
+use thirtyfour::prelude::*;
+use tokio::time::{sleep, Instant};
+
+use crate::{
+    popups, response,
+    session::{Driver, Element},
+    txlog::{self, Action},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// Extra tries of a single command before giving up on the element
+const COMMAND_RETRIES: u32 = 3;
+
+// Inline messages forms show instead of moving on when a field is rejected, looked for inside
+// the submitted form
+const VALIDATION_BANNERS: [&str; 3] = [
+    "//*[@role='alert']",
+    "//*[contains(@class, 'validation-error') or contains(@class, 'field-validation-error')]",
+    "//*[contains(@class, 'appError')]",
+];
+// The form a submit button belongs to, or the content area on pages without form elements
+const SUBMITTED_FORM: &str = "ancestor::*[self::form or self::main][1]";
+// How long a submitted form gets to show its validation messages
+const VALIDATION_WAIT: Duration = Duration::from_secs(2);
+
+/// `text` as an XPath string literal, for input that ends up in a selector. XPath 1.0 has no
+/// escapes, so text with both kinds of quotes is spliced together with `concat()`.
+pub fn literal(text: &str) -> String {
+    if !text.contains('\'') {
+        return format!("'{}'", text);
+    }
+    if !text.contains('"') {
+        return format!("\"{}\"", text);
+    }
+
+    let parts = text
+        .split('\'')
+        .map(|part| format!("'{}'", part))
+        .collect::<Vec<_>>()
+        .join(", \"'\", ");
+    format!("concat({})", parts)
+}
+
+/// An element's text with curly quotes made straight, for matching names whichever quotes the page
+/// prints them with. Compare it with `fold_quotes`d text.
+pub const FOLDED_TEXT: &str =
+    "translate(text(), '\u{2018}\u{2019}\u{201C}\u{201D}', concat(\"''\", '\"\"'))";
+
+pub fn fold_quotes(text: &str) -> String {
+    text.replace(['\u{2018}', '\u{2019}'], "'")
+        .replace(['\u{201C}', '\u{201D}'], "\"")
+}
+
+/// XPath condition on an element's text containing `text`, e.g. "O'Brien & Sons \"Holdings\"".
+pub fn contains_text(text: &str) -> String {
+    format!("contains({}, {})", FOLDED_TEXT, literal(&fold_quotes(text)))
+}
+
+// Errors where the element is fine but the page moved under it: re-rendered (stale) or covered by
+// an overlay for a moment
+fn is_transient(err: &WebDriverError) -> bool {
+    if let WebDriverError::NoSuchElement(_) = err {
+        // thirtyfour reports stale references as "no such element"
+        return true;
+    }
+    let message = err.to_string();
+    message.contains("stale element reference")
+        || is_intercepted(err)
+        || message.contains("element not interactable")
+}
+
+fn is_intercepted(err: &WebDriverError) -> bool {
+    err.to_string().contains("element click intercepted")
+}
+
+// Elements matching `xpath`, polled for up to `timeout` until there's one. Empty if none showed up.
+async fn wait_all(
+    driver: &dyn Driver,
+    xpath: &str,
+    timeout: Duration,
+    displayed_only: bool,
+) -> WebDriverResult<Vec<Box<dyn Element>>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut elements = driver.find_all(xpath).await?;
+        if displayed_only {
+            let mut displayed = vec![];
+            for element in elements {
+                if element.is_displayed().await.unwrap_or(false) {
+                    displayed.push(element);
+                }
+            }
+            elements = displayed;
+        }
+        if !elements.is_empty() || Instant::now() >= deadline {
+            return Ok(elements);
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Whether an element matching `xpath` shows up within `timeout`.
+pub async fn exists(driver: &dyn Driver, xpath: &str, timeout: Duration) -> WebDriverResult<bool> {
+    Ok(!wait_all(driver, xpath, timeout, false).await?.is_empty())
+}
+
+/// Texts of the elements matching `xpath`, waiting up to `timeout` for the first. They're
+/// cleaned with `normalize::clean`.
+pub async fn texts(
+    driver: &dyn Driver,
+    xpath: &str,
+    timeout: Duration,
+) -> WebDriverResult<Vec<String>> {
+    let mut texts = vec![];
+    for element in wait_all(driver, xpath, timeout, false).await? {
+        texts.push(normalize::clean(&element.text().await?));
+    }
+
+    Ok(texts)
+}
+
+/// Waits up to `timeout` for the first element matching `xpath` and runs `command` on it, once the
+/// known popups are dismissed. Transient failures resolve the element again and retry the command
+/// instead of failing the whole flow.
+pub async fn with_element<F, Fut>(
+    driver: &dyn Driver,
+    xpath: &str,
+    timeout: Duration,
+    command: F,
+) -> WebDriverResult<()>
+where
+    F: Fn(Box<dyn Element>) -> Fut,
+    Fut: Future<Output = WebDriverResult<()>>,
+{
+    popups::dismiss(driver).await;
+    let mut attempt = 0;
+    loop {
+        let element = wait_all(driver, xpath, timeout, false)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                WebDriverError::NoSuchElement(format!("no element matches {}", xpath))
+            })?;
+
+        match command(element).await {
+            Err(err) if attempt < COMMAND_RETRIES && is_transient(&err) => {
+                attempt += 1;
+                tracing::debug!("retrying command on {} ({}): {}", xpath, attempt, err);
+                sleep(Duration::from_millis(500)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Records the `lang` of the page the browser is on as the language of the response's data.
+pub async fn record_language(driver: &dyn Driver) -> WebDriverResult<()> {
+    if let Some(html) = driver.find_all("/html").await?.first() {
+        if let Some(language) = html.property("lang").await?.filter(|lang| !lang.is_empty()) {
+            response::record_language(&language);
+        }
+    }
+
+    Ok(())
+}
+
+/// Scrolls the element into view and clicks it. When something still covers it the known popups
+/// are dismissed again along with generic close buttons, and as a last resort the click is
+/// dispatched from JavaScript.
+pub async fn click(driver: &dyn Driver, xpath: &str, timeout: Duration) -> WebDriverResult<()> {
+    with_element(driver, xpath, timeout, |element| async move {
+        element.scroll_into_view().await?;
+        let Err(err) = element.click().await else {
+            return Ok(());
+        };
+        if !is_intercepted(&err) {
+            return Err(err);
+        }
+
+        popups::recover(driver).await;
+        if element.click().await.is_ok() {
+            return Ok(());
+        }
+        tracing::debug!("click still intercepted, clicking from script");
+        element.js_click().await
+    })
+    .await?;
+    txlog::record(driver, Action::Click, xpath).await;
+
+    Ok(())
+}
+
+/// Types `text` into the field. A retried attempt clears the field first, so text typed before
+/// the failure isn't typed twice.
+pub async fn send_keys(
+    driver: &dyn Driver,
+    xpath: &str,
+    timeout: Duration,
+    text: impl AsRef<str>,
+) -> WebDriverResult<()> {
+    let text = text.as_ref();
+    let attempted = AtomicBool::new(false);
+    with_element(driver, xpath, timeout, |element| {
+        let attempted = &attempted;
+        async move {
+            element.scroll_into_view().await?;
+            if attempted.swap(true, Ordering::Relaxed) {
+                element.clear().await?;
+                // not the value itself, it may be card details
+                if !element.value().await?.unwrap_or_default().is_empty() {
+                    return Err(WebDriverError::CustomError(format!(
+                        "{} isn't empty after clearing it",
+                        xpath
+                    )));
+                }
+            }
+            element.send_keys(text).await
+        }
+    })
+    .await?;
+    txlog::record(driver, Action::Input, xpath).await;
+
+    Ok(())
+}
+
+/// Form failures a flow can't get past by retrying a single command.
+#[derive(Debug)]
+pub enum FormError {
+    // the form or its email fields aren't on the page
+    EmailFieldsNotFound { form: String },
+    // the field didn't keep the typed value, e.g. it's masked or reformatted
+    EmailNotAccepted { expected: String, actual: String },
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormError::EmailFieldsNotFound { form } => {
+                write!(f, "no email fields found in {}", form)
+            }
+            FormError::EmailNotAccepted { expected, actual } => {
+                write!(
+                    f,
+                    "email field holds '{}' instead of '{}'",
+                    actual, expected
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FormError {}
+
+/// A submitted form came back with validation messages, e.g. "Invalid card number".
+#[derive(Debug)]
+pub struct FormValidationError {
+    pub form: String,
+    pub messages: Vec<String>,
+}
+
+impl fmt::Display for FormValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} was rejected: {}",
+            self.form,
+            self.messages.join("; ")
+        )
+    }
+}
+
+impl std::error::Error for FormValidationError {}
+
+/// Clicks the submit button of a form and takes a checkpoint of the outcome for the transaction
+/// log, without looking for validation messages. For the card payment, whose outcome is the
+/// processor's to tell and must never be mistaken for a form to fill in again.
+pub async fn submit_unchecked(
+    driver: &dyn Driver,
+    xpath: &str,
+    timeout: Duration,
+    form: &str,
+) -> anyhow::Result<()> {
+    click(driver, xpath, timeout).await?;
+    sleep(VALIDATION_WAIT).await;
+    txlog::checkpoint(driver, Action::Submit, form).await;
+
+    Ok(())
+}
+
+/// Like `submit_unchecked`, but fails with the validation messages the submitted form shows, if
+/// any. Messages elsewhere on the page, e.g. a site-wide notice, don't count.
+pub async fn submit(
+    driver: &dyn Driver,
+    xpath: &str,
+    timeout: Duration,
+    form: &str,
+) -> anyhow::Result<()> {
+    submit_unchecked(driver, xpath, timeout, form).await?;
+
+    let mut messages = vec![];
+    for banner in VALIDATION_BANNERS {
+        let banner = format!("({})[1]/{}{}", xpath, SUBMITTED_FORM, banner);
+        for element in driver.find_all(&banner).await? {
+            if !element.is_displayed().await.unwrap_or(false) {
+                continue;
+            }
+            let text = element.text().await?;
+            let text = text.trim();
+            if !text.is_empty() && !messages.iter().any(|message| message == text) {
+                messages.push(text.to_string());
+            }
+        }
+    }
+    if !messages.is_empty() {
+        return Err(FormValidationError {
+            form: form.to_string(),
+            messages,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Types `email` into every visible email field inside the `form` XPath and reads each back.
+/// Fields elsewhere on the page (newsletter signups, search boxes) are left alone.
+pub async fn fill_email(driver: &dyn Driver, form: &str, email: &str) -> anyhow::Result<()> {
+    let fields = wait_all(
+        driver,
+        &format!("{}//input[@type='email']", form),
+        Duration::from_secs(10),
+        true,
+    )
+    .await?;
+    if fields.is_empty() {
+        return Err(FormError::EmailFieldsNotFound {
+            form: form.to_string(),
+        }
+        .into());
+    }
+
+    for field in fields {
+        field.scroll_into_view().await?;
+        // a retried flow finds the field filled already
+        field.clear().await?;
+        field.send_keys(email).await?;
+
+        let actual = field.value().await?.unwrap_or_default();
+        if actual.trim() != email {
+            return Err(FormError::EmailNotAccepted {
+                expected: email.to_string(),
+                actual,
+            }
+            .into());
+        }
+    }
+    txlog::record(driver, Action::Input, &format!("email fields of {}", form)).await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Splits XPath arguments at the commas outside string literals and nested calls
+    fn arguments(list: &str) -> Vec<&str> {
+        let mut arguments = vec![];
+        let (mut start, mut quote, mut depth) = (0, None, 0);
+        for (i, c) in list.char_indices() {
+            match (quote, c) {
+                (None, '\'' | '"') => quote = Some(c),
+                (Some(open), _) if c == open => quote = None,
+                (None, '(') => depth += 1,
+                (None, ')') => depth -= 1,
+                (None, ',') if depth == 0 => {
+                    arguments.push(list[start..i].trim());
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        arguments.push(list[start..].trim());
+        arguments
+    }
+
+    // The string an XPath literal or `concat()` of literals stands for, as a browser reads it
+    fn evaluate(expression: &str) -> String {
+        if let Some(list) = expression
+            .strip_prefix("concat(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            return arguments(list).into_iter().map(evaluate).collect();
+        }
+
+        let quote = expression.chars().next().unwrap();
+        assert!(
+            quote == '\'' || quote == '"',
+            "not a literal: {}",
+            expression
+        );
+        let inner = &expression[1..expression.len() - 1];
+        assert!(
+            expression.ends_with(quote) && !inner.contains(quote),
+            "malformed literal: {}",
+            expression
+        );
+        inner.to_string()
+    }
+
+    #[test]
+    fn literal_quotes_plain_text_in_apostrophes() {
+        assert_eq!(literal("ACME INC."), "'ACME INC.'");
+    }
+
+    #[test]
+    fn literal_quotes_apostrophes_in_double_quotes() {
+        assert_eq!(literal("O'Brien & Sons"), "\"O'Brien & Sons\"");
+    }
+
+    #[test]
+    fn literal_quotes_double_quotes_in_apostrophes() {
+        assert_eq!(literal("\"Holdings\" Ltd."), "'\"Holdings\" Ltd.'");
+    }
+
+    #[test]
+    fn literal_concatenates_text_with_both_quotes() {
+        let text = "O'Brien & Sons \"Holdings\"";
+        let quoted = literal(text);
+
+        assert!(quoted.starts_with("concat("), "{}", quoted);
+        assert_eq!(evaluate(&quoted), text);
+    }
+
+    #[test]
+    fn literal_stands_for_the_text() {
+        for text in [
+            "",
+            "'",
+            "\"",
+            "''",
+            "'\"",
+            "'leading and trailing'",
+            "a'b\"c'd\"e",
+            "L'Équipe \"Nord\" d'Ontario",
+        ] {
+            assert_eq!(evaluate(&literal(text)), text, "{}", literal(text));
+        }
+    }
+
+    // `FOLDED_TEXT` run on an element whose text is `text`
+    fn folded(text: &str) -> String {
+        let list = FOLDED_TEXT
+            .strip_prefix("translate(text(), ")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .unwrap();
+        let [from, to] = arguments(list)[..] else {
+            panic!(
+                "translate() takes the text and two strings: {}",
+                FOLDED_TEXT
+            );
+        };
+        let (from, to): (Vec<char>, Vec<char>) = (
+            evaluate(from).chars().collect(),
+            evaluate(to).chars().collect(),
+        );
+
+        // XPath's translate() drops the characters with no counterpart in `to`
+        text.chars()
+            .filter_map(|c| match from.iter().position(|&f| f == c) {
+                Some(i) => to.get(i).copied(),
+                None => Some(c),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn folded_text_straightens_every_curly_quote() {
+        let text = "\u{2018}O\u{2019}Brien\u{2019} & Sons \u{201C}Holdings\u{201D}";
+
+        assert_eq!(folded(text), "'O'Brien' & Sons \"Holdings\"");
+        assert_eq!(folded(text), fold_quotes(text));
+    }
+
+    #[test]
+    fn folded_text_keeps_straight_quotes_and_other_text() {
+        let text = "O'Brien & Sons \"Holdings\" Ltée";
+
+        assert_eq!(folded(text), text);
+    }
+
+    #[test]
+    fn contains_text_matches_curly_and_straight_quotes_alike() {
+        let condition = contains_text("O\u{2019}Brien \u{201C}Holdings\u{201D}");
+        let list = condition
+            .strip_prefix(&format!("contains({}, ", FOLDED_TEXT))
+            .and_then(|rest| rest.strip_suffix(')'))
+            .unwrap();
+        let wanted = evaluate(list);
+
+        for page_text in [
+            "O'Brien \"Holdings\" Inc.",
+            "O\u{2019}Brien \u{201C}Holdings\u{201D} Inc.",
+        ] {
+            assert!(folded(page_text).contains(&wanted), "{}", page_text);
+        }
+    }
+}
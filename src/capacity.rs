@@ -0,0 +1,160 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::{
+    chromedriver,
+    config::{Mode, CONFIG},
+    load_shed,
+    lock::redis_connection,
+    queue,
+};
+
+// Workers with a live heartbeat, `capacity:worker:{id}`
+const WORKERS_KEY: &str = "capacity:workers";
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+// Tells this worker's heartbeats apart from the others'
+static WORKER_ID: Lazy<Uuid> = Lazy::new(Uuid::new_v4);
+
+fn worker_key(id: &str) -> String {
+    format!("capacity:worker:{}", id)
+}
+
+/// What a worker last reported of itself.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct Heartbeat {
+    max_sessions: usize,
+    used_sessions: usize,
+    // whether its chromedriver answered the version check
+    healthy: bool,
+}
+
+async fn healthy() -> bool {
+    if !chromedriver::in_use() {
+        return true;
+    }
+    match chromedriver::check().await {
+        Ok(()) => true,
+        Err(err) => {
+            tracing::warn!(
+                "reporting no capacity, chromedriver is unhealthy: {:#}",
+                err
+            );
+            false
+        }
+    }
+}
+
+async fn beat(slots: &Semaphore) -> Result<()> {
+    let heartbeat = Heartbeat {
+        max_sessions: CONFIG.worker_concurrency,
+        used_sessions: CONFIG
+            .worker_concurrency
+            .saturating_sub(slots.available_permits()),
+        healthy: healthy().await,
+    };
+
+    let url = CONFIG.redis_url.as_deref().context("no REDIS_URL")?;
+    let mut conn = redis_connection(url).await?;
+    let id = WORKER_ID.to_string();
+    conn.set_ex::<_, _, ()>(
+        worker_key(&id),
+        serde_json::to_string(&heartbeat)?,
+        HEARTBEAT_INTERVAL.as_secs() * 3,
+    )
+    .await?;
+    conn.sadd::<_, _, ()>(WORKERS_KEY, id).await?;
+
+    Ok(())
+}
+
+/// Reports the worker's sessions every few seconds for `/api/admin/capacity`. A worker that
+/// stops reporting drops out of the capacity after a few missed beats.
+pub fn spawn_heartbeat(slots: Arc<Semaphore>) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = beat(&slots).await {
+                tracing::warn!("failed to report the worker capacity: {}", err);
+            }
+            tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        }
+    });
+}
+
+/// Browser sessions of the worker tier, the metric its autoscaling tracks.
+#[derive(Serialize, Debug)]
+pub struct Capacity {
+    // sessions the healthy workers run at once
+    pub max_sessions: usize,
+    pub used_sessions: usize,
+    pub queued_jobs: usize,
+    // until a job queued now would start, `None` with no healthy worker to run it
+    pub projected_wait_secs: Option<u64>,
+    pub workers: usize,
+    pub unhealthy_workers: usize,
+}
+
+async fn heartbeats() -> Result<Vec<Heartbeat>> {
+    let url = CONFIG.redis_url.as_deref().context("no REDIS_URL")?;
+    let mut conn = redis_connection(url).await?;
+
+    let mut heartbeats = vec![];
+    for id in conn.smembers::<_, Vec<String>>(WORKERS_KEY).await? {
+        let heartbeat: Option<String> = conn.get(worker_key(&id)).await?;
+        match heartbeat {
+            Some(heartbeat) => heartbeats.push(serde_json::from_str(&heartbeat)?),
+            // expired, the worker is gone
+            None => conn.srem::<_, _, ()>(WORKERS_KEY, id).await?,
+        }
+    }
+
+    Ok(heartbeats)
+}
+
+/// The worker tier's capacity from their heartbeats. `all` instances run flows themselves, so
+/// they report their own scrapes against `LOAD_SHED_MAX_IN_FLIGHT`.
+pub async fn capacity() -> Result<Capacity> {
+    let heartbeats = match CONFIG.mode {
+        Mode::All => vec![Heartbeat {
+            max_sessions: CONFIG.load_shed_max_in_flight,
+            used_sessions: load_shed::in_flight(),
+            healthy: healthy().await,
+        }],
+        Mode::Api | Mode::Worker => heartbeats().await?,
+    };
+    let queued_jobs = match CONFIG.redis_url {
+        Some(_) => queue::queued().await?,
+        None => 0,
+    };
+
+    let healthy: Vec<&Heartbeat> = heartbeats.iter().filter(|beat| beat.healthy).collect();
+    let max_sessions: usize = healthy.iter().map(|beat| beat.max_sessions).sum();
+    let used_sessions: usize = healthy.iter().map(|beat| beat.used_sessions).sum();
+
+    // free sessions take queued jobs right away, the rest drain `max_sessions` jobs per average
+    // run once running ones finish
+    let free = max_sessions.saturating_sub(used_sessions);
+    let projected_wait_secs = if max_sessions == 0 {
+        None
+    } else if queued_jobs < free {
+        Some(0)
+    } else {
+        let rounds = ((queued_jobs - free) / max_sessions + 1) as u32;
+        Some((queue::average_duration().await * rounds).as_secs())
+    };
+
+    Ok(Capacity {
+        max_sessions,
+        used_sessions,
+        queued_jobs,
+        projected_wait_secs,
+        workers: heartbeats.len(),
+        unhealthy_workers: heartbeats.len() - healthy.len(),
+    })
+}
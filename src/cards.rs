@@ -0,0 +1,359 @@
+use std::{collections::BTreeMap, fmt, io::Read, path::Path};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{Context, Result};
+use aws_sdk_kms::{primitives::Blob, types::DataKeySpec};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use once_cell::sync::OnceCell;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroizing;
+
+use crate::{
+    config::{PaymentMode, VaultAction, CONFIG},
+    login::{self, Account},
+};
+
+/// Card detail that is wiped from memory when dropped and never shows in logs or panics.
+#[derive(Clone)]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret(Zeroizing::new(value))
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret([redacted])")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.expose())
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Secret::from)
+    }
+}
+
+/// Payment card the payment flow enters.
+#[derive(Serialize, Deserialize)]
+pub struct Card {
+    pub number: Secret,
+    pub name: Secret,
+    pub month: Secret,
+    pub year: Secret,
+    pub cvv: Secret,
+}
+
+// Only ever the last digits, so a logged card doesn't leak it
+impl fmt::Debug for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let number = self.number.expose();
+        let last4 = &number[number.len().saturating_sub(4)..];
+        write!(f, "Card(**** {})", last4)
+    }
+}
+
+/// `CARD_VAULT` file. Cards are encrypted with AES-256-GCM under a data key that is itself only
+/// stored encrypted by KMS, so neither the file nor the process environment holds a plaintext
+/// card number.
+#[derive(Serialize, Deserialize)]
+struct Vault {
+    key_id: String,
+    // KMS ciphertext of the data key, base64
+    wrapped_key: String,
+    cards: BTreeMap<String, Sealed>,
+    // registry accounts, see `login`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    accounts: BTreeMap<String, Sealed>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    nonce: String,
+    ciphertext: String,
+}
+
+static CARD: OnceCell<Card> = OnceCell::new();
+
+async fn kms() -> aws_sdk_kms::Client {
+    aws_sdk_kms::Client::new(&aws_config::load_from_env().await)
+}
+
+fn read(path: &Path) -> Result<Vault> {
+    let file = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read the card vault {}", path.display()))?;
+    serde_json::from_str(&file).with_context(|| format!("invalid card vault {}", path.display()))
+}
+
+fn write(path: &Path, vault: &Vault) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(vault)?)
+        .with_context(|| format!("failed to write the card vault {}", path.display()))
+}
+
+async fn data_key(vault: &Vault) -> Result<Key<Aes256Gcm>> {
+    let unwrapped = kms()
+        .await
+        .decrypt()
+        .key_id(&vault.key_id)
+        .ciphertext_blob(Blob::new(STANDARD.decode(&vault.wrapped_key)?))
+        .send()
+        .await
+        .context("KMS refused to unwrap the card vault key")?;
+    let plaintext = unwrapped
+        .plaintext()
+        .context("KMS returned no vault key")?
+        .as_ref();
+    anyhow::ensure!(plaintext.len() == 32, "the card vault key isn't 256 bits");
+
+    Ok(*Key::<Aes256Gcm>::from_slice(plaintext))
+}
+
+// The label is authenticated along with the card, so sealed cards can't be swapped between labels
+fn seal<T: Serialize>(key: &Key<Aes256Gcm>, label: &str, secret: &T) -> Result<Sealed> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let plaintext = Zeroizing::new(serde_json::to_vec(secret)?);
+    let ciphertext = Aes256Gcm::new(key)
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &plaintext,
+                aad: label.as_bytes(),
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("failed to encrypt {}", label))?;
+
+    Ok(Sealed {
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+}
+
+fn open<T: DeserializeOwned>(key: &Key<Aes256Gcm>, label: &str, sealed: &Sealed) -> Result<T> {
+    let nonce = STANDARD.decode(&sealed.nonce)?;
+    anyhow::ensure!(nonce.len() == 12, "{} has a malformed nonce", label);
+    let plaintext = Aes256Gcm::new(key)
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &STANDARD.decode(&sealed.ciphertext)?,
+                aad: label.as_bytes(),
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("{} doesn't decrypt with the vault key", label))
+        .map(Zeroizing::new)?;
+
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+// The gateway's sandbox Visa, for test mode without a configured test card
+fn builtin_test_card() -> Card {
+    Card {
+        number: Secret::from("4030000010001234".to_string()),
+        name: Secret::from("Test Card".to_string()),
+        month: Secret::from("12".to_string()),
+        year: Secret::from("30".to_string()),
+        cvv: Secret::from("123".to_string()),
+    }
+}
+
+fn from_env() -> Option<Card> {
+    Some(Card {
+        number: CONFIG.card_number.clone()?,
+        name: CONFIG.card_name.clone()?,
+        month: CONFIG.card_month.clone()?,
+        year: CONFIG.card_year.clone()?,
+        cvv: CONFIG.card_cvv.clone()?,
+    })
+}
+
+/// Loads the card of `CARD_LABEL` from `CARD_VAULT`, or the plaintext `CARD_*` settings on
+/// instances without a vault. In test payment mode the card of `TEST_CARD_LABEL` is loaded
+/// instead, falling back to the gateway's sandbox card. Test runs type the card into the form
+/// and screenshot it, so they never fall back to the live `CARD_*` card.
+pub async fn load() -> Result<()> {
+    let label = match CONFIG.payment_mode {
+        PaymentMode::Live => &CONFIG.card_label,
+        PaymentMode::Test => &CONFIG.test_card_label,
+    };
+    let card = match &CONFIG.card_vault {
+        Some(path) => {
+            let vault = read(path)?;
+            match vault.cards.get(label) {
+                Some(sealed) => open(&data_key(&vault).await?, label, sealed)?,
+                None if CONFIG.payment_mode == PaymentMode::Test => builtin_test_card(),
+                None => anyhow::bail!(
+                    "no card labelled {} in {}, it holds: {}",
+                    label,
+                    path.display(),
+                    vault.cards.keys().cloned().collect::<Vec<_>>().join(", ")
+                ),
+            }
+        }
+        None if CONFIG.payment_mode == PaymentMode::Test => builtin_test_card(),
+        None => {
+            tracing::warn!("no CARD_VAULT set, using the plaintext CARD_* settings");
+            from_env().context("payments need CARD_VAULT or all of the CARD_* settings")?
+        }
+    };
+    if CONFIG.payment_mode == PaymentMode::Test {
+        // e.g. a vault whose test label holds the live card
+        let live = from_env().is_some_and(|live| live.number.expose() == card.number.expose());
+        anyhow::ensure!(!live, "the test card {} is the live CARD_NUMBER", label);
+        tracing::warn!("test payment mode, card payments are filled in but never submitted");
+    }
+    tracing::info!("paying with {:?}", card);
+
+    CARD.set(card)
+        .map_err(|_| anyhow::anyhow!("card already loaded"))
+}
+
+pub fn card() -> Result<&'static Card> {
+    CARD.get().context("no payment card loaded")
+}
+
+// Sealed under a label of their own, an account can't be opened as a card of the same label
+fn account_label(label: &str) -> String {
+    format!("account:{}", label)
+}
+
+/// The registry account labelled `label` in the vault at `path`.
+pub async fn account(path: &Path, label: &str) -> Result<Account> {
+    let vault = read(path)?;
+    let sealed = vault.accounts.get(label).with_context(|| {
+        format!(
+            "no account labelled {} in {}, it holds: {}",
+            label,
+            path.display(),
+            vault
+                .accounts
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    })?;
+
+    open(&data_key(&vault).await?, &account_label(label), sealed)
+}
+
+/// Masks the loaded card's number and security code in `text`, e.g. a recorded WebDriver command,
+/// and the registry account's password.
+pub fn redact(text: &str) -> String {
+    let text = login::redact(text);
+    let Some(card) = CARD.get() else {
+        return text;
+    };
+
+    let cvv = Zeroizing::new(format!("\"{}\"", card.cvv.expose()));
+    text.replace(card.number.expose(), "[card number]")
+        .replace(cvv.as_str(), "\"[cvv]\"")
+}
+
+/// `card-vault` subcommands.
+pub async fn manage(action: &VaultAction) -> Result<()> {
+    let path = CONFIG
+        .card_vault
+        .as_deref()
+        .context("CARD_VAULT must name the vault file")?;
+
+    match action {
+        VaultAction::Init { kms_key_id } => {
+            anyhow::ensure!(!path.exists(), "{} already exists", path.display());
+            let generated = kms()
+                .await
+                .generate_data_key()
+                .key_id(kms_key_id)
+                .key_spec(DataKeySpec::Aes256)
+                .send()
+                .await
+                .context("KMS refused to generate a vault key")?;
+            let wrapped = generated
+                .ciphertext_blob()
+                .context("KMS returned no wrapped key")?;
+            write(
+                path,
+                &Vault {
+                    key_id: kms_key_id.clone(),
+                    wrapped_key: STANDARD.encode(wrapped.as_ref()),
+                    cards: BTreeMap::new(),
+                    accounts: BTreeMap::new(),
+                },
+            )?;
+            println!("created {}", path.display());
+        }
+        VaultAction::Add { label } => {
+            let mut input = Zeroizing::new(String::new());
+            std::io::stdin().read_to_string(&mut input)?;
+            let card: Card = serde_json::from_str(&input).context(
+                "expected a card on stdin: {\"number\", \"name\", \"month\", \"year\", \"cvv\"}",
+            )?;
+
+            let mut vault = read(path)?;
+            let sealed = seal(&data_key(&vault).await?, label, &card)?;
+            vault.cards.insert(label.clone(), sealed);
+            write(path, &vault)?;
+            println!("stored {:?} as {}", card, label);
+        }
+        VaultAction::Remove { label } => {
+            let mut vault = read(path)?;
+            anyhow::ensure!(
+                vault.cards.remove(label).is_some(),
+                "no card labelled {}",
+                label
+            );
+            write(path, &vault)?;
+            println!("removed {}", label);
+        }
+        VaultAction::AddAccount { label } => {
+            let mut input = Zeroizing::new(String::new());
+            std::io::stdin().read_to_string(&mut input)?;
+            let account: Account = serde_json::from_str(&input)
+                .context("expected an account on stdin: {\"username\", \"password\"}")?;
+
+            let mut vault = read(path)?;
+            let sealed = seal(&data_key(&vault).await?, &account_label(label), &account)?;
+            vault.accounts.insert(label.clone(), sealed);
+            write(path, &vault)?;
+            println!("stored {:?} as {}", account, label);
+        }
+        VaultAction::RemoveAccount { label } => {
+            let mut vault = read(path)?;
+            anyhow::ensure!(
+                vault.accounts.remove(label).is_some(),
+                "no account labelled {}",
+                label
+            );
+            write(path, &vault)?;
+            println!("removed {}", label);
+        }
+        VaultAction::List => {
+            let vault = read(path)?;
+            let key = data_key(&vault).await?;
+            for (label, sealed) in &vault.cards {
+                println!("{}\t{:?}", label, open::<Card>(&key, label, sealed)?);
+            }
+            for (label, sealed) in &vault.accounts {
+                let account: Account = open(&key, &account_label(label), sealed)?;
+                println!("{}\t{:?}", label, account);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::Response,
+    routing::MethodRouter,
+    Router,
+};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+use crate::auth::Principal;
+
+/// Who may call a route, on top of being authenticated.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    // any authenticated caller
+    Client,
+    // callers with the `admin` role, as the static token has
+    Admin,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RouteInfo {
+    pub path: &'static str,
+    // HEAD is answered wherever GET is
+    pub methods: Vec<&'static str>,
+    pub scope: Scope,
+}
+
+static ROUTES: OnceCell<Vec<RouteInfo>> = OnceCell::new();
+
+/// Router that lists every route it's given, for `/api/routes` and the scope checks.
+#[derive(Default)]
+pub struct Catalog {
+    router: Router,
+    routes: Vec<RouteInfo>,
+}
+
+impl Catalog {
+    pub fn route(
+        mut self,
+        path: &'static str,
+        methods: &[Method],
+        scope: Scope,
+        method_router: MethodRouter,
+    ) -> Self {
+        let mut listed: Vec<&'static str> = methods.iter().map(method_name).collect();
+        if methods.contains(&Method::GET) {
+            listed.push("HEAD");
+        }
+        self.routes.push(RouteInfo {
+            path,
+            methods: listed,
+            scope,
+        });
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    pub fn into_router(self) -> Router {
+        let _ = ROUTES.set(self.routes);
+        self.router
+    }
+}
+
+fn method_name(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::PATCH => "PATCH",
+        _ => "OTHER",
+    }
+}
+
+fn scope(path: &str) -> Option<Scope> {
+    ROUTES
+        .get()?
+        .iter()
+        .find(|route| route.path == path)
+        .map(|route| route.scope)
+}
+
+/// Rejects callers without the role of the route's scope with 403. Runs after `auth::auth`.
+pub async fn authorize(req: Request, next: Next) -> Result<Response, StatusCode> {
+    let required = req
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|path| scope(path.as_str()));
+    if required == Some(Scope::Admin) {
+        let is_admin = req
+            .extensions()
+            .get::<Principal>()
+            .is_some_and(Principal::is_admin);
+        if !is_admin {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    Ok(next.run(req).await)
+}
+
+/// Every route with its methods and scope, in the order they're declared.
+pub fn routes() -> Vec<RouteInfo> {
+    ROUTES.get().cloned().unwrap_or_default()
+}
@@ -0,0 +1,320 @@
+use async_trait::async_trait;
+use chromiumoxide::{
+    cdp::browser_protocol::{
+        network::{ClearBrowserCookiesParams, CookieParam, CookieSameSite},
+        page::Viewport,
+    },
+    error::CdpError,
+    page::ScreenshotParams,
+    Browser, BrowserConfig, Page,
+};
+use futures::StreamExt;
+use thirtyfour::{error::WebDriverError, prelude::WebDriverResult};
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::CONFIG,
+    cookies::{self, Cookie, SameSite},
+    politeness,
+    session::{self, Driver, Element},
+};
+
+// WebDriver's Enter key, which flows append to submit a field
+const ENTER: char = '\u{e007}';
+
+// Errors are reported like WebDriver ones so retries and error handling stay backend agnostic.
+// Nodes that went away become "no such element", which the flows treat as stale.
+fn webdriver_error(err: CdpError) -> WebDriverError {
+    let message = err.to_string();
+    if matches!(err, CdpError::NotFound)
+        || message.contains("No node with given id")
+        || message.contains("detached")
+    {
+        return WebDriverError::NoSuchElement(message);
+    }
+    WebDriverError::CustomError(message)
+}
+
+/// Chrome driven over the DevTools protocol, without chromedriver. Launches its own browser
+/// unless `CDP_URL` points at a running one, of which only a new tab is used.
+pub struct CdpSession {
+    browser: Browser,
+    page: Page,
+    handler: JoinHandle<()>,
+    launched: bool,
+}
+
+impl CdpSession {
+    pub async fn start() -> WebDriverResult<CdpSession> {
+        let (browser, mut handler, launched) = match &CONFIG.cdp_url {
+            Some(url) => {
+                let (browser, handler) = Browser::connect(url).await.map_err(webdriver_error)?;
+                (browser, handler, false)
+            }
+            None => {
+                let (browser, handler) =
+                    Browser::launch(config()?).await.map_err(webdriver_error)?;
+                (browser, handler, true)
+            }
+        };
+        // the connection only makes progress while its handler is polled
+        let handler = tokio::spawn(async move { while handler.next().await.is_some() {} });
+
+        let page = browser
+            .new_page("about:blank")
+            .await
+            .map_err(webdriver_error)?;
+        // set on the page, browsers connected to over CDP_URL were launched already
+        if let Some(user_agent) = politeness::user_agent(cookies::REGISTRY) {
+            page.set_user_agent(user_agent)
+                .await
+                .map_err(webdriver_error)?;
+        }
+
+        Ok(CdpSession {
+            browser,
+            page,
+            handler,
+            launched,
+        })
+    }
+}
+
+fn config() -> WebDriverResult<BrowserConfig> {
+    let mut builder = BrowserConfig::builder()
+        .user_data_dir("/tmp/user-data")
+        .arg("--ignore-certificate-errors")
+        .args(session::stealth_args().iter().copied());
+    if let Some(binary) = &CONFIG.chrome_binary {
+        builder = builder.chrome_executable(binary);
+    }
+    #[cfg(any(feature = "lambda", feature = "ecs", feature = "headless"))]
+    {
+        builder = builder.no_sandbox().args([
+            "--disable-dev-shm-usage",
+            "--disable-gpu",
+            "--disable-web-security",
+            "--no-zygote",
+            "--single-process",
+        ]);
+    }
+    #[cfg(not(any(feature = "lambda", feature = "ecs", feature = "headless")))]
+    {
+        builder = builder.with_head();
+    }
+
+    builder.build().map_err(WebDriverError::CustomError)
+}
+
+#[async_trait]
+impl Driver for CdpSession {
+    async fn goto(&self, url: &str) -> WebDriverResult<()> {
+        self.page.goto(url).await.map_err(webdriver_error)?;
+
+        Ok(())
+    }
+
+    async fn current_url(&self) -> WebDriverResult<String> {
+        let url = self.page.url().await.map_err(webdriver_error)?;
+
+        Ok(url.unwrap_or_default())
+    }
+
+    async fn title(&self) -> WebDriverResult<String> {
+        let title = self.page.get_title().await.map_err(webdriver_error)?;
+
+        Ok(title.unwrap_or_default())
+    }
+
+    async fn add_cookie(&self, cookie: &Cookie) -> WebDriverResult<()> {
+        let cookie = CookieParam::builder()
+            .name(&cookie.name)
+            .value(&cookie.value)
+            .domain(&cookie.domain)
+            .path(&cookie.path)
+            .same_site(match cookie.same_site {
+                SameSite::Strict => CookieSameSite::Strict,
+                SameSite::Lax => CookieSameSite::Lax,
+                SameSite::None => CookieSameSite::None,
+            })
+            .build()
+            .map_err(WebDriverError::CustomError)?;
+        self.page
+            .set_cookie(cookie)
+            .await
+            .map_err(webdriver_error)?;
+
+        Ok(())
+    }
+
+    async fn get_cookies(&self) -> WebDriverResult<Vec<Cookie>> {
+        let cookies = self.page.get_cookies().await.map_err(webdriver_error)?;
+
+        Ok(cookies
+            .into_iter()
+            .map(|cookie| Cookie {
+                name: cookie.name,
+                value: cookie.value,
+                domain: cookie.domain,
+                path: cookie.path,
+                same_site: match cookie.same_site {
+                    Some(CookieSameSite::Strict) => SameSite::Strict,
+                    Some(CookieSameSite::None) => SameSite::None,
+                    _ => SameSite::Lax,
+                },
+            })
+            .collect())
+    }
+
+    async fn delete_all_cookies(&self) -> WebDriverResult<()> {
+        self.page
+            .execute(ClearBrowserCookiesParams::default())
+            .await
+            .map_err(webdriver_error)?;
+
+        Ok(())
+    }
+
+    async fn screenshot_png(&self) -> WebDriverResult<Vec<u8>> {
+        self.page
+            .screenshot(ScreenshotParams::builder().build())
+            .await
+            .map_err(webdriver_error)
+    }
+
+    async fn thumbnail_png(&self) -> WebDriverResult<Vec<u8>> {
+        let viewport = self
+            .page
+            .layout_metrics()
+            .await
+            .map_err(webdriver_error)?
+            .css_layout_viewport;
+        let clip = Viewport {
+            x: viewport.page_x as f64,
+            y: viewport.page_y as f64,
+            width: viewport.client_width as f64,
+            height: viewport.client_height as f64,
+            scale: session::THUMBNAIL_SCALE,
+        };
+        self.page
+            .screenshot(ScreenshotParams::builder().clip(clip).build())
+            .await
+            .map_err(webdriver_error)
+    }
+
+    async fn find_all(&self, xpath: &str) -> WebDriverResult<Vec<Box<dyn Element>>> {
+        let elements = match self.page.find_xpaths(xpath).await {
+            Ok(elements) => elements,
+            // WebDriver answers an empty list instead
+            Err(CdpError::NotFound) => vec![],
+            Err(err) => return Err(webdriver_error(err)),
+        };
+
+        Ok(elements
+            .into_iter()
+            .map(|element| Box::new(CdpElement(element)) as Box<dyn Element>)
+            .collect())
+    }
+
+    async fn quit(mut self: Box<Self>) -> WebDriverResult<()> {
+        let result = if self.launched {
+            let closed = self.browser.close().await.map(|_| ());
+            let _ = self.browser.wait().await;
+            closed
+        } else {
+            self.page.clone().close().await
+        };
+        self.handler.abort();
+
+        result.map_err(webdriver_error)
+    }
+}
+
+struct CdpElement(chromiumoxide::Element);
+
+impl CdpElement {
+    async fn call(&self, function: &str) -> WebDriverResult<Option<serde_json::Value>> {
+        let returned = self
+            .0
+            .call_js_fn(function, false)
+            .await
+            .map_err(webdriver_error)?;
+
+        Ok(returned.result.value)
+    }
+}
+
+#[async_trait]
+impl Element for CdpElement {
+    async fn click(&self) -> WebDriverResult<()> {
+        self.0.click().await.map_err(webdriver_error)?;
+
+        Ok(())
+    }
+
+    async fn js_click(&self) -> WebDriverResult<()> {
+        self.call("function() { this.click(); }").await?;
+
+        Ok(())
+    }
+
+    async fn scroll_into_view(&self) -> WebDriverResult<()> {
+        self.call("function() { this.scrollIntoView({block: 'center', inline: 'nearest'}); }")
+            .await?;
+
+        Ok(())
+    }
+
+    async fn send_keys(&self, text: &str) -> WebDriverResult<()> {
+        self.0.focus().await.map_err(webdriver_error)?;
+        let mut parts = text.split(ENTER).peekable();
+        while let Some(part) = parts.next() {
+            if !part.is_empty() {
+                self.0.type_str(part).await.map_err(webdriver_error)?;
+            }
+            if parts.peek().is_some() {
+                self.0.press_key("Enter").await.map_err(webdriver_error)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn clear(&self) -> WebDriverResult<()> {
+        self.call(
+            "function() { this.value = ''; this.dispatchEvent(new Event('input', {bubbles: \
+             true})); }",
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn value(&self) -> WebDriverResult<Option<String>> {
+        self.0
+            .string_property("value")
+            .await
+            .map_err(webdriver_error)
+    }
+
+    async fn property(&self, name: &str) -> WebDriverResult<Option<String>> {
+        self.0.string_property(name).await.map_err(webdriver_error)
+    }
+
+    async fn text(&self) -> WebDriverResult<String> {
+        let text = self.0.inner_text().await.map_err(webdriver_error)?;
+
+        Ok(text.unwrap_or_default())
+    }
+
+    async fn is_displayed(&self) -> WebDriverResult<bool> {
+        let displayed = self
+            .call(
+                "function() { return !!(this.offsetWidth || this.offsetHeight || \
+                 this.getClientRects().length); }",
+            )
+            .await?;
+
+        Ok(displayed.and_then(|value| value.as_bool()).unwrap_or(false))
+    }
+}
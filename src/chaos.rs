@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use thirtyfour::{error::WebDriverError, prelude::WebDriverResult};
+
+use crate::{
+    config::{PaymentMode, CONFIG},
+    cookies::Cookie,
+    session::{Driver, Element, Session},
+};
+
+// Only ever on for test payments, so a stray setting can't break real orders
+static ENABLED: Lazy<bool> = Lazy::new(|| {
+    let configured = CONFIG.chaos_failure_rate > 0.0
+        || CONFIG.chaos_drop_rate > 0.0
+        || CONFIG.chaos_max_delay_ms > 0;
+    if !configured {
+        return false;
+    }
+    if CONFIG.payment_mode != PaymentMode::Test {
+        tracing::warn!("ignoring the CHAOS_* settings, they need PAYMENT_MODE=test");
+        return false;
+    }
+    tracing::warn!(
+        "chaos mode: {:.0}% of browser commands fail, {:.0}% of lookups find nothing, up to {}ms \
+         of delay",
+        CONFIG.chaos_failure_rate * 100.0,
+        CONFIG.chaos_drop_rate * 100.0,
+        CONFIG.chaos_max_delay_ms
+    );
+    true
+});
+
+/// Reports whether chaos mode is on, or why its settings are ignored.
+pub fn init() {
+    Lazy::force(&ENABLED);
+}
+
+/// `session` with failures injected into its commands when chaos mode is on, for checking the
+/// retries and checkpoints against flakiness on demand.
+pub fn wrap(session: Session) -> Session {
+    if !*ENABLED {
+        return session;
+    }
+    Box::new(Chaotic(session))
+}
+
+// Errors like the ones real sessions fail with, transient ones (see `browser::is_transient`)
+// included
+fn injected(command: &str) -> WebDriverError {
+    match rand::thread_rng().gen_range(0..3) {
+        0 => WebDriverError::NoSuchElement(format!("{} lost its element (chaos)", command)),
+        1 => WebDriverError::CustomError(format!(
+            "stale element reference during {} (chaos)",
+            command
+        )),
+        _ => WebDriverError::CustomError(format!("{} failed (chaos)", command)),
+    }
+}
+
+fn delay() -> Duration {
+    Duration::from_millis(rand::thread_rng().gen_range(0..=CONFIG.chaos_max_delay_ms))
+}
+
+fn fails() -> bool {
+    rand::thread_rng().gen_bool(CONFIG.chaos_failure_rate.clamp(0.0, 1.0))
+}
+
+async fn disturb(command: &str) -> WebDriverResult<()> {
+    tokio::time::sleep(delay()).await;
+    if fails() {
+        tracing::debug!("chaos: failing {}", command);
+        return Err(injected(command));
+    }
+
+    Ok(())
+}
+
+struct Chaotic(Session);
+
+#[async_trait]
+impl Driver for Chaotic {
+    async fn goto(&self, url: &str) -> WebDriverResult<()> {
+        disturb("goto").await?;
+        self.0.goto(url).await
+    }
+
+    async fn current_url(&self) -> WebDriverResult<String> {
+        disturb("current_url").await?;
+        self.0.current_url().await
+    }
+
+    async fn title(&self) -> WebDriverResult<String> {
+        disturb("title").await?;
+        self.0.title().await
+    }
+
+    async fn add_cookie(&self, cookie: &Cookie) -> WebDriverResult<()> {
+        disturb("add_cookie").await?;
+        self.0.add_cookie(cookie).await
+    }
+
+    async fn get_cookies(&self) -> WebDriverResult<Vec<Cookie>> {
+        disturb("get_cookies").await?;
+        self.0.get_cookies().await
+    }
+
+    async fn delete_all_cookies(&self) -> WebDriverResult<()> {
+        disturb("delete_all_cookies").await?;
+        self.0.delete_all_cookies().await
+    }
+
+    async fn screenshot_png(&self) -> WebDriverResult<Vec<u8>> {
+        disturb("screenshot").await?;
+        self.0.screenshot_png().await
+    }
+
+    async fn thumbnail_png(&self) -> WebDriverResult<Vec<u8>> {
+        disturb("thumbnail").await?;
+        self.0.thumbnail_png().await
+    }
+
+    async fn find_all(&self, xpath: &str) -> WebDriverResult<Vec<Box<dyn Element>>> {
+        disturb("find_all").await?;
+        let elements = self.0.find_all(xpath).await?;
+        if rand::thread_rng().gen_bool(CONFIG.chaos_drop_rate.clamp(0.0, 1.0)) {
+            tracing::debug!("chaos: dropping the elements of {}", xpath);
+            return Ok(vec![]);
+        }
+
+        Ok(elements
+            .into_iter()
+            .map(|element| Box::new(ChaoticElement(element)) as Box<dyn Element>)
+            .collect())
+    }
+
+    // never fails, a session that can't quit would leak
+    async fn quit(self: Box<Self>) -> WebDriverResult<()> {
+        self.0.quit().await
+    }
+}
+
+struct ChaoticElement(Box<dyn Element>);
+
+#[async_trait]
+impl Element for ChaoticElement {
+    async fn click(&self) -> WebDriverResult<()> {
+        disturb("click").await?;
+        self.0.click().await
+    }
+
+    async fn js_click(&self) -> WebDriverResult<()> {
+        disturb("js_click").await?;
+        self.0.js_click().await
+    }
+
+    async fn scroll_into_view(&self) -> WebDriverResult<()> {
+        disturb("scroll_into_view").await?;
+        self.0.scroll_into_view().await
+    }
+
+    async fn send_keys(&self, text: &str) -> WebDriverResult<()> {
+        disturb("send_keys").await?;
+        self.0.send_keys(text).await
+    }
+
+    async fn clear(&self) -> WebDriverResult<()> {
+        disturb("clear").await?;
+        self.0.clear().await
+    }
+
+    async fn value(&self) -> WebDriverResult<Option<String>> {
+        disturb("value").await?;
+        self.0.value().await
+    }
+
+    async fn property(&self, name: &str) -> WebDriverResult<Option<String>> {
+        disturb("property").await?;
+        self.0.property(name).await
+    }
+
+    async fn text(&self) -> WebDriverResult<String> {
+        disturb("text").await?;
+        self.0.text().await
+    }
+
+    async fn is_displayed(&self) -> WebDriverResult<bool> {
+        disturb("is_displayed").await?;
+        self.0.is_displayed().await
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use tokio::{process::Command, sync::OnceCell};
+
+use crate::{
+    config::{Mode, CONFIG},
+    session,
+};
+
+// Tried in order when CHROME_BINARY isn't set
+const CHROME_BINARIES: [&str; 4] = [
+    "google-chrome",
+    "chromium",
+    "chromium-browser",
+    "/opt/chrome/chrome",
+];
+
+static BROWSER_VERSION: OnceCell<Option<String>> = OnceCell::const_new();
+
+// `ChromeDriver 120.0.6099.109 (...)`, `Google Chrome 120.0.6099.109` -> 120
+fn major(version: &str) -> Option<u32> {
+    version
+        .split_whitespace()
+        .find(|part| part.starts_with(|c: char| c.is_ascii_digit()))?
+        .split('.')
+        .next()?
+        .parse()
+        .ok()
+}
+
+async fn driver_version() -> Result<String> {
+    let url = format!("{}/status", CONFIG.chromedriver_url.trim_end_matches('/'));
+    let status: Value = reqwest::Client::new()
+        .get(&url)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .with_context(|| format!("chromedriver isn't reachable at {}", url))?
+        .json()
+        .await?;
+
+    status
+        .pointer("/value/build/version")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .with_context(|| format!("{} has no driver version: {}", url, status))
+}
+
+// chromedriver doesn't report the browser, so it's asked directly. Unknown when no binary is found.
+async fn browser_version() -> Option<String> {
+    BROWSER_VERSION
+        .get_or_init(|| async {
+            let configured = CONFIG
+                .chrome_binary
+                .as_ref()
+                .map(|path| path.display().to_string());
+            let candidates = configured
+                .into_iter()
+                .chain(CHROME_BINARIES.iter().map(|binary| binary.to_string()));
+            for binary in candidates {
+                if let Ok(output) = Command::new(&binary).arg("--version").output().await {
+                    if output.status.success() {
+                        return Some(String::from_utf8_lossy(&output.stdout).trim().to_string());
+                    }
+                }
+            }
+            None
+        })
+        .await
+        .clone()
+}
+
+fn ensure_compatible(driver: &str, browser: Option<&str>) -> Result<()> {
+    if let Some(browser) = browser {
+        anyhow::ensure!(
+            major(driver) == major(browser),
+            "chromedriver {} doesn't support {}, install the chromedriver release of the same \
+             major version",
+            driver,
+            browser
+        );
+    }
+
+    Ok(())
+}
+
+/// Asks chromedriver and Chrome for their versions and fails when their major versions differ,
+/// which otherwise only shows as a failed session creation in the middle of a request.
+pub async fn check() -> Result<()> {
+    let driver = driver_version().await?;
+    ensure_compatible(&driver, browser_version().await.as_deref())
+}
+
+/// Whether this instance drives Chrome itself: `api` instances leave that to workers, and a
+/// replayed recording needs no chromedriver.
+pub fn in_use() -> bool {
+    session::is_chromedriver() && CONFIG.mode != Mode::Api && CONFIG.webdriver_replay.is_none()
+}
+
+/// Startup check: an incompatible pair stops the service, a chromedriver that isn't up yet is only
+/// reported since it may be started after the service.
+pub async fn check_on_startup() -> Result<()> {
+    if !in_use() {
+        return Ok(());
+    }
+
+    let driver = match driver_version().await {
+        Ok(driver) => driver,
+        Err(err) => {
+            tracing::warn!("skipping the chromedriver version check: {:#}", err);
+            return Ok(());
+        }
+    };
+    let browser = browser_version().await;
+    ensure_compatible(&driver, browser.as_deref())?;
+    tracing::info!(
+        "chromedriver {}, browser {}",
+        driver,
+        browser.as_deref().unwrap_or("unknown")
+    );
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Deserialize;
+
+use crate::config::CONFIG;
+
+/// Share of the browsers one API client gets, keyed by the authenticated subject.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Quota {
+    // jobs taken from this client's queue per job of a weight 1 client, when both are waiting.
+    // Only the redis queue of `api` and `worker` instances weighs clients, in `all` mode jobs run
+    // as they're requested
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default)]
+    pub max_in_flight: Option<usize>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+static QUOTAS: OnceCell<HashMap<String, Quota>> = OnceCell::new();
+// Inline jobs running per client, `api` and `worker` instances count in redis instead
+static RUNNING: Lazy<std::sync::Mutex<HashMap<String, usize>>> = Lazy::new(Default::default);
+
+/// Loads `CLIENT_QUOTAS`, a JSON object of subject to quota, e.g.
+/// `{"reports-team": {"weight": 3, "max_in_flight": 4}}`. Other clients get weight 1 and
+/// `CLIENT_MAX_IN_FLIGHT`. Weights only order the redis queue, without it a client over its
+/// `max_in_flight` is answered 503 rather than queued.
+pub fn load() -> Result<()> {
+    let quotas = match &CONFIG.client_quotas {
+        Some(path) => {
+            let file = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            serde_json::from_str(&file)
+                .with_context(|| format!("invalid client quotas in {}", path.display()))?
+        }
+        None => HashMap::new(),
+    };
+
+    QUOTAS
+        .set(quotas)
+        .map_err(|_| anyhow::anyhow!("client quotas already loaded"))
+}
+
+pub fn quota(client: &str) -> Quota {
+    QUOTAS
+        .get()
+        .and_then(|quotas| quotas.get(client))
+        .copied()
+        .unwrap_or(Quota {
+            weight: 1,
+            max_in_flight: CONFIG.client_max_in_flight,
+        })
+}
+
+/// Held while an inline job of `client` runs.
+pub struct Running(String);
+
+impl Running {
+    /// `None` when the client already runs its `max_in_flight` jobs.
+    pub fn try_start(client: &str) -> Option<Running> {
+        let mut running = RUNNING.lock().unwrap();
+        let count = running.entry(client.to_string()).or_default();
+        if quota(client).max_in_flight.is_some_and(|max| *count >= max) {
+            return None;
+        }
+        *count += 1;
+
+        Some(Running(client.to_string()))
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let mut running = RUNNING.lock().unwrap();
+        if let Some(count) = running.get_mut(&self.0) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
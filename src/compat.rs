@@ -0,0 +1,55 @@
+use axum::http::{HeaderMap, StatusCode};
+use dump_core::{legacy::to_legacy, models::AnnualFilings};
+use serde_json::{json, Value};
+
+use crate::{
+    config::CONFIG,
+    response::{self, Envelope},
+};
+
+/// Whether the response should keep the legacy shapes. Clients opt in or out per request with
+/// `X-Compat: legacy` or `X-Compat: current`, otherwise `LEGACY_RESPONSES` decides.
+pub fn requested(headers: &HeaderMap) -> bool {
+    match headers
+        .get("x-compat")
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(value) if value.eq_ignore_ascii_case("legacy") => true,
+        Some(value) if value.eq_ignore_ascii_case("current") => false,
+        _ => CONFIG.legacy_responses,
+    }
+}
+
+/// Legacy clients get an empty company search as the 404 `{"error": "No results found"}` it used
+/// to be.
+pub fn no_results(envelope: Envelope<Value>) -> Envelope<Value> {
+    let empty = envelope.status == StatusCode::OK && envelope.data.get("total") == Some(&json!(0));
+    if !empty || !response::legacy() {
+        return envelope;
+    }
+
+    Envelope::new(
+        StatusCode::NOT_FOUND,
+        json!({ "error": "No results found" }),
+    )
+}
+
+/// Rewrites every `annual_filings_details` of a serialized response to the legacy shape, wherever
+/// it's nested (corporation data, job results, ...).
+pub fn downgrade(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if key == "annual_filings_details" {
+                    if let Ok(filings) = serde_json::from_value::<AnnualFilings>(value.clone()) {
+                        *value = serde_json::to_value(to_legacy(filings)).unwrap_or_default();
+                        continue;
+                    }
+                }
+                downgrade(value);
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(downgrade),
+        _ => {}
+    }
+}
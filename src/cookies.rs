@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use thirtyfour::prelude::WebDriverResult;
+
+use crate::{config::CONFIG, session::Driver};
+
+// Provider of the registry search and payment flows
+pub const REGISTRY: &str = "registry";
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    #[default]
+    Lax,
+    None,
+}
+
+/// A cookie a provider expects before a flow starts, e.g. the registry's timezone.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    #[serde(default = "root")]
+    pub path: String,
+    #[serde(default)]
+    pub same_site: SameSite,
+}
+
+fn root() -> String {
+    "/".to_string()
+}
+
+static PROVIDERS: OnceCell<BTreeMap<String, Vec<Cookie>>> = OnceCell::new();
+
+fn builtin() -> BTreeMap<String, Vec<Cookie>> {
+    BTreeMap::from([(
+        REGISTRY.to_string(),
+        vec![Cookie {
+            name: "x-catalyst-timezone".to_string(),
+            value: "America/Toronto".to_string(),
+            domain: "redacted".to_string(),
+            path: root(),
+            same_site: SameSite::Lax,
+        }],
+    )])
+}
+
+/// Loads the provider cookies: the built-in ones plus those of `PROVIDER_COOKIES`, a JSON object
+/// of provider name to cookies, e.g. `{"registry": [{"name": "x-catalyst-timezone", "value":
+/// "America/Toronto", "domain": "redacted", "same_site": "Lax"}]}`. Providers defined in the file
+/// replace the built-in ones of the same name.
+pub fn load() -> Result<()> {
+    let mut providers = builtin();
+    if let Some(path) = &CONFIG.provider_cookies {
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let custom: BTreeMap<String, Vec<Cookie>> = serde_json::from_str(&file)
+            .with_context(|| format!("invalid provider cookies in {}", path.display()))?;
+        providers.extend(custom);
+    }
+
+    PROVIDERS
+        .set(providers)
+        .map_err(|_| anyhow::anyhow!("provider cookies already loaded"))
+}
+
+/// Sets the cookies of `provider` in the session, which has to be on one of its pages already.
+pub async fn apply(driver: &dyn Driver, provider: &str) -> WebDriverResult<()> {
+    let cookies = PROVIDERS
+        .get()
+        .and_then(|providers| providers.get(provider))
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    for cookie in cookies {
+        driver.add_cookie(cookie).await?;
+    }
+
+    Ok(())
+}
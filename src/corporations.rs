@@ -0,0 +1,656 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use dump_core::{
+    enrich::city_and_province,
+    models::{CorporationData, CorporationSection},
+    normalize::normalize_name,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{config::CONFIG, report, storage, webhooks};
+
+const COLLECTION: &str = "corporations";
+// Latest snapshot of each corporation, the snapshots are kept in `snapshots(id)`
+const HISTORY: &str = "corporation-history";
+// Marks the name index built, see `names`
+const NAME_INDEX: &str = "corporation-names";
+// Letters of each name word a corporation is indexed under
+const NAME_PREFIX_LEN: usize = 3;
+
+/// Latest known data of a scraped corporation.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StoredCorporation {
+    pub id: String,
+    // none for records only imported from open data, which doesn't say when the registry showed
+    // it, see `scraped_at()`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scraped_at: Option<DateTime<Utc>>,
+    // set while the record only holds the open data it was imported from, a scrape clears it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imported_at: Option<DateTime<Utc>>,
+    // set by a deletion request, the record is hidden until the purger deletes it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    // when each section was last scraped, `scraped_at` for records stored before it was kept
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sections_scraped_at: BTreeMap<String, DateTime<Utc>>,
+    pub data: CorporationData,
+}
+
+impl StoredCorporation {
+    pub fn corporate_name(&self) -> Option<&str> {
+        self.data.corporate_name()
+    }
+
+    pub fn status(&self) -> Option<&str> {
+        self.data
+            .corp_details
+            .iter()
+            .flatten()
+            .find_map(|row| row.get("Status"))
+            .map(String::as_str)
+    }
+
+    /// When the registry was last scraped for the corporation, none while the record only holds
+    /// imported open data. Imports used to store their own time, which isn't a scrape either.
+    pub fn scraped_at(&self) -> Option<DateTime<Utc>> {
+        self.scraped_at.filter(|_| self.imported_at.is_none())
+    }
+
+    pub fn section_scraped_at(&self, section: CorporationSection) -> Option<DateTime<Utc>> {
+        self.sections_scraped_at
+            .get(section.name())
+            .copied()
+            .or(self.scraped_at())
+    }
+
+    /// Those of `sections` a differential scrape answers from this record: the ones it holds
+    /// that aren't volatile and were scraped less than `DIFFERENTIAL_MAX_AGE_HOURS` ago.
+    pub fn reusable(&self, sections: &[CorporationSection]) -> Vec<CorporationSection> {
+        let oldest = Utc::now() - Duration::hours(CONFIG.differential_max_age_hours as i64);
+        sections
+            .iter()
+            .copied()
+            .filter(|section| {
+                !CorporationSection::VOLATILE.contains(section)
+                    && self.data.has(*section)
+                    && self
+                        .section_scraped_at(*section)
+                        .is_some_and(|scraped_at| scraped_at > oldest)
+            })
+            .collect()
+    }
+}
+
+/// Stores a fresh scrape. Sections that weren't extracted this time are kept from the previous
+/// scrape so partial (`?sections=`) requests don't erase data. New corporations and changed
+/// sections are recorded in the change feed.
+pub async fn save(id: &str, data: &CorporationData) -> Result<()> {
+    let mut data = data.clone();
+    if CONFIG.minimize_pii {
+        data.minimize_pii();
+    }
+    // an imported record is replaced rather than updated: its sections would pass for scraped
+    // ones, and the feed never saw it, so its first scrape is what creates it there
+    let live = live(id).await?;
+    let indexed_name = live
+        .as_ref()
+        .and_then(|live| live.corporate_name().map(str::to_string));
+    let previous = live.filter(|previous| previous.imported_at.is_none());
+    let change = change(previous.as_ref(), &data)?;
+    let previous_status = previous
+        .as_ref()
+        .and_then(|previous| previous.status().map(str::to_string));
+
+    let now = Utc::now();
+    let mut sections_scraped_at = BTreeMap::new();
+    for section in CorporationSection::ALL {
+        let scraped_at = match &previous {
+            _ if data.has(section) => now,
+            Some(previous) if previous.data.has(section) => {
+                match previous.section_scraped_at(section) {
+                    Some(scraped_at) => scraped_at,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        sections_scraped_at.insert(section.name().to_string(), scraped_at);
+    }
+
+    if let Some(previous) = previous {
+        let previous = previous.data;
+        data.corp_details = data.corp_details.or(previous.corp_details);
+        data.address_details = data.address_details.or(previous.address_details);
+        data.director_details = data.director_details.or(previous.director_details);
+        data.annual_filings_details = data
+            .annual_filings_details
+            .or(previous.annual_filings_details);
+        data.corp_history_details = data.corp_history_details.or(previous.corp_history_details);
+        data.share_structure = data.share_structure.or(previous.share_structure);
+        data.share_restrictions = data.share_restrictions.or(previous.share_restrictions);
+        data.jurisdiction = data.jurisdiction.or(previous.jurisdiction);
+        data.entity_type = data.entity_type.or(previous.entity_type);
+    }
+
+    let stored = StoredCorporation {
+        id: id.to_string(),
+        scraped_at: Some(now),
+        imported_at: None,
+        deleted_at: None,
+        sections_scraped_at,
+        data,
+    };
+    index_name(id, indexed_name.as_deref(), stored.corporate_name()).await?;
+    store(&stored, now, change, previous_status).await
+}
+
+/// Stores a corporation of the open-data bulk files, unless it was scraped already: a scrape is
+/// as recent and has more sections. Returns whether it was stored. Imports aren't changes the
+/// registry showed, so they stay out of the history, the change feed and the webhooks.
+pub async fn import(id: &str, mut data: CorporationData) -> Result<bool> {
+    if CONFIG.minimize_pii {
+        data.minimize_pii();
+    }
+    let previous = live(id).await?;
+    if previous
+        .as_ref()
+        .is_some_and(|previous| previous.imported_at.is_none())
+    {
+        return Ok(false);
+    }
+
+    let now = Utc::now();
+    let stored = StoredCorporation {
+        id: id.to_string(),
+        scraped_at: None,
+        imported_at: Some(now),
+        deleted_at: None,
+        sections_scraped_at: BTreeMap::new(),
+        data,
+    };
+    let indexed_name = previous
+        .as_ref()
+        .and_then(|previous| previous.corporate_name());
+    index_name(id, indexed_name, stored.corporate_name()).await?;
+    storage::save(COLLECTION, id, &stored).await?;
+
+    Ok(true)
+}
+
+// Stored corporations by the first letters of a word of their normalized name, so name checks
+// look up the corporations sharing a word start rather than reading every record
+fn names(prefix: &str) -> String {
+    format!("corporation-names:{}", prefix)
+}
+
+fn name_prefixes(name: &str) -> BTreeSet<String> {
+    normalize_name(name)
+        .split(' ')
+        .filter(|word| !word.is_empty())
+        .map(|word| word.chars().take(NAME_PREFIX_LEN).collect())
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct NameEntry {
+    id: String,
+}
+
+// Moves the corporation from the index entries of `before` to those of `after`. The entries are
+// saved again on every store, so they expire with the record.
+async fn index_name(id: &str, before: Option<&str>, after: Option<&str>) -> Result<()> {
+    let after = after.map(name_prefixes).unwrap_or_default();
+    for prefix in before.map(name_prefixes).unwrap_or_default() {
+        if !after.contains(&prefix) {
+            storage::delete(&names(&prefix), id).await?;
+        }
+    }
+    for prefix in &after {
+        let entry = NameEntry { id: id.to_string() };
+        storage::save(&names(prefix), id, &entry).await?;
+    }
+
+    Ok(())
+}
+
+// Indexes the records stored before the index was kept, once
+async fn build_name_index() -> Result<()> {
+    if storage::load::<Value>(NAME_INDEX, "built").await?.is_some() {
+        return Ok(());
+    }
+    for stored in all().await? {
+        index_name(&stored.id, None, stored.corporate_name()).await?;
+    }
+
+    storage::keep(
+        NAME_INDEX,
+        "built",
+        &serde_json::json!({ "built_at": Utc::now() }),
+    )
+    .await
+}
+
+/// The stored corporations whose name shares the start of a word with `name`, the candidates of a
+/// name check.
+pub async fn with_name_like(name: &str) -> Result<Vec<StoredCorporation>> {
+    build_name_index().await?;
+
+    let mut ids = BTreeSet::new();
+    for prefix in name_prefixes(name) {
+        for entry in storage::load_all::<NameEntry>(&names(&prefix)).await? {
+            ids.insert(entry.id);
+        }
+    }
+
+    let mut found = vec![];
+    for id in ids {
+        found.extend(live(&id).await?);
+    }
+
+    Ok(found)
+}
+
+/// The imported record of the corporation when it holds every one of `sections`, so the request
+/// can be answered without scraping.
+pub async fn imported(
+    id: &str,
+    sections: &[CorporationSection],
+) -> Result<Option<StoredCorporation>> {
+    let Some(stored) = live(id).await? else {
+        return Ok(None);
+    };
+    if stored.imported_at.is_none() {
+        return Ok(None);
+    }
+
+    let complete = sections.iter().all(|section| stored.data.has(*section));
+
+    Ok(complete.then_some(stored))
+}
+
+type Changed = Option<(ChangeKind, Vec<String>)>;
+
+fn change(previous: Option<&StoredCorporation>, data: &CorporationData) -> Result<Changed> {
+    Ok(match previous {
+        None => Some((ChangeKind::Created, vec![])),
+        Some(previous) => {
+            let sections = changed_sections(&previous.data, data)?;
+            (!sections.is_empty()).then_some((ChangeKind::Updated, sections))
+        }
+    })
+}
+
+async fn store(
+    stored: &StoredCorporation,
+    scraped_at: DateTime<Utc>,
+    change: Changed,
+    previous_status: Option<String>,
+) -> Result<()> {
+    storage::save(COLLECTION, &stored.id, stored).await?;
+    History::record(stored, scraped_at).await?;
+
+    if let Some((kind, sections)) = change {
+        webhooks::corporation_changed(&stored.id, kind, &sections);
+        Change::record(&stored.id, kind, sections).await?;
+    }
+    // records without the details section have no status to compare
+    if let (Some(previous), Some(current)) = (previous_status, stored.status()) {
+        if previous != current {
+            webhooks::corporation_status_changed(&stored.id, &previous, current);
+        }
+    }
+
+    Ok(())
+}
+
+// Names of the sections present in `current` whose content differs from `previous`
+fn changed_sections(previous: &CorporationData, current: &CorporationData) -> Result<Vec<String>> {
+    let previous = serde_json::to_value(previous)?;
+    let current = serde_json::to_value(current)?;
+
+    let Some(current) = current.as_object() else {
+        return Ok(vec![]);
+    };
+    let sections = current
+        .iter()
+        .filter(|(key, value)| previous.get(key.as_str()) != Some(value))
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    Ok(sections)
+}
+
+// Keys sorted at every level, so equal data serializes the same whatever order maps iterate in
+fn canonical(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let sorted: BTreeMap<String, Value> = map
+                .into_iter()
+                .map(|(key, value)| (key, canonical(value)))
+                .collect();
+            Value::Object(sorted.into_iter().collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonical).collect()),
+        value => value,
+    }
+}
+
+/// SHA-256 of the canonical JSON of `data`, hex encoded.
+pub fn content_hash(data: &CorporationData) -> Result<String> {
+    let json = serde_json::to_string(&canonical(serde_json::to_value(data)?))?;
+    let digest = Sha256::digest(json.as_bytes());
+
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// A distinct version of a corporation's data and every scrape that found it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+    // `{id}#{first seen}`, sorts like the snapshots were taken
+    pub cursor: String,
+    pub content_hash: String,
+    pub seen_at: Vec<DateTime<Utc>>,
+    pub data: CorporationData,
+}
+
+// Which snapshot is the latest, so a scrape only reads that one. Empty for histories stored as
+// one document, before snapshots were kept apart, whose next snapshot starts over.
+#[derive(Serialize, Deserialize, Debug)]
+struct Latest {
+    #[serde(default)]
+    cursor: String,
+    #[serde(default)]
+    content_hash: String,
+}
+
+fn snapshots(id: &str) -> String {
+    format!("{}:{}", HISTORY, id)
+}
+
+/// Versions of a corporation's data, oldest first. Scrapes that find the data unchanged only add
+/// to the `seen_at` of the latest version, so daily monitoring keeps one copy per change. Each
+/// version is a document of its own, read a page at a time.
+#[derive(Serialize, Debug)]
+pub struct History {
+    pub id: String,
+    pub snapshots: Vec<Snapshot>,
+    // pass as `cursor` to continue after the last snapshot
+    pub next_cursor: Option<String>,
+}
+
+impl History {
+    pub fn minimize_pii(&mut self) {
+        for snapshot in &mut self.snapshots {
+            snapshot.data.minimize_pii();
+        }
+    }
+
+    async fn record(stored: &StoredCorporation, scraped_at: DateTime<Utc>) -> Result<()> {
+        let content_hash = content_hash(&stored.data)?;
+        let latest: Option<Latest> = storage::load(HISTORY, &stored.id).await?;
+        let unchanged = match latest {
+            Some(latest) if latest.content_hash == content_hash => {
+                storage::load::<Snapshot>(&snapshots(&stored.id), &latest.cursor).await?
+            }
+            _ => None,
+        };
+
+        let snapshot = match unchanged {
+            Some(mut snapshot) => {
+                snapshot.seen_at.push(scraped_at);
+                snapshot
+            }
+            None => {
+                // zero padded so cursors compare like timestamps
+                let cursor = format!("{}#{:020}", stored.id, scraped_at.timestamp_micros());
+                Snapshot {
+                    cursor,
+                    content_hash,
+                    seen_at: vec![scraped_at],
+                    data: stored.data.clone(),
+                }
+            }
+        };
+        storage::save(&snapshots(&stored.id), &snapshot.cursor, &snapshot).await?;
+        let latest = Latest {
+            cursor: snapshot.cursor,
+            content_hash: snapshot.content_hash,
+        };
+        storage::save(HISTORY, &stored.id, &latest).await
+    }
+
+    /// Up to `limit` snapshots taken after the one at `cursor`, oldest first. None when the
+    /// corporation has no history.
+    pub async fn find(id: &str, cursor: Option<&str>, limit: usize) -> Result<Option<History>> {
+        let deleted = storage::load::<StoredCorporation>(COLLECTION, id)
+            .await?
+            .is_some_and(|stored| stored.deleted_at.is_some());
+        if deleted || storage::load::<Latest>(HISTORY, id).await?.is_none() {
+            return Ok(None);
+        }
+
+        let snapshots: Vec<Snapshot> = storage::load_all::<Snapshot>(&snapshots(id))
+            .await?
+            .into_iter()
+            .filter(|snapshot| cursor.is_none_or(|cursor| snapshot.cursor.as_str() > cursor))
+            .take(limit)
+            .collect();
+        let next_cursor = snapshots
+            .last()
+            .map(|snapshot| snapshot.cursor.clone())
+            .or(cursor.map(str::to_string));
+
+        Ok(Some(History {
+            id: id.to_string(),
+            snapshots,
+            next_cursor,
+        }))
+    }
+}
+
+pub async fn all() -> Result<Vec<StoredCorporation>> {
+    let stored = storage::load_all::<StoredCorporation>(COLLECTION).await?;
+
+    Ok(stored
+        .into_iter()
+        .filter(|stored| stored.deleted_at.is_none())
+        .collect())
+}
+
+/// The stored scrape of the corporation, none when it was never scraped or only imported.
+pub async fn scraped(id: &str) -> Result<Option<StoredCorporation>> {
+    Ok(live(id)
+        .await?
+        .filter(|stored| stored.imported_at.is_none()))
+}
+
+// The stored record unless it's soft deleted. A scrape or import of a soft-deleted corporation
+// purges it first, so it starts over instead of reviving the deleted data.
+async fn live(id: &str) -> Result<Option<StoredCorporation>> {
+    match storage::load::<StoredCorporation>(COLLECTION, id).await? {
+        Some(stored) if stored.deleted_at.is_some() => {
+            purge(&[stored.id]).await?;
+            Ok(None)
+        }
+        stored => Ok(stored),
+    }
+}
+
+/// Purges the corporation when it's soft deleted, for writes of its data outside this module.
+pub async fn purge_if_deleted(id: &str) -> Result<()> {
+    live(id).await?;
+
+    Ok(())
+}
+
+/// Hides the corporation from every read until the purger deletes it, its history, its change
+/// feed entries and its reports. False when it isn't stored.
+pub async fn soft_delete(id: &str) -> Result<bool> {
+    let Some(mut stored) = storage::load::<StoredCorporation>(COLLECTION, id).await? else {
+        return Ok(false);
+    };
+    if stored.deleted_at.is_none() {
+        stored.deleted_at = Some(Utc::now());
+        storage::save(COLLECTION, id, &stored).await?;
+    }
+
+    Ok(true)
+}
+
+/// Deletes the soft-deleted corporations for good, returning their ids.
+pub async fn purge_deleted() -> Result<Vec<String>> {
+    let deleted: Vec<String> = storage::load_all::<StoredCorporation>(COLLECTION)
+        .await?
+        .into_iter()
+        .filter(|stored| stored.deleted_at.is_some())
+        .map(|stored| stored.id)
+        .collect();
+    if !deleted.is_empty() {
+        purge(&deleted).await?;
+    }
+
+    Ok(deleted)
+}
+
+// The record goes last, so a purge cut short is picked up again by the next one
+async fn purge(ids: &[String]) -> Result<()> {
+    report::purge(ids).await?;
+    for id in ids {
+        if let Some(stored) = storage::load::<StoredCorporation>(COLLECTION, id).await? {
+            index_name(id, stored.corporate_name(), None).await?;
+        }
+    }
+    for change in storage::load_all::<Change>(CHANGES).await? {
+        if ids.contains(&change.corporation_id) {
+            storage::delete(CHANGES, &change.cursor).await?;
+        }
+    }
+    for id in ids {
+        for snapshot in storage::load_all::<Snapshot>(&snapshots(id)).await? {
+            storage::delete(&snapshots(id), &snapshot.cursor).await?;
+        }
+        storage::delete(HISTORY, id).await?;
+        storage::delete(COLLECTION, id).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize, Debug)]
+pub struct DirectorAssociation {
+    pub corporation_id: String,
+    pub corporate_name: Option<String>,
+    pub address: Option<String>,
+    // none for corporations only imported from open data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scraped_at: Option<DateTime<Utc>>,
+}
+
+/// A person found in the stored director lists and every corporation they're listed on.
+#[derive(Serialize, Debug)]
+pub struct DirectorMatch {
+    pub name: String,
+    pub corporations: Vec<DirectorAssociation>,
+}
+
+impl DirectorMatch {
+    pub fn minimize_pii(&mut self) {
+        for association in &mut self.corporations {
+            association.address = association.address.as_deref().and_then(city_and_province);
+        }
+    }
+}
+
+/// Directors whose name contains every word of `name`, in any order, so "Doe John" also finds
+/// "JOHN A. DOE". Matching happens on normalized names.
+pub async fn find_directors(name: &str) -> Result<Vec<DirectorMatch>> {
+    let wanted = normalize_name(name);
+    let wanted: Vec<&str> = wanted.split(' ').filter(|w| !w.is_empty()).collect();
+
+    let mut matches: BTreeMap<String, DirectorMatch> = BTreeMap::new();
+    for corporation in all().await? {
+        let directors = corporation.data.directors().unwrap_or_default();
+
+        for director in directors.current {
+            let normalized = normalize_name(&director.name);
+            let words: Vec<&str> = normalized.split(' ').collect();
+            if !wanted.iter().all(|w| words.contains(w)) {
+                continue;
+            }
+
+            matches
+                .entry(normalized.clone())
+                .or_insert_with(|| DirectorMatch {
+                    name: director.name.clone(),
+                    corporations: vec![],
+                })
+                .corporations
+                .push(DirectorAssociation {
+                    corporation_id: corporation.id.clone(),
+                    corporate_name: corporation.corporate_name().map(str::to_string),
+                    address: director.address,
+                    scraped_at: corporation.scraped_at(),
+                });
+        }
+    }
+
+    Ok(matches.into_values().collect())
+}
+
+const CHANGES: &str = "changes";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+}
+
+/// Entry of the change feed. Cursors sort in the order changes were detected.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Change {
+    pub cursor: String,
+    pub corporation_id: String,
+    pub kind: ChangeKind,
+    pub sections: Vec<String>,
+    pub detected_at: DateTime<Utc>,
+}
+
+impl Change {
+    async fn record(corporation_id: &str, kind: ChangeKind, sections: Vec<String>) -> Result<()> {
+        let detected_at = Utc::now();
+        // zero padded so ids compare like timestamps, the suffix keeps same-instant changes apart
+        let cursor = format!(
+            "{:020}-{}",
+            detected_at.timestamp_micros(),
+            &Uuid::new_v4().simple().to_string()[..8]
+        );
+
+        let change = Change {
+            cursor: cursor.clone(),
+            corporation_id: corporation_id.to_string(),
+            kind,
+            sections,
+            detected_at,
+        };
+        storage::save(CHANGES, &cursor, &change).await
+    }
+
+    /// Up to `limit` changes recorded after `since`, oldest first.
+    pub async fn since(since: Option<&str>, limit: usize) -> Result<Vec<Change>> {
+        let changes = storage::load_all::<Change>(CHANGES)
+            .await?
+            .into_iter()
+            .filter(|change| since.is_none_or(|since| change.cursor.as_str() > since))
+            .take(limit)
+            .collect();
+
+        Ok(changes)
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{jobs::Job, lock::Lock, storage};
+
+const COLLECTION: &str = "dead-letter";
+// Outlives a retry request by far, the claim is dropped once `retried_as` is saved
+const CLAIM_TTL: Duration = Duration::from_secs(60);
+
+/// A job that failed after its flow used up every retry, kept to be looked into and requeued
+/// once the cause is fixed. Keyed by the id of the failed job.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadLetter {
+    // as it failed, with its params, client and error
+    pub job: Job,
+    // tries of the flow, unknown for jobs a worker ran
+    pub attempts: Option<u32>,
+    // of the request that ran the flow inline
+    pub request_id: Option<Uuid>,
+    pub failed_at: DateTime<Utc>,
+    // the job it was requeued as, see `/api/admin/dead-letter/:id/retry`
+    #[serde(default)]
+    pub retried_as: Option<Uuid>,
+    #[serde(default)]
+    pub retried_at: Option<DateTime<Utc>>,
+}
+
+pub async fn record(job: &Job, attempts: Option<u32>, request_id: Option<Uuid>) {
+    let letter = DeadLetter {
+        job: job.clone(),
+        attempts,
+        request_id,
+        failed_at: job.updated_at,
+        retried_as: None,
+        retried_at: None,
+    };
+    tracing::warn!(
+        "job {} exhausted its retries: {}",
+        job.id,
+        job.error.as_deref().unwrap_or_default()
+    );
+    if let Err(err) = save(&letter).await {
+        tracing::error!("failed to dead-letter job {}: {}", job.id, err);
+    }
+}
+
+async fn save(letter: &DeadLetter) -> Result<()> {
+    storage::save(COLLECTION, &letter.job.id.to_string(), letter).await
+}
+
+/// Every dead-lettered job, the latest failure first.
+pub async fn list() -> Result<Vec<DeadLetter>> {
+    let mut letters: Vec<DeadLetter> = storage::load_all(COLLECTION).await?;
+    letters.sort_by_key(|letter| std::cmp::Reverse(letter.failed_at));
+
+    Ok(letters)
+}
+
+pub async fn find(id: Uuid) -> Result<Option<DeadLetter>> {
+    storage::load(COLLECTION, &id.to_string()).await
+}
+
+/// Held while the letter is being retried, `None` if another request is retrying it.
+pub async fn claim(id: Uuid) -> Result<Option<Lock>> {
+    Lock::try_acquire(format!("dead-letter-retry:{}", id), CLAIM_TTL).await
+}
+
+/// Notes that the letter was requeued as `job`, so it isn't retried twice by accident.
+pub async fn retried(mut letter: DeadLetter, job: &Job) -> Result<DeadLetter> {
+    letter.retried_as = Some(job.id);
+    letter.retried_at = Some(job.created_at);
+    save(&letter).await?;
+
+    Ok(letter)
+}
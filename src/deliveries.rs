@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use dump_core::normalize::normalize_name;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    artifacts::artifacts,
+    config::CONFIG,
+    cookies,
+    handler::{self, Order, RequestBusinessProfileReportParams},
+    jobs::{Deliverable, Job, JobKind, JobStatus},
+    lock::Lock,
+    login, politeness, robots, storage,
+};
+
+const COLLECTION: &str = "deliveries";
+// Held by the instance polling the orders, so instances don't download the same documents
+const POLL_LOCK: &str = "delivery-poll";
+
+/// An order whose documents were downloaded, so they aren't downloaded again.
+#[derive(Serialize, Deserialize, Debug)]
+struct Delivery {
+    order_id: String,
+    // the payment job that bought it, none for purchases made elsewhere
+    job_id: Option<Uuid>,
+    artifact: String,
+    downloaded_at: DateTime<Utc>,
+}
+
+// Latest succeeded purchase of the order's product for its company not given its documents yet
+fn originating_job<'a>(jobs: &'a [Job], order: &Order) -> Option<&'a Job> {
+    let company = normalize_name(&order.company);
+    jobs.iter()
+        .filter(|job| job.kind == JobKind::PaymentPage && job.status == JobStatus::Succeeded)
+        .filter(|job| job.deliverables.is_empty())
+        .filter(|job| {
+            serde_json::from_value::<RequestBusinessProfileReportParams>(job.params.clone())
+                .is_ok_and(|params| {
+                    normalize_name(&params.selected_company) == company
+                        && params.search_product.eq_ignore_ascii_case(&order.product)
+                })
+        })
+        .max_by_key(|job| job.created_at)
+}
+
+fn extension(content_type: &str) -> &'static str {
+    match content_type.split(';').next().unwrap_or_default().trim() {
+        "application/pdf" => "pdf",
+        "application/zip" => "zip",
+        "text/html" => "html",
+        _ => "bin",
+    }
+}
+
+// Downloaded with the cookies of the signed in session, delivery links are the account's own.
+// Links are relative to the order history they were read from, and the cookies only go along
+// to the hosts they were set for.
+async fn download(order: &Order, href: &str) -> Result<String> {
+    let url = Url::parse(handler::ORDER_HISTORY_URL)?.join(href)?;
+    anyhow::ensure!(url.scheme() == "https", "delivery link {} isn't https", url);
+    let host = url
+        .host_str()
+        .with_context(|| format!("delivery link {} has no host", url))?;
+    let cookies = login::cookie_header(host)
+        .with_context(|| format!("no signed in registry session for {}", host))?;
+    robots::ensure(cookies::REGISTRY, url.as_str()).await?;
+    politeness::pace(cookies::REGISTRY).await;
+    let response = politeness::client(cookies::REGISTRY)
+        .get(url)
+        .header(reqwest::header::COOKIE, cookies)
+        .send()
+        .await?
+        .error_for_status()?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let bytes = response.bytes().await?;
+
+    // order ids end up in the artifact key
+    let id: String = order
+        .order_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    anyhow::ensure!(
+        !id.is_empty(),
+        "order {:?} has no usable id",
+        order.order_id
+    );
+    let key = format!("orders/{}.{}", id, extension(&content_type));
+    artifacts().put(&key, &bytes, &content_type).await?;
+
+    Ok(key)
+}
+
+async fn deliver(order: &Order, url: &str, jobs: &mut [Job]) -> Result<()> {
+    let artifact = download(order, url).await?;
+    let downloaded_at = Utc::now();
+
+    let job = originating_job(jobs, order).map(|job| job.id);
+    if let Some(job) = jobs.iter_mut().find(|found| Some(found.id) == job) {
+        *job = job
+            .clone()
+            .attach(Deliverable {
+                order_id: order.order_id.clone(),
+                artifact: artifact.clone(),
+                downloaded_at,
+            })
+            .await?;
+    }
+    tracing::info!(
+        "downloaded the documents of order {} to {}",
+        order.order_id,
+        artifact
+    );
+
+    let delivery = Delivery {
+        order_id: order.order_id.clone(),
+        job_id: job,
+        artifact,
+        downloaded_at,
+    };
+    storage::save(COLLECTION, &order.order_id, &delivery).await
+}
+
+async fn poll() -> Result<()> {
+    // expires by the next cycle, should this instance die polling
+    let ttl = Duration::from_secs(CONFIG.delivery_poll_secs);
+    let Some(_lock) = Lock::try_acquire(POLL_LOCK.to_string(), ttl).await? else {
+        return Ok(());
+    };
+    let orders = handler::order_history()
+        .await
+        .map_err(|err| anyhow::anyhow!("{}", err))?;
+    let mut jobs = Job::all().await?;
+
+    for order in &orders {
+        let Some(url) = &order.delivery_url else {
+            continue;
+        };
+        if storage::load::<Delivery>(COLLECTION, &order.order_id)
+            .await?
+            .is_some()
+        {
+            continue;
+        }
+        if let Err(err) = deliver(order, url, &mut jobs).await {
+            tracing::warn!(
+                "failed to download the documents of order {}: {}",
+                order.order_id,
+                err
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Every `DELIVERY_POLL_SECS`, reads the registry account's order history and downloads the
+/// documents of orders delivered since, as artifacts attached to the payment job that bought them.
+pub fn spawn_poller() {
+    if !login::configured() || CONFIG.delivery_poll_secs == 0 {
+        return;
+    }
+
+    tokio::spawn(async {
+        let interval = Duration::from_secs(CONFIG.delivery_poll_secs);
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(err) = poll().await {
+                tracing::warn!("failed to check the registry orders: {}", err);
+            }
+        }
+    });
+}
@@ -1,45 +1,233 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde::Serialize;
 use uuid::Uuid;
 
+use crate::{response, retry::Attempt};
+
 pub enum ErrorKind {
     InternalServerError(anyhow::Error),
+    Conflict(String),
+    NotFound(String),
+    BadRequest(String),
+    // The caller may not do this, whoever they are
+    Forbidden(String),
+    // The registry refused what the flow filled in
+    Unprocessable(String),
+    // The registry sent the flow somewhere it must not follow
+    BadGateway(String),
+    // The request may succeed later, `retry_after` is sent as Retry-After in seconds
+    Unavailable { message: String, retry_after: u64 },
+    // Like `Unavailable`, while the provider is down for scheduled maintenance, see `maintenance`
+    MaintenanceWindow { message: String, retry_after: u64 },
 }
 
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_url: Option<String>,
+    // set where clients may want to tell the failure apart from others of the same status
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
     pub message: String,
+    // tries of the flow that failed before this answer, see `retry`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<Attempt>,
+}
+
+impl ErrorResponse {
+    fn json(error_id: Uuid, message: String) -> Json<ErrorResponse> {
+        let (trace_id, trace_url) = response::trace();
+        Json(ErrorResponse {
+            error_id,
+            trace_id,
+            trace_url,
+            code: None,
+            message,
+            attempts: response::failed_attempts(),
+        })
+    }
 }
 
 pub struct AppError(ErrorKind);
 
+impl AppError {
+    /// Whether running the same flow again may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.0,
+            ErrorKind::InternalServerError(_) | ErrorKind::Unavailable { .. }
+        )
+    }
+
+    /// HTTP status the error is answered with.
+    pub fn status(&self) -> StatusCode {
+        match &self.0 {
+            ErrorKind::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorKind::Conflict(_) => StatusCode::CONFLICT,
+            ErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
+            ErrorKind::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ErrorKind::Forbidden(_) => StatusCode::FORBIDDEN,
+            ErrorKind::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorKind::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            ErrorKind::Unavailable { .. } | ErrorKind::MaintenanceWindow { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        }
+    }
+
+    /// Seconds the client is asked to wait before trying again, sent as Retry-After.
+    pub fn retry_after(&self) -> Option<u64> {
+        match &self.0 {
+            ErrorKind::Unavailable { retry_after, .. }
+            | ErrorKind::MaintenanceWindow { retry_after, .. } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    /// The error of `class` a job failed with on a worker, from what the job record kept of it,
+    /// so it's answered as it would have been had the job run in the request.
+    pub fn rebuild(class: &str, message: String, retry_after: Option<u64>) -> AppError {
+        let retry_after = retry_after.unwrap_or_default();
+        let kind = match class {
+            "conflict" => ErrorKind::Conflict(message),
+            "not_found" => ErrorKind::NotFound(message),
+            "bad_request" => ErrorKind::BadRequest(message),
+            "forbidden" => ErrorKind::Forbidden(message),
+            "unprocessable" => ErrorKind::Unprocessable(message),
+            "bad_gateway" => ErrorKind::BadGateway(message),
+            "unavailable" => ErrorKind::Unavailable {
+                message,
+                retry_after,
+            },
+            "maintenance_window" => ErrorKind::MaintenanceWindow {
+                message,
+                retry_after,
+            },
+            _ => ErrorKind::InternalServerError(anyhow::anyhow!(message)),
+        };
+
+        AppError(kind)
+    }
+
+    /// Kind of failure for logs and the attempt history, without the details.
+    pub fn class(&self) -> &'static str {
+        match &self.0 {
+            ErrorKind::InternalServerError(err)
+                if err.is::<thirtyfour::error::WebDriverError>() =>
+            {
+                "webdriver"
+            }
+            ErrorKind::InternalServerError(err) if err.is::<reqwest::Error>() => "http",
+            ErrorKind::InternalServerError(_) => "internal",
+            ErrorKind::Conflict(_) => "conflict",
+            ErrorKind::NotFound(_) => "not_found",
+            ErrorKind::BadRequest(_) => "bad_request",
+            ErrorKind::Forbidden(_) => "forbidden",
+            ErrorKind::Unprocessable(_) => "unprocessable",
+            ErrorKind::BadGateway(_) => "bad_gateway",
+            ErrorKind::Unavailable { .. } => "unavailable",
+            ErrorKind::MaintenanceWindow { .. } => "maintenance_window",
+        }
+    }
+}
+
+// Errors reuse the request id so they can be found in the logs
+fn error_id() -> Uuid {
+    response::request_id().unwrap_or_else(Uuid::new_v4)
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let Self(err) = self;
 
         match err {
             ErrorKind::InternalServerError(err) => {
-                let error_id = Uuid::new_v4();
+                let error_id = error_id();
                 tracing::error!("{}: Internal Server Error: {}", error_id, err);
 
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error_id,
-                        message: "Internal Server Error".into(),
-                    }),
+                    ErrorResponse::json(error_id, "Internal Server Error".into()),
+                )
+            }
+            ErrorKind::Conflict(message) => (
+                StatusCode::CONFLICT,
+                ErrorResponse::json(error_id(), message),
+            ),
+            ErrorKind::NotFound(message) => (
+                StatusCode::NOT_FOUND,
+                ErrorResponse::json(error_id(), message),
+            ),
+            ErrorKind::BadRequest(message) => (
+                StatusCode::BAD_REQUEST,
+                ErrorResponse::json(error_id(), message),
+            ),
+            ErrorKind::Forbidden(message) => (
+                StatusCode::FORBIDDEN,
+                ErrorResponse::json(error_id(), message),
+            ),
+            ErrorKind::Unprocessable(message) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorResponse::json(error_id(), message),
+            ),
+            ErrorKind::BadGateway(message) => (
+                StatusCode::BAD_GATEWAY,
+                ErrorResponse::json(error_id(), message),
+            ),
+            ErrorKind::Unavailable {
+                message,
+                retry_after,
+            } => {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                    ErrorResponse::json(error_id(), message),
+                )
+                    .into_response();
+            }
+            ErrorKind::MaintenanceWindow {
+                message,
+                retry_after,
+            } => {
+                let Json(mut response) = ErrorResponse::json(error_id(), message);
+                response.code = Some("maintenance_window");
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                    Json(response),
                 )
+                    .into_response();
             }
         }
         .into_response()
     }
 }
 
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            ErrorKind::InternalServerError(err) => write!(f, "{}", err),
+            ErrorKind::Conflict(message)
+            | ErrorKind::NotFound(message)
+            | ErrorKind::BadRequest(message)
+            | ErrorKind::Forbidden(message)
+            | ErrorKind::Unprocessable(message)
+            | ErrorKind::BadGateway(message)
+            | ErrorKind::Unavailable { message, .. }
+            | ErrorKind::MaintenanceWindow { message, .. } => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
 impl<E> From<E> for ErrorKind
 where
     E: Into<anyhow::Error>,
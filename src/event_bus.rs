@@ -0,0 +1,155 @@
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use aws_credential_types::provider::ProvideCredentials;
+use aws_sigv4::{
+    http_request::{sign, SignableBody, SignableRequest, SigningSettings},
+    sign::v4,
+};
+use chrono::Utc;
+use serde_json::{json, Value};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::{config::CONFIG, jobs::Job};
+
+static AWS: OnceCell<aws_config::SdkConfig> = OnceCell::const_new();
+
+async fn aws() -> &'static aws_config::SdkConfig {
+    AWS.get_or_init(aws_config::load_from_env).await
+}
+
+/// Publishes the job's new status as a `job.{status}` event, e.g. `job.succeeded`, to the SNS
+/// topic and EventBridge bus configured. Their consumers subscribe on the AWS side, unlike
+/// webhook subscribers, and missed events aren't retried.
+pub fn job_changed(job: &Job) {
+    if CONFIG.event_bus_topic_arn.is_none() && CONFIG.event_bus_name.is_none() {
+        return;
+    }
+    let kind = format!("job.{}", json!(job.status).as_str().unwrap_or_default());
+    let event = json!({
+        "id": Uuid::new_v4(),
+        "type": kind,
+        "created_at": Utc::now(),
+        "data": {
+            "job_id": job.id,
+            "kind": job.kind,
+            "status": job.status,
+            "client": job.client,
+            "error": job.error,
+        },
+    });
+
+    tokio::spawn(async move {
+        if let Some(topic) = &CONFIG.event_bus_topic_arn {
+            if let Err(err) = publish_sns(topic, &kind, &event).await {
+                tracing::warn!("failed to publish {} to {}: {:#}", kind, topic, err);
+            }
+        }
+        if let Some(bus) = &CONFIG.event_bus_name {
+            if let Err(err) = put_eventbridge(bus, &kind, &event).await {
+                tracing::warn!("failed to put {} on {}: {:#}", kind, bus, err);
+            }
+        }
+    });
+}
+
+// `type` is a message attribute too, for subscription filter policies
+async fn publish_sns(topic: &str, kind: &str, event: &Value) -> Result<()> {
+    let body = form_urlencoded::Serializer::new(String::new())
+        .append_pair("Action", "Publish")
+        .append_pair("Version", "2010-03-31")
+        .append_pair("TopicArn", topic)
+        .append_pair("Message", &event.to_string())
+        .append_pair("MessageAttributes.entry.1.Name", "type")
+        .append_pair("MessageAttributes.entry.1.Value.DataType", "String")
+        .append_pair("MessageAttributes.entry.1.Value.StringValue", kind)
+        .finish();
+
+    send("sns", "application/x-www-form-urlencoded", None, body).await?;
+    Ok(())
+}
+
+async fn put_eventbridge(bus: &str, kind: &str, event: &Value) -> Result<()> {
+    let body = json!({
+        "Entries": [{
+            "EventBusName": bus,
+            "Source": env!("CARGO_PKG_NAME"),
+            "DetailType": kind,
+            "Detail": event.to_string(),
+        }],
+    });
+
+    let response = send(
+        "events",
+        "application/x-amz-json-1.1",
+        Some("AWSEvents.PutEvents"),
+        body.to_string(),
+    )
+    .await?;
+    let output: Value = serde_json::from_str(&response)?;
+    if output["FailedEntryCount"].as_u64().unwrap_or_default() > 0 {
+        anyhow::bail!("rejected: {}", output["Entries"][0]["ErrorMessage"]);
+    }
+
+    Ok(())
+}
+
+// POSTs `body` to the regional endpoint of `service`, signed with SigV4 under the credentials
+// the other AWS clients use
+async fn send(
+    service: &str,
+    content_type: &str,
+    target: Option<&str>,
+    body: String,
+) -> Result<String> {
+    let config = aws().await;
+    let region = config.region().context("no AWS region configured")?;
+    let credentials = config
+        .credentials_provider()
+        .context("no AWS credentials configured")?
+        .provide_credentials()
+        .await?;
+    let identity = credentials.into();
+    let url = format!("https://{}.{}.amazonaws.com/", service, region);
+
+    let mut headers = vec![("content-type", content_type)];
+    if let Some(target) = target {
+        headers.push(("x-amz-target", target));
+    }
+    let params = v4::SigningParams::builder()
+        .identity(&identity)
+        .region(region.as_ref())
+        .name(service)
+        .time(SystemTime::now())
+        .settings(SigningSettings::default())
+        .build()?
+        .into();
+    let request = SignableRequest::new(
+        "POST",
+        &url,
+        headers.iter().copied(),
+        SignableBody::Bytes(body.as_bytes()),
+    )?;
+    let (signature, _) = sign(request, &params)?.into_parts();
+
+    let mut request = reqwest::Client::new().post(&url).body(body.clone());
+    for (name, value) in &headers {
+        request = request.header(*name, *value);
+    }
+    for header in signature.into_parts().0 {
+        request = request.header(header.name(), header.value());
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    let text = response.text().await?;
+    anyhow::ensure!(
+        status.is_success(),
+        "{} answered {}: {}",
+        service,
+        status,
+        text
+    );
+
+    Ok(text)
+}
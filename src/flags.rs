@@ -0,0 +1,132 @@
+use std::{collections::BTreeMap, sync::RwLock, time::Duration};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use redis::AsyncCommands;
+use serde::Serialize;
+
+use crate::{config::CONFIG, lock::redis_connection};
+
+// Flags flipped at runtime, shared by the instances through redis
+const OVERRIDES_KEY: &str = "feature-flags";
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Risky behaviors that can be turned on and off without a redeploy.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Flag {
+    // French corporation page labels mapped to English during extraction, see `dump_core::labels`
+    BilingualLabels,
+    // `--browser chrome-cdp` uses the DevTools backend, plain chromedriver Chrome when off
+    Cdp,
+    // Chrome started without the automation switches sites detect bots by
+    Stealth,
+}
+
+const FLAGS: [Flag; 3] = [Flag::BilingualLabels, Flag::Cdp, Flag::Stealth];
+
+impl Flag {
+    pub fn name(self) -> &'static str {
+        match self {
+            Flag::BilingualLabels => "bilingual_labels",
+            Flag::Cdp => "cdp",
+            Flag::Stealth => "stealth",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Flag> {
+        FLAGS.into_iter().find(|flag| flag.name() == name)
+    }
+
+    fn default(self) -> bool {
+        match self {
+            Flag::BilingualLabels | Flag::Cdp => true,
+            Flag::Stealth => false,
+        }
+    }
+}
+
+// `FEATURE_FLAGS`, then what was flipped at runtime
+static CONFIGURED: Lazy<BTreeMap<Flag, bool>> = Lazy::new(|| {
+    let mut flags: BTreeMap<Flag, bool> = FLAGS.iter().map(|f| (*f, f.default())).collect();
+    for name in &CONFIG.feature_flags {
+        let (name, enabled) = match name.strip_prefix('-') {
+            Some(name) => (name, false),
+            None => (name.as_str(), true),
+        };
+        match Flag::parse(name) {
+            Some(flag) => {
+                flags.insert(flag, enabled);
+            }
+            None => tracing::warn!("ignoring unknown feature flag {}", name),
+        }
+    }
+    flags
+});
+static OVERRIDES: Lazy<RwLock<BTreeMap<Flag, bool>>> = Lazy::new(Default::default);
+
+pub fn enabled(flag: Flag) -> bool {
+    OVERRIDES
+        .read()
+        .unwrap()
+        .get(&flag)
+        .copied()
+        .unwrap_or(CONFIGURED[&flag])
+}
+
+/// Every flag and whether it's on.
+pub fn all() -> BTreeMap<Flag, bool> {
+    FLAGS.iter().map(|flag| (*flag, enabled(*flag))).collect()
+}
+
+/// Flips `flag` on this instance and, with redis, on the others within `REFRESH_INTERVAL`.
+pub async fn set(flag: Flag, enabled: bool) -> Result<()> {
+    OVERRIDES.write().unwrap().insert(flag, enabled);
+    tracing::warn!("feature flag {} turned {}", flag.name(), on_off(enabled));
+
+    if let Some(url) = &CONFIG.redis_url {
+        let mut conn = redis_connection(url).await?;
+        conn.hset::<_, _, _, ()>(OVERRIDES_KEY, flag.name(), enabled)
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+async fn refresh(url: &str) -> Result<()> {
+    let mut conn = redis_connection(url).await?;
+    let shared: BTreeMap<String, bool> = conn.hgetall(OVERRIDES_KEY).await?;
+    let shared = shared
+        .into_iter()
+        .filter_map(|(name, enabled)| Some((Flag::parse(&name)?, enabled)))
+        .collect();
+    *OVERRIDES.write().unwrap() = shared;
+
+    Ok(())
+}
+
+/// Reads `FEATURE_FLAGS`, reporting unknown ones, and keeps the flags flipped on other
+/// instances in sync when redis is configured.
+pub fn init() {
+    Lazy::force(&CONFIGURED);
+    let Some(url) = CONFIG.redis_url.clone() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = refresh(&url).await {
+                tracing::warn!("failed to refresh the feature flags: {}", err);
+            }
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+}
@@ -0,0 +1,364 @@
+use std::{collections::HashMap, fmt, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tokio::time::{sleep, Instant};
+use tracing::Instrument;
+use zeroize::Zeroizing;
+
+use crate::{
+    browser,
+    config::{PaymentMode, CONFIG},
+    metrics::{self, Checkpoint},
+    products,
+    session::Driver,
+    txlog::{self, Action},
+};
+
+// How long a step waits for its element
+const STEP_TIMEOUT: Duration = Duration::from_secs(20);
+
+// XPaths steps refer to by key, so a registry redesign is fixed in one place
+pub const SELECTORS: [(&str, &str); 11] = [
+    (
+        "search_products",
+        "//span[contains(text(), 'Request Search Products')]",
+    ),
+    (
+        "from_ministry",
+        "//label[contains(text(), 'from the Ministry')]",
+    ),
+    ("continue", "//span[contains(text(), 'Continue')]"),
+    ("credit_card", "//option[contains(text(), 'Credit Card')]"),
+    (
+        "payment_method_next",
+        "(//div[@class='appBoxChildren appBlockChildren'])[last()]/button[1]",
+    ),
+    ("payment_method_submit", "//button[@id='submit_btn']"),
+    ("card_owner", "//input[@name='trnCardOwner']"),
+    ("card_number", "//input[@name='trnCardNumber']"),
+    ("card_month", "//input[@id='trnExpMonth']"),
+    ("card_year", "//input[@id='trnExpYear']"),
+    ("card_cvv", "//input[@name='trnCardCvd']"),
+];
+
+const PAY_BUTTON: &str = "//button[@id='submitButton']";
+
+/// The page card details were about to be typed into isn't served from one of `PAYMENT_HOSTS`.
+#[derive(Debug)]
+pub struct UnexpectedPaymentHost {
+    pub url: String,
+}
+
+impl fmt::Display for UnexpectedPaymentHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the payment page {} isn't on an allowed payment host",
+            self.url
+        )
+    }
+}
+
+impl std::error::Error for UnexpectedPaymentHost {}
+
+/// Whether `url` is served from one of `PAYMENT_HOSTS`, whose pages take card details.
+pub fn is_payment_host(url: &str) -> bool {
+    let Some(host) = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+    else {
+        return false;
+    };
+
+    CONFIG.payment_hosts.iter().any(|allowed| {
+        let allowed = allowed.trim().to_lowercase();
+        host == allowed || host.ends_with(&format!(".{}", allowed))
+    })
+}
+
+// Checked before every step handing over card details, a redirect to another host must never
+// receive them
+async fn ensure_payment_host(driver: &dyn Driver) -> Result<()> {
+    let url = driver.current_url().await?;
+    if !is_payment_host(&url) {
+        return Err(UnexpectedPaymentHost { url }.into());
+    }
+
+    Ok(())
+}
+
+/// One step of a flow. `target`s are either a key of `SELECTORS` or an XPath, and like `value`s
+/// and `url`s may hold `{name}` placeholders filled in from the flow's `Vars`. In XPaths a
+/// placeholder stands for a whole string literal, quotes included, e.g. `contains(text(), {name})`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum Step {
+    Goto { url: String },
+    Click { target: String },
+    Fill { target: String, value: String },
+    FillEmail { form: String },
+    // clicks a submit button and fails on the validation messages the form shows
+    Submit { target: String, form: String },
+    // fails unless the element shows up
+    WaitFor { target: String },
+    Sleep { secs: u64 },
+    Screenshot { label: String },
+    // the option steps of the product in `{product}`, see `products`
+    ProductSteps,
+    // submits the card payment, or only takes a screenshot in test payment mode. Like fills of
+    // `{card_*}` values, it fails with `UnexpectedPaymentHost` off the `PAYMENT_HOSTS`
+    Pay,
+    // records the time since the flow started or reached the previous lap under `checkpoint`
+    Lap { checkpoint: Checkpoint },
+}
+
+fn click(target: &str) -> Step {
+    Step::Click {
+        target: target.to_string(),
+    }
+}
+
+fn fill(target: &str, value: &str) -> Step {
+    Step::Fill {
+        target: target.to_string(),
+        value: value.to_string(),
+    }
+}
+
+fn submit(target: &str, form: &str) -> Step {
+    Step::Submit {
+        target: target.to_string(),
+        form: form.to_string(),
+    }
+}
+
+/// From the company in the search results to the submitted card payment.
+pub fn payment() -> Vec<Step> {
+    vec![
+        click(&format!(
+            "//span[contains({}, {{company}})]",
+            browser::FOLDED_TEXT
+        )),
+        click("search_products"),
+        click("from_ministry"),
+        Step::Lap {
+            checkpoint: Checkpoint::ProductPage,
+        },
+        click(&format!(
+            "//label[contains({}, {{product}})]",
+            browser::FOLDED_TEXT
+        )),
+        submit("continue", "product selection"),
+        Step::ProductSteps,
+        click("credit_card"),
+        Step::Sleep { secs: 5 },
+        click("payment_method_next"),
+        submit("payment_method_submit", "payment method"),
+        Step::Sleep { secs: 5 },
+        Step::WaitFor {
+            target: "card_owner".to_string(),
+        },
+        Step::Lap {
+            checkpoint: Checkpoint::PaymentForm,
+        },
+        // one after the other, the payment form's scripts react to each field as it's filled
+        fill("card_owner", "{card_name}"),
+        fill("card_number", "{card_number}"),
+        fill("card_month", "{card_month}"),
+        fill("card_year", "{card_year}"),
+        fill("card_cvv", "{card_cvv}"),
+        Step::Pay,
+    ]
+}
+
+/// Values of a flow's placeholders. Card details among them, so they're never logged and, like
+/// the templates filled in with them, wiped when dropped.
+#[derive(Default)]
+pub struct Vars(HashMap<&'static str, Zeroizing<String>>);
+
+impl Vars {
+    pub fn set(&mut self, name: &'static str, value: &str) -> &mut Self {
+        self.0.insert(name, Zeroizing::new(value.to_string()));
+        self
+    }
+
+    fn get(&self, name: &str) -> Result<&str> {
+        self.0
+            .get(name)
+            .map(|value| value.as_str())
+            .with_context(|| format!("the flow needs {{{}}}", name))
+    }
+
+    fn fill_in(&self, template: &str, quoted: bool) -> Zeroizing<String> {
+        // reserved for what's filled in, quoting included, as a reallocation would leave a copy
+        // of the values behind
+        let mut len = 0;
+        self.pieces(template, quoted, |piece| len += piece.len());
+        let mut filled = Zeroizing::new(String::with_capacity(len));
+        self.pieces(template, quoted, |piece| filled.push_str(piece));
+
+        filled
+    }
+
+    // The template's text and the values of its placeholders in order, in one pass, so values
+    // that look like placeholders are left as they are
+    fn pieces(&self, template: &str, quoted: bool, mut piece: impl FnMut(&str)) {
+        let mut rest = template;
+        while let Some((before, after)) = rest.split_once('{') {
+            piece(before);
+            let known = after
+                .split_once('}')
+                .and_then(|(name, after)| Some((self.0.get(name)?, after)));
+            match known {
+                Some((val, after)) if quoted => {
+                    let folded = Zeroizing::new(browser::fold_quotes(val));
+                    piece(&Zeroizing::new(browser::literal(&folded)));
+                    rest = after;
+                }
+                Some((val, after)) => {
+                    piece(val);
+                    rest = after;
+                }
+                None => {
+                    piece("{");
+                    rest = after;
+                }
+            }
+        }
+        piece(rest);
+    }
+
+    fn render(&self, template: &str) -> Zeroizing<String> {
+        self.fill_in(template, false)
+    }
+
+    fn uses_card(template: &str) -> bool {
+        template.contains("{card_")
+    }
+
+    // Values are quoted, so they can't end the literal and change what the selector matches, and
+    // their quotes are straightened to compare with `browser::FOLDED_TEXT`
+    fn xpath(&self, target: &str) -> Zeroizing<String> {
+        let xpath = SELECTORS
+            .iter()
+            .find(|(key, _)| *key == target)
+            .map_or(target, |(_, xpath)| xpath);
+        self.fill_in(xpath, true)
+    }
+}
+
+impl Step {
+    // What the step did, for errors and the transaction log. Never holds values, which may be
+    // card details.
+    fn describe(&self) -> String {
+        match self {
+            Step::Goto { url } => format!("goto {}", url),
+            Step::Click { target } => format!("click {}", target),
+            Step::Fill { target, .. } => format!("fill {}", target),
+            Step::FillEmail { form } => format!("fill email in {}", form),
+            Step::Submit { form, .. } => format!("submit {}", form),
+            Step::WaitFor { target } => format!("wait for {}", target),
+            Step::Sleep { secs } => format!("sleep {}s", secs),
+            Step::Screenshot { label } => format!("screenshot {}", label),
+            Step::ProductSteps => "product steps".to_string(),
+            Step::Pay => "pay".to_string(),
+            Step::Lap { checkpoint } => format!("lap {}", checkpoint.name()),
+        }
+    }
+}
+
+async fn run_product_steps(driver: &dyn Driver, vars: &Vars) -> Result<()> {
+    for step in products::steps(vars.get("product")?).unwrap_or_default() {
+        match step {
+            products::Step::Click { xpath } => browser::click(driver, xpath, STEP_TIMEOUT).await?,
+            products::Step::Submit { xpath } => {
+                browser::submit(driver, xpath, STEP_TIMEOUT, "order details").await?
+            }
+            products::Step::Wait { secs } => sleep(Duration::from_secs(*secs)).await,
+            products::Step::FillEmail { form } => {
+                browser::fill_email(driver, form, vars.get("email")?).await?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_step(driver: &dyn Driver, step: &Step, vars: &Vars) -> Result<()> {
+    match step {
+        Step::Goto { url } => {
+            driver.goto(&vars.render(url)).await?;
+            txlog::record(driver, Action::Navigate, url).await;
+        }
+        Step::Click { target } => browser::click(driver, &vars.xpath(target), STEP_TIMEOUT).await?,
+        Step::Fill { target, value } => {
+            if Vars::uses_card(value) {
+                ensure_payment_host(driver).await?;
+            }
+            browser::send_keys(
+                driver,
+                &vars.xpath(target),
+                STEP_TIMEOUT,
+                vars.render(value),
+            )
+            .await?
+        }
+        Step::FillEmail { form } => {
+            browser::fill_email(driver, &vars.xpath(form), vars.get("email")?).await?
+        }
+        Step::Submit { target, form } => {
+            browser::submit(driver, &vars.xpath(target), STEP_TIMEOUT, form).await?
+        }
+        Step::WaitFor { target } => {
+            let xpath = vars.xpath(target);
+            anyhow::ensure!(
+                browser::exists(driver, &xpath, STEP_TIMEOUT).await?,
+                "{} didn't show up",
+                xpath.as_str()
+            );
+        }
+        Step::Sleep { secs } => sleep(Duration::from_secs(*secs)).await,
+        Step::Screenshot { label } => {
+            txlog::checkpoint(driver, Action::Screenshot, &vars.render(label)).await
+        }
+        Step::ProductSteps => run_product_steps(driver, vars).await?,
+        Step::Pay if CONFIG.payment_mode == PaymentMode::Test => {
+            txlog::checkpoint(driver, Action::Screenshot, "card payment, not submitted").await
+        }
+        Step::Pay => {
+            ensure_payment_host(driver).await?;
+            browser::submit_unchecked(driver, PAY_BUTTON, STEP_TIMEOUT, "card payment").await?
+        }
+        // timed by `run`, which knows when the previous lap was
+        Step::Lap { .. } => {}
+    }
+
+    Ok(())
+}
+
+/// Runs `steps` in order, each in its own `step` span.
+/// `Lap`s record their time into the checkpoint histograms, see `metrics::checkpoints`.
+/// Element commands retry their transient failures (see `browser::with_element`) within the step
+/// timeout; a step that still fails stops the flow with a screenshot of the page it failed on, and
+/// the error names the step. Errors keep their cause, so form rejections can still be told apart by
+/// downcasting.
+pub async fn run(driver: &dyn Driver, steps: &[Step], vars: &Vars) -> Result<()> {
+    let mut lap = Instant::now();
+    for (i, step) in steps.iter().enumerate() {
+        if let Step::Lap { checkpoint } = step {
+            metrics::record_checkpoint(*checkpoint, lap.elapsed()).await;
+            lap = Instant::now();
+            continue;
+        }
+        let described = format!("step {} ({})", i + 1, step.describe());
+        let span = tracing::debug_span!("step", n = i + 1, step = %step.describe());
+        if let Err(err) = run_step(driver, step, vars).instrument(span).await {
+            txlog::checkpoint(driver, Action::Screenshot, &format!("{} failed", described)).await;
+            return Err(err.context(format!("{} failed", described)));
+        }
+        txlog::step(driver, &described).await;
+    }
+
+    Ok(())
+}
@@ -1,87 +1,148 @@
-use std::{collections::HashMap, fmt::Debug, hash::Hash, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Debug,
+    hash::Hash,
+    pin::pin,
+    time::Duration,
+};
 
 use anyhow::Result;
-use axum::{extract::Path, http::StatusCode, Json};
-use futures::future::join_all;
-use itertools::Itertools;
-use reqwest::{Client, Url};
-use scraper::{Html, Selector};
+use axum::{
+    body::Body,
+    extract::{Multipart, Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use dump_core::{
+    board::Board,
+    enrich::enrich,
+    extract,
+    labels::{self, Lang},
+    models::{CorporationData, CorporationSection},
+    normalize,
+    patterns::*,
+    search,
+};
+use futures::{FutureExt, Stream, TryStreamExt};
+use reqwest::Client;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use thirtyfour::{cookie::SameSite, prelude::*};
-use tokio::time::sleep;
-
-use crate::{config::CONFIG, errors::AppError};
+use thirtyfour::prelude::*;
+use tokio::{io::AsyncWriteExt, time::sleep};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::{
+    artifacts::{self, artifacts},
+    auth::Principal,
+    browser::{self, FormError, FormValidationError},
+    capacity::{self, Capacity},
+    cards,
+    catalog::{self, RouteInfo},
+    chromedriver, clients, compat,
+    config::{IgnoredFilters, Mode, PaymentMode, CONFIG},
+    cookies,
+    corporations::{self, Change, DirectorMatch, History},
+    dead_letter::{self, DeadLetter},
+    errors::{AppError, ErrorKind},
+    flags::{self, Flag},
+    flow,
+    jobs::{Job, JobKind, JobStatus, RunWindow},
+    lock::{self, Lock},
+    login, maintenance,
+    metrics::{self, Checkpoint, HeldRows, Histogram, MemoryUsage},
+    name_check::{self, NameCheck},
+    notify, off_peak, open_data, politeness, products, queue, report,
+    response::{self, Envelope, Source, Warning},
+    retention::Erasure,
+    retry, robots, search_cache,
+    session::{self, Driver},
+    slo::{self, Slo},
+    support_bundle,
+    txlog::{self, Action, TraceLevel, TransactionLog},
+    verify,
+    version::{self, BuildInfo},
+    webhooks::{
+        self, Delivery, DeliveryAttempt, Event, EventKind, OutboxEntry, OutboxStatus, RetryPolicy,
+        Subscriber,
+    },
+};
 
 pub async fn health_check() -> (StatusCode, String) {
-    let health = true;
-    match health {
-        true => (StatusCode::OK, "Healthy!".to_string()),
-        false => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Not healthy!".to_string(),
-        ),
+    if chromedriver::in_use() {
+        if let Err(err) = chromedriver::check().await {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Not healthy! {:#}", err),
+            );
+        }
     }
+
+    (StatusCode::OK, "Healthy!".to_string())
 }
 
-async fn get_chrome_driver() -> Result<WebDriver, WebDriverError> {
-    let mut caps = DesiredCapabilities::chrome();
-    caps.set_ignore_certificate_errors()?;
-    caps.add_chrome_arg("--disable-dev-tools")?;
-    caps.add_chrome_arg("--user-data-dir=/tmp/user-data")?;
-    #[cfg(any(feature = "lambda", feature = "ecs", feature = "headless"))]
-    {
-        caps.set_disable_dev_shm_usage()?;
-        caps.set_disable_gpu()?;
-        caps.set_disable_web_security()?;
-        caps.set_headless()?;
-        caps.set_no_sandbox()?;
-        caps.add_chrome_arg("--no-zygote")?;
-        caps.add_chrome_arg("--single-process")?;
-    }
-    let driver = WebDriver::new("http://localhost:9515", caps).await;
-    driver
+/// Readiness probe: 503 until the `--verify` checks of the providers passed.
+pub async fn readiness_get() -> Response {
+    let readiness = verify::readiness();
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(readiness)).into_response()
 }
 
 pub async fn test_handler() -> ApiResponse<Value> {
-    tryhard::retry_fn(|| async {
-        let driver = get_chrome_driver().await?;
+    retry::retry(|| async {
+        let driver = session::start().await?;
         driver.goto("https://example.com").await?;
         let title = driver.title().await?;
         driver.quit().await?;
 
-        Ok((StatusCode::OK, Json(json!({ "title": title }))))
+        Ok(Envelope::new(StatusCode::OK, json!({ "title": title })))
     })
-    .retries(10)
-    .max_delay(Duration::from_secs(10))
-    .exponential_backoff(Duration::from_secs(1))
     .await
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct RequestBusinessProfileReportParams {
     pub search_business_params: SearchBusinessRegistryParams,
     pub selected_company: String,
     pub search_product: String,
     #[serde(default = "default_email")]
     pub email: String,
+    // email a summary to `email` once the purchase completes or fails
+    #[serde(default)]
+    pub notify: bool,
+    // `steps` adds every flow step with a thumbnail to the job log
+    #[serde(default)]
+    pub trace_level: TraceLevel,
 }
 
 fn default_email() -> String {
     CONFIG.default_email.clone()
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, JsonSchema)]
 pub enum StatusKey {
     Active,
     Inactive,
     #[serde(rename(serialize = "-- All Statuses --"))]
+    // jobs store the serialized form and workers read it back
+    #[serde(alias = "-- All Statuses --")]
+    #[schemars(rename = "All")]
     All,
 }
 
-#[derive(Deserialize, Serialize, Debug, Hash, Eq, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Hash, Eq, PartialEq, JsonSchema)]
 pub enum RegisterType {
     #[serde(rename(serialize = "-- All Registers --"))]
+    #[serde(alias = "-- All Registers --")]
+    #[schemars(rename = "All")]
     All,
     Corporations,
     #[serde(rename = "Business Names")]
@@ -89,7 +150,7 @@ pub enum RegisterType {
     Partnerships,
 }
 
-#[derive(Deserialize, Serialize, Eq, PartialEq)]
+#[derive(Deserialize, Serialize, Eq, PartialEq, JsonSchema)]
 pub enum SearchOperator {
     On,
     Before,
@@ -98,16 +159,14 @@ pub enum SearchOperator {
     Between,
 }
 
-#[derive(Deserialize, Default, Clone, Debug)]
+#[derive(Deserialize, Serialize, Default, Clone, Debug, JsonSchema)]
 #[serde(try_from = "String")]
 pub struct DateInput(String);
 impl TryFrom<String> for DateInput {
     type Error = String;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        // Month Day, Year
-        let re = regex::Regex::new(r"^([A-Z][a-z]+) (\d{1,2}), (\d{4})$").unwrap();
-        if re.is_match(&value) {
+        if DATE_INPUT.is_match(&value) {
             Ok(DateInput(value))
         } else {
             Err("Invalid date format, must be 'Month Day, Year' e.g. 'January 1, 2021'".to_string())
@@ -120,7 +179,7 @@ impl AsRef<str> for DateInput {
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, JsonSchema)]
 #[serde(try_from = "SearchBusinessRegistryParamsShadow")]
 pub struct SearchBusinessRegistryParams {
     pub query_word: String,
@@ -143,39 +202,45 @@ pub struct SearchBusinessRegistryParamsShadow {
     pub end_date: Option<DateInput>,
 }
 
+// Business types the advanced search offers for each register
+fn business_types() -> HashMap<RegisterType, Vec<&'static str>> {
+    let mut register_to_business_type_map: HashMap<RegisterType, Vec<&str>> = HashMap::new();
+    register_to_business_type_map.insert(
+        RegisterType::Corporations,
+        vec![
+            "-- Any type --",
+            // redacted
+        ],
+    );
+
+    register_to_business_type_map.insert(
+        RegisterType::BusinessNames,
+        vec![
+            "-- Any type --",
+            // redacted
+        ],
+    );
+
+    register_to_business_type_map.insert(
+        RegisterType::Partnerships,
+        vec![
+            "-- Any type --",
+            // redacted
+        ],
+    );
+
+    register_to_business_type_map
+}
+
 impl TryFrom<SearchBusinessRegistryParamsShadow> for SearchBusinessRegistryParams {
     type Error = String;
 
     fn try_from(value: SearchBusinessRegistryParamsShadow) -> Result<Self, Self::Error> {
-        let mut register_to_business_type_map: HashMap<RegisterType, Vec<&str>> = HashMap::new();
-        register_to_business_type_map.insert(
-            RegisterType::Corporations,
-            vec![
-                "-- Any type --",
-                // redacted
-            ],
-        );
-
-        register_to_business_type_map.insert(
-            RegisterType::BusinessNames,
-            vec![
-                "-- Any type --",
-                // redacted
-            ],
-        );
-
-        register_to_business_type_map.insert(
-            RegisterType::Partnerships,
-            vec![
-                "-- Any type --",
-                // redacted
-            ],
-        );
-
+        let register_to_business_type_map = business_types();
         match value.register_type_key {
             Some(RegisterType::All) | None => {}
             Some(ref register_type) => {
-                if let Some(business_types) = register_to_business_type_map.get(&register_type) {
+                if let Some(business_types) = register_to_business_type_map.get(register_type) {
                     if let Some(business_type) = value.business_type_selection.as_deref() {
                         if business_type != "-- Any type --"
                             && !business_types.contains(&business_type)
@@ -203,211 +268,41 @@ impl TryFrom<SearchBusinessRegistryParamsShadow> for SearchBusinessRegistryParam
     }
 }
 
-async fn goto_payment_page(
-    driver: &WebDriver,
-    param: &RequestBusinessProfileReportParams,
-) -> WebDriverResult<()> {
-    let RequestBusinessProfileReportParams {
-        selected_company,
-        search_product,
-        email,
-        ..
-    } = param;
-    let search_element = driver
-        .query(By::XPath(&format!(
-            "//span[contains(text(), '{}')]",
-            selected_company
-        )))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    search_element.click().await?;
-
-    // page3
-    let search_element = driver
-        .query(By::XPath(
-            "//span[contains(text(), 'Request Search Products')]",
-        ))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    search_element.click().await?;
-
-    // page4
-    // from here profile report is getting started
-    let radio_button = driver
-        .query(By::XPath("//label[contains(text(), 'from the Ministry')]"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    radio_button.click().await?;
-
-    let radio_button = driver
-        .query(By::XPath(&format!(
-            "//label[contains(text(), '{}')]",
-            search_product
-        )))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    radio_button.click().await?;
-
-    let search_element = driver
-        .query(By::XPath("//span[contains(text(), 'Continue')]"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    search_element.click().await?;
-
-    // page5
-    // option1
-    if search_product == "Profile Report" {
-        let radio_button = driver
-            .query(By::XPath("//label[contains(text(), 'Current Report')]"))
-            .wait(Duration::from_secs(20), Duration::from_secs(1))
-            .first()
-            .await?;
-        radio_button.click().await?;
-        // sleep 5 seconds
-        sleep(Duration::from_secs(5)).await;
-
-        let email_inputs = driver
-            .query(By::XPath("//input[@type='email']"))
-            .wait(Duration::from_secs(10), Duration::from_secs(1))
-            .all()
-            .await?;
-        for email_input in email_inputs {
-            email_input.send_keys(email).await?;
-        }
-
-        let submit_element = driver
-            .query(By::XPath("//span[contains(text(), 'Submit')]"))
-            .wait(Duration::from_secs(20), Duration::from_secs(1))
-            .first()
-            .await?;
-        submit_element.click().await?;
-    }
-    // option2
-    if search_product == "Document Copies" {
-        let check_box = driver
-            .query(By::XPath(
-                "//label[contains(text(), 'Select all Documents')]",
-            ))
-            .wait(Duration::from_secs(20), Duration::from_secs(1))
-            .first()
-            .await?;
-        check_box.click().await?;
-        // sleep 5 seconds
-        sleep(Duration::from_secs(5)).await;
-
-        let email_inputs = driver
-            .query(By::XPath("//input[@type='email']"))
-            .wait(Duration::from_secs(10), Duration::from_secs(1))
-            .all()
-            .await?;
-        for email_input in email_inputs {
-            email_input.send_keys(email).await?;
+impl SearchBusinessRegistryParams {
+    // The registry only filters by business type within one register
+    fn ignored_business_type(&self) -> Option<&str> {
+        match self.register_type_key {
+            Some(RegisterType::All) | None => self
+                .business_type_selection
+                .as_deref()
+                .filter(|business_type| *business_type != "-- Any type --"),
+            Some(_) => None,
         }
-
-        let submit_element = driver
-            .query(By::XPath("//span[contains(text(), 'Request Documents')]"))
-            .wait(Duration::from_secs(20), Duration::from_secs(1))
-            .first()
-            .await?;
-        submit_element.click().await?;
-    }
-    // option 3
-    if search_product == "Certificate of Status" {
-        println!("Certificate of Status is excuted");
-        let email_inputs = driver
-            .query(By::XPath("//input[@type='email']"))
-            .wait(Duration::from_secs(10), Duration::from_secs(1))
-            .all()
-            .await?;
-        for email_input in email_inputs {
-            email_input.send_keys(email).await?;
-        }
-
-        let submit_element = driver
-            .query(By::XPath("//span[contains(text(), 'Submit')]"))
-            .wait(Duration::from_secs(20), Duration::from_secs(1))
-            .first()
-            .await?;
-        submit_element.click().await?;
     }
-    // page6
-    let credit_dropdown = driver
-        .query(By::XPath("//option[contains(text(), 'Credit Card')]"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    credit_dropdown.click().await?;
-
-    // sleep 5 seconds
-    sleep(Duration::from_secs(5)).await;
-
-    let submit_element = driver
-        .query(By::XPath(
-            "(//div[@class='appBoxChildren appBlockChildren'])[last()]/button[1]",
-        ))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    submit_element.click().await?;
-    // page7
-    let make_payment = driver
-        .query(By::XPath("//button[@id='submit_btn']"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-
-    make_payment.click().await?;
-    sleep(Duration::from_secs(5)).await;
-
-    let trn_card_owner = driver
-        .query(By::XPath("//input[@name='trnCardOwner']"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    trn_card_owner.send_keys(&CONFIG.card_name).await?;
-    let trn_card_number = driver
-        .query(By::XPath("//input[@name='trnCardNumber']"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    trn_card_number.send_keys(&CONFIG.card_number).await?;
-    let trn_exp_month = driver
-        .query(By::XPath("//input[@id='trnExpMonth']"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    trn_exp_month.send_keys(&CONFIG.card_month).await?;
-    let trn_exp_year = driver
-        .query(By::XPath("//input[@id='trnExpYear']"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    trn_exp_year.send_keys(&CONFIG.card_year).await?;
-    let trn_card_cvd = driver
-        .query(By::XPath("//input[@name='trnCardCvd']"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    trn_card_cvd.send_keys(&CONFIG.card_cvv).await?;
-    let submit_payment = driver
-        .query(By::XPath("//button[@id='submitButton']"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    submit_payment.click().await?;
+}
 
-    Ok(())
+async fn goto_payment_page(
+    driver: &dyn Driver,
+    param: &RequestBusinessProfileReportParams,
+) -> Result<()> {
+    let card = cards::card()?;
+    let mut vars = flow::Vars::default();
+    vars.set("company", &param.selected_company)
+        .set("product", &param.search_product)
+        .set("email", &param.email)
+        .set("card_name", card.name.expose())
+        .set("card_number", card.number.expose())
+        .set("card_month", card.month.expose())
+        .set("card_year", card.year.expose())
+        .set("card_cvv", card.cvv.expose());
+
+    flow::run(driver, &flow::payment(), &vars).await
 }
 
 async fn goto_search_result_page(
-    driver: &WebDriver,
+    driver: &dyn Driver,
     params: &SearchBusinessRegistryParams,
-) -> WebDriverResult<Option<Url>> {
+) -> WebDriverResult<Option<String>> {
     let SearchBusinessRegistryParams {
         query_word,
         register_type_key,
@@ -434,141 +329,138 @@ async fn goto_search_result_page(
     //     .await?;
     // search_element.click().await?;
 
-    driver.goto("redacted").await?;
-    let mut headers: HashMap<&str, &str> = HashMap::new();
-    headers.insert("x-catalyst-timezone", "America/Toronto");
-
-    for (key, value) in headers {
-        let mut cookie = Cookie::new(key, value);
-        cookie.set_domain("redacted");
-        cookie.set_path("/");
-        cookie.set_same_site(Some(SameSite::Lax));
-        driver.add_cookie(cookie).await?;
-    }
+    driver.goto(REGISTRY_SEARCH_URL).await?;
+    txlog::record(driver, Action::Navigate, "registry search").await;
+    cookies::apply(driver, cookies::REGISTRY).await?;
 
-    println!("Current URL: {}", driver.current_url().await?.to_string());
+    println!("Current URL: {}", driver.current_url().await?);
 
     // page2
-    let searchquery_element = driver
-        .query(By::XPath("//input[@name='QueryString']"))
-        .wait(Duration::from_secs(160), Duration::from_secs(1))
-        .first()
-        .await?;
-    searchquery_element.send_keys(query_word).await?;
+    browser::send_keys(
+        driver,
+        "//input[@name='QueryString']",
+        Duration::from_secs(160),
+        query_word,
+    )
+    .await?;
 
-    let advanced_button = driver
-        .query(By::XPath("//a[@aria-label=' Advanced']"))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    advanced_button.click().await?;
+    browser::click(
+        driver,
+        "//a[@aria-label=' Advanced']",
+        Duration::from_secs(20),
+    )
+    .await?;
 
-    let register_select = driver
-        .query(By::XPath(&format!(
+    browser::click(
+        driver,
+        &format!(
             "//option[contains(text(), '{}')]",
             serde_json::to_string(&register_type_key)
                 .unwrap()
                 .trim_matches('"')
-        )))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    register_select.click().await?;
+        ),
+        Duration::from_secs(20),
+    )
+    .await?;
     sleep(Duration::from_secs(2)).await;
 
     if let Some(business_type_selection) = business_type_selection {
-        let business_type_select = driver
-            .query(By::XPath(&format!(
-                "//option[contains(text(), '{}')]",
-                business_type_selection
-            )))
-            .wait(Duration::from_secs(20), Duration::from_secs(1))
-            .first()
-            .await?;
-        business_type_select.click().await?;
+        browser::click(
+            driver,
+            &format!(
+                "//option[{}]",
+                browser::contains_text(business_type_selection)
+            ),
+            Duration::from_secs(20),
+        )
+        .await?;
     }
 
     if let Some(status_key) = status_key {
-        let status_select = driver
-            .query(By::XPath(&format!(
+        browser::click(
+            driver,
+            &format!(
                 "//option[contains(text(), '{}')]",
                 serde_json::to_string(&status_key)
                     .unwrap()
                     .trim_matches('"')
-            )))
-            .wait(Duration::from_secs(20), Duration::from_secs(1))
-            .first()
-            .await?;
-        status_select.click().await?;
+            ),
+            Duration::from_secs(20),
+        )
+        .await?;
     }
 
     if let Some(date_input) = date_input {
-        let registered_date_field = driver
-            .query(By::XPath("//input[@name='RegistrationDate']"))
-            .wait(Duration::from_secs(20), Duration::from_secs(1))
-            .first()
-            .await?;
-        registered_date_field.send_keys(date_input).await?;
+        browser::send_keys(
+            driver,
+            "//input[@name='RegistrationDate']",
+            Duration::from_secs(20),
+            date_input,
+        )
+        .await?;
     }
 
     if let Some(search_operator) = search_operator {
-        let search_operator_select = driver
-            .query(By::XPath(&format!(
+        browser::click(
+            driver,
+            &format!(
                 "//option[contains(text(), '{}')]",
                 serde_json::to_string(&search_operator)
                     .unwrap()
                     .trim_matches('"')
-            )))
-            .wait(Duration::from_secs(20), Duration::from_secs(1))
-            .first()
-            .await?;
-        search_operator_select.click().await?;
+            ),
+            Duration::from_secs(20),
+        )
+        .await?;
     }
 
     if let Some(SearchOperator::Between) = search_operator {
         sleep(Duration::from_secs(2)).await;
-        let end_date_input = driver
-            .query(By::XPath("//input[@name='RegistrationDate2']"))
-            .wait(Duration::from_secs(20), Duration::from_secs(1))
-            .first()
-            .await?;
-        end_date_input
-            .send_keys(end_date.clone().unwrap_or_default())
-            .await?;
-        end_date_input.send_keys("" + Key::Enter).await?;
+        let end_date = end_date.as_ref().map(AsRef::as_ref).unwrap_or_default();
+        browser::send_keys(
+            driver,
+            "//input[@name='RegistrationDate2']",
+            Duration::from_secs(20),
+            end_date.to_string() + Key::Enter,
+        )
+        .await?;
     }
 
-    let searchbutton_element = driver
-        .query(By::XPath(
-            "//div[@class='appBox appBlock registerItemSearch-tabs-criteriaAndButtons-buttonPad \
-             appButtonPad appSearchButtonPad appNotReadOnly appIndex1 appChildCount3']/div/button",
-        ))
-        .wait(Duration::from_secs(20), Duration::from_secs(1))
-        .first()
-        .await?;
-    searchbutton_element.click().await?;
+    let submitted = tokio::time::Instant::now();
+    browser::click(
+        driver,
+        "//div[@class='appBox appBlock registerItemSearch-tabs-criteriaAndButtons-buttonPad \
+         appButtonPad appSearchButtonPad appNotReadOnly appIndex1 appChildCount3']/div/button",
+        Duration::from_secs(20),
+    )
+    .await?;
 
     sleep(Duration::from_secs(5)).await;
 
     // check if #appSearchNoResults exists
-    if let Ok(_) = driver
-        .query(By::XPath("//div[@id='appSearchNoResults']"))
-        .wait(Duration::from_secs(5), Duration::from_secs(1))
-        .first()
-        .await
+    if browser::exists(
+        driver,
+        "//div[@id='appSearchNoResults']",
+        Duration::from_secs(5),
+    )
+    .await
+    .unwrap_or(false)
     {
         println!("No results found");
         return Ok(None);
     }
 
-    let page_size_selector = driver
-        .query(By::XPath(&format!(
+    browser::click(
+        driver,
+        &format!(
             "//div[@class='appSearchPageSize']/select/option[contains(text(), '{}')]",
             200
-        )))
-        .first()
-        .await?;
-    page_size_selector.click().await?;
+        ),
+        Duration::from_secs(20),
+    )
+    .await?;
+    // the page size only shows with the results
+    metrics::record_checkpoint(Checkpoint::SearchResults, submitted.elapsed()).await;
     sleep(Duration::from_secs(15)).await;
 
     let current_url = driver.current_url().await?;
@@ -577,118 +469,922 @@ async fn goto_search_result_page(
 }
 
 pub async fn get_payment_page_handler(
+    Extension(principal): Extension<Principal>,
     Json(params): Json<RequestBusinessProfileReportParams>,
 ) -> ApiResponse<Value> {
-    tryhard::retry_fn(|| async {
-        let driver = get_chrome_driver().await?;
+    if products::steps(&params.search_product).is_none() {
+        return Err(ErrorKind::BadRequest(format!(
+            "Unknown product '{}', must be one of {}",
+            params.search_product,
+            products::names().join(", ")
+        ))
+        .into());
+    }
+    if !notify::allowed(&params.email) {
+        return Err(
+            ErrorKind::Forbidden(format!("Reports can't be sent to {}", params.email)).into(),
+        );
+    }
+    if CONFIG.account_products.contains(&params.search_product) && !login::configured() {
+        return Err(ErrorKind::Unprocessable(format!(
+            "'{}' is only sold to registry accounts and none is set up",
+            params.search_product
+        ))
+        .into());
+    }
 
-        if goto_search_result_page(&driver, &params.search_business_params)
+    let notify = params.notify.then(|| params.email.clone());
+    run_job(
+        JobKind::PaymentPage,
+        &params,
+        notify,
+        &principal.subject,
+        RunWindow::Now,
+    )
+    .await
+}
+
+async fn payment_page_flow(params: &RequestBusinessProfileReportParams) -> ApiResponse<Value> {
+    retry::retry(|| async {
+        let driver = session::start().await?;
+
+        login::sign_in(&*driver).await.map_err(form_failure)?;
+        if goto_search_result_page(&*driver, &params.search_business_params)
             .await?
             .is_none()
         {
-            return Ok((
+            return Ok(Envelope::new(
                 StatusCode::NOT_FOUND,
-                Json(json!({ "error": "No results found" })),
+                json!({ "error": "No results found" }),
             ));
         }
-        goto_payment_page(&driver, &params).await?;
+        goto_payment_page(&*driver, params)
+            .await
+            .map_err(form_failure)?;
+        txlog::checkpoint(&*driver, Action::Screenshot, "payment result").await;
 
         let dcurrent_url = driver.current_url().await?;
 
-        let result_json = json!({
+        let mut result_json = json!({
             "current_url": dcurrent_url.to_string(),
         });
+        if CONFIG.payment_mode == PaymentMode::Test {
+            result_json["payment_mode"] = json!("test");
+        }
 
         driver.quit().await?;
 
-        Ok((StatusCode::OK, Json(result_json)))
+        Ok(Envelope::new(StatusCode::OK, result_json))
     })
-    .retries(10)
-    .max_delay(Duration::from_secs(10))
-    .exponential_backoff(Duration::from_secs(1))
     .await
 }
 
+// Form errors are the client's to see, anything else stays an internal error
+fn form_failure(err: anyhow::Error) -> AppError {
+    if let Some(err) = err.downcast_ref::<FormError>() {
+        return ErrorKind::Unprocessable(err.to_string()).into();
+    }
+    if let Some(err) = err.downcast_ref::<FormValidationError>() {
+        return ErrorKind::Unprocessable(err.to_string()).into();
+    }
+    // not retried, the card details stay where they are
+    if let Some(err) = err.downcast_ref::<flow::UnexpectedPaymentHost>() {
+        return ErrorKind::BadGateway(err.to_string()).into();
+    }
+    err.into()
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct CompanySearchResult {
+    company_names: Vec<String>,
+    // results on this page
+    total: usize,
+    // result page, none when nothing matched
+    current_url: Option<String>,
+    page: u32,
+    // pass as `?cursor=` to get the next page, none on the last one
+    next_cursor: Option<String>,
+}
+
+// Pager button of the search results, disabled on the last page
+const NEXT_PAGE: &str =
+    "//button[contains(@class, 'appPagerNext') and not(contains(@class, 'appDisabled'))]";
+pub const REGISTRY_SEARCH_URL: &str = "redacted";
+
+/// Where a company search left off: the first result page, which restores the search, and how
+/// many pages were served. Clients only see it base64 encoded, as an opaque `next_cursor`.
+#[derive(Serialize, Deserialize)]
+pub struct SearchCursor {
+    url: String,
+    page: u32,
+}
+
+impl SearchCursor {
+    fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(cursor: &str) -> Result<SearchCursor, AppError> {
+        let invalid = || ErrorKind::BadRequest("Invalid cursor".to_string());
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let cursor: SearchCursor = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        // the browser follows it, so it must stay on the registry
+        if !on_search_page(&cursor.url) {
+            return Err(invalid().into());
+        }
+
+        Ok(cursor)
+    }
+}
+
+// Same scheme, host and port as the registry search, on its path or below it. Compared parsed,
+// as a prefix `https://registry.example` would also take `https://registry.example.evil.com`.
+fn on_search_page(url: &str) -> bool {
+    let (Ok(url), Ok(search)) = (
+        reqwest::Url::parse(url),
+        reqwest::Url::parse(REGISTRY_SEARCH_URL),
+    ) else {
+        return false;
+    };
+    let path = search.path().trim_end_matches('/');
+
+    url.scheme() == search.scheme()
+        && url.host_str() == search.host_str()
+        && url.port_or_known_default() == search.port_or_known_default()
+        && url.username().is_empty()
+        && url.password().is_none()
+        && (url.path() == search.path()
+            || url.path() == path
+            || url.path().starts_with(&format!("{}/", path)))
+}
+
+#[derive(Deserialize)]
+pub struct CompanySearchQuery {
+    cursor: Option<String>,
+    #[serde(default)]
+    run_window: RunWindow,
+    // searches live even when cached, and caches the result
+    #[serde(default)]
+    refresh: bool,
+}
+
+/// Common queries are answered from `search_cache`. A stale entry is still served right away,
+/// flagged `stale`, while a background search refreshes it. Either way `scraped_at` says when
+/// the results were searched, and `refresh=true` searches live.
 pub async fn get_companies_list_handler(
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<CompanySearchQuery>,
     Json(params): Json<SearchBusinessRegistryParams>,
 ) -> ApiResponse<Value> {
-    tryhard::retry_fn(|| async {
-        let driver = get_chrome_driver().await?;
+    let options = SearchOptions {
+        cursor: query.cursor,
+        window: query.run_window,
+        refresh: query.refresh,
+    };
+    search_companies(&principal, options, &params).await
+}
 
-        if goto_search_result_page(&driver, &params).await?.is_none() {
-            return Ok((
-                StatusCode::NOT_FOUND,
-                Json(json!({ "error": "No results found" })),
+/// The search parameters of `POST /api/search-companies` in the query string, for clients that
+/// can't send a body. Empty values count as left out, as forms send them.
+#[derive(Deserialize)]
+pub struct CompanySearchGetQuery {
+    query: String,
+    register_type: Option<String>,
+    business_type: Option<String>,
+    status: Option<String>,
+    date: Option<String>,
+    operator: Option<String>,
+    end_date: Option<String>,
+    cursor: Option<String>,
+    #[serde(default)]
+    run_window: RunWindow,
+    #[serde(default)]
+    refresh: bool,
+}
+
+// Parsed as the same field of the JSON body would be, so a malformed value is a 422 like the
+// body's and not the plain-text rejection of `Query`
+fn search_query_param<T: serde::de::DeserializeOwned>(
+    name: &str,
+    value: Option<String>,
+) -> Result<Option<T>, AppError> {
+    let Some(value) = value.filter(|value| !value.is_empty()) else {
+        return Ok(None);
+    };
+
+    serde_json::from_value(Value::String(value.clone()))
+        .map(Some)
+        .map_err(|err| {
+            ErrorKind::Unprocessable(format!("Invalid {} '{}': {}", name, value, err)).into()
+        })
+}
+
+pub async fn search_companies_get(
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<CompanySearchGetQuery>,
+) -> ApiResponse<Value> {
+    // validated like the JSON body, and rejected with the same 422
+    let params = SearchBusinessRegistryParams::try_from(SearchBusinessRegistryParamsShadow {
+        query_word: query.query,
+        register_type_key: search_query_param("register_type", query.register_type)?,
+        business_type_selection: query.business_type.filter(|value| !value.is_empty()),
+        status_key: search_query_param("status", query.status)?,
+        date_input: search_query_param("date", query.date)?,
+        search_operator: search_query_param("operator", query.operator)?,
+        end_date: search_query_param("end_date", query.end_date)?,
+    })
+    .map_err(ErrorKind::Unprocessable)?;
+
+    let options = SearchOptions {
+        cursor: query.cursor,
+        window: query.run_window,
+        refresh: query.refresh,
+    };
+    search_companies(&principal, options, &params).await
+}
+
+// Of both ways to search, outside of the search parameters
+struct SearchOptions {
+    cursor: Option<String>,
+    window: RunWindow,
+    refresh: bool,
+}
+
+async fn search_companies(
+    principal: &Principal,
+    options: SearchOptions,
+    params: &SearchBusinessRegistryParams,
+) -> ApiResponse<Value> {
+    if let Some(business_type) = params.ignored_business_type() {
+        let message = format!(
+            "business_type_selection '{}' has no effect without a register_type_key other than All",
+            business_type
+        );
+        match CONFIG.ignored_filters {
+            IgnoredFilters::Warn => response::warn(Warning {
+                code: "ignored_filter",
+                field: "business_type_selection",
+                message,
+            }),
+            IgnoredFilters::Reject => return Err(ErrorKind::Unprocessable(message).into()),
+        }
+    }
+    let mut params = search_cache::normalize(params)?;
+    if let Some(cursor) = options.cursor {
+        SearchCursor::decode(&cursor)?;
+        params["cursor"] = json!(cursor);
+    }
+    let key = search_cache::key(&params);
+
+    let cached = search_cache::get(&key).filter(|_| !options.refresh);
+    if let Some(cached) = cached {
+        if cached.stale && search_cache::start_refresh(&key) {
+            tokio::spawn(refresh_company_search(
+                key,
+                params,
+                principal.subject.clone(),
             ));
         }
+        response::record_cache_hit(cached.stale);
+        let mut envelope = Envelope::new(StatusCode::OK, cached.data);
+        envelope.scraped_at = Some(cached.scraped_at);
+        return Ok(compat::no_results(envelope));
+    }
 
-        let company_links = driver
-            .query(By::XPath(
-                "//a[@class='registerItemSearch-results-page-line-ItemBox-resultLeft-viewMenu \
-                 appMenu appMenuItem appMenuDepth0 appItemSearchResult noSave \
-                 viewInstanceUpdateStackPush appReadOnly appIndex0']",
-            ))
-            .all()
-            .await?;
-        let company_names: Vec<String> = join_all(company_links.iter().map(|link| link.text()))
-            .await
-            .into_iter()
-            .map(|x| x.unwrap())
-            .collect();
+    let mut envelope = run_job(
+        JobKind::CompanySearch,
+        &params,
+        None,
+        &principal.subject,
+        options.window,
+    )
+    .await?;
+    if envelope.status == StatusCode::OK {
+        envelope.scraped_at = Some(search_cache::put(key, envelope.data.clone()));
+    }
+
+    Ok(compat::no_results(envelope))
+}
+
+async fn refresh_company_search(key: String, params: Value, client: String) {
+    match run_job(
+        JobKind::CompanySearch,
+        &params,
+        None,
+        &client,
+        RunWindow::Now,
+    )
+    .await
+    {
+        Ok(envelope) if envelope.status == StatusCode::OK => {
+            search_cache::put(key.clone(), envelope.data);
+        }
+        Ok(envelope) => {
+            tracing::debug!("refresh of {} answered {}", key, envelope.status);
+        }
+        Err(err) => tracing::warn!("failed to refresh the cached search {}: {}", key, err),
+    }
+    search_cache::finish_refresh(&key);
+}
+
+async fn company_search_flow(
+    params: &SearchBusinessRegistryParams,
+    cursor: Option<&SearchCursor>,
+) -> ApiResponse<Value> {
+    retry::retry(|| async {
+        let driver = session::start().await?;
+
+        let (first_page_url, page) = match cursor {
+            // the result page doesn't remember how far it was paged, so it's paged again
+            Some(cursor) => {
+                driver.goto(&cursor.url).await?;
+                for _ in 0..cursor.page {
+                    browser::click(&*driver, NEXT_PAGE, Duration::from_secs(20)).await?;
+                    sleep(Duration::from_secs(3)).await;
+                }
+                (cursor.url.clone(), cursor.page + 1)
+            }
+            // an empty search is an answer like any other, not a missing resource
+            None => match goto_search_result_page(&*driver, params).await? {
+                Some(url) => (url, 1),
+                None => {
+                    driver.quit().await?;
+                    let result_json = serde_json::to_value(CompanySearchResult {
+                        company_names: vec![],
+                        total: 0,
+                        current_url: None,
+                        page: 1,
+                        next_cursor: None,
+                    })?;
+                    return Ok(Envelope::new(StatusCode::OK, result_json));
+                }
+            },
+        };
+
+        let company_names = browser::texts(
+            &*driver,
+            "//a[@class='registerItemSearch-results-page-line-ItemBox-resultLeft-viewMenu appMenu \
+             appMenuItem appMenuDepth0 appItemSearchResult noSave viewInstanceUpdateStackPush \
+             appReadOnly appIndex0']",
+            Duration::from_secs(20),
+        )
+        .await?;
+        browser::record_language(&*driver).await?;
 
         let current_url = driver.current_url().await?;
+        let next_cursor = browser::exists(&*driver, NEXT_PAGE, Duration::from_secs(2))
+            .await?
+            .then(|| {
+                SearchCursor {
+                    url: first_page_url,
+                    page,
+                }
+                .encode()
+            });
 
-        let result_json = json!({
-            "company_names": company_names,
-            "current_url": current_url.to_string(),
-        });
+        let result_json = serde_json::to_value(CompanySearchResult {
+            total: company_names.len(),
+            company_names,
+            current_url: Some(current_url.to_string()),
+            page,
+            next_cursor,
+        })?;
 
         driver.quit().await?;
 
-        Ok((StatusCode::OK, Json(result_json)))
+        Ok(Envelope::new(StatusCode::OK, result_json))
     })
-    .retries(10)
-    .max_delay(Duration::from_secs(10))
-    .exponential_backoff(Duration::from_secs(1))
     .await
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub enum Either<L, R> {
-    Left(L),
-    Right(R),
+/// Register and business type of a registration number, named like the search filters so they
+/// can be sent back as they are.
+#[derive(Serialize, JsonSchema)]
+pub struct EntityTypeResult {
+    number: String,
+    name: String,
+    register_type_key: RegisterType,
+    business_type_selection: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Scrap {
-    corporate_number: String,
-    first_name: String,
-    last_name: String,
-    phone_number: String,
-    #[serde(default = "default_email")]
-    email: String,
-    summarize_data: Vec<Value>,
-    contact: String,
-    url: String,
+#[derive(Serialize, Deserialize)]
+struct EntityTypeParams {
+    number: String,
 }
 
-impl Scrap {
-    async fn create_request(&self, client: &Client) -> Result<(), reqwest::Error> {
-        let url_contacts = format!("{}/cntcts", self.url);
-        let payload_contacts = serde_json::json!({
-            "contactMethod": {
-                "phoneNumber": self.phone_number,
-                "emailAddress": self.email
-            },
-            "firstName": self.first_name,
-            "lastName": self.last_name
-        });
-
-        let response_contacts = client
-            .post(&url_contacts)
-            .json(&payload_contacts)
-            .send()
-            .await?;
+// Result lines show the kind of entity, e.g. "Ontario Business Corporation", next to its name
+// and registration number
+const RESULT_LINE: &str =
+    "(//div[contains(@class, 'registerItemSearch-results-page-line-ItemBox')])[1]";
+const RESULT_NAME: &str = "(//div[contains(@class, \
+                           'registerItemSearch-results-page-line-ItemBox')])[1]//a[contains(@\
+                           class, 'resultLeft-viewMenu')]";
+const RESULT_BUSINESS_TYPE: &str = "(//div[contains(@class, \
+                                    'registerItemSearch-results-page-line-ItemBox')])[1]//\
+                                    div[contains(@class, 'resultRight')]";
+
+// Whether `line`, the text of a result line, shows `number` as a whole word. The search also
+// matches names and numbers merely containing it, which aren't the entity asked for.
+fn shows_number(line: &str, number: &str) -> bool {
+    line.split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| word.eq_ignore_ascii_case(number.trim()))
+}
+
+// The register offering `business_type` in the advanced search, guessed from its wording for
+// types the table doesn't list
+fn register_of(business_type: &str) -> RegisterType {
+    let listed = business_types()
+        .into_iter()
+        .find(|(_, business_types)| business_types.contains(&business_type))
+        .map(|(register_type, _)| register_type);
+
+    listed.unwrap_or_else(|| {
+        let business_type = business_type.to_uppercase();
+        if business_type.contains("PARTNERSHIP") {
+            RegisterType::Partnerships
+        } else if business_type.contains("BUSINESS NAME")
+            || business_type.contains("SOLE PROPRIETORSHIP")
+        {
+            RegisterType::BusinessNames
+        } else {
+            RegisterType::Corporations
+        }
+    })
+}
+
+async fn entity_type_flow(number: &str) -> ApiResponse<Value> {
+    let params = SearchBusinessRegistryParams {
+        query_word: number.to_string(),
+        register_type_key: Some(RegisterType::All),
+        business_type_selection: None,
+        status_key: None,
+        date_input: None,
+        search_operator: None,
+        end_date: None,
+    };
+
+    retry::retry(|| async {
+        let driver = session::start().await?;
+
+        if goto_search_result_page(&*driver, &params).await?.is_none() {
+            driver.quit().await?;
+            return Err(ErrorKind::NotFound(format!("Nothing is registered as {}", number)).into());
+        }
+        let line = browser::texts(&*driver, RESULT_LINE, Duration::from_secs(20)).await?;
+        let name = browser::texts(&*driver, RESULT_NAME, Duration::from_secs(20)).await?;
+        let business_type =
+            browser::texts(&*driver, RESULT_BUSINESS_TYPE, Duration::from_secs(20)).await?;
+        browser::record_language(&*driver).await?;
+        driver.quit().await?;
+
+        if !line.first().is_some_and(|line| shows_number(line, number)) {
+            return Err(ErrorKind::NotFound(format!("Nothing is registered as {}", number)).into());
+        }
+
+        let (Some(name), Some(business_type)) = (name.first(), business_type.first()) else {
+            return Err(anyhow::anyhow!("the result of {} has no name or type", number).into());
+        };
+        let result_json = serde_json::to_value(EntityTypeResult {
+            number: number.to_string(),
+            name: name.clone(),
+            register_type_key: register_of(business_type),
+            business_type_selection: business_type.clone(),
+        })?;
+
+        Ok(Envelope::new(StatusCode::OK, result_json))
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct RunWindowQuery {
+    #[serde(default)]
+    run_window: RunWindow,
+}
+
+/// Register and business type of a registration number, for the filters of follow-up searches.
+pub async fn entity_type_get(
+    Extension(principal): Extension<Principal>,
+    Path(number): Path<String>,
+    Query(query): Query<RunWindowQuery>,
+) -> ApiResponse<Value> {
+    let params = EntityTypeParams { number };
+    run_job(
+        JobKind::EntityType,
+        &params,
+        None,
+        &principal.subject,
+        query.run_window,
+    )
+    .await
+}
+
+/// Purchase of the signed in registry account, as its order history lists it.
+#[derive(Serialize, JsonSchema)]
+pub struct Order {
+    pub order_id: String,
+    pub product: String,
+    pub company: String,
+    pub status: String,
+    // where the ordered documents are downloaded, once delivered
+    pub delivery_url: Option<String>,
+}
+
+pub const ORDER_HISTORY_URL: &str = "redacted";
+const ORDER_ROWS: &str = "//table[contains(@class, 'orderHistory')]//tbody/tr";
+// Pages read at most, the oldest orders are left out past them
+const MAX_ORDER_PAGES: u32 = 20;
+
+async fn order_cell(driver: &dyn Driver, row: usize, column: usize) -> Result<String> {
+    let cell = driver
+        .find_all(&format!("({})[{}]/td[{}]", ORDER_ROWS, row, column))
+        .await?;
+    let text = match cell.first() {
+        Some(cell) => cell.text().await?,
+        None => String::new(),
+    };
+
+    Ok(normalize::clean(&text))
+}
+
+async fn order_page(driver: &dyn Driver) -> Result<Vec<Order>> {
+    let rows = driver.find_all(ORDER_ROWS).await?.len();
+    let mut orders = Vec::with_capacity(rows);
+    for row in 1..=rows {
+        let link = driver
+            .find_all(&format!("({})[{}]/td[5]//a", ORDER_ROWS, row))
+            .await?;
+        let delivery_url = match link.first() {
+            Some(link) => link.property("href").await?,
+            None => None,
+        };
+        orders.push(Order {
+            order_id: order_cell(driver, row, 1).await?,
+            product: order_cell(driver, row, 2).await?,
+            company: order_cell(driver, row, 3).await?,
+            status: order_cell(driver, row, 4).await?,
+            delivery_url,
+        });
+    }
+
+    Ok(orders)
+}
+
+/// Orders of the account, read by a session signed in to it.
+pub async fn order_history() -> Result<Vec<Order>, AppError> {
+    retry::retry(|| async {
+        let driver = session::start().await?;
+
+        login::sign_in(&*driver).await.map_err(form_failure)?;
+        driver.goto(ORDER_HISTORY_URL).await?;
+        txlog::record(&*driver, Action::Navigate, "order history").await;
+
+        let mut orders = vec![];
+        // an account without orders shows no table at all
+        if browser::exists(&*driver, ORDER_ROWS, Duration::from_secs(20)).await? {
+            for page in 1..=MAX_ORDER_PAGES {
+                orders.extend(order_page(&*driver).await?);
+                if page == MAX_ORDER_PAGES
+                    || !browser::exists(&*driver, NEXT_PAGE, Duration::from_secs(2)).await?
+                {
+                    break;
+                }
+                browser::click(&*driver, NEXT_PAGE, Duration::from_secs(20)).await?;
+                sleep(Duration::from_secs(3)).await;
+            }
+        }
+        browser::record_language(&*driver).await?;
+        driver.quit().await?;
+
+        Ok(orders)
+    })
+    .await
+}
+
+async fn order_history_flow() -> ApiResponse<Value> {
+    let orders = order_history().await?;
+    Ok(Envelope::new(StatusCode::OK, serde_json::to_value(orders)?))
+}
+
+/// Orders of the registry account flows sign in with, newest first like the registry lists them,
+/// for reconciling purchases with the payments. Admins only, the account buys for every client.
+pub async fn orders_get(
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<RunWindowQuery>,
+) -> ApiResponse<Value> {
+    if !login::configured() {
+        return Err(ErrorKind::Unprocessable("No registry account is set up".to_string()).into());
+    }
+
+    run_job(
+        JobKind::OrderHistory,
+        &json!({}),
+        None,
+        &principal.subject,
+        query.run_window,
+    )
+    .await
+}
+
+/// Runs the browser flow of a job, in the API process or on a worker.
+pub async fn execute(job: &Job) -> ApiResponse<Value> {
+    match job.kind {
+        JobKind::PaymentPage => {
+            let params: RequestBusinessProfileReportParams =
+                serde_json::from_value(job.params.clone())?;
+            txlog::scope(job.id, params.trace_level, payment_page_flow(&params)).await
+        }
+        JobKind::CompanySearch => {
+            let cursor = job
+                .params
+                .get("cursor")
+                .and_then(Value::as_str)
+                .map(SearchCursor::decode)
+                .transpose()?;
+            company_search_flow(
+                &serde_json::from_value(job.params.clone())?,
+                cursor.as_ref(),
+            )
+            .await
+        }
+        JobKind::EntityType => {
+            let params: EntityTypeParams = serde_json::from_value(job.params.clone())?;
+            entity_type_flow(&params.number).await
+        }
+        JobKind::OrderHistory => order_history_flow().await,
+    }
+}
+
+// Records the flow as a job and tags its response with the job id. In `api` mode the job is
+// queued for a worker and the response waits for its result.
+async fn run_job<P: Serialize>(
+    kind: JobKind,
+    params: &P,
+    notify: Option<String>,
+    client: &str,
+    window: RunWindow,
+) -> ApiResponse<Value> {
+    if window == RunWindow::OffPeak {
+        if let Some(run_at) = off_peak::deferred_until(chrono::Utc::now())? {
+            return defer_job(Job::schedule(kind, params, notify, client, run_at).await?).await;
+        }
+    }
+    // held until the job finished, so concurrent purchases of the same report are rejected
+    let lock = admit(kind, &serde_json::to_value(params)?, notify.as_deref()).await?;
+    if CONFIG.mode == Mode::Api {
+        return wait_for_job(Job::enqueue(kind, params, notify, client, lock).await?).await;
+    }
+
+    let _running = start_running(client)?;
+    let job = Job::start(kind, params, notify, client).await?;
+    let id = job.id;
+
+    match execute(&job).await {
+        Ok(mut envelope) => {
+            job.succeed(
+                envelope.status.as_u16(),
+                envelope.language.clone(),
+                envelope.data.clone(),
+            )
+            .await?;
+            if let Some(result) = envelope.data.as_object_mut() {
+                result.insert("job_id".to_string(), json!(id));
+            }
+            Ok(envelope)
+        }
+        Err(err) => {
+            fail_job(job, &err).await?;
+            Err(err)
+        }
+    }
+}
+
+// The checks a job passes before it runs, whether requested or retried: every job is a registry
+// flow, reports only go to allowed mailboxes and one purchase of a report runs at a time
+async fn admit(
+    kind: JobKind,
+    params: &Value,
+    notify: Option<&str>,
+) -> Result<Option<Lock>, AppError> {
+    maintenance::check(cookies::REGISTRY)?;
+    if let Some(email) = notify.filter(|email| !notify::allowed(email)) {
+        return Err(ErrorKind::Forbidden(format!("Reports can't be sent to {}", email)).into());
+    }
+    if kind != JobKind::PaymentPage {
+        return Ok(None);
+    }
+
+    let params: RequestBusinessProfileReportParams = serde_json::from_value(params.clone())?;
+    let Some(lock) = lock::payment_lock(&params.selected_company, &params.search_product).await?
+    else {
+        return Err(ErrorKind::Conflict(format!(
+            "A '{}' purchase for '{}' is already in progress",
+            params.search_product, params.selected_company
+        ))
+        .into());
+    };
+
+    Ok(Some(lock))
+}
+
+/// Key of the lock `admit` handed over to a queued job, for the worker to release.
+pub fn job_lock_key(job: &Job) -> Option<String> {
+    if job.kind != JobKind::PaymentPage {
+        return None;
+    }
+    let params: RequestBusinessProfileReportParams =
+        serde_json::from_value(job.params.clone()).ok()?;
+
+    Some(lock::payment_key(
+        &params.selected_company,
+        &params.search_product,
+    ))
+}
+
+// Counts an inline job against the client's `max_in_flight`, workers count theirs in redis.
+// Nothing queues inline jobs, so weights don't apply and a client over its quota has to retry
+fn start_running(client: &str) -> Result<clients::Running, AppError> {
+    clients::Running::try_start(client).ok_or_else(|| {
+        ErrorKind::Unavailable {
+            message: format!("{} runs as many scrapes as its quota allows", client),
+            retry_after: CONFIG.load_shed_retry_after_secs,
+        }
+        .into()
+    })
+}
+
+/// Marks the job failed, dead-lettering it when the flow gave up on an error that retrying
+/// could have fixed rather than one the request caused.
+pub async fn fail_job(mut job: Job, err: &AppError) -> Result<Job> {
+    job.error_status = Some(err.status().as_u16());
+    job.error_code = Some(err.class().to_string());
+    job.retry_after = err.retry_after();
+    let job = job.fail(err.to_string()).await?;
+    if err.is_retryable() {
+        dead_letter::record(&job, response::attempts(), response::request_id()).await;
+    }
+
+    Ok(job)
+}
+
+// Polls the job until a worker finishes it, answers 202 with the job id once `JOB_WAIT_SECS`
+// pass so the client can follow up on /api/jobs/:id
+async fn wait_for_job(job: Job) -> ApiResponse<Value> {
+    let id = job.id;
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(CONFIG.job_wait_secs);
+
+    let mut job = job;
+    while tokio::time::Instant::now() < deadline {
+        sleep(Duration::from_secs(1)).await;
+        job = Job::find(id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("job {} disappeared", id))?;
+
+        match job.status {
+            JobStatus::Succeeded => {
+                let status = job
+                    .result_status
+                    .and_then(|status| StatusCode::from_u16(status).ok())
+                    .unwrap_or(StatusCode::OK);
+                if let Some(language) = &job.language {
+                    response::record_language(language);
+                }
+                let mut result = job.result.unwrap_or_default();
+                if let Some(result) = result.as_object_mut() {
+                    result.insert("job_id".to_string(), json!(id));
+                }
+                return Ok(Envelope::new(status, result));
+            }
+            JobStatus::Failed => {
+                let message = job.error.unwrap_or_default();
+                return Err(match job.error_code.as_deref() {
+                    Some(code) => AppError::rebuild(code, message, job.retry_after),
+                    // failed before jobs kept their error's kind
+                    None => anyhow::anyhow!("job {} failed: {}", id, message).into(),
+                });
+            }
+            JobStatus::Queued | JobStatus::Running => {}
+        }
+    }
+
+    accepted(&job).await
+}
+
+// 202 with where the unfinished job stands
+async fn accepted(job: &Job) -> ApiResponse<Value> {
+    let mut accepted = json!({ "job_id": job.id, "status": job.status });
+    if let Some(run_at) = job.run_at {
+        accepted["run_at"] = json!(run_at);
+    }
+    if let Some(wait) = queue_wait(job).await {
+        accepted["queue_position"] = json!(wait.queue_position);
+        accepted["estimated_wait_secs"] = json!(wait.estimated_wait_secs);
+    }
+
+    Ok(Envelope::new(StatusCode::ACCEPTED, accepted))
+}
+
+// Tells the client where an unfinished job stands through `X-Queue-Position` and
+// `X-Estimated-Wait` (seconds). An estimate that can't be made leaves the headers out.
+async fn queue_wait(job: &Job) -> Option<queue::Wait> {
+    let wait = match queue::wait(job).await {
+        Ok(wait) => wait?,
+        Err(err) => {
+            tracing::warn!("failed to estimate the wait of job {}: {}", job.id, err);
+            return None;
+        }
+    };
+    response::set_header("x-queue-position", wait.queue_position);
+    response::set_header("x-estimated-wait", wait.estimated_wait_secs);
+
+    Some(wait)
+}
+
+// The job when `principal` asked for it or is an admin. Other clients' jobs are answered like
+// unknown ones, so their ids can't be probed
+async fn owned_job(principal: &Principal, id: Uuid) -> Result<Job, AppError> {
+    match Job::find(id).await? {
+        Some(job) if job.client == principal.subject || principal.is_admin() => Ok(job),
+        _ => Err(ErrorKind::NotFound(format!("Job {} not found", id)).into()),
+    }
+}
+
+pub async fn job_get(
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+) -> ApiResponse<Job> {
+    let job = owned_job(&principal, id).await?;
+    queue_wait(&job).await;
+    if let Some(language) = &job.language {
+        response::record_language(language);
+    }
+    Ok(Envelope::new(StatusCode::OK, job))
+}
+
+pub async fn job_log_get(
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+) -> ApiResponse<TransactionLog> {
+    owned_job(&principal, id).await?;
+    match txlog::find(id).await? {
+        Some(log) => Ok(Envelope::new(StatusCode::OK, log)),
+        None => Err(ErrorKind::NotFound(format!("No transaction log for job {}", id)).into()),
+    }
+}
+
+/// Zip of the job's log, screenshots, page snapshots, scrubbed params and attempts, see
+/// `support_bundle::assemble`.
+pub async fn support_bundle_get(
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let job = owned_job(&principal, id).await?;
+    let bundle = support_bundle::assemble(&job).await?;
+
+    let headers = [
+        (header::CONTENT_TYPE, "application/zip".to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"job-{}-support-bundle.zip\"", id),
+        ),
+    ];
+    Ok((headers, bundle).into_response())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Scrap {
+    corporate_number: String,
+    first_name: String,
+    last_name: String,
+    phone_number: String,
+    #[serde(default = "default_email")]
+    email: String,
+    summarize_data: Vec<Value>,
+    contact: String,
+    url: String,
+}
+
+// Federal corporation search by name, a page of results at a time
+pub const FEDERAL_SEARCH_URL: &str = "https://redacted/cc/lgcy/fdrlCrpSrch.html";
+
+impl Scrap {
+    async fn create_request(&self, client: &Client) -> Result<(), reqwest::Error> {
+        let url_contacts = format!("{}/cntcts", self.url);
+        let payload_contacts = serde_json::json!({
+            "contactMethod": {
+                "phoneNumber": self.phone_number,
+                "emailAddress": self.email
+            },
+            "firstName": self.first_name,
+            "lastName": self.last_name
+        });
+
+        politeness::pace(maintenance::FEDERAL).await;
+        let response_contacts = client
+            .post(&url_contacts)
+            .json(&payload_contacts)
+            .send()
+            .await?;
 
         println!("Status Code Contacts: {}", response_contacts.status());
         println!(
@@ -707,6 +1403,7 @@ impl Scrap {
             ("phnn", &self.phone_number),
         ];
 
+        politeness::pace(maintenance::FEDERAL).await;
         let response_contacts_query = client
             .get(&url_contacts_query)
             .query(&params_contacts_query)
@@ -745,6 +1442,7 @@ impl Scrap {
 
     async fn summary_data(&mut self, client: &Client) -> Result<(), reqwest::Error> {
         let url = format!("{}/dcmnts?crprtnid={}", self.url, self.corporate_number);
+        politeness::pace(maintenance::FEDERAL).await;
         let response = client.get(&url).send().await?;
 
         if response.status().is_success() {
@@ -756,62 +1454,70 @@ impl Scrap {
         Ok(())
     }
 
+    async fn fetch_search_page(
+        corporate_name: &str,
+        page_number: usize,
+    ) -> Result<(Vec<HashMap<String, String>>, bool), reqwest::Error> {
+        println!("extracting page {}", page_number);
+        // encoded, names like "Smith & Sons" would end the parameter early
+        let url = reqwest::Url::parse_with_params(
+            FEDERAL_SEARCH_URL,
+            &[
+                ("p", page_number.to_string().as_str()),
+                ("crpNm", corporate_name),
+                ("crpNmbr", ""),
+                ("bsNmbr", ""),
+                ("cProv", ""),
+                ("cStatus", ""),
+                ("cAct", ""),
+            ],
+        )
+        .expect("the federal search URL is valid");
+        politeness::pace(maintenance::FEDERAL).await;
+        let response = politeness::client(maintenance::FEDERAL)
+            .get(url.clone())
+            .send()
+            .await?;
+        let html = response.text().await?;
+        response::record_page(url.as_str(), &html);
+
+        let page = search::parse_search_page(&html);
+        if page.skipped > 0 {
+            tracing::warn!(
+                "skipped {} results of search page {}, they aren't laid out as expected",
+                page.skipped,
+                page_number
+            );
+        }
+        Ok((page.rows, page.has_next))
+    }
+
+    // Search results a page at a time, so callers decide how much to keep in memory
+    fn search_pages(
+        corporate_name: &str,
+    ) -> impl Stream<Item = Result<Vec<HashMap<String, String>>, reqwest::Error>> + '_ {
+        futures::stream::try_unfold(Some(0), move |page_number| async move {
+            let Some(page_number) = page_number else {
+                return Ok(None);
+            };
+            let (rows, has_next) = Scrap::fetch_search_page(corporate_name, page_number).await?;
+            Ok(Some((rows, has_next.then_some(page_number + 1))))
+        })
+    }
+
     async fn extract_data(
         corporate_name: &str,
         num_of_records: Option<usize>,
-    ) -> Result<Vec<HashMap<String, String>>, reqwest::Error> {
+    ) -> Result<Vec<HashMap<String, String>>, AppError> {
+        robots::check(maintenance::FEDERAL, FEDERAL_SEARCH_URL).await?;
         let mut data: Vec<HashMap<String, String>> = Vec::new();
-        let mut page_number = 0;
-        let mut next_page = true;
-
-        while next_page && data.len() < num_of_records.unwrap_or(usize::MAX) {
-            println!("extracting page {}", page_number);
-            let url = format!("https://redacted/cc/lgcy/fdrlCrpSrch.html?p={}&crpNm={}&crpNmbr=&bsNmbr=&cProv=&cStatus=&cAct=", page_number, corporate_name);
-            let response = reqwest::get(&url).await?;
-            let html = response.text().await?;
+        let mut pages = pin!(Scrap::search_pages(corporate_name));
 
-            let document = Html::parse_document(&html);
-
-            let rows_selector = Selector::parse("div.col-md-11").unwrap();
-            let rows = document.select(&rows_selector);
-
-            for row in rows {
-                let row_spans = row
-                    .select(&Selector::parse("span").unwrap())
-                    .collect::<Vec<_>>();
-                let business_name = row_spans[0]
-                    .select(&Selector::parse("a").unwrap())
-                    .next()
-                    .unwrap()
-                    .inner_html();
-                let status = row_spans[1].inner_html();
-                let status = status.split(':').nth(1).unwrap().trim();
-                let corporation_number = row_spans[2].inner_html();
-                let corporation_number = corporation_number.split(':').nth(1).unwrap().trim();
-                let business_number = row_spans[3].inner_html();
-                let business_number = business_number.split(':').nth(1).unwrap().trim();
-
-                let mut row_data: HashMap<String, String> = HashMap::new();
-                row_data.insert("business_name".to_string(), business_name);
-                row_data.insert("status".to_string(), status.to_string());
-                row_data.insert(
-                    "corporation_number".to_string(),
-                    corporation_number.replace('-', ""),
-                );
-                row_data.insert("business_number".to_string(), business_number.to_string());
-
-                data.push(row_data);
-            }
-
-            if document
-                .select(&Selector::parse("a[rel=\"next\"]").unwrap())
-                .next()
-                .is_none()
-            {
-                next_page = false;
-            }
-
-            page_number += 1;
+        while data.len() < num_of_records.unwrap_or(usize::MAX) {
+            let Some(rows) = pages.try_next().await? else {
+                break;
+            };
+            data.extend(rows);
         }
 
         Ok(data)
@@ -826,6 +1532,7 @@ impl Scrap {
             "contact": self.contact
         });
 
+        politeness::pace(maintenance::FEDERAL).await;
         let response = client.post(&url).json(&payload).send().await?;
 
         println!("Status Code: {}", response.status());
@@ -834,347 +1541,1007 @@ impl Scrap {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CorporationDataExtract {
-    url: String,
+// Fetches the profile page of a federal corporation, in `lang` when given, and extracts
+// `sections` of it
+async fn extract_corporation_data(
+    id: &str,
+    sections: &[CorporationSection],
+    lang: Option<Lang>,
+) -> Result<CorporationData, AppError> {
+    let url = extract::profile_url(id);
+    robots::check(maintenance::FEDERAL, &url).await?;
+    // without a language the registry picks one from its session
+    let mut request = politeness::client(maintenance::FEDERAL).get(&url);
+    if let Some(lang) = lang {
+        request = request.header(reqwest::header::ACCEPT_LANGUAGE, lang.accept_language());
+    }
+    politeness::pace(maintenance::FEDERAL).await;
+    let response = request.send().await?.error_for_status()?;
+    let html = response.text().await?;
+    response::record_page(&url, &html);
+
+    let options = extract::Options {
+        bilingual_labels: flags::enabled(Flag::BilingualLabels),
+    };
+    Ok(extract::corporation(&html, sections, options)?)
 }
 
-impl CorporationDataExtract {
-    fn gen_url(corporation_id: String) -> String {
-        format!(
-            "https://redacted/cc/lgcy/fdrlCrpDtls.html?p=0&corpId={corporation_id}&V_TOKEN=null&crpNm=Tech&crpNmbr=&bsNmbr=&cProv=&cStatus=&cAct=",
-            corporation_id = corporation_id
-        )
+/// Parses a comma separated list like `corp_details,directors`. An empty list is refused rather
+/// than taken for no sections, which would answer nothing.
+pub fn parse_sections(list: &str) -> Result<Vec<CorporationSection>, AppError> {
+    let names: Vec<&str> = list
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.is_empty() {
+        return Err(ErrorKind::BadRequest("No sections listed".to_string()).into());
     }
 
-    fn extract_corp_details(html_data: &Html) -> Vec<HashMap<String, String>> {
-        let rows = html_data
-            .select(&Selector::parse("div.col-sm-12").unwrap())
-            .nth(2)
-            .unwrap();
-        let rows = rows
-            .select(&Selector::parse("div.data-display-group").unwrap())
-            .collect_vec();
-        let mut data: Vec<HashMap<String, String>> = Vec::new();
+    names
+        .into_iter()
+        .map(|name| {
+            serde_json::from_value(Value::String(name.to_string())).map_err(|_| {
+                ErrorKind::BadRequest(format!(
+                    "Unknown section '{}', must be one of corp_details, address_details, \
+                     director_details, annual_filings_details, corp_history_details, \
+                     share_structure, share_restrictions",
+                    name
+                ))
+                .into()
+            })
+        })
+        .collect()
+}
 
-        for row in rows {
-            let key = row
-                .select(&Selector::parse("b").unwrap())
-                .next()
-                .unwrap()
-                .inner_html();
+#[derive(Deserialize)]
+pub struct CorporationQuery {
+    // sections returned in the response
+    fields: Option<String>,
+    // sections extracted from the page, defaults to `fields` or everything
+    sections: Option<String>,
+    // page language and labels of the response, English labels by default
+    lang: Option<Lang>,
+    #[serde(default)]
+    minimize_pii: bool,
+    // scrapes every section live, even those imported or stored by a differential scrape
+    #[serde(default)]
+    refresh: bool,
+}
 
-            let value = if key == "Corporate Name" {
-                row.select(&Selector::parse("div.col-sm-8").unwrap())
-                    .next()
-                    .unwrap()
-                    .text()
-                    .map(|s| s.trim().to_string())
-                    .join("")
-                    .split("<br>")
-                    .next()
-                    .unwrap()
-                    .to_string()
-            } else {
-                row.select(&Selector::parse("div.col-sm-8").unwrap())
-                    .next()
-                    .unwrap()
-                    .text()
-                    .map(|s| s.trim().to_string())
-                    .join("")
-                    .to_string()
-            };
+#[derive(Deserialize)]
+pub struct PiiQuery {
+    // only the city and province of director addresses, whatever the caller's roles
+    #[serde(default)]
+    minimize_pii: bool,
+}
+
+// Director addresses are cut down for callers that ask, and under `MINIMIZE_PII` for those
+// without the `pii` role
+fn minimizes_pii(principal: &Principal, requested: bool) -> bool {
+    requested || (CONFIG.minimize_pii && !principal.sees_pii())
+}
 
-            let mut row_data: HashMap<String, String> = HashMap::new();
-            row_data.insert(key.trim().to_string(), value.trim().to_string());
-            data.push(row_data);
+/// Federal profile scraped from the registry, in `lang` when given, or read from the open data
+/// when the scrape fails or the page has none of the expected details, e.g. after a redesign.
+/// Labels are English whichever language the page is in. The envelope's `source` says which
+/// answered.
+pub async fn fetch_corporation(
+    id: &str,
+    sections: &[CorporationSection],
+    lang: Option<Lang>,
+) -> ApiResponse<CorporationData> {
+    maintenance::check(maintenance::FEDERAL)?;
+    match extract_corporation_data(id, sections, lang).await {
+        Ok(data) if !open_data::enabled() || !data.lacks_details(sections) => {
+            response::record_source(Source::Scrape);
+            return Ok(Envelope::new(StatusCode::OK, data));
         }
+        Ok(_) => tracing::warn!("the profile of {} has no details, trying open data", id),
+        Err(err) if !open_data::enabled() => return Err(err),
+        Err(err) => tracing::warn!("scraping {} failed, trying open data: {}", id, err),
+    }
+
+    let data = open_data::corporation(id, sections).await?;
+    response::record_source(Source::OpenData);
+    Ok(Envelope::new(StatusCode::OK, data))
+}
 
-        data
+// Stores what `fetch_corporation` answered. The open data it fell back to is stored like the bulk
+// import, so it isn't taken for a scrape of today or recorded as a change.
+async fn store_fetched(id: &str, response: &Envelope<CorporationData>) -> Result<()> {
+    if response.source == Some(Source::OpenData) {
+        corporations::import(id, response.data.clone()).await?;
+        return Ok(());
     }
 
-    fn extract_address_details(html_data: &Html) -> String {
-        let html_data = html_data
-            .select(&Selector::parse("div.col-sm-12").unwrap())
-            .nth(3)
-            .unwrap();
-        let address = html_data
-            .select(&Selector::parse("div").unwrap())
-            .next()
-            .unwrap()
-            .text()
-            .collect_vec();
-
-        address
-            .iter()
-            .filter_map(|s| {
-                let s = s.trim();
-                if s.is_empty() {
-                    None
-                } else {
-                    Some(s.to_string())
-                }
-            })
-            .join(", ")
-    }
-
-    fn extract_director_details(html_data: &Html) -> HashMap<String, Vec<HashMap<String, String>>> {
-        let html_data = html_data
-            .select(&Selector::parse("div.col-sm-12").unwrap())
-            .nth(5)
-            .unwrap();
-
-        let director_count = html_data
-            .select(&Selector::parse("div.inline-group").unwrap())
-            .next()
-            .unwrap();
-        let mut director_count_data: Vec<HashMap<String, String>> = Vec::new();
-        for row in director_count.select(&Selector::parse("div").unwrap()) {
-            if let Some(key) = row.select(&Selector::parse("b").unwrap()).next() {
-                let value = row
-                    .select(&Selector::parse("span").unwrap())
-                    .next()
-                    .unwrap()
-                    .inner_html();
-                let mut row_data: HashMap<String, String> = HashMap::new();
-                row_data.insert(
-                    key.inner_html().trim().to_string(),
-                    value.trim().to_string(),
-                );
-                director_count_data.push(row_data);
+    corporations::save(id, &response.data).await
+}
+
+pub async fn corporation_get(
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Query(query): Query<CorporationQuery>,
+) -> ApiResponse<CorporationData> {
+    let fields = query.fields.as_deref().map(parse_sections).transpose()?;
+    let sections = match query.sections.as_deref() {
+        Some(sections) => parse_sections(sections)?,
+        None => fields
+            .clone()
+            .unwrap_or_else(|| CorporationSection::ALL.to_vec()),
+    };
+
+    let imported = if query.refresh {
+        None
+    } else {
+        corporations::imported(&id, &sections).await?
+    };
+    // imported from the open data, which holds everything asked for
+    let mut response = match imported {
+        Some(stored) => {
+            response::record_source(Source::OpenData);
+            // open data doesn't say when the registry showed it, so there's no `scraped_at`
+            Envelope::new(StatusCode::OK, stored.data)
+        }
+        // the stored sections are in the language they were scraped in
+        None if CONFIG.differential_scrape && query.lang.is_none() && !query.refresh => {
+            differential_corporation(&id, &sections).await?
+        }
+        None => {
+            let mut response = fetch_corporation(&id, &sections, query.lang).await?;
+            store_fetched(&id, &response).await?;
+            if response.source != Some(Source::OpenData) {
+                response.scraped_at = Some(chrono::Utc::now());
             }
+            response
         }
+    };
+    if let Some(fields) = fields {
+        response.data.retain(&fields);
+    }
+    if minimizes_pii(&principal, query.minimize_pii) {
+        response.data.minimize_pii();
+    }
+    if let Some(lang) = query.lang {
+        labels::localize(&mut response.data, lang);
+    }
 
-        let directors_lists = html_data
-            .select(&Selector::parse("li.full-width").unwrap())
-            .collect_vec();
+    Ok(response)
+}
+
+/// How fresh a section of a differential scrape is, see `differential_corporation`.
+#[derive(Serialize, Debug, Clone)]
+pub struct Freshness {
+    pub source: FreshnessSource,
+    pub scraped_at: chrono::DateTime<chrono::Utc>,
+}
 
-        let mut directors_personal_data: Vec<HashMap<String, String>> = Vec::new();
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FreshnessSource {
+    // fetched by this request
+    Live,
+    // answered from the stored scrape
+    Stored,
+}
 
-        for row in directors_lists {
-            let director_p = row.text().map(|s| s.trim().to_string()).collect_vec();
-            let name = director_p[0].to_string();
-            let address = director_p[1..].join(", ");
-            let mut row_data: HashMap<String, String> = HashMap::new();
-            row_data.insert("name".to_string(), name);
-            row_data.insert("address".to_string(), address);
-            directors_personal_data.push(row_data);
+// Fetches only the volatile sections, and the others the stored scrape lacks or holds for too
+// long, answering the rest from storage. The envelope's `freshness` says which is which.
+async fn differential_corporation(
+    id: &str,
+    sections: &[CorporationSection],
+) -> ApiResponse<CorporationData> {
+    let stored = corporations::scraped(id).await?;
+    let reused = stored
+        .as_ref()
+        .map(|stored| stored.reusable(sections))
+        .unwrap_or_default();
+    let fetched: Vec<_> = sections
+        .iter()
+        .copied()
+        .filter(|section| !reused.contains(section))
+        .collect();
+
+    let mut response = if fetched.is_empty() {
+        Envelope::new(StatusCode::OK, CorporationData::default())
+    } else {
+        let response = fetch_corporation(id, &fetched, None).await?;
+        store_fetched(id, &response).await?;
+        response
+    };
+
+    let now = chrono::Utc::now();
+    let mut freshness: BTreeMap<String, Freshness> = fetched
+        .iter()
+        .map(|section| {
+            let freshness = Freshness {
+                source: FreshnessSource::Live,
+                scraped_at: now,
+            };
+            (section.name().to_string(), freshness)
+        })
+        .collect();
+    if let Some(stored) = &stored {
+        response.data.fill(&stored.data, &reused);
+        enrich(&mut response.data);
+        // reused sections are the ones scraped recently enough
+        for section in &reused {
+            let Some(scraped_at) = stored.section_scraped_at(*section) else {
+                continue;
+            };
+            let freshness_of = Freshness {
+                source: FreshnessSource::Stored,
+                scraped_at,
+            };
+            freshness.insert(section.name().to_string(), freshness_of);
         }
+    }
+    // as old as its oldest section
+    response.scraped_at = freshness
+        .values()
+        .map(|freshness| freshness.scraped_at)
+        .min();
+    response.freshness = Some(freshness);
+
+    Ok(response)
+}
 
-        let mut directors_final_data: HashMap<String, Vec<HashMap<String, String>>> =
-            HashMap::new();
-        directors_final_data.insert("director_count".to_string(), director_count_data.to_vec());
-        directors_final_data.insert(
-            "director_personal_data".to_string(),
-            directors_personal_data.to_vec(),
-        );
+/// What build serves the request, see `--print-build-info`.
+pub async fn version_get() -> ApiResponse<BuildInfo> {
+    Ok(Envelope::new(StatusCode::OK, version::build_info()))
+}
 
-        directors_final_data
+/// Every distinct version of the corporation's data scraped so far, oldest first.
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    cursor: Option<String>,
+    limit: Option<usize>,
+    #[serde(default)]
+    minimize_pii: bool,
+}
+
+pub async fn corporation_history_get(
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> ApiResponse<History> {
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let Some(mut history) = History::find(&id, query.cursor.as_deref(), limit).await? else {
+        return Err(ErrorKind::NotFound(format!("Corporation {} was never scraped", id)).into());
+    };
+    if minimizes_pii(&principal, query.minimize_pii) {
+        history.minimize_pii();
     }
 
-    fn extract_annual_filings_details(html_data: &Html) -> AnnualFilingDetails {
-        let rows = html_data
-            .select(&Selector::parse("div.col-sm-12").unwrap())
-            .nth(7)
-            .unwrap();
-        let rows = rows
-            .select(&Selector::parse("div.data-display-group").unwrap())
-            .collect_vec();
-        let mut data: AnnualFilingDetails = Vec::new();
+    Ok(Envelope::new(StatusCode::OK, history))
+}
 
-        for row in rows {
-            let key = row
-                .select(&Selector::parse("b").unwrap())
-                .next()
-                .unwrap()
-                .text()
-                .map(|s| s.trim().to_string())
-                .join("");
-
-            let value = if key != "Status of Annual Filings" {
-                let value = row
-                    .select(&Selector::parse("div.col-sm-9").unwrap())
-                    .next()
-                    .unwrap()
-                    .text()
-                    .map(|s| s.split(' ').map(|s| s.trim()).join(" "))
-                    .join("")
-                    .trim()
-                    .to_string();
-                Either::Left(value)
-            } else {
-                let status_div = row
-                    .select(&Selector::parse("div.col-sm-9").unwrap())
-                    .next()
-                    .unwrap();
-                let list_elements = status_div
-                    .select(&Selector::parse("li").unwrap())
-                    .collect_vec();
-                let value = list_elements
-                    .iter()
-                    .map(|l| {
-                        let text = l.text().map(|s| s.trim().to_string()).join("");
-                        let text = text.split('-').collect_vec();
-                        let key = text[0].to_string();
-                        let value = text[1].to_string();
-                        let mut row_data: HashMap<String, String> = HashMap::new();
-                        row_data.insert(key, value);
-                        row_data
-                    })
-                    .collect_vec();
-                Either::Right(value)
-            };
+/// Board composition from a fresh scrape of the directors section, which is stored like a
+/// `/api/corporation/:id?sections=director_details` request.
+pub async fn board_get(
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Query(query): Query<PiiQuery>,
+) -> ApiResponse<Board> {
+    let response = fetch_corporation(&id, &[CorporationSection::Directors], None).await?;
+    store_fetched(&id, &response).await?;
+
+    let mut board = Board::from(response.data.directors().unwrap_or_default());
+    if minimizes_pii(&principal, query.minimize_pii) {
+        board.minimize_pii();
+    }
+    Ok(Envelope::new(StatusCode::OK, board))
+}
+
+#[derive(Deserialize)]
+pub struct DirectorSearchQuery {
+    name: String,
+    #[serde(default)]
+    minimize_pii: bool,
+}
+
+pub async fn directors_search(
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<DirectorSearchQuery>,
+) -> ApiResponse<Vec<DirectorMatch>> {
+    if normalize::normalize_name(&query.name).is_empty() {
+        return Err(ErrorKind::BadRequest("name must not be empty".to_string()).into());
+    }
+
+    let mut matches = corporations::find_directors(&query.name).await?;
+    if minimizes_pii(&principal, query.minimize_pii) {
+        matches.iter_mut().for_each(DirectorMatch::minimize_pii);
+    }
+    Ok(Envelope::new(StatusCode::OK, matches))
+}
+
+// Live scrapes of one search, each takes a browser for a while
+const MAX_LIVE_SCRAPES: usize = 10;
+
+#[derive(Deserialize)]
+pub struct SearchByDirectorRequest {
+    name: String,
+    // corporations to scrape the directors of before searching
+    #[serde(default)]
+    corporation_ids: Vec<String>,
+    // also scrape the corporations a registry search for the name finds
+    #[serde(default)]
+    live: bool,
+    #[serde(default)]
+    minimize_pii: bool,
+}
 
-            let mut row_data: HashMap<String, Either<String, Vec<HashMap<String, String>>>> =
-                HashMap::new();
-            row_data.insert(key.trim().to_string(), value);
-            data.push(row_data);
+/// Corporations a person is a director of, from the store like `/api/directors/search`. The
+/// directors of the given corporations, and with `live` of those a registry search for the name
+/// finds, are scraped first, at most `MAX_LIVE_SCRAPES` of them. A failed scrape is a warning.
+pub async fn search_by_director(
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<SearchByDirectorRequest>,
+) -> ApiResponse<Vec<DirectorMatch>> {
+    if normalize::normalize_name(&request.name).is_empty() {
+        return Err(ErrorKind::BadRequest("name must not be empty".to_string()).into());
+    }
+
+    let mut candidates = request.corporation_ids;
+    if request.live {
+        let found = search_corporations(&request.name, Some(MAX_LIVE_SCRAPES)).await?;
+        candidates.extend(
+            found
+                .into_iter()
+                .filter_map(|row| row.get("corporation_number").cloned()),
+        );
+    }
+    let mut seen = HashSet::new();
+    candidates.retain(|id| seen.insert(id.clone()));
+    if candidates.len() > MAX_LIVE_SCRAPES {
+        response::warn(Warning {
+            code: "too_many_candidates",
+            field: "corporation_ids",
+            message: format!(
+                "only the first {} of {} corporations were scraped",
+                MAX_LIVE_SCRAPES,
+                candidates.len()
+            ),
+        });
+        candidates.truncate(MAX_LIVE_SCRAPES);
+    }
+
+    for id in &candidates {
+        let scraped = fetch_corporation(id, &[CorporationSection::Directors], None).await;
+        match scraped {
+            Ok(response) => store_fetched(id, &response).await?,
+            Err(err) => response::warn(Warning {
+                code: "scrape_failed",
+                field: "corporation_ids",
+                message: format!("corporation {} couldn't be scraped: {}", id, err),
+            }),
         }
+    }
 
-        data
+    let mut matches = corporations::find_directors(&request.name).await?;
+    if minimizes_pii(&principal, request.minimize_pii) {
+        matches.iter_mut().for_each(DirectorMatch::minimize_pii);
     }
+    Ok(Envelope::new(StatusCode::OK, matches))
+}
 
-    fn extract_corp_history_details(
-        html_data: &Html,
-    ) -> HashMap<String, Vec<HashMap<String, String>>> {
-        let html_data = html_data
-            .select(&Selector::parse("div.col-sm-12").unwrap())
-            .nth(8)
-            .unwrap();
+#[derive(Deserialize)]
+pub struct ChangesQuery {
+    since: Option<String>,
+    limit: Option<usize>,
+}
 
-        let table_data = html_data
-            .select(&Selector::parse("table").unwrap())
-            .next()
-            .unwrap();
-        let heading = table_data
-            .select(&Selector::parse("thead").unwrap())
-            .next()
-            .unwrap()
-            .text()
-            .map(|s| s.trim().to_string())
-            .join("");
-        let td_data = table_data
-            .select(&Selector::parse("td").unwrap())
-            .collect_vec();
-        let table_info = td_data
-            .iter()
-            .map(|data| {
-                let row_val = data
-                    .text()
-                    .flat_map(|s| s.split(' ').map(|s| s.trim()))
-                    .filter(|s| !s.is_empty())
-                    .collect_vec()
-                    .join(" ");
-                row_val
-            })
-            .collect_vec();
-
-        let name_history_data = table_info
-            .chunks(2)
-            .map(|data| {
-                let key = data[0].to_string();
-                let value = data[1].to_string();
-                let mut row_data: HashMap<String, String> = HashMap::new();
-                row_data.insert(key, value);
-                row_data
-            })
-            .collect_vec();
-
-        let section = html_data
-            .select(&Selector::parse("section.panel-info").unwrap())
-            .next()
-            .unwrap();
-        let section_header = section
-            .select(&Selector::parse("header").unwrap())
-            .next()
-            .unwrap()
-            .text()
-            .map(|s| s.trim().to_string())
-            .join("");
-
-        let panel_body = section
-            .select(&Selector::parse("div.panel-body").unwrap())
-            .next()
-            .unwrap();
-
-        let rows = panel_body
-            .select(&Selector::parse("div.data-display-group").unwrap())
-            .collect_vec();
-        let mut panel_data: Vec<HashMap<String, String>> = Vec::new();
-        for row in rows {
-            let key = row
-                .select(&Selector::parse("b").unwrap())
-                .next()
-                .unwrap()
-                .text()
-                .map(|s| s.trim().to_string())
-                .join("");
-            let value = row
-                .select(&Selector::parse("div.col-sm-6").unwrap())
-                .next()
-                .unwrap()
-                .text()
-                .map(|s| s.trim().to_string())
-                .join("");
-            let mut row_data: HashMap<String, String> = HashMap::new();
-            row_data.insert(key.trim().to_string(), value.trim().to_string());
-            panel_data.push(row_data);
+#[derive(Serialize)]
+pub struct ChangeFeed {
+    changes: Vec<Change>,
+    // pass as `since` to continue after the last change
+    next_cursor: Option<String>,
+}
+
+pub async fn changes_get(Query(query): Query<ChangesQuery>) -> ApiResponse<ChangeFeed> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let changes = Change::since(query.since.as_deref(), limit).await?;
+    let next_cursor = changes
+        .last()
+        .map(|change| change.cursor.clone())
+        .or(query.since);
+
+    Ok(Envelope::new(
+        StatusCode::OK,
+        ChangeFeed {
+            changes,
+            next_cursor,
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct WebhookSubscriberRequest {
+    url: String,
+    // generated when registering without one, kept when updating without one
+    secret: Option<String>,
+    #[serde(default)]
+    events: Vec<EventKind>,
+    #[serde(default)]
+    retry: RetryPolicy,
+}
+
+impl WebhookSubscriberRequest {
+    async fn validate(&self) -> Result<(), AppError> {
+        webhooks::check_url(&self.url)
+            .await
+            .map_err(|err| ErrorKind::BadRequest(format!("Invalid url {}: {}", self.url, err)))?;
+        if !(1..=10).contains(&self.retry.max_attempts) {
+            return Err(ErrorKind::BadRequest(
+                "retry.max_attempts must be between 1 and 10".to_string(),
+            )
+            .into());
         }
 
-        let mut data: HashMap<String, Vec<HashMap<String, String>>> = HashMap::new();
-        data.insert(heading, name_history_data);
-        data.insert(section_header, panel_data);
+        Ok(())
+    }
+}
 
-        data
+// Subscribers are the business of the client that registered them, configured ones of admins
+async fn visible_subscriber(principal: &Principal, id: &str) -> Result<Subscriber, AppError> {
+    Subscriber::find(id)
+        .await?
+        .filter(|subscriber| {
+            principal.is_admin() || subscriber.client.as_deref() == Some(&principal.subject)
+        })
+        .ok_or_else(|| ErrorKind::NotFound(format!("Webhook subscriber {} not found", id)).into())
+}
+
+async fn registered_subscriber(principal: &Principal, id: &str) -> Result<Subscriber, AppError> {
+    let subscriber = visible_subscriber(principal, id).await?;
+    if subscriber.client.is_none() {
+        return Err(ErrorKind::Forbidden(format!(
+            "Webhook subscriber {} is set up in WEBHOOK_SUBSCRIBERS and can't be changed here",
+            id
+        ))
+        .into());
     }
 
-    async fn extract_corporation_data(url: String) -> ApiResponse<CorporationData> {
-        let response = reqwest::get(&url).await.unwrap();
-        let html = response.text().await.unwrap();
-        let document = Html::parse_document(&html);
+    Ok(subscriber)
+}
 
-        let corp_details = CorporationDataExtract::extract_corp_details(&document);
-        let address_details = CorporationDataExtract::extract_address_details(&document);
-        let director_details = CorporationDataExtract::extract_director_details(&document);
-        let annual_filings_details =
-            CorporationDataExtract::extract_annual_filings_details(&document);
-        let corp_history_details = CorporationDataExtract::extract_corp_history_details(&document);
+/// Registers a subscriber for the caller's events, answering with the secret its deliveries are
+/// signed with. It's only shown this once.
+pub async fn webhooks_post(
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<WebhookSubscriberRequest>,
+) -> ApiResponse<Subscriber> {
+    request.validate().await?;
+    let subscriber = Subscriber::new(
+        &principal.subject,
+        request.url,
+        request.secret,
+        request.events,
+        request.retry,
+    );
+    subscriber.save().await?;
+
+    Ok(Envelope::new(StatusCode::CREATED, subscriber))
+}
 
-        let data = CorporationData {
-            corp_details,
-            address_details,
-            director_details,
-            annual_filings_details,
-            corp_history_details,
-        };
+/// The caller's subscribers, every one for admins.
+pub async fn webhooks_get(
+    Extension(principal): Extension<Principal>,
+) -> ApiResponse<Vec<Subscriber>> {
+    let subscribers = Subscriber::all()
+        .await?
+        .into_iter()
+        .filter(|subscriber| {
+            principal.is_admin() || subscriber.client.as_deref() == Some(&principal.subject)
+        })
+        .map(Subscriber::redacted)
+        .collect();
+
+    Ok(Envelope::new(StatusCode::OK, subscribers))
+}
+
+pub async fn webhook_get(
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> ApiResponse<Subscriber> {
+    let subscriber = visible_subscriber(&principal, &id).await?;
+
+    Ok(Envelope::new(StatusCode::OK, subscriber.redacted()))
+}
+
+pub async fn webhook_put(
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+    Json(request): Json<WebhookSubscriberRequest>,
+) -> ApiResponse<Subscriber> {
+    request.validate().await?;
+    let mut subscriber = registered_subscriber(&principal, &id).await?;
+    subscriber.url = request.url;
+    if let Some(secret) = request.secret {
+        subscriber.secret = secret;
+    }
+    subscriber.events = request.events;
+    subscriber.retry = request.retry;
+    subscriber.save().await?;
+
+    Ok(Envelope::new(StatusCode::OK, subscriber.redacted()))
+}
+
+/// Stops deliveries to the subscriber, answering with what it was.
+pub async fn webhook_delete(
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> ApiResponse<Subscriber> {
+    let subscriber = registered_subscriber(&principal, &id).await?;
+    subscriber.delete().await?;
+
+    Ok(Envelope::new(StatusCode::OK, subscriber.redacted()))
+}
+
+/// Every attempt at delivering an event to the subscriber in the last `STORAGE_TTL_SECS`,
+/// oldest first, with the status its receiver answered or why it couldn't be reached.
+pub async fn webhook_deliveries_get(
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<String>,
+) -> ApiResponse<Vec<DeliveryAttempt>> {
+    let subscriber = visible_subscriber(&principal, &id).await?;
+    let attempts = DeliveryAttempt::of(&subscriber.id).await?;
+
+    Ok(Envelope::new(StatusCode::OK, attempts))
+}
+
+#[derive(Deserialize)]
+pub struct WebhookTestRequest {
+    subscriber: String,
+}
+
+/// Sends a signed `webhook.test` event to a subscriber, so it can check its receiver verifies the
+/// signature. Answers with what was sent and the status the receiver answered.
+pub async fn webhook_test(
+    Extension(principal): Extension<Principal>,
+    Json(request): Json<WebhookTestRequest>,
+) -> ApiResponse<Delivery> {
+    let subscriber = visible_subscriber(&principal, &request.subscriber).await?;
+    webhooks::check_url(&subscriber.url)
+        .await
+        .map_err(|err| ErrorKind::BadRequest(format!("Invalid url {}: {}", subscriber.url, err)))?;
+    let event = Event::new(
+        EventKind::WebhookTest,
+        json!({ "subscriber": subscriber.id }),
+    );
+    let delivery = webhooks::deliver(&subscriber, &event, 1)
+        .await
+        .map_err(|err| {
+            ErrorKind::BadGateway(format!("Failed to reach {}: {}", subscriber.url, err))
+        })?;
 
-        Ok((StatusCode::OK, Json(data)))
+    Ok(Envelope::new(StatusCode::OK, delivery))
+}
+
+/// Deletes a corporation's stored data on request. It's hidden at once and deleted with its
+/// history, change feed entries, reports and their documents by the next purge, the answer is the
+/// record kept of the request.
+pub async fn data_delete(
+    Extension(principal): Extension<Principal>,
+    Path(corporation_id): Path<String>,
+) -> ApiResponse<Erasure> {
+    let erasure = Erasure::request(&corporation_id, &principal.subject)
+        .await?
+        .ok_or_else(|| {
+            ErrorKind::NotFound(format!(
+                "No data of corporation {} is stored",
+                corporation_id
+            ))
+        })?;
+
+    Ok(Envelope::new(StatusCode::ACCEPTED, erasure))
+}
+
+#[derive(Deserialize)]
+pub struct OutboxQuery {
+    status: Option<OutboxStatus>,
+}
+
+/// Entries of the webhook outbox, oldest first, e.g. `?status=failed` for the deliveries that
+/// gave up.
+pub async fn webhook_outbox_get(Query(query): Query<OutboxQuery>) -> ApiResponse<Vec<OutboxEntry>> {
+    let entries = OutboxEntry::all()
+        .await?
+        .into_iter()
+        .filter(|entry| query.status.is_none_or(|status| entry.status == status))
+        .collect();
+
+    Ok(Envelope::new(StatusCode::OK, entries))
+}
+
+/// Where the delivery of a webhook event to each of its subscribers stands.
+pub async fn webhook_event_get(Path(id): Path<Uuid>) -> ApiResponse<Vec<OutboxEntry>> {
+    let entries = OutboxEntry::of_event(id).await?;
+    if entries.is_empty() {
+        return Err(ErrorKind::NotFound(format!("Webhook event {} not found", id)).into());
     }
+
+    Ok(Envelope::new(StatusCode::OK, entries))
 }
 
-type AnnualFilingDetails = Vec<HashMap<String, Either<String, Vec<HashMap<String, String>>>>>;
+/// Federal corporation search by name, up to `limit` results.
+pub async fn search_corporations(
+    name: &str,
+    limit: Option<usize>,
+) -> Result<Vec<HashMap<String, String>>, AppError> {
+    Scrap::extract_data(name, limit).await
+}
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CorporationData {
-    corp_details: Vec<HashMap<String, String>>,
-    address_details: String,
-    director_details: HashMap<String, Vec<HashMap<String, String>>>,
-    annual_filings_details: AnnualFilingDetails,
-    corp_history_details: HashMap<String, Vec<HashMap<String, String>>>,
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Ndjson,
 }
 
-pub async fn corporation_get(Path(id): Path<String>) -> ApiResponse<CorporationData> {
-    CorporationDataExtract::extract_corporation_data(CorporationDataExtract::gen_url(id)).await
+#[derive(Deserialize)]
+pub struct RegistriesQuery {
+    #[serde(default)]
+    format: ExportFormat,
 }
 
+/// Every result of a federal search. JSON responses are held in memory and capped at
+/// `SEARCH_MAX_ROWS`, `?format=ndjson` exports any number of rows through a temporary file.
 pub async fn registries_get(
     Path(search_keyword): Path<String>,
-) -> ApiResponse<Vec<HashMap<String, String>>> {
-    let data = Scrap::extract_data(&search_keyword, None).await?;
-    Ok((StatusCode::OK, Json(data)))
+    Query(query): Query<RegistriesQuery>,
+) -> Result<Response, AppError> {
+    robots::check(maintenance::FEDERAL, FEDERAL_SEARCH_URL).await?;
+    let mut pages = pin!(Scrap::search_pages(&search_keyword));
+
+    if query.format == ExportFormat::Ndjson {
+        return export_ndjson(&mut pages).await;
+    }
+
+    let mut data: Vec<HashMap<String, String>> = Vec::new();
+    let mut held = HeldRows::default();
+    while let Some(rows) = pages.try_next().await? {
+        data.extend(rows);
+        held.set(data.len());
+        if data.len() > CONFIG.search_max_rows {
+            return Err(ErrorKind::BadRequest(format!(
+                "More than {} results, use ?format=ndjson to export them",
+                CONFIG.search_max_rows
+            ))
+            .into());
+        }
+    }
+
+    Ok(Envelope::new(StatusCode::OK, data).into_response())
+}
+
+// Rows are written out page by page while scraping, so only one page is in memory. The file is
+// served once the scrape completed, a failure is still reported as an error response.
+async fn export_ndjson(
+    pages: &mut (impl Stream<Item = Result<Vec<HashMap<String, String>>, reqwest::Error>> + Unpin),
+) -> Result<Response, AppError> {
+    let path = std::env::temp_dir().join(format!("search-{}.ndjson", Uuid::new_v4()));
+
+    let spilled = async {
+        let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(&path).await?);
+        let mut count = 0;
+        while let Some(rows) = pages.try_next().await? {
+            for row in rows {
+                file.write_all(&serde_json::to_vec(&row)?).await?;
+                file.write_all(b"\n").await?;
+                count += 1;
+            }
+        }
+        file.flush().await?;
+
+        anyhow::Ok((tokio::fs::File::open(&path).await?, count))
+    }
+    .await;
+    // the open handle keeps the content readable after the file is unlinked
+    let _ = tokio::fs::remove_file(&path).await;
+    let (file, count) = spilled?;
+
+    let headers = [
+        (header::CONTENT_TYPE, "application/x-ndjson".to_string()),
+        (
+            header::HeaderName::from_static("x-total-count"),
+            count.to_string(),
+        ),
+    ];
+    Ok((headers, Body::from_stream(ReaderStream::new(file))).into_response())
+}
+
+pub async fn memory_get() -> ApiResponse<MemoryUsage> {
+    Ok(Envelope::new(StatusCode::OK, metrics::memory_usage()))
+}
+
+pub async fn checkpoints_get() -> ApiResponse<BTreeMap<&'static str, Histogram>> {
+    Ok(Envelope::new(StatusCode::OK, metrics::checkpoints().await))
+}
+
+pub async fn capacity_get() -> ApiResponse<Capacity> {
+    Ok(Envelope::new(StatusCode::OK, capacity::capacity().await?))
+}
+
+pub async fn slo_get() -> ApiResponse<Slo> {
+    Ok(Envelope::new(StatusCode::OK, slo::summary().await))
+}
+
+pub async fn dead_letter_get() -> ApiResponse<Vec<DeadLetter>> {
+    Ok(Envelope::new(StatusCode::OK, dead_letter::list().await?))
+}
+
+// Holds a job scheduled for the off-peak hours back until they come, and answers 202 with when
+// it runs
+async fn defer_job(job: Job) -> ApiResponse<Value> {
+    let run_at = job.run_at.unwrap_or_else(chrono::Utc::now);
+    queue::defer(job.id, &job.client, run_at).await?;
+
+    accepted(&job).await
+}
+
+/// Runs the deferred jobs whose off-peak hours came on instances without workers, returning how
+/// many were started. Jobs of clients running their `max_in_flight` already are deferred again,
+/// to the next release.
+pub async fn run_due() -> Result<usize> {
+    let due = queue::take_due().await?;
+    let mut started = 0;
+    // they're off the set already, so one failing to start doesn't hold back the others
+    for id in &due {
+        let job = match Job::find(*id).await {
+            Ok(Some(job)) => job,
+            Ok(None) => {
+                tracing::warn!("job {} was deferred but no record exists", id);
+                continue;
+            }
+            Err(err) => {
+                tracing::error!("failed to load the off-peak job {}: {}", id, err);
+                continue;
+            }
+        };
+        let Some(running) = clients::Running::try_start(&job.client) else {
+            if let Err(err) = queue::defer(job.id, &job.client, chrono::Utc::now()).await {
+                tracing::error!("failed to defer the off-peak job {}: {}", id, err);
+            }
+            continue;
+        };
+        match job.run().await {
+            Ok(job) => {
+                tokio::spawn(run_detached(job, running));
+                started += 1;
+            }
+            Err(err) => tracing::error!("failed to start the off-peak job {}: {}", id, err),
+        }
+    }
+
+    Ok(started)
+}
+
+// Runs a job inline on instances without workers, off-peak jobs and retries, its result is only
+// in the job record. It counts against the client's `max_in_flight` until it finished.
+async fn run_detached(job: Job, _running: clients::Running) {
+    let id = job.id;
+    let finished = match response::detached(execute(&job)).await {
+        Ok(envelope) => {
+            job.succeed(envelope.status.as_u16(), envelope.language, envelope.data)
+                .await
+        }
+        Err(err) => fail_job(job, &err).await,
+    };
+    if let Err(err) = finished {
+        tracing::error!("failed to record the outcome of job {}: {}", id, err);
+    }
+}
+
+/// Requeues a dead-lettered job as a new job with the same params and client, answering 202
+/// with it right away. The job is admitted like a new request of the client.
+pub async fn dead_letter_retry(Path(id): Path<Uuid>) -> ApiResponse<Job> {
+    // taken before the letter is read, so two retries can't both see it unretried
+    let Some(_claim) = dead_letter::claim(id).await? else {
+        return Err(ErrorKind::Conflict(format!("Job {} is being retried", id)).into());
+    };
+    let letter = dead_letter::find(id)
+        .await?
+        .ok_or_else(|| ErrorKind::NotFound(format!("Job {} isn't dead-lettered", id)))?;
+    if let Some(retried_as) = letter.retried_as {
+        return Err(ErrorKind::Conflict(format!(
+            "Job {} was already retried as {}",
+            id, retried_as
+        ))
+        .into());
+    }
+
+    let failed = &letter.job;
+    let lock = admit(failed.kind, &failed.params, failed.notify.as_deref()).await?;
+    let job = if CONFIG.mode == Mode::Api {
+        Job::enqueue(
+            failed.kind,
+            &failed.params,
+            failed.notify.clone(),
+            &failed.client,
+            lock,
+        )
+        .await?
+    } else {
+        let running = start_running(&failed.client)?;
+        let job = Job::start(
+            failed.kind,
+            &failed.params,
+            failed.notify.clone(),
+            &failed.client,
+        )
+        .await?;
+        tokio::spawn(run_detached(job.clone(), running).map(move |_| drop(lock)));
+        job
+    };
+    dead_letter::retried(letter, &job).await?;
+
+    Ok(Envelope::new(StatusCode::ACCEPTED, job))
+}
+
+pub async fn flags_get() -> ApiResponse<BTreeMap<Flag, bool>> {
+    Ok(Envelope::new(StatusCode::OK, flags::all()))
+}
+
+#[derive(Deserialize)]
+pub struct FlagUpdate {
+    enabled: bool,
+}
+
+pub async fn flag_put(
+    Path(name): Path<String>,
+    Json(update): Json<FlagUpdate>,
+) -> ApiResponse<BTreeMap<Flag, bool>> {
+    let flag = Flag::parse(&name)
+        .ok_or_else(|| ErrorKind::NotFound(format!("Feature flag {} not found", name)))?;
+    flags::set(flag, update.enabled).await?;
+
+    Ok(Envelope::new(StatusCode::OK, flags::all()))
+}
+
+const REGISTRY_API: &str = "https://redacted/cc/api";
+
+#[derive(Deserialize)]
+pub struct DocumentDownloadQuery {
+    // keep a copy in the artifact store
+    #[serde(default)]
+    store: bool,
+}
+
+pub async fn registry_document_download(
+    Path(id): Path<String>,
+    Query(query): Query<DocumentDownloadQuery>,
+) -> Result<Response, AppError> {
+    // decoded already, so it could otherwise hold `/` and `..` into the URL and artifact key
+    if id.is_empty() || !id.bytes().all(|byte| byte.is_ascii_alphanumeric()) {
+        return Err(ErrorKind::BadRequest(format!("Invalid document id '{}'", id)).into());
+    }
+    let url = format!("{}/dcmnts/{}", REGISTRY_API, id);
+    robots::check(maintenance::FEDERAL, &url).await?;
+    politeness::pace(maintenance::FEDERAL).await;
+    let upstream = politeness::client(maintenance::FEDERAL)
+        .get(url)
+        .header("accept", "application/pdf")
+        .send()
+        .await?;
+    if upstream.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(ErrorKind::NotFound(format!("Document {} not found", id)).into());
+    }
+    let upstream = upstream.error_for_status()?;
+
+    let content_type = upstream
+        .headers()
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/pdf")
+        .to_string();
+    let headers = [
+        (header::CONTENT_TYPE, content_type.clone()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.pdf\"", id),
+        ),
+    ];
+
+    if !query.store {
+        let body = Body::from_stream(upstream.bytes_stream());
+        return Ok((headers, body).into_response());
+    }
+
+    let bytes = upstream.bytes().await?;
+    let location = artifacts()
+        .put(&report::document_key(&id), &bytes, &content_type)
+        .await?;
+    tracing::info!("stored document {} at {}", id, location);
+
+    // stored reports are parsed right away so their data is available without the PDF
+    if content_type.starts_with("application/pdf") {
+        match report::parse_profile_report_pdf(bytes.to_vec()).await {
+            Ok(data) => {
+                // without its report nothing would find the document to purge it
+                if let Err(err) = report::store(&id, data).await {
+                    if let Err(err) = artifacts().delete(&report::document_key(&id)).await {
+                        tracing::warn!("failed to delete document {}: {}", id, err);
+                    }
+                    return Err(err.into());
+                }
+            }
+            Err(err) => tracing::warn!("could not parse document {}: {}", id, err),
+        }
+    }
+
+    Ok((headers, bytes).into_response())
 }
 
 #[derive(Deserialize)]
+pub struct ArtifactQuery {
+    // epoch seconds, set by `FilesystemArtifacts::url` ...
+    expires: i64,
+    // ... and signed along with the key
+    signature: String,
+}
+
+/// Artifact links of the filesystem store, e.g. offloaded responses.
+pub async fn artifact_get(
+    Path(key): Path<String>,
+    Query(query): Query<ArtifactQuery>,
+) -> Result<Response, AppError> {
+    // a link to another key or with a later expiry has another signature
+    if !artifacts::verify_link(&key, query.expires, &query.signature) {
+        return Err(ErrorKind::Forbidden(format!("Invalid link to {}", key)).into());
+    }
+    if query.expires < chrono::Utc::now().timestamp() {
+        return Err(ErrorKind::NotFound(format!("The link to {} expired", key)).into());
+    }
+    let path = artifacts::filesystem()
+        .path(&key)
+        .ok_or_else(|| ErrorKind::BadRequest(format!("Invalid artifact key {}", key)))?;
+    let bytes = match tokio::fs::read(&path).await {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(ErrorKind::NotFound(format!("Artifact {} not found", key)).into())
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let content_type = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        Some("pdf") => "application/pdf",
+        Some("html") => "text/html",
+        _ => "application/octet-stream",
+    };
+
+    Ok(([(header::CONTENT_TYPE, content_type)], bytes).into_response())
+}
+
+pub async fn report_parse(
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<PiiQuery>,
+    mut multipart: Multipart,
+) -> ApiResponse<CorporationData> {
+    let field = multipart
+        .next_field()
+        .await
+        .map_err(|err| ErrorKind::BadRequest(err.to_string()))?
+        .ok_or_else(|| ErrorKind::BadRequest("Expected a PDF file upload".to_string()))?;
+    let bytes = field
+        .bytes()
+        .await
+        .map_err(|err| ErrorKind::BadRequest(err.to_string()))?;
+
+    let mut data = report::parse_profile_report_pdf(bytes.to_vec())
+        .await
+        .map_err(|err| ErrorKind::BadRequest(format!("Could not parse report: {}", err)))?;
+    if minimizes_pii(&principal, query.minimize_pii) {
+        data.minimize_pii();
+    }
+
+    Ok(Envelope::new(StatusCode::OK, data))
+}
+
+#[derive(Deserialize, JsonSchema)]
 pub struct RegistryRequest {
     corporate_number: String,
     first_name: String,
@@ -1191,7 +2558,15 @@ async fn request_registry(
     last_name: String,
     phone_number: String,
     email: String,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), AppError> {
+    // every endpoint the request goes through, before any of it is sent
+    for url in [
+        format!("{}/cntcts", REGISTRY_API),
+        format!("{}/dcmnts?crprtnid={}", REGISTRY_API, corporate_number),
+        format!("{}/rqsts", REGISTRY_API),
+    ] {
+        robots::check(maintenance::FEDERAL, &url).await?;
+    }
     let mut scrap = Scrap {
         corporate_number,
         first_name,
@@ -1200,7 +2575,7 @@ async fn request_registry(
         email,
         summarize_data: vec![],
         contact: String::new(),
-        url: "https://redacted/cc/api".to_string(),
+        url: REGISTRY_API.to_string(),
     };
 
     scrap.create_request(&client).await?;
@@ -1212,7 +2587,7 @@ async fn request_registry(
 }
 
 pub async fn registry_request(Json(request): Json<RegistryRequest>) -> ApiResponse<Value> {
-    let client = Client::new();
+    let client = politeness::client(maintenance::FEDERAL);
 
     let RegistryRequest {
         corporate_number,
@@ -1232,10 +2607,10 @@ pub async fn registry_request(Json(request): Json<RegistryRequest>) -> ApiRespon
     )
     .await?;
 
-    Ok((StatusCode::OK, Json(json!("success"))))
+    Ok(Envelope::new(StatusCode::OK, json!("success")))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 pub struct RegistryRequestByName {
     search_keyword: String,
     first_name: String,
@@ -1248,7 +2623,7 @@ pub struct RegistryRequestByName {
 pub async fn registry_request_by_name(
     Json(request): Json<RegistryRequestByName>,
 ) -> ApiResponse<Value> {
-    let client = Client::new();
+    let client = politeness::client(maintenance::FEDERAL);
 
     let RegistryRequestByName {
         search_keyword,
@@ -1259,7 +2634,15 @@ pub async fn registry_request_by_name(
     } = request;
 
     let data = Scrap::extract_data(&search_keyword, Some(1)).await?;
-    let corporate_number = data[0].get("corporation_number").unwrap().to_string();
+    let Some(corporate_number) = data
+        .first()
+        .and_then(|row| row.get("corporation_number"))
+        .cloned()
+    else {
+        return Err(
+            ErrorKind::NotFound(format!("No corporation found for '{}'", search_keyword)).into(),
+        );
+    };
 
     request_registry(
         client.clone(),
@@ -1271,7 +2654,86 @@ pub async fn registry_request_by_name(
     )
     .await?;
 
-    Ok((StatusCode::OK, Json(json!("success"))))
+    Ok(Envelope::new(StatusCode::OK, json!("success")))
+}
+
+#[derive(Deserialize)]
+pub struct NormalizeNameRequest {
+    name: String,
+}
+
+#[derive(Serialize)]
+pub struct NormalizedName {
+    name: String,
+    normalized: String,
+}
+
+pub async fn normalize_name_post(
+    Json(request): Json<NormalizeNameRequest>,
+) -> ApiResponse<NormalizedName> {
+    let normalized = normalize::normalize_name(&request.name);
+
+    Ok(Envelope::new(
+        StatusCode::OK,
+        NormalizedName {
+            name: request.name,
+            normalized,
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct NameCheckRequest {
+    name: String,
+}
+
+/// Name availability before filing, see `name_check::check`.
+pub async fn name_check_post(Json(request): Json<NameCheckRequest>) -> ApiResponse<NameCheck> {
+    if request.name.trim().is_empty() {
+        return Err(ErrorKind::BadRequest("name must not be empty".to_string()).into());
+    }
+    let normalized = normalize::normalize_name(&request.name);
+    if normalized.is_empty() {
+        return Err(ErrorKind::BadRequest(format!(
+            "'{}' has no letters or digits",
+            request.name.trim()
+        ))
+        .into());
+    }
+    // normalizing keeps the last word, so a bare "Inc." would be checked as "INC"
+    if normalize::is_legal_suffix(&normalized) {
+        return Err(ErrorKind::BadRequest(format!(
+            "'{}' is only a legal form, the name needs a distinctive part",
+            request.name.trim()
+        ))
+        .into());
+    }
+
+    Ok(Envelope::new(
+        StatusCode::OK,
+        name_check::check(&request.name).await?,
+    ))
+}
+
+/// The API surface, for integrators to discover.
+pub async fn routes_get() -> ApiResponse<Vec<RouteInfo>> {
+    Ok(Envelope::new(StatusCode::OK, catalog::routes()))
+}
+
+pub async fn schema_get(Path(name): Path<String>) -> ApiResponse<RootSchema> {
+    let schema = match name.as_str() {
+        "corporation-data" => schema_for!(CorporationData),
+        "company-search-result" => schema_for!(CompanySearchResult),
+        "entity-type-result" => schema_for!(EntityTypeResult),
+        "order" => schema_for!(Order),
+        "search-companies-request" => schema_for!(SearchBusinessRegistryParams),
+        "payment-page-request" => schema_for!(RequestBusinessProfileReportParams),
+        "registry-request" => schema_for!(RegistryRequest),
+        "registry-request-by-name" => schema_for!(RegistryRequestByName),
+        _ => return Err(ErrorKind::NotFound(format!("No schema named '{}'", name)).into()),
+    };
+
+    Ok(Envelope::new(StatusCode::OK, schema))
 }
 
-type ApiResponse<T> = Result<(StatusCode, Json<T>), AppError>;
+type ApiResponse<T> = Result<Envelope<T>, AppError>;
@@ -0,0 +1,259 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{lock::Lock, notify, queue, slo, storage, webhooks};
+
+const COLLECTION: &str = "jobs";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    PaymentPage,
+    CompanySearch,
+    EntityType,
+    OrderHistory,
+}
+
+/// When a job may run: right away, or deferred to the `OFF_PEAK` hours like bulk and monitoring
+/// jobs nobody waits on, to leave the registry to interactive requests.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunWindow {
+    #[default]
+    Now,
+    OffPeak,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    // Waiting for a worker to pick it up
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+/// Record of a single browser flow run, persisted so results outlive the request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub params: Value,
+    pub result: Option<Value>,
+    // HTTP status the flow answered its result with, e.g. 404 when nothing was found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_status: Option<u16>,
+    // of the pages the result was scraped from, answered as Content-Language with it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    pub error: Option<String>,
+    // Status, `AppError::class` and Retry-After of the error the job failed with, so waiting
+    // requests answer it like the job had run in them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+    // Address emailed when the job finishes
+    #[serde(default)]
+    pub notify: Option<String>,
+    // Subject of the API client that asked for it, whose queue and quota it counts against
+    #[serde(default)]
+    pub client: String,
+    pub created_at: DateTime<Utc>,
+    // When the flow started running, what wait estimates go by
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    // Earliest it runs, for jobs deferred to the off-peak hours
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+    // Documents of the purchase, downloaded once the registry delivered them, see `deliveries`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deliverables: Vec<Deliverable>,
+}
+
+/// A delivered document of an order, stored as an artifact.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Deliverable {
+    pub order_id: String,
+    pub artifact: String,
+    pub downloaded_at: DateTime<Utc>,
+}
+
+impl Job {
+    pub async fn start<P: Serialize>(
+        kind: JobKind,
+        params: &P,
+        notify: Option<String>,
+        client: &str,
+    ) -> Result<Job> {
+        Job::create(kind, JobStatus::Running, params, notify, client, None).await
+    }
+
+    /// Records the job and pushes it onto the client's queue workers consume. The `lock` the job
+    /// runs under is handed over to it first, the worker releases it once the job finished.
+    pub async fn enqueue<P: Serialize>(
+        kind: JobKind,
+        params: &P,
+        notify: Option<String>,
+        client: &str,
+        lock: Option<Lock>,
+    ) -> Result<Job> {
+        let job = Job::create(kind, JobStatus::Queued, params, notify, client, None).await?;
+        if let Some(lock) = lock {
+            lock.hand_over(job.id).await?;
+        }
+        queue::push(job.id, client).await?;
+
+        Ok(job)
+    }
+
+    /// Records a job that waits until `run_at`, see `queue::defer` for handing it to workers.
+    pub async fn schedule<P: Serialize>(
+        kind: JobKind,
+        params: &P,
+        notify: Option<String>,
+        client: &str,
+        run_at: DateTime<Utc>,
+    ) -> Result<Job> {
+        Job::create(
+            kind,
+            JobStatus::Queued,
+            params,
+            notify,
+            client,
+            Some(run_at),
+        )
+        .await
+    }
+
+    async fn create<P: Serialize>(
+        kind: JobKind,
+        status: JobStatus,
+        params: &P,
+        notify: Option<String>,
+        client: &str,
+        run_at: Option<DateTime<Utc>>,
+    ) -> Result<Job> {
+        let now = Utc::now();
+        let job = Job {
+            id: Uuid::new_v4(),
+            kind,
+            status,
+            params: serde_json::to_value(params)?,
+            result: None,
+            result_status: None,
+            language: None,
+            error: None,
+            error_status: None,
+            error_code: None,
+            retry_after: None,
+            notify,
+            client: client.to_string(),
+            created_at: now,
+            started_at: (status == JobStatus::Running).then_some(now),
+            run_at,
+            updated_at: now,
+            deliverables: vec![],
+        };
+        job.save().await?;
+        job.published();
+
+        Ok(job)
+    }
+
+    /// Marks a queued job as picked up by a worker.
+    pub async fn run(mut self) -> Result<Job> {
+        self.status = JobStatus::Running;
+        self.updated_at = Utc::now();
+        self.started_at = Some(self.updated_at);
+        self.save().await?;
+        self.published();
+        if let Ok(wait) = (self.updated_at - self.created_at).to_std() {
+            slo::record_queue_wait(wait).await;
+        }
+
+        Ok(self)
+    }
+
+    pub async fn succeed(
+        mut self,
+        status: u16,
+        language: Option<String>,
+        result: Value,
+    ) -> Result<Job> {
+        self.status = JobStatus::Succeeded;
+        self.result = Some(result);
+        self.result_status = Some(status);
+        self.language = language;
+        self.updated_at = Utc::now();
+        self.save().await?;
+        self.record_duration().await;
+        self.notify().await;
+        webhooks::job_completed(&self);
+        self.published();
+
+        Ok(self)
+    }
+
+    pub async fn fail(mut self, error: String) -> Result<Job> {
+        self.status = JobStatus::Failed;
+        self.error = Some(error);
+        self.updated_at = Utc::now();
+        self.save().await?;
+        self.record_duration().await;
+        self.notify().await;
+        webhooks::job_completed(&self);
+        self.published();
+
+        Ok(self)
+    }
+
+    pub async fn find(id: Uuid) -> Result<Option<Job>> {
+        storage::load(COLLECTION, &id.to_string()).await
+    }
+
+    pub async fn all() -> Result<Vec<Job>> {
+        storage::load_all(COLLECTION).await
+    }
+
+    pub async fn attach(mut self, deliverable: Deliverable) -> Result<Job> {
+        self.deliverables.push(deliverable);
+        self.updated_at = Utc::now();
+        self.save().await?;
+
+        Ok(self)
+    }
+
+    // Tells the event bus consumers the job moved on
+    fn published(&self) {
+        #[cfg(feature = "event-bus")]
+        crate::event_bus::job_changed(self);
+    }
+
+    async fn record_duration(&self) {
+        let duration = self
+            .started_at
+            .and_then(|started| (self.updated_at - started).to_std().ok());
+        if let Some(duration) = duration {
+            queue::record_duration(duration).await;
+        }
+    }
+
+    async fn notify(&self) {
+        if let Some(to) = &self.notify {
+            notify::job_finished(to, self).await;
+        }
+    }
+
+    async fn save(&self) -> Result<()> {
+        storage::save(COLLECTION, &self.id.to_string(), self).await
+    }
+}
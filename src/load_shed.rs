@@ -0,0 +1,116 @@
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    config::CONFIG,
+    errors::{AppError, ErrorKind},
+};
+
+// Routes that drive a browser or fetch from the registry, everything else is cheap
+const SCRAPE_ROUTES: [&str; 10] = [
+    "/api/test-chrome",
+    "/api/payment-page",
+    "/api/search-companies",
+    "/api/registries/:search_keyword",
+    "/api/registry/request",
+    "/api/registry/request_by_name",
+    "/api/registry/documents/:id/download",
+    "/api/corporation/:id",
+    "/api/corporation/:id/board",
+    "/api/name-check",
+];
+
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+static LOOP_LATENCY_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Measures how late a short sleep wakes up, a proxy for how starved the runtime is.
+pub fn spawn_latency_monitor() {
+    tokio::spawn(async {
+        let interval = Duration::from_millis(100);
+        loop {
+            let started = Instant::now();
+            tokio::time::sleep(interval).await;
+            let lag = started.elapsed().saturating_sub(interval).as_millis() as u64;
+            // smooth out single slow ticks
+            let previous = LOOP_LATENCY_MS.load(Ordering::Relaxed);
+            LOOP_LATENCY_MS.store((previous * 3 + lag) / 4, Ordering::Relaxed);
+        }
+    });
+}
+
+pub fn in_flight() -> usize {
+    IN_FLIGHT.load(Ordering::Relaxed)
+}
+
+pub fn loop_latency_ms() -> u64 {
+    LOOP_LATENCY_MS.load(Ordering::Relaxed)
+}
+
+/// Whether the matched route `path` drives a browser or fetches from the registry.
+pub fn is_scrape(path: &str) -> bool {
+    SCRAPE_ROUTES.contains(&path)
+}
+
+struct InFlight;
+
+impl InFlight {
+    fn enter() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+        InFlight
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Rejects scrape requests with 503 before the instance is overloaded. Requests sent with
+/// `X-Priority: low` are shed first: once `LOAD_SHED_LOW_PRIORITY_IN_FLIGHT` scrapes run or the
+/// event loop lags more than `LOAD_SHED_MAX_LOOP_LATENCY_MS`. Everything is shed past
+/// `LOAD_SHED_MAX_IN_FLIGHT`.
+pub async fn shed(req: Request, next: Next) -> Response {
+    let is_scrape = req
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|path| is_scrape(path.as_str()));
+    if !is_scrape {
+        return next.run(req).await;
+    }
+
+    let low_priority = req
+        .headers()
+        .get("x-priority")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("low"));
+
+    let in_flight = in_flight();
+    let lagging = loop_latency_ms() > CONFIG.load_shed_max_loop_latency_ms;
+    let overloaded = in_flight >= CONFIG.load_shed_max_in_flight
+        || (low_priority && (lagging || in_flight >= CONFIG.load_shed_low_priority_in_flight));
+    if overloaded {
+        tracing::warn!(
+            "shedding {} (in flight: {}, loop latency: {}ms)",
+            req.uri().path(),
+            in_flight,
+            loop_latency_ms()
+        );
+        return AppError::from(ErrorKind::Unavailable {
+            message: "Too many scrapes in progress, try again later".to_string(),
+            retry_after: CONFIG.load_shed_retry_after_secs,
+        })
+        .into_response();
+    }
+
+    let _in_flight = InFlight::enter();
+    next.run(req).await
+}
@@ -0,0 +1,184 @@
+use std::{collections::HashSet, sync::Mutex, time::Duration};
+
+use anyhow::Result;
+use dump_core::normalize::normalize_name;
+use once_cell::sync::Lazy;
+use redis::{aio::ConnectionManager, Script};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::config::CONFIG;
+
+// Keys held by this process when no redis is configured
+static LOCAL_LOCKS: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+static REDIS: OnceCell<ConnectionManager> = OnceCell::const_new();
+
+// Only delete the key if it still holds our token, so an expired lock taken over by another
+// instance is never released by us
+static RELEASE_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        if redis.call("get", KEYS[1]) == ARGV[1] then
+            return redis.call("del", KEYS[1])
+        else
+            return 0
+        end
+        "#,
+    )
+});
+
+// Hands the key over to a new token if it still holds ours, restarting its expiry
+static HAND_OVER_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        if redis.call("get", KEYS[1]) == ARGV[1] then
+            redis.call("set", KEYS[1], ARGV[2], "PX", ARGV[3])
+            return 1
+        else
+            return 0
+        end
+        "#,
+    )
+});
+
+/// Connection shared by everything coordinating through redis.
+pub async fn redis_connection(url: &str) -> Result<ConnectionManager> {
+    let conn = REDIS
+        .get_or_try_init(|| async {
+            let client = redis::Client::open(url)?;
+            ConnectionManager::new(client).await
+        })
+        .await?;
+
+    Ok(conn.clone())
+}
+
+enum Holder {
+    Local,
+    Redis {
+        conn: ConnectionManager,
+        token: String,
+    },
+    // passed on to a job, which releases it with `release`
+    HandedOver,
+}
+
+/// Held by one request or job at a time across the instances sharing the same redis, released
+/// on drop.
+pub struct Lock {
+    key: String,
+    holder: Holder,
+    ttl: Duration,
+}
+
+impl Lock {
+    /// Tries to take `key`, returns `None` if someone else holds it. The redis key expires after
+    /// `ttl`, so a crashed holder doesn't keep it forever.
+    pub async fn try_acquire(key: String, ttl: Duration) -> Result<Option<Self>> {
+        let Some(url) = CONFIG.redis_url.as_deref() else {
+            let acquired = LOCAL_LOCKS.lock().unwrap().insert(key.clone());
+            return Ok(acquired.then_some(Lock {
+                key,
+                holder: Holder::Local,
+                ttl,
+            }));
+        };
+
+        let mut conn = redis_connection(url).await?;
+        let token = Uuid::new_v4().to_string();
+        let acquired: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(&token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await?;
+
+        Ok(acquired.map(|_| Lock {
+            key,
+            holder: Holder::Redis { conn, token },
+            ttl,
+        }))
+    }
+
+    /// Passes the lock on to the job `id`, held until whoever runs it calls `release` with the
+    /// id, or for another `ttl` at most.
+    pub async fn hand_over(mut self, id: Uuid) -> Result<()> {
+        if let Holder::Redis { conn, token } = &mut self.holder {
+            let handed: i32 = HAND_OVER_SCRIPT
+                .key(&self.key)
+                .arg(&*token)
+                .arg(id.to_string())
+                .arg(self.ttl.as_millis() as u64)
+                .invoke_async(conn)
+                .await?;
+            anyhow::ensure!(
+                handed == 1,
+                "{} expired before it was handed over",
+                self.key
+            );
+        }
+        self.holder = Holder::HandedOver;
+
+        Ok(())
+    }
+}
+
+/// Releases `key` if the job `id` still holds it, see `Lock::hand_over`.
+pub async fn release(key: &str, id: Uuid) -> Result<()> {
+    let Some(url) = CONFIG.redis_url.as_deref() else {
+        LOCAL_LOCKS.lock().unwrap().remove(key);
+        return Ok(());
+    };
+
+    let mut conn = redis_connection(url).await?;
+    RELEASE_SCRIPT
+        .key(key)
+        .arg(id.to_string())
+        .invoke_async::<_, i32>(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+pub fn payment_key(company: &str, product: &str) -> String {
+    format!(
+        "payment-lock:{}:{}",
+        normalize_name(company),
+        product.trim().to_lowercase()
+    )
+}
+
+/// Held for the duration of a payment flow for `(company, product)`, `None` if another request
+/// already runs one.
+pub async fn payment_lock(company: &str, product: &str) -> Result<Option<Lock>> {
+    let ttl = Duration::from_secs(CONFIG.payment_lock_ttl_secs);
+    Lock::try_acquire(payment_key(company, product), ttl).await
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        match &self.holder {
+            Holder::Local => {
+                LOCAL_LOCKS.lock().unwrap().remove(&self.key);
+            }
+            Holder::HandedOver => {}
+            Holder::Redis { conn, token } => {
+                let mut conn = conn.clone();
+                let key = std::mem::take(&mut self.key);
+                let token = token.clone();
+                tokio::spawn(async move {
+                    let released: redis::RedisResult<i32> = RELEASE_SCRIPT
+                        .key(&key)
+                        .arg(&token)
+                        .invoke_async(&mut conn)
+                        .await;
+                    if let Err(err) = released {
+                        tracing::warn!("failed to release {}: {}", key, err);
+                    }
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use zeroize::Zeroizing;
+
+use crate::{
+    browser,
+    cards::{self, Secret},
+    config::CONFIG,
+    cookies::Cookie,
+    session::Driver,
+    txlog::{self, Action},
+};
+
+pub const SIGN_IN_URL: &str = "redacted";
+const USERNAME: &str = "//input[@id='username']";
+const PASSWORD: &str = "//input[@id='password']";
+const SUBMIT: &str = "//button[@type='submit']";
+const MFA_CODE: &str = "//input[@autocomplete='one-time-code']";
+// shown on every registry page while signed in
+const SIGNED_IN: &str = "//a[contains(text(), 'Sign Out')]";
+
+const STEP_TIMEOUT: Duration = Duration::from_secs(20);
+// How long the one-time code prompt takes to show up after the password, if it does
+const MFA_PROMPT_TIMEOUT: Duration = Duration::from_secs(10);
+const MFA_COMMAND_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// ONe-key account of the Ontario Business Registry, which account-only products are bought with.
+#[derive(Serialize, Deserialize)]
+pub struct Account {
+    pub username: Secret,
+    pub password: Secret,
+}
+
+impl fmt::Debug for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Account({})", self.username.expose())
+    }
+}
+
+static ACCOUNT: OnceCell<Option<Account>> = OnceCell::new();
+
+// Cookies of the last signed in session, reused by later flows until `OBR_SESSION_TTL_SECS`
+static SESSION: Mutex<Option<(Instant, Vec<Cookie>)>> = Mutex::new(None);
+
+/// Whether an account is set up, flows check out as guests otherwise.
+pub fn configured() -> bool {
+    CONFIG.obr_account.is_some() || CONFIG.obr_username.is_some()
+}
+
+/// Loads the account labelled `OBR_ACCOUNT` from `CARD_VAULT`, or the plaintext `OBR_USERNAME`
+/// and `OBR_PASSWORD` settings.
+pub async fn load() -> Result<()> {
+    let account = match (&CONFIG.obr_account, &CONFIG.card_vault) {
+        (Some(label), Some(path)) => Some(cards::account(path, label).await?),
+        (Some(_), None) => anyhow::bail!("OBR_ACCOUNT names an account in CARD_VAULT, set it too"),
+        (None, _) => match (&CONFIG.obr_username, &CONFIG.obr_password) {
+            (Some(username), Some(password)) => Some(Account {
+                username: username.clone(),
+                password: password.clone(),
+            }),
+            (None, None) => None,
+            _ => anyhow::bail!("OBR_USERNAME and OBR_PASSWORD are only used together"),
+        },
+    };
+    if let Some(account) = &account {
+        tracing::info!("signing in to the registry as {:?}", account);
+    }
+
+    ACCOUNT
+        .set(account)
+        .map_err(|_| anyhow::anyhow!("registry account already loaded"))
+}
+
+/// Masks the loaded account's password in `text`, like `cards::redact` does card details.
+pub fn redact(text: &str) -> String {
+    match ACCOUNT.get().and_then(Option::as_ref) {
+        Some(account) => text.replace(account.password.expose(), "[password]"),
+        None => text.to_string(),
+    }
+}
+
+// The code `OBR_MFA_COMMAND` prints, e.g. a script reading the account's authenticator or inbox
+async fn mfa_code() -> Result<Zeroizing<String>> {
+    let command = CONFIG
+        .obr_mfa_command
+        .as_deref()
+        .context("the registry asks for a one-time code and OBR_MFA_COMMAND isn't set")?;
+    let output = tokio::time::timeout(
+        MFA_COMMAND_TIMEOUT,
+        Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .context("OBR_MFA_COMMAND timed out")??;
+    anyhow::ensure!(
+        output.status.success(),
+        "OBR_MFA_COMMAND failed with {}",
+        output.status
+    );
+
+    let stdout = Zeroizing::new(output.stdout);
+    let code = Zeroizing::new(std::str::from_utf8(&stdout)?.trim().to_string());
+    anyhow::ensure!(!code.is_empty(), "OBR_MFA_COMMAND printed no code");
+
+    Ok(code)
+}
+
+fn saved_session() -> Option<Vec<Cookie>> {
+    let ttl = Duration::from_secs(CONFIG.obr_session_ttl_secs);
+    let mut session = SESSION.lock().unwrap();
+    if session.as_ref().is_some_and(|(at, _)| at.elapsed() >= ttl) {
+        *session = None;
+    }
+    session.as_ref().map(|(_, cookies)| cookies.clone())
+}
+
+// Whether a cookie set for `domain` is sent to `host`, the domain itself or any subdomain of it
+fn domain_matches(domain: &str, host: &str) -> bool {
+    let domain = domain.trim_start_matches('.');
+    host.eq_ignore_ascii_case(domain)
+        || host
+            .to_ascii_lowercase()
+            .ends_with(&format!(".{}", domain.to_ascii_lowercase()))
+}
+
+/// `Cookie` header of the signed in session for requests to `host`, for downloads outside the
+/// browser. `None` once the session expired or if none of its cookies is for the host.
+pub fn cookie_header(host: &str) -> Option<String> {
+    let cookies = saved_session()?;
+    let pairs: Vec<String> = cookies
+        .iter()
+        .filter(|cookie| domain_matches(&cookie.domain, host))
+        .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+        .collect();
+
+    (!pairs.is_empty()).then(|| pairs.join("; "))
+}
+
+// Whether the cookies of an earlier sign in still hold
+async fn resume(driver: &dyn Driver, cookies: &[Cookie]) -> Result<bool> {
+    // cookies are only set for the domain the session is on
+    driver.goto(SIGN_IN_URL).await?;
+    for cookie in cookies {
+        driver.add_cookie(cookie).await?;
+    }
+    driver.goto(SIGN_IN_URL).await?;
+
+    Ok(browser::exists(driver, SIGNED_IN, MFA_PROMPT_TIMEOUT).await?)
+}
+
+/// Signs the session in to the registry with the loaded account, so account-only products can be
+/// ordered. The cookies of a sign in are reused by later sessions while they're fresh, one-time
+/// codes come from `OBR_MFA_COMMAND`. Does nothing without an account.
+pub async fn sign_in(driver: &dyn Driver) -> Result<()> {
+    let Some(account) = ACCOUNT.get().and_then(Option::as_ref) else {
+        return Ok(());
+    };
+
+    if let Some(cookies) = saved_session() {
+        if resume(driver, &cookies).await? {
+            txlog::record(driver, Action::Navigate, "signed in session").await;
+            return Ok(());
+        }
+        *SESSION.lock().unwrap() = None;
+    }
+
+    driver.goto(SIGN_IN_URL).await?;
+    txlog::record(driver, Action::Navigate, "sign in").await;
+    browser::send_keys(driver, USERNAME, STEP_TIMEOUT, account.username.expose()).await?;
+    browser::send_keys(driver, PASSWORD, STEP_TIMEOUT, account.password.expose()).await?;
+    browser::submit(driver, SUBMIT, STEP_TIMEOUT, "sign in").await?;
+    if browser::exists(driver, MFA_CODE, MFA_PROMPT_TIMEOUT).await? {
+        browser::send_keys(driver, MFA_CODE, STEP_TIMEOUT, mfa_code().await?).await?;
+        browser::submit(driver, SUBMIT, STEP_TIMEOUT, "one-time code").await?;
+    }
+    anyhow::ensure!(
+        browser::exists(driver, SIGNED_IN, STEP_TIMEOUT).await?,
+        "signing in to the registry as {:?} didn't succeed",
+        account
+    );
+
+    *SESSION.lock().unwrap() = Some((Instant::now(), driver.get_cookies().await?));
+
+    Ok(())
+}
@@ -1,26 +1,140 @@
+mod alerts;
+mod allowlist;
+mod artifacts;
+mod audit;
+mod auth;
+mod browser;
+mod capacity;
+mod cards;
+mod catalog;
+#[cfg(feature = "cdp")]
+mod cdp;
+mod chaos;
+mod chromedriver;
+mod clients;
+mod compat;
 mod config;
+mod cookies;
+mod corporations;
+mod dead_letter;
+mod deliveries;
 mod errors;
+#[cfg(feature = "event-bus")]
+mod event_bus;
+mod flags;
+mod flow;
 mod handler;
+mod jobs;
+mod load_shed;
+mod lock;
+mod login;
+mod maintenance;
+mod metrics;
+mod name_check;
+mod notify;
+mod off_peak;
+mod offload;
+mod open_data;
+mod panics;
+mod politeness;
+mod pool;
+mod popups;
+mod products;
+mod queue;
+mod recording;
+mod report;
+mod response;
+mod retention;
+mod retry;
+mod robots;
+mod scrape;
+mod search_cache;
+mod session;
+mod slo;
+mod smoke;
+mod storage;
+mod support_bundle;
+#[cfg(all(any(debug_assertions, feature = "ecs"), not(feature = "lambda")))]
+mod tls;
+mod txlog;
+mod verify;
+mod version;
+mod webhooks;
+mod worker;
 use anyhow::Result;
 use axum::{
-    extract::Request,
-    http::{self, StatusCode},
-    middleware::{self, Next},
-    response::Response,
-    routing::{get, post},
+    http::Method,
+    middleware,
+    routing::{delete, get, post, put},
     Router,
 };
-use config::CONFIG;
-use tower_http::{compression::CompressionLayer, cors::CorsLayer, trace::TraceLayer};
+use catalog::{Catalog, Scope};
+use config::{Command, Mode, StorageBackend, CONFIG};
+use tower_http::{
+    catch_panic::CatchPanicLayer, compression::CompressionLayer, cors::CorsLayer, trace::TraceLayer,
+};
 use tracing::Level;
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    if std::env::args()
+        .skip(1)
+        .any(|arg| arg == "--print-build-info")
+    {
+        version::print();
+        return Ok(());
+    }
     configure_tracing();
+    panics::install_hook();
+    dump_core::patterns::check()?;
+    products::load()?;
+    cookies::load()?;
+    politeness::load()?;
+    popups::load()?;
+    webhooks::load()?;
+    clients::load()?;
+    maintenance::load()?;
+    off_peak::load()?;
+    match &CONFIG.command {
+        Some(Command::SmokeTest { company, json }) => return smoke::run(company, *json).await,
+        Some(Command::CardVault { action }) => return cards::manage(action).await,
+        Some(Command::Scrape { target, output }) => return scrape::run(target, *output).await,
+        Some(Command::ImportOpenData { files }) => return open_data::import(files).await,
+        None => {}
+    }
+    check_mode()?;
+    version::check()?;
+    // `api` instances never pay
+    if CONFIG.mode != Mode::Api {
+        cards::load().await?;
+        login::load().await?;
+    }
+    chromedriver::check_on_startup().await?;
+    storage::init().await?;
+    artifacts::init().await?;
+    flags::init();
+    chaos::init();
+    webhooks::spawn_dispatcher();
+    retention::spawn_purger();
+
+    if CONFIG.mode != Mode::Api {
+        pool::spawn_keepalive();
+        deliveries::spawn_poller();
+    }
+    // workers release the off-peak jobs of `api` instances
+    if CONFIG.mode == Mode::All && CONFIG.redis_url.is_some() {
+        off_peak::spawn_releaser();
+    }
+    if CONFIG.mode == Mode::Worker {
+        return worker::run().await;
+    }
+    verify::spawn();
+    load_shed::spawn_latency_monitor();
+    slo::spawn_publisher();
 
     let app = router()?;
 
-    #[cfg(any(debug_assertions, feature = "ecs"))]
+    #[cfg(all(any(debug_assertions, feature = "ecs"), not(feature = "lambda")))]
     axum_http(app).await?;
 
     #[cfg(feature = "lambda")]
@@ -29,13 +143,37 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-#[cfg(any(debug_assertions, feature = "ecs"))]
+// `api` and `worker` instances only see each other's jobs through shared stores
+fn check_mode() -> Result<()> {
+    if CONFIG.mode != Mode::All {
+        anyhow::ensure!(
+            CONFIG.redis_url.is_some(),
+            "api and worker modes need REDIS_URL for the job queue"
+        );
+        anyhow::ensure!(
+            CONFIG.storage_backend == StorageBackend::Dynamodb,
+            "api and worker modes need --storage-backend dynamodb"
+        );
+    }
+
+    Ok(())
+}
+
+// debug builds of `lambda` answer invocations like release ones, e.g. under `cargo lambda watch`
+#[cfg(all(any(debug_assertions, feature = "ecs"), not(feature = "lambda")))]
 async fn axum_http(app: Router) -> Result<()> {
     let addr = std::net::SocketAddr::from(([0, 0, 0, 0], config::CONFIG.port));
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let app = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
     println!("🚀 listening on: {}", addr);
 
+    match tls::config()? {
+        Some(tls) => axum_server::bind_rustls(addr, tls).serve(app).await?,
+        None => {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            axum::serve(listener, app).await?;
+        }
+    }
+
     Ok(())
 }
 
@@ -55,10 +193,27 @@ async fn lambda_http(app: Router) -> Result<()> {
 
 fn router() -> Result<Router> {
     let app = routes()
+        // innermost, bodies are still uncompressed and of known size there
+        .layer(middleware::from_fn(offload::offload))
+        // sees what the client sent and got, offloaded bodies as the link to them
+        .layer(middleware::from_fn(audit::capture_exchange))
         .layer(CorsLayer::permissive())
         .layer(TraceLayer::new_for_http())
         .layer(CompressionLayer::new().gzip(true).deflate(true))
-        .route_layer(middleware::from_fn(auth));
+        // inside the alerts and objectives, so a panicked handler counts as the 500 it answers
+        .layer(CatchPanicLayer::custom(panics::recover))
+        .route_layer(middleware::from_fn(alerts::track))
+        .route_layer(middleware::from_fn(load_shed::shed))
+        // outside the shedding, a shed request missed its objective too
+        .route_layer(middleware::from_fn(slo::track))
+        .route_layer(middleware::from_fn(catalog::authorize))
+        .route_layer(middleware::from_fn(auth::auth))
+        .layer(middleware::from_fn(allowlist::check))
+        // around the middlewares too, so their panics don't drop the connection...
+        .layer(CatchPanicLayer::custom(panics::recover))
+        // ... and outermost, so the refusals of the allowlist, auth and load shedding carry the
+        // request id and trace like any other answer
+        .layer(middleware::from_fn(response::context));
 
     Ok(app)
 }
@@ -66,18 +221,240 @@ fn router() -> Result<Router> {
 fn routes() -> Router {
     use handler::*;
 
-    Router::new()
-        .route("/healthz", get(health_check))
-        .route("/api/test-chrome", get(test_handler))
-        .route("/api/payment-page", post(get_payment_page_handler))
-        .route("/api/search-companies", post(get_companies_list_handler))
-        .route("/api/registries/:search_keyword", get(registries_get))
-        .route("/api/registry/request", post(registry_request))
+    Catalog::default()
+        .route("/healthz", &[Method::GET], Scope::Client, get(health_check))
+        .route("/readyz", &[Method::GET], Scope::Client, get(readiness_get))
+        .route(
+            "/api/test-chrome",
+            &[Method::GET],
+            Scope::Client,
+            get(test_handler),
+        )
+        .route(
+            "/api/payment-page",
+            &[Method::POST],
+            Scope::Client,
+            post(get_payment_page_handler),
+        )
+        .route(
+            "/api/search-companies",
+            &[Method::POST, Method::GET],
+            Scope::Client,
+            post(get_companies_list_handler).get(search_companies_get),
+        )
+        .route(
+            "/api/registries/:search_keyword",
+            &[Method::GET],
+            Scope::Client,
+            get(registries_get),
+        )
+        .route(
+            "/api/registry/request",
+            &[Method::POST],
+            Scope::Client,
+            post(registry_request),
+        )
         .route(
             "/api/registry/request_by_name",
+            &[Method::POST],
+            Scope::Client,
             post(registry_request_by_name),
         )
-        .route("/api/corporation/:id", get(corporation_get))
+        .route(
+            "/api/registry/entity-type/:number",
+            &[Method::GET],
+            Scope::Client,
+            get(entity_type_get),
+        )
+        .route(
+            "/api/registry/orders",
+            &[Method::GET],
+            Scope::Admin,
+            get(orders_get),
+        )
+        .route(
+            "/api/registry/documents/:id/download",
+            &[Method::GET],
+            Scope::Client,
+            get(registry_document_download),
+        )
+        .route(
+            "/api/reports/parse",
+            &[Method::POST],
+            Scope::Client,
+            post(report_parse),
+        )
+        .route(
+            "/api/normalize-name",
+            &[Method::POST],
+            Scope::Client,
+            post(normalize_name_post),
+        )
+        .route(
+            "/api/name-check",
+            &[Method::POST],
+            Scope::Client,
+            post(name_check_post),
+        )
+        .route(
+            "/api/corporation/:id",
+            &[Method::GET],
+            Scope::Client,
+            get(corporation_get),
+        )
+        .route(
+            "/api/corporation/:id/board",
+            &[Method::GET],
+            Scope::Client,
+            get(board_get),
+        )
+        .route(
+            "/api/corporation/:id/history",
+            &[Method::GET],
+            Scope::Client,
+            get(corporation_history_get),
+        )
+        .route(
+            "/api/directors/search",
+            &[Method::GET],
+            Scope::Client,
+            get(directors_search),
+        )
+        .route(
+            "/api/search-by-director",
+            &[Method::POST],
+            Scope::Client,
+            post(search_by_director),
+        )
+        .route(
+            "/api/changes",
+            &[Method::GET],
+            Scope::Client,
+            get(changes_get),
+        )
+        .route("/api/jobs/:id", &[Method::GET], Scope::Client, get(job_get))
+        .route(
+            "/api/webhooks",
+            &[Method::POST, Method::GET],
+            Scope::Client,
+            post(webhooks_post).get(webhooks_get),
+        )
+        .route(
+            "/api/webhooks/:id",
+            &[Method::GET, Method::PUT, Method::DELETE],
+            Scope::Client,
+            get(webhook_get).put(webhook_put).delete(webhook_delete),
+        )
+        .route(
+            "/api/webhooks/:id/deliveries",
+            &[Method::GET],
+            Scope::Client,
+            get(webhook_deliveries_get),
+        )
+        .route(
+            "/api/webhooks/test",
+            &[Method::POST],
+            Scope::Client,
+            post(webhook_test),
+        )
+        .route(
+            "/api/jobs/:id/log",
+            &[Method::GET],
+            Scope::Client,
+            get(job_log_get),
+        )
+        .route(
+            "/api/jobs/:id/support-bundle",
+            &[Method::GET],
+            Scope::Client,
+            get(support_bundle_get),
+        )
+        .route(
+            "/api/metrics/memory",
+            &[Method::GET],
+            Scope::Client,
+            get(memory_get),
+        )
+        .route(
+            "/api/metrics/checkpoints",
+            &[Method::GET],
+            Scope::Client,
+            get(checkpoints_get),
+        )
+        .route(
+            "/api/admin/capacity",
+            &[Method::GET],
+            Scope::Admin,
+            get(capacity_get),
+        )
+        .route("/api/admin/slo", &[Method::GET], Scope::Admin, get(slo_get))
+        .route(
+            "/api/admin/flags",
+            &[Method::GET],
+            Scope::Admin,
+            get(flags_get),
+        )
+        .route(
+            "/api/admin/dead-letter",
+            &[Method::GET],
+            Scope::Admin,
+            get(dead_letter_get),
+        )
+        .route(
+            "/api/admin/dead-letter/:id/retry",
+            &[Method::POST],
+            Scope::Admin,
+            post(dead_letter_retry),
+        )
+        .route(
+            "/api/admin/data/:corporation_id",
+            &[Method::DELETE],
+            Scope::Admin,
+            delete(data_delete),
+        )
+        .route(
+            "/api/admin/flags/:name",
+            &[Method::PUT],
+            Scope::Admin,
+            put(flag_put),
+        )
+        .route(
+            "/api/admin/webhooks/outbox",
+            &[Method::GET],
+            Scope::Admin,
+            get(webhook_outbox_get),
+        )
+        .route(
+            "/api/admin/webhooks/events/:id",
+            &[Method::GET],
+            Scope::Admin,
+            get(webhook_event_get),
+        )
+        .route(
+            "/api/version",
+            &[Method::GET],
+            Scope::Client,
+            get(version_get),
+        )
+        .route(
+            "/api/routes",
+            &[Method::GET],
+            Scope::Client,
+            get(routes_get),
+        )
+        .route(
+            "/api/schema/:type",
+            &[Method::GET],
+            Scope::Client,
+            get(schema_get),
+        )
+        .route(
+            "/api/artifacts/*key",
+            &[Method::GET],
+            Scope::Client,
+            get(artifact_get),
+        )
+        .into_router()
 }
 
 fn configure_tracing() {
@@ -93,18 +470,3 @@ fn configure_tracing() {
         .without_time()
         .init();
 }
-
-async fn auth(req: Request, next: Next) -> Result<Response, StatusCode> {
-    let auth_header = req
-        .headers()
-        .get(http::header::AUTHORIZATION)
-        .and_then(|header| header.to_str().ok());
-
-    if let Some(auth_header) = auth_header {
-        if auth_header == CONFIG.token {
-            return Ok(next.run(req).await);
-        }
-    }
-
-    Err(StatusCode::UNAUTHORIZED)
-}
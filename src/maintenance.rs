@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Timelike, Utc};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+use crate::{
+    config::CONFIG,
+    errors::{AppError, ErrorKind},
+};
+
+// Provider of the federal corporation profiles, the Ontario one is `cookies::REGISTRY`
+pub const FEDERAL: &str = "federal";
+
+// How far ahead the end of a window is looked for, longer windows are answered with this
+const MAX_LOOKAHEAD_MINS: i64 = 7 * 24 * 60;
+
+/// One field of a cron expression: the values it matches, and whether it was `*`.
+#[derive(Debug)]
+struct Field {
+    values: Vec<u32>,
+    any: bool,
+}
+
+impl Field {
+    fn parse(field: &str, min: u32, max: u32) -> Result<Field> {
+        let mut values = vec![];
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>()?),
+                None => (part, 1),
+            };
+            anyhow::ensure!(step > 0, "a step of 0 in {}", field);
+            let (start, end) = match range {
+                "*" => (min, max),
+                range => match range.split_once('-') {
+                    Some((start, end)) => (start.parse()?, end.parse()?),
+                    // `5/10` runs from 5 to the end
+                    None if part.contains('/') => (range.parse()?, max),
+                    None => (range.parse()?, range.parse()?),
+                },
+            };
+            anyhow::ensure!(
+                min <= start && start <= end && end <= max,
+                "{} is out of {}-{}",
+                part,
+                min,
+                max
+            );
+            values.extend((start..=end).step_by(step as usize));
+        }
+
+        Ok(Field {
+            values,
+            any: field == "*",
+        })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.values.contains(&value)
+    }
+}
+
+/// Minutes a cron expression matches, `minute hour day-of-month month day-of-week` like crontab.
+#[derive(Debug)]
+pub struct Schedule {
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+impl Schedule {
+    pub fn parse(cron: &str) -> Result<Schedule> {
+        let fields: Vec<&str> = cron.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            anyhow::bail!("{:?} doesn't have 5 fields", cron);
+        };
+
+        Ok(Schedule {
+            minute: Field::parse(minute, 0, 59)?,
+            hour: Field::parse(hour, 0, 23)?,
+            day: Field::parse(day, 1, 31)?,
+            month: Field::parse(month, 1, 12)?,
+            // 0 and 7 are both Sunday
+            weekday: Field::parse(weekday, 0, 7)?,
+        })
+    }
+
+    pub fn matches(&self, at: &DateTime<FixedOffset>) -> bool {
+        let weekday = at.weekday().num_days_from_sunday();
+        let weekday = self.weekday.matches(weekday) || (weekday == 0 && self.weekday.matches(7));
+        let day = self.day.matches(at.day());
+        // like cron, restricting both days matches either
+        let day = match (self.day.any, self.weekday.any) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+
+        self.minute.matches(at.minute())
+            && self.hour.matches(at.hour())
+            && self.month.matches(at.month())
+            && day
+    }
+}
+
+#[derive(Deserialize)]
+struct WindowSpec {
+    cron: String,
+    // of the times in `cron`, e.g. "-05:00", UTC if unset
+    #[serde(default)]
+    utc_offset: Option<String>,
+}
+
+struct Window {
+    schedule: Schedule,
+    offset: FixedOffset,
+}
+
+/// An offset like "-05:00", UTC if unset.
+pub fn utc_offset(offset: Option<&str>) -> Result<FixedOffset> {
+    match offset {
+        Some(offset) => offset
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid UTC offset {:?}", offset)),
+        None => Ok(FixedOffset::east_opt(0).unwrap()),
+    }
+}
+
+static WINDOWS: OnceCell<BTreeMap<String, Vec<Window>>> = OnceCell::new();
+
+/// Loads `MAINTENANCE_WINDOWS`, a JSON object of provider name to windows whose every minute is
+/// one `cron` matches, e.g. `{"registry": [{"cron": "* 2-3 * * *", "utc_offset": "-05:00"}]}`
+/// for the two hours from 2am Eastern Standard Time.
+pub fn load() -> Result<()> {
+    let mut windows = BTreeMap::new();
+    if let Some(path) = &CONFIG.maintenance_windows {
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let specs: BTreeMap<String, Vec<WindowSpec>> = serde_json::from_str(&file)
+            .with_context(|| format!("invalid maintenance windows in {}", path.display()))?;
+        for (provider, specs) in specs {
+            let parsed = specs
+                .iter()
+                .map(|spec| {
+                    Ok(Window {
+                        schedule: Schedule::parse(&spec.cron).with_context(|| {
+                            format!("invalid maintenance window of {}", provider)
+                        })?,
+                        offset: utc_offset(spec.utc_offset.as_deref())?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            windows.insert(provider, parsed);
+        }
+    }
+
+    WINDOWS
+        .set(windows)
+        .map_err(|_| anyhow::anyhow!("maintenance windows already loaded"))
+}
+
+fn in_window(provider: &str, at: DateTime<Utc>) -> bool {
+    WINDOWS
+        .get()
+        .and_then(|windows| windows.get(provider))
+        .is_some_and(|windows| {
+            windows
+                .iter()
+                .any(|window| window.schedule.matches(&at.with_timezone(&window.offset)))
+        })
+}
+
+/// End of the maintenance window `provider` is in at `now`, none outside windows.
+pub fn ends_at(provider: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if !in_window(provider, now) {
+        return None;
+    }
+
+    let start = now.with_second(0)?.with_nanosecond(0)?;
+    let end = (1..=MAX_LOOKAHEAD_MINS)
+        .map(|minutes| start + Duration::minutes(minutes))
+        .find(|at| !in_window(provider, *at))
+        .unwrap_or(start + Duration::minutes(MAX_LOOKAHEAD_MINS));
+    Some(end)
+}
+
+/// Fails with `ErrorKind::MaintenanceWindow` while `provider` is in a maintenance window, whose
+/// scrapes would only fail.
+pub fn check(provider: &str) -> Result<(), AppError> {
+    let now = Utc::now();
+    let Some(end) = ends_at(provider, now) else {
+        return Ok(());
+    };
+
+    Err(ErrorKind::MaintenanceWindow {
+        message: format!(
+            "Scrapes of the {} provider are paused for maintenance until {}",
+            provider,
+            end.to_rfc3339()
+        ),
+        retry_after: (end - now).num_seconds().max(1) as u64,
+    }
+    .into())
+}
@@ -0,0 +1,242 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::CONFIG, lock::redis_connection};
+
+// Search result rows currently buffered by requests
+static ROWS_IN_MEMORY: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts rows a request holds in memory towards `search_rows_in_memory`, until dropped.
+#[derive(Default)]
+pub struct HeldRows(usize);
+
+impl HeldRows {
+    pub fn set(&mut self, rows: usize) {
+        if rows >= self.0 {
+            ROWS_IN_MEMORY.fetch_add(rows - self.0, Ordering::Relaxed);
+        } else {
+            ROWS_IN_MEMORY.fetch_sub(self.0 - rows, Ordering::Relaxed);
+        }
+        self.0 = rows;
+    }
+}
+
+impl Drop for HeldRows {
+    fn drop(&mut self) {
+        ROWS_IN_MEMORY.fetch_sub(self.0, Ordering::Relaxed);
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct MemoryUsage {
+    // resident set size now and at its highest, from /proc (absent on other platforms)
+    pub rss_bytes: Option<u64>,
+    pub peak_rss_bytes: Option<u64>,
+    pub search_rows_in_memory: usize,
+}
+
+// `VmRSS:    123456 kB` style lines of /proc/self/status
+fn proc_status_kb(status: &str, key: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .and_then(|value| value.trim().strip_suffix("kB"))
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+}
+
+pub fn memory_usage() -> MemoryUsage {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+
+    MemoryUsage {
+        rss_bytes: proc_status_kb(&status, "VmRSS"),
+        peak_rss_bytes: proc_status_kb(&status, "VmHWM"),
+        search_rows_in_memory: ROWS_IN_MEMORY.load(Ordering::Relaxed),
+    }
+}
+
+/// Page transitions of the registry flows, timed so a slowdown of the registry is pinned to the
+/// page that got slower.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Checkpoint {
+    // from the search button to the result list
+    SearchResults,
+    // from the result list to the products of the company
+    ProductPage,
+    // from the products to the card form
+    PaymentForm,
+}
+
+impl Checkpoint {
+    const ALL: [Checkpoint; 3] = [
+        Checkpoint::SearchResults,
+        Checkpoint::ProductPage,
+        Checkpoint::PaymentForm,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Checkpoint::SearchResults => "search_submit_to_results",
+            Checkpoint::ProductPage => "results_to_product_page",
+            Checkpoint::PaymentForm => "product_page_to_payment_form",
+        }
+    }
+}
+
+// Upper bounds of the histogram buckets, the flows' fixed waits included
+const BUCKETS_MS: [u64; 10] = [
+    1_000, 2_500, 5_000, 10_000, 15_000, 20_000, 30_000, 45_000, 60_000, 120_000,
+];
+// Of every checkpoint, in a hash of per bucket counts, so `api` instances see the workers' flows
+const CHECKPOINTS_KEY: &str = "metrics:checkpoint";
+
+#[derive(Default, Clone)]
+struct Counts {
+    // per bucket, the last one over the highest bound
+    buckets: [u64; BUCKETS_MS.len() + 1],
+    sum_ms: u64,
+}
+
+// Seen by this process since it started, all there is without redis
+static CHECKPOINTS: Lazy<Mutex<BTreeMap<Checkpoint, Counts>>> = Lazy::new(Default::default);
+
+fn bucket(ms: u64) -> usize {
+    BUCKETS_MS
+        .iter()
+        .position(|bound| ms <= *bound)
+        .unwrap_or(BUCKETS_MS.len())
+}
+
+fn bucket_field(bucket: usize) -> String {
+    BUCKETS_MS
+        .get(bucket)
+        .map_or("inf".to_string(), |bound| bound.to_string())
+}
+
+/// Records how long the flow took to get past `checkpoint`.
+pub async fn record_checkpoint(checkpoint: Checkpoint, elapsed: Duration) {
+    let ms = elapsed.as_millis() as u64;
+    tracing::debug!("{} took {}ms", checkpoint.name(), ms);
+    {
+        let mut checkpoints = CHECKPOINTS.lock().unwrap();
+        let counts = checkpoints.entry(checkpoint).or_default();
+        counts.buckets[bucket(ms)] += 1;
+        counts.sum_ms += ms;
+    }
+
+    let Some(url) = &CONFIG.redis_url else {
+        return;
+    };
+    let shared = async {
+        let mut conn = redis_connection(url).await?;
+        let key = format!("{}:{}", CHECKPOINTS_KEY, checkpoint.name());
+        redis::pipe()
+            .hincr(&key, bucket_field(bucket(ms)), 1)
+            .hincr(&key, "sum_ms", ms)
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        anyhow::Ok(())
+    };
+    if let Err(err) = shared.await {
+        tracing::warn!("failed to record the {} time: {}", checkpoint.name(), err);
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Bucket {
+    // none for the bucket without an upper bound
+    pub le_ms: Option<u64>,
+    // of the times up to `le_ms`, cumulative like Prometheus buckets
+    pub count: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Histogram {
+    pub count: u64,
+    pub sum_ms: u64,
+    pub buckets: Vec<Bucket>,
+}
+
+impl From<Counts> for Histogram {
+    fn from(counts: Counts) -> Self {
+        let mut count = 0;
+        let buckets = counts
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(bucket, in_bucket)| {
+                count += in_bucket;
+                Bucket {
+                    le_ms: BUCKETS_MS.get(bucket).copied(),
+                    count,
+                }
+            })
+            .collect();
+
+        Histogram {
+            count,
+            sum_ms: counts.sum_ms,
+            buckets,
+        }
+    }
+}
+
+async fn shared_counts(url: &str, checkpoint: Checkpoint) -> Result<Counts> {
+    let mut conn = redis_connection(url).await?;
+    let key = format!("{}:{}", CHECKPOINTS_KEY, checkpoint.name());
+    let fields: HashMap<String, u64> = conn.hgetall(&key).await?;
+
+    let mut counts = Counts {
+        sum_ms: fields.get("sum_ms").copied().unwrap_or_default(),
+        ..Default::default()
+    };
+    for (bucket, count) in counts.buckets.iter_mut().enumerate() {
+        *count = fields
+            .get(&bucket_field(bucket))
+            .copied()
+            .unwrap_or_default();
+    }
+
+    Ok(counts)
+}
+
+/// Time histograms of every checkpoint, of all instances with redis or this one's when it doesn't
+/// answer.
+pub async fn checkpoints() -> BTreeMap<&'static str, Histogram> {
+    let mut histograms = BTreeMap::new();
+    for checkpoint in Checkpoint::ALL {
+        let local = || {
+            CHECKPOINTS
+                .lock()
+                .unwrap()
+                .get(&checkpoint)
+                .cloned()
+                .unwrap_or_default()
+        };
+        let counts = match &CONFIG.redis_url {
+            Some(url) => match shared_counts(url, checkpoint).await {
+                Ok(counts) => counts,
+                Err(err) => {
+                    tracing::warn!("failed to read the {} times: {}", checkpoint.name(), err);
+                    local()
+                }
+            },
+            None => local(),
+        };
+        histograms.insert(checkpoint.name(), counts.into());
+    }
+
+    histograms
+}
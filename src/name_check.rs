@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+
+use dump_core::normalize::{normalize_name, similarity};
+use serde::Serialize;
+
+use crate::{config::CONFIG, corporations, errors::AppError, handler, maintenance};
+
+// Results kept of each federal search
+const MAX_RESULTS: usize = 100;
+// Where a conflicting name was found besides the federal search
+const STORED: &str = "stored";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Available,
+    // a corporation has the same name once normalized
+    ExactConflict,
+    // names at least `NAME_CHECK_SIMILARITY` alike, none the same
+    SimilarConflicts,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Conflict {
+    pub name: String,
+    pub normalized: String,
+    pub provider: &'static str,
+    pub corporation_number: Option<String>,
+    pub status: Option<String>,
+    // `normalize::similarity` to the checked name, 1 for exact conflicts
+    pub score: f64,
+}
+
+// A corporation whose name was compared
+struct Found {
+    provider: &'static str,
+    corporation_number: Option<String>,
+    name: String,
+    status: Option<String>,
+}
+
+/// Whether a name is free to incorporate under, the `/api/name-check` response.
+#[derive(Serialize, Debug)]
+pub struct NameCheck {
+    pub name: String,
+    pub normalized: String,
+    pub verdict: Verdict,
+    // most similar first
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Checks `name` against the federal search, once for the name as given and once for its most
+/// distinctive word so spelling variants show up too, and against the stored corporations sharing
+/// the start of a word with it, which include the imported open data. A failed search fails the
+/// check rather than finding the name available.
+pub async fn check(name: &str) -> Result<NameCheck, AppError> {
+    let normalized = normalize_name(name);
+
+    let mut found = vec![];
+    let mut queries = vec![name.to_string()];
+    if let Some(word) = normalized.split(' ').max_by_key(|word| word.len()) {
+        if word != normalized {
+            queries.push(word.to_string());
+        }
+    }
+    for query in &queries {
+        for mut row in handler::search_corporations(query, Some(MAX_RESULTS)).await? {
+            let Some(name) = row.remove("business_name") else {
+                continue;
+            };
+            found.push(Found {
+                provider: maintenance::FEDERAL,
+                corporation_number: row.remove("corporation_number"),
+                name,
+                status: row.remove("status"),
+            });
+        }
+    }
+    for stored in corporations::with_name_like(name).await? {
+        if let Some(name) = stored.corporate_name() {
+            found.push(Found {
+                provider: STORED,
+                corporation_number: Some(stored.id.clone()),
+                name: name.to_string(),
+                status: stored.status().map(str::to_string),
+            });
+        }
+    }
+
+    // one conflict per corporation, the federal search's when both found it
+    let mut conflicts: BTreeMap<String, Conflict> = BTreeMap::new();
+    for found in found {
+        let normalized_found = normalize_name(&found.name);
+        let score = similarity(&normalized, &normalized_found);
+        if score < CONFIG.name_check_similarity {
+            continue;
+        }
+        let key = found
+            .corporation_number
+            .clone()
+            .unwrap_or_else(|| found.name.clone());
+        conflicts.entry(key).or_insert(Conflict {
+            name: found.name,
+            normalized: normalized_found,
+            provider: found.provider,
+            corporation_number: found.corporation_number,
+            status: found.status,
+            score: (score * 1000.0).round() / 1000.0,
+        });
+    }
+    let mut conflicts: Vec<Conflict> = conflicts.into_values().collect();
+    conflicts.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let verdict = if conflicts
+        .iter()
+        .any(|conflict| conflict.normalized == normalized)
+    {
+        Verdict::ExactConflict
+    } else if conflicts.is_empty() {
+        Verdict::Available
+    } else {
+        Verdict::SimilarConflicts
+    };
+
+    Ok(NameCheck {
+        name: name.to_string(),
+        normalized,
+        verdict,
+        conflicts,
+    })
+}
@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use once_cell::sync::OnceCell;
+use serde_json::Value;
+
+use crate::{
+    config::CONFIG,
+    jobs::{Job, JobStatus},
+};
+
+static MAILER: OnceCell<AsyncSmtpTransport<Tokio1Executor>> = OnceCell::new();
+
+fn mailer(url: &str) -> Result<&'static AsyncSmtpTransport<Tokio1Executor>> {
+    MAILER.get_or_try_init(|| Ok(AsyncSmtpTransport::<Tokio1Executor>::from_url(url)?.build()))
+}
+
+/// Whether reports may be sent to `email`: `DEFAULT_EMAIL`, or an address or a domain of
+/// `REPORT_EMAIL_ALLOWLIST`. Keeps the registry from mailing orders to whoever a caller names.
+/// `email` must be a single bare address, lists like "ops@allowed.com, me@elsewhere.com" are
+/// refused whatever they start with.
+pub fn allowed(email: &str) -> bool {
+    let email = email.trim().to_lowercase();
+    if email.contains([',', ';']) {
+        return false;
+    }
+    let Ok(address) = email.parse::<Address>() else {
+        return false;
+    };
+    if CONFIG.report_email_allowlist.is_empty() || email == CONFIG.default_email.to_lowercase() {
+        return true;
+    }
+
+    let domain = address.domain();
+    CONFIG.report_email_allowlist.iter().any(|allowed| {
+        let allowed = allowed.trim().to_lowercase();
+        if allowed.contains('@') {
+            allowed == email
+        } else {
+            domain == allowed
+        }
+    })
+}
+
+fn param<'a>(job: &'a Job, key: &str) -> &'a str {
+    job.params.get(key).and_then(Value::as_str).unwrap_or("-")
+}
+
+fn summary(job: &Job) -> (String, String) {
+    let company = param(job, "selected_company");
+    let product = param(job, "search_product");
+    let status = match job.status {
+        JobStatus::Queued => "queued",
+        JobStatus::Running => "running",
+        JobStatus::Succeeded => "completed",
+        JobStatus::Failed => "failed",
+    };
+
+    let mut body = format!(
+        "Job: {}\nCompany: {}\nProduct: {}\nStatus: {}\n",
+        job.id, company, product, status
+    );
+    if let Some(receipt) = job
+        .result
+        .as_ref()
+        .and_then(|result| result.get("current_url"))
+        .and_then(Value::as_str)
+    {
+        body.push_str(&format!("Receipt: {}\n", receipt));
+    }
+    if let Some(error) = &job.error {
+        body.push_str(&format!("Error: {}\n", error));
+    }
+
+    (format!("{} for {} {}", product, company, status), body)
+}
+
+async fn send(to: &str, job: &Job) -> Result<()> {
+    let url = CONFIG
+        .smtp_url
+        .as_deref()
+        .context("SMTP_URL is not configured")?;
+    let from = CONFIG
+        .notify_from
+        .as_deref()
+        .context("NOTIFY_FROM is not configured")?;
+
+    let (subject, body) = summary(job);
+    let message = Message::builder()
+        .from(from.parse()?)
+        .to(to.parse()?)
+        .subject(subject)
+        .body(body)?;
+    mailer(url)?.send(message).await?;
+
+    Ok(())
+}
+
+/// Emails the job summary to `to`. Delivery problems are logged, never surfaced to the caller.
+pub async fn job_finished(to: &str, job: &Job) {
+    if let Err(err) = send(to, job).await {
+        tracing::warn!("failed to notify {} about job {}: {}", to, job.id, err);
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration as StdDuration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, FixedOffset, Timelike, Utc};
+use once_cell::sync::OnceCell;
+
+use crate::{
+    config::{Mode, CONFIG},
+    cookies,
+    errors::{AppError, ErrorKind},
+    handler,
+    maintenance::{self, Schedule},
+    queue,
+};
+
+// How far ahead the next off-peak minute is looked for
+const MAX_LOOKAHEAD_MINS: i64 = 7 * 24 * 60;
+
+static OFF_PEAK: OnceCell<Option<(Schedule, FixedOffset)>> = OnceCell::new();
+
+/// Parses `OFF_PEAK`, the cron expression of the minutes `run_window=off_peak` jobs run in.
+pub fn load() -> Result<()> {
+    let off_peak = match &CONFIG.off_peak {
+        Some(cron) => Some((
+            Schedule::parse(cron).context("invalid OFF_PEAK")?,
+            maintenance::utc_offset(CONFIG.off_peak_utc_offset.as_deref())?,
+        )),
+        None => None,
+    };
+
+    OFF_PEAK
+        .set(off_peak)
+        .map_err(|_| anyhow::anyhow!("off-peak hours already loaded"))
+}
+
+/// When a job of `run_window=off_peak` asked for at `now` may start, none when it's off-peak
+/// already.
+pub fn deferred_until(now: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, AppError> {
+    let Some((schedule, offset)) = OFF_PEAK.get().and_then(Option::as_ref) else {
+        return Err(ErrorKind::Unprocessable("No off-peak hours are set up".to_string()).into());
+    };
+    // deferred jobs wait in redis, so they survive restarts
+    if CONFIG.redis_url.is_none() {
+        return Err(ErrorKind::Unprocessable(
+            "Off-peak jobs need REDIS_URL, which isn't set up".to_string(),
+        )
+        .into());
+    }
+    let off_peak = |at: &DateTime<Utc>| schedule.matches(&at.with_timezone(offset));
+    if off_peak(&now) {
+        return Ok(None);
+    }
+
+    let start = now
+        .with_second(0)
+        .and_then(|at| at.with_nanosecond(0))
+        .unwrap_or(now);
+    let at = (1..=MAX_LOOKAHEAD_MINS)
+        .map(|minutes| start + Duration::minutes(minutes))
+        .find(off_peak)
+        .ok_or_else(|| {
+            ErrorKind::Unprocessable("OFF_PEAK has no minute in the coming week".to_string())
+        })?;
+    Ok(Some(at))
+}
+
+/// Every 30 seconds, moves the deferred jobs whose off-peak hours came onto the queue workers
+/// consume, or runs them on `all` instances, which have no workers.
+pub fn spawn_releaser() {
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(StdDuration::from_secs(30)).await;
+            // the due jobs stay deferred through a maintenance window, which often falls in the
+            // off-peak hours, and are released once it's over
+            if let Some(end) = maintenance::ends_at(cookies::REGISTRY, Utc::now()) {
+                tracing::debug!("off-peak jobs held back for maintenance until {}", end);
+                continue;
+            }
+            let released = match CONFIG.mode {
+                Mode::All => handler::run_due().await,
+                Mode::Api | Mode::Worker => queue::release_due().await,
+            };
+            match released {
+                Ok(0) => {}
+                Ok(released) => tracing::info!("released {} off-peak jobs", released),
+                Err(err) => tracing::warn!("failed to release the off-peak jobs: {}", err),
+            }
+        }
+    });
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{artifacts::artifacts, config::CONFIG, errors::AppError, response};
+
+/// Replaces JSON bodies larger than `OFFLOAD_RESPONSE_BYTES` with a pointer to the artifact they
+/// were stored as: `{"result_url": ..., "expires_at": ...}`, keeping the status. Requests sent with
+/// `X-No-Offload: true` always get the body itself, and streamed bodies are never offloaded.
+pub async fn offload(req: Request, next: Next) -> Response {
+    // links to offloaded responses must not point to yet another link
+    let opted_out = req.uri().path().starts_with("/api/artifacts/")
+        || req
+            .headers()
+            .get("x-no-offload")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.eq_ignore_ascii_case("true"));
+    let response = next.run(req).await;
+    let Some(limit) = CONFIG.offload_response_bytes else {
+        return response;
+    };
+    if opted_out {
+        return response;
+    }
+
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let size = response.body().size_hint().exact();
+    if !is_json || size.is_none_or(|size| size <= limit as u64) {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => return AppError::from(anyhow::anyhow!(err)).into_response(),
+    };
+    let id = response::request_id().unwrap_or_else(Uuid::new_v4);
+    let key = format!("responses/{}.json", id);
+    let expires_in = Duration::from_secs(CONFIG.offload_url_ttl_secs);
+
+    let stored = async {
+        artifacts().put(&key, &bytes, "application/json").await?;
+        artifacts().url(&key, expires_in).await
+    };
+    match stored.await {
+        Ok(url) => {
+            tracing::info!("offloaded a {} byte response to {}", bytes.len(), key);
+            let expires_at = Utc::now() + chrono::Duration::seconds(expires_in.as_secs() as i64);
+            let mut pointer =
+                Json(json!({ "result_url": url, "expires_at": expires_at })).into_response();
+            *pointer.status_mut() = parts.status;
+            pointer
+        }
+        // better a large answer than none
+        Err(err) => {
+            tracing::warn!("failed to offload the response, sending it inline: {}", err);
+            Response::from_parts(parts, Body::from(bytes))
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use dump_core::{
+    enrich::enrich,
+    models::{CorporationData, CorporationSection, CORPORATE_NAME_LABEL, CORPORATION_NUMBER_LABEL},
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    config::{StorageBackend, CONFIG},
+    corporations,
+    errors::{AppError, ErrorKind},
+    storage,
+};
+
+// Governing acts by the codes the API gives them, named like on the profile page so `enrich`
+// reads them the same
+const ACTS: [(&str, &str); 4] = [
+    ("CBCA", "Canada Business Corporations Act"),
+    ("NFP", "Canada Not-for-profit Corporations Act"),
+    ("CCA", "Canada Cooperatives Act"),
+    ("BTA", "Boards of Trade Act"),
+];
+
+/// Whether `OPEN_DATA_URL` is set, federal scrapes have no fallback otherwise.
+pub fn enabled() -> bool {
+    CONFIG.open_data_url.is_some()
+}
+
+fn string(value: Option<&Value>) -> Option<String> {
+    value
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+// Entries of a list like `corporationNames`, each wrapped in an object of its own, current first
+fn current<'a>(record: &'a Value, list: &str, entry: &str) -> Vec<&'a Value> {
+    let mut entries: Vec<&Value> = record
+        .get(list)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|wrapped| wrapped.get(entry).unwrap_or(wrapped))
+        .collect();
+    entries.sort_by_key(|entry| entry.get("current").and_then(Value::as_bool) != Some(true));
+    entries
+}
+
+fn address(record: &Value) -> Option<String> {
+    // the API spells it `adresses`
+    let address = current(record, "adresses", "address")
+        .into_iter()
+        .chain(current(record, "addresses", "address"))
+        .next()?;
+    let lines = address
+        .get("addressLine")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|line| string(Some(line)));
+    let rest = ["city", "provinceCode", "postalCode", "countryCode"]
+        .into_iter()
+        .filter_map(|key| string(address.get(key)));
+
+    Some(lines.chain(rest).collect::<Vec<_>>().join(", ")).filter(|address| !address.is_empty())
+}
+
+fn details(id: &str, record: &Value) -> Vec<HashMap<String, String>> {
+    let name = current(record, "corporationNames", "CorporationName")
+        .into_iter()
+        .find_map(|name| string(name.get("name")));
+    let act = string(record.get("act")).map(|act| {
+        ACTS.iter()
+            .find(|(code, _)| code.eq_ignore_ascii_case(&act))
+            .map_or(act, |(_, name)| name.to_string())
+    });
+    let business_number = string(
+        record
+            .get("businessNumbers")
+            .and_then(|numbers| numbers.get("businessNumber")),
+    );
+
+    [
+        (CORPORATE_NAME_LABEL, name),
+        (CORPORATION_NUMBER_LABEL, Some(id.to_string())),
+        ("Business Number (BN)", business_number),
+        ("Governing Legislation", act),
+        ("Status", string(record.get("status"))),
+    ]
+    .into_iter()
+    .filter_map(|(label, value)| Some(HashMap::from([(label.to_string(), value?)])))
+    .collect()
+}
+
+/// Federal profile from the Corporations Canada open-data API, for when the profile page can't
+/// be scraped. It only holds the details and the registered office, the other sections are left
+/// out even when asked for.
+pub async fn corporation(
+    id: &str,
+    sections: &[CorporationSection],
+) -> Result<CorporationData, AppError> {
+    let base = CONFIG
+        .open_data_url
+        .as_deref()
+        .context("OPEN_DATA_URL isn't set")?;
+    let mut request = reqwest::Client::new()
+        .get(format!(
+            "{}/corporations/{}.json",
+            base.trim_end_matches('/'),
+            id
+        ))
+        .query(&[("lang", "eng")]);
+    if let Some(key) = &CONFIG.open_data_key {
+        request = request.header("user-key", key);
+    }
+    let not_found = || ErrorKind::NotFound(format!("Corporation {} isn't in the open data", id));
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(not_found().into());
+    }
+    let answer: Value = response.error_for_status()?.json().await?;
+
+    // a list of the matching corporations
+    let record = match &answer {
+        Value::Array(records) => records.first(),
+        record => Some(record),
+    }
+    .filter(|record| record.is_object())
+    .ok_or_else(not_found)?;
+
+    Ok(profile(id, record, sections))
+}
+
+fn profile(id: &str, record: &Value, sections: &[CorporationSection]) -> CorporationData {
+    let mut data = CorporationData {
+        corp_details: sections
+            .contains(&CorporationSection::Corp)
+            .then(|| details(id, record)),
+        address_details: sections
+            .contains(&CorporationSection::Address)
+            .then(|| address(record))
+            .flatten(),
+        director_details: None,
+        annual_filings_details: None,
+        corp_history_details: None,
+        share_structure: None,
+        share_restrictions: None,
+        jurisdiction: None,
+        entity_type: None,
+    };
+    enrich(&mut data);
+
+    data
+}
+
+// XML unescaped, the bulk files escape names like `SMITH &amp; SONS`
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((before, after)) = rest.split_once('&') {
+        unescaped.push_str(before);
+        let entity = after.split_once(';').and_then(|(entity, after)| {
+            let c = match entity {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                _ => match entity.strip_prefix("#x") {
+                    Some(hex) => char::from_u32(u32::from_str_radix(hex, 16).ok()?)?,
+                    None => char::from_u32(entity.strip_prefix('#')?.parse().ok()?)?,
+                },
+            };
+            Some((c, after))
+        });
+        match entity {
+            Some((c, after)) => {
+                unescaped.push(c);
+                rest = after;
+            }
+            None => {
+                unescaped.push('&');
+                rest = after;
+            }
+        }
+    }
+    unescaped.push_str(rest);
+
+    unescaped
+}
+
+struct Node {
+    name: String,
+    fields: Map<String, Value>,
+    text: String,
+}
+
+impl Node {
+    // Elements holding only text become strings, repeated children lists
+    fn into_value(self) -> Value {
+        let text = self.text.trim();
+        if self.fields.is_empty() {
+            return Value::String(text.to_string());
+        }
+        let mut fields = self.fields;
+        if !text.is_empty() {
+            fields.insert("text".to_string(), Value::String(text.to_string()));
+        }
+        Value::Object(fields)
+    }
+
+    fn add(&mut self, name: String, value: Value) {
+        match self.fields.get_mut(&name) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+            None => {
+                self.fields.insert(name, value);
+            }
+        }
+    }
+}
+
+// The `<corporation>` elements of a bulk file, each turned into JSON as by `Node::into_value`
+fn bulk_records(xml: &str) -> Result<Vec<Value>> {
+    let mut records = vec![];
+    let mut open: Vec<Node> = vec![];
+
+    for token in xmlparser::Tokenizer::from(xml) {
+        match token? {
+            xmlparser::Token::ElementStart { local, .. } => open.push(Node {
+                name: local.to_string(),
+                fields: Map::new(),
+                text: String::new(),
+            }),
+            xmlparser::Token::Attribute { local, value, .. } => {
+                if let Some(node) = open.last_mut() {
+                    node.add(local.to_string(), Value::String(unescape(&value)));
+                }
+            }
+            xmlparser::Token::Text { text } | xmlparser::Token::Cdata { text, .. } => {
+                if let Some(node) = open.last_mut() {
+                    node.text.push_str(&unescape(&text));
+                }
+            }
+            xmlparser::Token::ElementEnd {
+                end: xmlparser::ElementEnd::Close(..) | xmlparser::ElementEnd::Empty,
+                ..
+            } => {
+                let node = open.pop().context("unbalanced XML")?;
+                let name = node.name.clone();
+                let value = node.into_value();
+                match open.last_mut() {
+                    // kept out of the tree, which would hold the whole file otherwise
+                    _ if name == "corporation" => records.push(value),
+                    Some(parent) => parent.add(name, value),
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(records)
+}
+
+// Children of a list element like `<names>`, one or many
+fn entries<'a>(record: &'a Value, list: &str, entry: &str) -> Vec<&'a Value> {
+    match record.get(list).and_then(|list| list.get(entry)) {
+        Some(Value::Array(entries)) => entries.iter().collect(),
+        Some(entry) => vec![entry],
+        None => vec![],
+    }
+}
+
+fn entry_text(entry: &Value) -> Option<String> {
+    match entry {
+        Value::String(text) => Some(text.clone()),
+        entry => string(entry.get("text")).or_else(|| string(entry.get("code"))),
+    }
+}
+
+fn is_current(entry: &Value) -> bool {
+    entry.get("current").and_then(Value::as_str) == Some("true")
+}
+
+// A bulk record in the shape of the API's answers, which `profile` reads
+fn from_bulk(record: &Value) -> Option<(String, Value)> {
+    let id = string(record.get("corporationId"))?;
+    let latest = |list: &str, entry: &str| {
+        let entries = entries(record, list, entry);
+        entries
+            .iter()
+            .find(|entry| is_current(entry))
+            .or(entries.last())
+            .and_then(|entry| entry_text(entry))
+    };
+
+    let names: Vec<Value> = entries(record, "names", "name")
+        .into_iter()
+        .filter_map(|name| {
+            Some(json!({ "CorporationName": { "name": entry_text(name)?, "current": is_current(name) } }))
+        })
+        .collect();
+    let addresses: Vec<Value> = entries(record, "addresses", "address")
+        .into_iter()
+        .map(|address| {
+            let lines: Vec<Value> = match address.get("addressLine") {
+                Some(Value::Array(lines)) => lines.clone(),
+                Some(line) => vec![line.clone()],
+                None => vec![],
+            };
+            let mut shaped = json!({ "addressLine": lines, "current": is_current(address) });
+            for key in ["city", "provinceCode", "postalCode", "countryCode"] {
+                if let Some(value) = address.get(key) {
+                    shaped[key] = value.clone();
+                }
+            }
+            json!({ "address": shaped })
+        })
+        .collect();
+
+    let shaped = json!({
+        "corporationId": id,
+        "corporationNames": names,
+        "adresses": addresses,
+        "act": latest("acts", "act"),
+        "status": latest("statuses", "status"),
+        "businessNumbers": { "businessNumber": latest("businessNumbers", "businessNumber") },
+    });
+    Some((id, shaped))
+}
+
+// Records of a bulk file: XML, or the API's JSON records as a list or one per line
+fn read(path: &Path) -> Result<Vec<(String, Value)>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let content = content.trim_start();
+
+    if content.starts_with('<') {
+        return Ok(bulk_records(content)
+            .with_context(|| format!("invalid XML in {}", path.display()))?
+            .iter()
+            .filter_map(from_bulk)
+            .collect());
+    }
+    let records: Vec<Value> = match content.starts_with('[') {
+        true => serde_json::from_str(content)?,
+        false => content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .with_context(|| format!("invalid JSON lines in {}", path.display()))?,
+    };
+
+    Ok(records
+        .into_iter()
+        .filter_map(|record| Some((string(record.get("corporationId"))?, record)))
+        .collect())
+}
+
+/// The `import-open-data` subcommand: stores the corporations of Corporations Canada bulk files,
+/// so lookups of their details and registered office are answered without scraping. Scraped
+/// corporations are left as they are.
+pub async fn import(files: &[PathBuf]) -> Result<()> {
+    anyhow::ensure!(
+        CONFIG.storage_backend != StorageBackend::Memory,
+        "importing into the memory store would be lost on exit, set --storage-backend dynamodb or \
+         filesystem"
+    );
+    storage::init().await?;
+
+    let sections = [CorporationSection::Corp, CorporationSection::Address];
+    let (mut imported, mut kept) = (0, 0);
+    for path in files {
+        let records = read(path)?;
+        tracing::info!(
+            "importing {} corporations from {}",
+            records.len(),
+            path.display()
+        );
+        for (id, record) in records {
+            match corporations::import(&id, profile(&id, &record, &sections)).await? {
+                true => imported += 1,
+                false => kept += 1,
+            }
+        }
+    }
+    println!(
+        "imported {} corporations, kept {} scraped ones",
+        imported, kept
+    );
+
+    Ok(())
+}
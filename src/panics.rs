@@ -0,0 +1,55 @@
+use std::{any::Any, backtrace::Backtrace};
+
+use axum::response::{IntoResponse, Response};
+
+use crate::{artifacts::artifacts, cards, errors::AppError, response};
+
+/// Logs panics raised while serving a request with the request id and a backtrace, with card
+/// details masked. Panics outside requests keep the default output.
+pub fn install_hook() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| match response::request_id() {
+        Some(request_id) => {
+            let info = cards::redact(&info.to_string());
+            tracing::error!("{}: {}\n{}", request_id, info, Backtrace::force_capture())
+        }
+        None => default(info),
+    }));
+}
+
+fn message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// The error a job whose flow panicked fails with, card details masked.
+pub fn failure(panic: Box<dyn Any + Send>) -> AppError {
+    let message = cards::redact(message(panic.as_ref()));
+    AppError::from(anyhow::anyhow!("panicked: {}", message))
+}
+
+/// Turns a panicked request into a regular 500, saving the page it was extracting from when
+/// there is one.
+pub fn recover(panic: Box<dyn Any + Send>) -> Response {
+    let message = cards::redact(message(panic.as_ref()));
+
+    if let (Some(request_id), Some(page)) = (response::request_id(), response::take_page()) {
+        tokio::spawn(async move {
+            let key = format!("panics/{}.html", request_id);
+            match artifacts()
+                .put(&key, page.html.as_bytes(), "text/html")
+                .await
+            {
+                Ok(location) => {
+                    tracing::error!("{}: page {} saved to {}", request_id, page.url, location)
+                }
+                Err(err) => tracing::warn!("{}: failed to save page: {}", request_id, err),
+            }
+        });
+    }
+
+    AppError::from(anyhow::anyhow!("panicked: {}", message)).into_response()
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Deserialize;
+use thirtyfour::prelude::WebDriverResult;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+use crate::{
+    config::CONFIG,
+    cookies::Cookie,
+    session::{Driver, Element, Session},
+};
+
+/// How gently a provider is scraped.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Etiquette {
+    // between two page loads or HTTP requests to the provider, across sessions
+    #[serde(default)]
+    pub min_delay_ms: u64,
+    // browser sessions on the provider at once in this process, unlimited if unset
+    #[serde(default)]
+    pub max_sessions: Option<usize>,
+    // sent instead of the browser's own, the one of SCRAPER_CONTACT if unset
+    #[serde(default)]
+    pub user_agent: Option<String>,
+}
+
+struct Provider {
+    etiquette: Etiquette,
+    sessions: Option<Arc<Semaphore>>,
+}
+
+static PROVIDERS: OnceCell<BTreeMap<String, Provider>> = OnceCell::new();
+// Earliest the next request to each provider may go out
+static NEXT_REQUEST: Lazy<Mutex<HashMap<String, Instant>>> = Lazy::new(Default::default);
+
+/// Loads `PROVIDER_POLITENESS`, a JSON object of provider name to its etiquette, e.g.
+/// `{"registry": {"min_delay_ms": 2000, "max_sessions": 2}}`. Providers left out are scraped
+/// without delays or limits.
+pub fn load() -> Result<()> {
+    let mut etiquettes: BTreeMap<String, Etiquette> = BTreeMap::new();
+    if let Some(path) = &CONFIG.provider_politeness {
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        etiquettes = serde_json::from_str(&file)
+            .with_context(|| format!("invalid provider politeness in {}", path.display()))?;
+    }
+
+    let mut providers = BTreeMap::new();
+    for (name, etiquette) in etiquettes {
+        anyhow::ensure!(
+            etiquette.max_sessions != Some(0),
+            "max_sessions of {} is 0, nothing could be scraped",
+            name
+        );
+        let sessions = etiquette
+            .max_sessions
+            .map(|max| Arc::new(Semaphore::new(max)));
+        providers.insert(
+            name,
+            Provider {
+                etiquette,
+                sessions,
+            },
+        );
+    }
+
+    PROVIDERS
+        .set(providers)
+        .map_err(|_| anyhow::anyhow!("provider politeness already loaded"))
+}
+
+fn provider(name: &str) -> Option<&'static Provider> {
+    PROVIDERS.get().and_then(|providers| providers.get(name))
+}
+
+/// User agent scrapes of `provider` identify themselves with, none to keep the browser's own.
+pub fn user_agent(provider: &str) -> Option<String> {
+    let configured =
+        self::provider(provider).and_then(|provider| provider.etiquette.user_agent.clone());
+    configured.or_else(|| {
+        CONFIG.scraper_contact.as_ref().map(|contact| {
+            format!(
+                "{}/{} (+mailto:{})",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+                contact
+            )
+        })
+    })
+}
+
+/// Waits until `MIN_DELAY_MS` passed since the last request to `provider`.
+pub async fn pace(provider: &str) {
+    let delay = self::provider(provider).map_or(0, |provider| provider.etiquette.min_delay_ms);
+    if delay == 0 {
+        return;
+    }
+
+    // taken before sleeping, so requests waiting at once go out one delay apart
+    let at = {
+        let mut next = NEXT_REQUEST.lock().unwrap();
+        let now = Instant::now();
+        let at = next
+            .get(provider)
+            .copied()
+            .filter(|at| *at > now)
+            .unwrap_or(now);
+        next.insert(provider.to_string(), at + Duration::from_millis(delay));
+        at
+    };
+    tokio::time::sleep_until(at).await;
+}
+
+/// HTTP client for scrapes of `provider`, with its user agent. Each request is still to be
+/// `pace`d.
+pub fn client(provider: &str) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder();
+    if let Some(user_agent) = user_agent(provider) {
+        builder = builder.user_agent(user_agent);
+    }
+    builder.build().unwrap_or_default()
+}
+
+/// Holds one of the `max_sessions` of `provider` until the session is quit, waiting for one to
+/// be free, and paces its page loads.
+pub async fn wrap(
+    provider: &'static str,
+    start: impl std::future::Future<Output = WebDriverResult<Session>>,
+) -> WebDriverResult<Session> {
+    let permit = match self::provider(provider).and_then(|provider| provider.sessions.clone()) {
+        Some(sessions) => Some(
+            sessions
+                .acquire_owned()
+                .await
+                .expect("the session semaphore is never closed"),
+        ),
+        None => None,
+    };
+    let session = start.await?;
+    if self::provider(provider).is_none() {
+        return Ok(session);
+    }
+
+    Ok(Box::new(Polite {
+        inner: session,
+        provider,
+        _permit: permit,
+    }))
+}
+
+struct Polite {
+    inner: Session,
+    provider: &'static str,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+#[async_trait]
+impl Driver for Polite {
+    async fn goto(&self, url: &str) -> WebDriverResult<()> {
+        pace(self.provider).await;
+        self.inner.goto(url).await
+    }
+
+    async fn current_url(&self) -> WebDriverResult<String> {
+        self.inner.current_url().await
+    }
+
+    async fn title(&self) -> WebDriverResult<String> {
+        self.inner.title().await
+    }
+
+    async fn add_cookie(&self, cookie: &Cookie) -> WebDriverResult<()> {
+        self.inner.add_cookie(cookie).await
+    }
+
+    async fn get_cookies(&self) -> WebDriverResult<Vec<Cookie>> {
+        self.inner.get_cookies().await
+    }
+
+    async fn delete_all_cookies(&self) -> WebDriverResult<()> {
+        self.inner.delete_all_cookies().await
+    }
+
+    async fn screenshot_png(&self) -> WebDriverResult<Vec<u8>> {
+        self.inner.screenshot_png().await
+    }
+
+    async fn thumbnail_png(&self) -> WebDriverResult<Vec<u8>> {
+        self.inner.thumbnail_png().await
+    }
+
+    async fn find_all(&self, xpath: &str) -> WebDriverResult<Vec<Box<dyn Element>>> {
+        self.inner.find_all(xpath).await
+    }
+
+    // the permit is released once the session is gone
+    async fn quit(self: Box<Self>) -> WebDriverResult<()> {
+        self.inner.quit().await
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use thirtyfour::prelude::WebDriverResult;
+use tokio::sync::Mutex;
+
+use crate::{
+    config::CONFIG,
+    cookies::Cookie,
+    flow, login,
+    session::{self, Driver, Element, Session},
+};
+
+struct Idle {
+    session: Session,
+    created: Instant,
+}
+
+static IDLE: Lazy<Mutex<Vec<Idle>>> = Lazy::new(Default::default);
+
+/// Whether finished sessions are kept for the next flow. Replays need the recorded session
+/// lifecycle, so they never pool.
+pub fn enabled() -> bool {
+    CONFIG.browser_pool_size > 0 && CONFIG.webdriver_replay.is_none()
+}
+
+fn max_age() -> Duration {
+    Duration::from_secs(CONFIG.browser_pool_max_age_secs)
+}
+
+// Quitting may fail on a session chromedriver already reaped, which is what retiring it is for
+async fn retire(session: Session) {
+    if let Err(err) = session.quit().await {
+        tracing::debug!("retired pooled session failed to quit: {}", err);
+    }
+}
+
+// A cheap command that fails on sessions the driver dropped
+async fn alive(session: &Session) -> bool {
+    session.title().await.is_ok()
+}
+
+/// An idle session that still answers, or a new one. Sessions past `BROWSER_POOL_MAX_AGE_SECS`
+/// are retired instead of handed out, so they don't carry expired registry sessions.
+pub async fn checkout() -> WebDriverResult<Session> {
+    loop {
+        let Some(idle) = IDLE.lock().await.pop() else {
+            break;
+        };
+        if idle.created.elapsed() < max_age() && alive(&idle.session).await {
+            return Ok(Box::new(Pooled {
+                inner: idle.session,
+                created: idle.created,
+                private: AtomicBool::new(false),
+            }));
+        }
+        retire(idle.session).await;
+    }
+
+    Ok(Box::new(Pooled {
+        inner: session::launch().await?,
+        created: Instant::now(),
+        private: AtomicBool::new(false),
+    }))
+}
+
+// The next flow starts without the cookies of the last one. WebDriver only deletes those of the
+// current page, so that's done before leaving it. Cookies other hosts set stay, which is why
+// sessions that signed in or reached a payment host are retired instead
+async fn reset(session: &Session) -> WebDriverResult<()> {
+    session.delete_all_cookies().await?;
+    session.goto("about:blank").await
+}
+
+async fn release(session: Session, created: Instant, private: bool) {
+    if private || created.elapsed() >= max_age() || reset(&session).await.is_err() {
+        return retire(session).await;
+    }
+
+    let mut idle = IDLE.lock().await;
+    if idle.len() >= CONFIG.browser_pool_size {
+        drop(idle);
+        return retire(session).await;
+    }
+    idle.push(Idle { session, created });
+}
+
+/// Every `BROWSER_POOL_KEEPALIVE_SECS`, pings the idle sessions so the driver doesn't reap them,
+/// retires the dead and old ones and tops the pool back up to `BROWSER_POOL_SIZE`.
+pub fn spawn_keepalive() {
+    if !enabled() {
+        return;
+    }
+
+    tokio::spawn(async {
+        let interval = Duration::from_secs(CONFIG.browser_pool_keepalive_secs.max(1));
+        loop {
+            // checked out of the pool meanwhile, flows get fresh sessions until they're back
+            let sessions = std::mem::take(&mut *IDLE.lock().await);
+            let mut kept = vec![];
+            for idle in sessions {
+                if idle.created.elapsed() < max_age() && alive(&idle.session).await {
+                    kept.push(idle);
+                } else {
+                    retire(idle.session).await;
+                }
+            }
+
+            while kept.len() + IDLE.lock().await.len() < CONFIG.browser_pool_size {
+                match session::launch().await {
+                    Ok(session) => kept.push(Idle {
+                        session,
+                        created: Instant::now(),
+                    }),
+                    Err(err) => {
+                        tracing::warn!("failed to warm up a pooled session: {}", err);
+                        break;
+                    }
+                }
+            }
+            IDLE.lock().await.extend(kept);
+
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+// Checked-out session, quitting hands it back to the pool
+struct Pooled {
+    inner: Session,
+    created: Instant,
+    // signed in to the registry or went to a payment host, never handed to another flow
+    private: AtomicBool,
+}
+
+impl Pooled {
+    fn visit(&self, url: &str) {
+        if url == login::SIGN_IN_URL || flow::is_payment_host(url) {
+            self.private.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+#[async_trait]
+impl Driver for Pooled {
+    async fn goto(&self, url: &str) -> WebDriverResult<()> {
+        self.visit(url);
+        self.inner.goto(url).await
+    }
+
+    async fn current_url(&self) -> WebDriverResult<String> {
+        let url = self.inner.current_url().await?;
+        self.visit(&url);
+
+        Ok(url)
+    }
+
+    async fn title(&self) -> WebDriverResult<String> {
+        self.inner.title().await
+    }
+
+    async fn add_cookie(&self, cookie: &Cookie) -> WebDriverResult<()> {
+        self.inner.add_cookie(cookie).await
+    }
+
+    async fn get_cookies(&self) -> WebDriverResult<Vec<Cookie>> {
+        self.inner.get_cookies().await
+    }
+
+    async fn delete_all_cookies(&self) -> WebDriverResult<()> {
+        self.inner.delete_all_cookies().await
+    }
+
+    async fn screenshot_png(&self) -> WebDriverResult<Vec<u8>> {
+        self.inner.screenshot_png().await
+    }
+
+    async fn thumbnail_png(&self) -> WebDriverResult<Vec<u8>> {
+        self.inner.thumbnail_png().await
+    }
+
+    async fn find_all(&self, xpath: &str) -> WebDriverResult<Vec<Box<dyn Element>>> {
+        self.inner.find_all(xpath).await
+    }
+
+    async fn quit(self: Box<Self>) -> WebDriverResult<()> {
+        // payment pages are reached by clicking through, not only by `goto`
+        if let Ok(url) = self.inner.current_url().await {
+            self.visit(&url);
+        }
+        let private = self.private.load(Ordering::Relaxed);
+        release(self.inner, self.created, private).await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+use crate::{config::CONFIG, cookies, session::Driver};
+
+/// Popups a provider's pages show over the forms, e.g. cookie consent or a survey, and how to
+/// get rid of them.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Popups {
+    // parts of the URLs of the provider's pages, the popups are looked for on every page if empty
+    #[serde(default)]
+    pub urls: Vec<String>,
+    // XPaths of the buttons that close them, clicked when displayed
+    pub dismiss: Vec<String>,
+    // generic close buttons that might as well belong to the page itself, only clicked once
+    // something covered the element a click was meant for
+    #[serde(default)]
+    pub recover: Vec<String>,
+}
+
+static PROVIDERS: OnceCell<BTreeMap<String, Popups>> = OnceCell::new();
+
+fn builtin() -> BTreeMap<String, Popups> {
+    BTreeMap::from([(
+        cookies::REGISTRY.to_string(),
+        Popups {
+            urls: vec![],
+            dismiss: vec![
+                "//button[@id='onetrust-accept-btn-handler']".to_string(),
+                "//div[contains(@class, 'cookie')]//button".to_string(),
+            ],
+            // inside a dialog, the page's own Close buttons (e.g. of a help panel) are left alone
+            recover: vec![
+                "//*[@role='dialog' or @aria-modal='true' or contains(@class, \
+                 'modal')]//button[@aria-label='Close']"
+                    .to_string(),
+            ],
+        },
+    )])
+}
+
+/// Loads the popup registry: the built-in popups plus those of `PROVIDER_POPUPS`, a JSON object
+/// of provider name to its popups, e.g. `{"registry": {"urls": ["/onbis/"], "dismiss":
+/// ["//button[contains(., 'No thanks')]"], "recover": ["//button[.='Close']"]}}`. Providers
+/// defined in the file replace the built-in ones of the same name.
+pub fn load() -> Result<()> {
+    let mut providers = builtin();
+    if let Some(path) = &CONFIG.provider_popups {
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let custom: BTreeMap<String, Popups> = serde_json::from_str(&file)
+            .with_context(|| format!("invalid provider popups in {}", path.display()))?;
+        providers.extend(custom);
+    }
+
+    PROVIDERS
+        .set(providers)
+        .map_err(|_| anyhow::anyhow!("provider popups already loaded"))
+}
+
+/// Clicks away the displayed popups of the providers whose page the browser is on, ignoring the
+/// ones that fail. Called before each interaction, so nothing covers the element it is on.
+pub async fn dismiss(driver: &dyn Driver) {
+    close(driver, false).await
+}
+
+/// Like `dismiss`, but also clicks the providers' generic close buttons. For when a click was
+/// intercepted, so something is known to cover the page.
+pub async fn recover(driver: &dyn Driver) {
+    close(driver, true).await
+}
+
+async fn close(driver: &dyn Driver, recovering: bool) {
+    let Some(providers) = PROVIDERS.get() else {
+        return;
+    };
+    let url = driver.current_url().await.unwrap_or_default();
+    let popups = providers.iter().filter(|(_, popups)| {
+        popups.urls.is_empty() || popups.urls.iter().any(|part| url.contains(part.as_str()))
+    });
+
+    for (provider, popups) in popups {
+        let recover = popups.recover.iter().filter(|_| recovering);
+        for xpath in popups.dismiss.iter().chain(recover) {
+            let Ok(elements) = driver.find_all(xpath).await else {
+                continue;
+            };
+            for element in elements {
+                if element.is_displayed().await.unwrap_or(false) && element.click().await.is_ok() {
+                    tracing::debug!("dismissed {} popup {}", provider, xpath);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+use crate::config::CONFIG;
+
+/// One action on the product options page, between choosing the product and paying for it.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum Step {
+    Click {
+        xpath: String,
+    },
+    // clicks a submit button and fails on the validation messages the form shows
+    Submit {
+        xpath: String,
+    },
+    Wait {
+        secs: u64,
+    },
+    // types the order email into the email fields of the order form, `form` being its XPath
+    FillEmail {
+        #[serde(default = "order_form")]
+        form: String,
+    },
+}
+
+// Content area of the registry pages, which leaves out signup boxes in the header and footer
+fn order_form() -> String {
+    "//main".to_string()
+}
+
+static FLOWS: OnceCell<BTreeMap<String, Vec<Step>>> = OnceCell::new();
+
+fn click(xpath: &str) -> Step {
+    Step::Click {
+        xpath: xpath.to_string(),
+    }
+}
+
+fn submit(xpath: &str) -> Step {
+    Step::Submit {
+        xpath: xpath.to_string(),
+    }
+}
+
+fn builtin() -> BTreeMap<String, Vec<Step>> {
+    BTreeMap::from([
+        (
+            "Profile Report".to_string(),
+            vec![
+                click("//label[contains(text(), 'Current Report')]"),
+                Step::Wait { secs: 5 },
+                Step::FillEmail { form: order_form() },
+                submit("//span[contains(text(), 'Submit')]"),
+            ],
+        ),
+        (
+            "Document Copies".to_string(),
+            vec![
+                click("//label[contains(text(), 'Select all Documents')]"),
+                Step::Wait { secs: 5 },
+                Step::FillEmail { form: order_form() },
+                submit("//span[contains(text(), 'Request Documents')]"),
+            ],
+        ),
+        (
+            "Certificate of Status".to_string(),
+            vec![
+                Step::FillEmail { form: order_form() },
+                submit("//span[contains(text(), 'Submit')]"),
+            ],
+        ),
+    ])
+}
+
+/// Loads the product flows: the built-in ones plus those of `PRODUCT_FLOWS`, a JSON object of
+/// product name to steps, e.g. `{"Certificate of No Match": [{"step": "fill_email"}, ...]}`.
+/// Products defined in the file replace the built-in ones of the same name.
+pub fn load() -> Result<()> {
+    let mut flows = builtin();
+    if let Some(path) = &CONFIG.product_flows {
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let custom: BTreeMap<String, Vec<Step>> = serde_json::from_str(&file)
+            .with_context(|| format!("invalid product flows in {}", path.display()))?;
+        flows.extend(custom);
+    }
+
+    FLOWS
+        .set(flows)
+        .map_err(|_| anyhow::anyhow!("product flows already loaded"))
+}
+
+pub fn steps(product: &str) -> Option<&'static [Step]> {
+    FLOWS.get()?.get(product).map(Vec::as_slice)
+}
+
+pub fn names() -> Vec<&'static str> {
+    FLOWS
+        .get()
+        .map(|flows| flows.keys().map(String::as_str).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,389 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use redis::{aio::ConnectionManager, AsyncCommands, LposOptions, Script};
+use serde::Serialize;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::{
+    clients,
+    config::CONFIG,
+    jobs::{Job, JobStatus},
+    lock::redis_connection,
+};
+
+// Jobs queued before queues were kept per client, still drained after the client queues
+const QUEUE: &str = "job-queue";
+// Clients with a queue of their own, `job-queue:{client}`
+const CLIENTS_KEY: &str = "job-queue:clients";
+// Smoothed run time of finished jobs in milliseconds, shared by the instances through redis
+const DURATION_KEY: &str = "job-duration-ms";
+// Jobs waiting for their off-peak hours, `{id}:{client}` scored by when they may run
+const DEFERRED_KEY: &str = "job-deferred";
+
+// Moves the due members of the deferred set onto their clients' queues in one go, so a job is
+// never off the set without being queued
+static RELEASE_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        local due = redis.call("zrangebyscore", KEYS[1], "-inf", ARGV[1])
+        for _, member in ipairs(due) do
+            local id, client = string.match(member, "^([^:]+):(.*)$")
+            if id then
+                redis.call("lpush", "job-queue:" .. client, id)
+                redis.call("sadd", KEYS[2], client)
+            end
+            redis.call("zrem", KEYS[1], member)
+        end
+        return #due
+        "#,
+    )
+});
+
+// Takes the due members off the deferred set, for instances running them without a queue
+static TAKE_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        local due = redis.call("zrangebyscore", KEYS[1], "-inf", ARGV[1])
+        for _, member in ipairs(due) do
+            redis.call("zrem", KEYS[1], member)
+        end
+        return due
+        "#,
+    )
+});
+
+// Counts the popped job as running for its client, unless that takes the client past its
+// `max_in_flight` (ARGV[1], -1 for none): then the job goes back to the tail of its queue, where
+// it's popped next again. One script, so workers popping at once can't both take the last slot.
+static CLAIM_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        local running = redis.call("incr", KEYS[1])
+        redis.call("expire", KEYS[1], ARGV[2])
+        if tonumber(ARGV[1]) >= 0 and running > tonumber(ARGV[1]) then
+            if running <= 1 then
+                redis.call("del", KEYS[1])
+            else
+                redis.call("decr", KEYS[1])
+            end
+            redis.call("rpush", KEYS[2], ARGV[3])
+            return 0
+        end
+        return 1
+        "#,
+    )
+});
+
+// BRPOP blocks its connection, so consumers don't share the one used for locks and pushes
+static CONSUMER: OnceCell<ConnectionManager> = OnceCell::const_new();
+// Smooth weighted round-robin state of this worker, per client
+static CURRENT_WEIGHTS: Lazy<Mutex<HashMap<String, i64>>> = Lazy::new(Default::default);
+// The same figure as seen by this process, all there is without redis
+static LOCAL_DURATION_MS: AtomicU64 = AtomicU64::new(0);
+
+fn redis_url() -> Result<&'static str> {
+    CONFIG
+        .redis_url
+        .as_deref()
+        .context("the job queue needs REDIS_URL")
+}
+
+fn client_queue(client: &str) -> String {
+    format!("job-queue:{}", client)
+}
+
+fn running_key(client: &str) -> String {
+    format!("job-running:{}", client)
+}
+
+pub async fn push(id: Uuid, client: &str) -> Result<()> {
+    let mut conn = redis_connection(redis_url()?).await?;
+    conn.lpush::<_, _, ()>(client_queue(client), id.to_string())
+        .await?;
+    conn.sadd::<_, _, ()>(CLIENTS_KEY, client).await?;
+
+    Ok(())
+}
+
+/// Holds the job back until `at`, when `release_due` pushes it onto the client's queue.
+pub async fn defer(id: Uuid, client: &str, at: DateTime<Utc>) -> Result<()> {
+    let mut conn = redis_connection(redis_url()?).await?;
+    conn.zadd::<_, _, _, ()>(DEFERRED_KEY, format!("{}:{}", id, client), at.timestamp())
+        .await?;
+
+    Ok(())
+}
+
+/// Pushes the deferred jobs whose time came onto their clients' queues, how many were.
+pub async fn release_due() -> Result<usize> {
+    let mut conn = redis_connection(redis_url()?).await?;
+    let released = RELEASE_SCRIPT
+        .key(DEFERRED_KEY)
+        .key(CLIENTS_KEY)
+        .arg(Utc::now().timestamp())
+        .invoke_async(&mut conn)
+        .await?;
+
+    Ok(released)
+}
+
+/// Takes the deferred jobs whose time came off the set, for `all` instances, which run them
+/// themselves.
+pub async fn take_due() -> Result<Vec<Uuid>> {
+    let mut conn = redis_connection(redis_url()?).await?;
+    let due: Vec<String> = TAKE_SCRIPT
+        .key(DEFERRED_KEY)
+        .arg(Utc::now().timestamp())
+        .invoke_async(&mut conn)
+        .await?;
+
+    Ok(due
+        .iter()
+        .filter_map(|member| member.split_once(':')?.0.parse().ok())
+        .collect())
+}
+
+// Smooth weighted round-robin over the clients: whoever is picked goes first and the others
+// follow by how long they've been passed over, so empty queues don't leave a worker idle
+fn schedule(clients: Vec<String>) -> Vec<String> {
+    let mut current = CURRENT_WEIGHTS.lock().unwrap();
+    current.retain(|client, _| clients.contains(client));
+    let total: i64 = clients
+        .iter()
+        .map(|client| clients::quota(client).weight as i64)
+        .sum();
+    for client in &clients {
+        *current.entry(client.clone()).or_default() += clients::quota(client).weight as i64;
+    }
+
+    let mut order = clients;
+    order.sort_by_key(|client| std::cmp::Reverse(current[client]));
+    if let Some(first) = order.first() {
+        *current.get_mut(first).unwrap() -= total;
+    }
+
+    order
+}
+
+/// A job taken off the queue.
+pub struct Popped {
+    pub id: Uuid,
+    // whose running count the job holds, release it with `finished`. None for a job of the old
+    // queue without a record.
+    pub client: Option<String>,
+}
+
+/// Next queued job, taking turns between the clients' queues by their weights and skipping
+/// clients that run their `max_in_flight` jobs already. Each client's queue is served oldest
+/// first. The job is counted as running for its client before it's handed out. `None` when
+/// nothing arrived within `timeout_secs`, or what arrived went back as its client started
+/// running its `max_in_flight` jobs meanwhile.
+pub async fn pop(timeout_secs: f64) -> Result<Option<Popped>> {
+    let mut conn = redis_connection(redis_url()?).await?;
+    let mut eligible = vec![];
+    for client in conn.smembers::<_, Vec<String>>(CLIENTS_KEY).await? {
+        if let Some(max) = clients::quota(&client).max_in_flight {
+            let running: Option<usize> = conn.get(running_key(&client)).await?;
+            if running.unwrap_or(0) >= max {
+                continue;
+            }
+        }
+        eligible.push(client);
+    }
+
+    let mut queues: Vec<String> = schedule(eligible)
+        .iter()
+        .map(|client| client_queue(client))
+        .collect();
+    queues.push(QUEUE.to_string());
+
+    let mut consumer = CONSUMER
+        .get_or_try_init(|| async {
+            let client = redis::Client::open(redis_url()?)?;
+            anyhow::Ok(ConnectionManager::new(client).await?)
+        })
+        .await?
+        .clone();
+    let popped: Option<(String, String)> = consumer.brpop(queues, timeout_secs).await?;
+    let Some((queue, id)) = popped else {
+        return Ok(None);
+    };
+    let id: Uuid = id.parse()?;
+
+    // jobs of the old queue only say whose they are in their record
+    let client = match queue.strip_prefix("job-queue:") {
+        Some(client) => client.to_string(),
+        None => match Job::find(id).await? {
+            Some(job) => job.client,
+            None => return Ok(Some(Popped { id, client: None })),
+        },
+    };
+    if !claim(&mut conn, &client, &queue, id).await? {
+        return Ok(None);
+    }
+
+    Ok(Some(Popped {
+        id,
+        client: Some(client),
+    }))
+}
+
+// Counts the job as running for `max_in_flight` across workers, or puts it back. The count
+// expires like payment locks, so a worker that died mid-job doesn't hold the client's slot
+// forever.
+async fn claim(conn: &mut ConnectionManager, client: &str, queue: &str, id: Uuid) -> Result<bool> {
+    let max = clients::quota(client)
+        .max_in_flight
+        .map_or(-1, |max| max as i64);
+    let claimed: i64 = CLAIM_SCRIPT
+        .key(running_key(client))
+        .key(queue)
+        .arg(max)
+        .arg(CONFIG.payment_lock_ttl_secs)
+        .arg(id.to_string())
+        .invoke_async(conn)
+        .await?;
+
+    Ok(claimed == 1)
+}
+
+pub async fn finished(client: &str) -> Result<()> {
+    let mut conn = redis_connection(redis_url()?).await?;
+    let running: i64 = conn.decr(running_key(client), 1).await?;
+    if running <= 0 {
+        conn.del::<_, ()>(running_key(client)).await?;
+    }
+
+    Ok(())
+}
+
+/// Jobs waiting for a worker, across the clients' queues.
+pub async fn queued() -> Result<usize> {
+    let mut conn = redis_connection(redis_url()?).await?;
+    let mut queued: usize = conn.llen(QUEUE).await?;
+    for client in conn.smembers::<_, Vec<String>>(CLIENTS_KEY).await? {
+        queued += conn.llen::<_, usize>(client_queue(&client)).await?;
+    }
+
+    Ok(queued)
+}
+
+// Jobs are pushed at the head and popped from the tail, so the tail is next. 1 for the next job
+// of the client, `None` once a worker took it.
+async fn position(id: Uuid, client: &str) -> Result<Option<usize>> {
+    let mut conn = redis_connection(redis_url()?).await?;
+    let queue = client_queue(client);
+    let index: Option<usize> = conn
+        .lpos(&queue, id.to_string(), LposOptions::default())
+        .await?;
+    let Some(index) = index else {
+        return Ok(None);
+    };
+    let len: usize = conn.llen(&queue).await?;
+
+    Ok(Some(len.saturating_sub(index)))
+}
+
+fn smooth(previous: Option<u64>, ms: u64) -> u64 {
+    previous.map_or(ms, |previous| (previous * 3 + ms) / 4)
+}
+
+/// Folds the run time of a finished job into the average wait estimates are based on.
+pub async fn record_duration(duration: Duration) {
+    let ms = duration.as_millis() as u64;
+    let local = LOCAL_DURATION_MS.load(Ordering::Relaxed);
+    LOCAL_DURATION_MS.store(smooth((local > 0).then_some(local), ms), Ordering::Relaxed);
+
+    if CONFIG.redis_url.is_none() {
+        return;
+    }
+    let shared = async {
+        let mut conn = redis_connection(redis_url()?).await?;
+        let previous: Option<u64> = conn.get(DURATION_KEY).await?;
+        conn.set::<_, _, ()>(DURATION_KEY, smooth(previous, ms))
+            .await?;
+        anyhow::Ok(())
+    };
+    if let Err(err) = shared.await {
+        tracing::warn!("failed to record the job duration: {}", err);
+    }
+}
+
+/// Typical run time of a job, from the finished ones or `JOB_DURATION_ESTIMATE_SECS` before any.
+pub async fn average_duration() -> Duration {
+    let mut ms = None;
+    if CONFIG.redis_url.is_some() {
+        let shared = async {
+            let mut conn = redis_connection(redis_url()?).await?;
+            anyhow::Ok(conn.get::<_, Option<u64>>(DURATION_KEY).await?)
+        };
+        ms = shared.await.ok().flatten();
+    }
+    let local = LOCAL_DURATION_MS.load(Ordering::Relaxed);
+    let ms = ms
+        .or((local > 0).then_some(local))
+        .unwrap_or(CONFIG.job_duration_estimate_secs * 1000);
+
+    Duration::from_millis(ms)
+}
+
+/// Where an unfinished job stands, sent as `X-Queue-Position` and `X-Estimated-Wait`.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Wait {
+    // 1 for the next job of the client to be picked up, 0 once it runs
+    pub queue_position: usize,
+    // until the result is expected, assuming `WORKER_CONCURRENCY` jobs run at once
+    pub estimated_wait_secs: u64,
+}
+
+pub async fn wait(job: &Job) -> Result<Option<Wait>> {
+    let average = average_duration().await;
+    let concurrency = CONFIG.worker_concurrency.max(1) as u32;
+
+    let wait = match job.status {
+        // deferred to the off-peak hours, so not on the queue yet
+        JobStatus::Queued if job.run_at.is_some_and(|at| at > Utc::now()) => {
+            let until = job
+                .run_at
+                .and_then(|at| (at - Utc::now()).to_std().ok())
+                .unwrap_or_default();
+            Wait {
+                queue_position: 0,
+                estimated_wait_secs: (until + average).as_secs(),
+            }
+        }
+        JobStatus::Queued => {
+            // a worker may have taken it since the job was read
+            let position = position(job.id, &job.client).await?.unwrap_or(0);
+            let rounds = (position as u32).div_ceil(concurrency).max(1);
+            Wait {
+                queue_position: position,
+                estimated_wait_secs: (average * rounds).as_secs(),
+            }
+        }
+        JobStatus::Running => {
+            let running = job
+                .started_at
+                .and_then(|started| (Utc::now() - started).to_std().ok())
+                .unwrap_or_default();
+            Wait {
+                queue_position: 0,
+                estimated_wait_secs: average.saturating_sub(running).as_secs(),
+            }
+        }
+        JobStatus::Succeeded | JobStatus::Failed => return Ok(None),
+    };
+
+    Ok(Some(wait))
+}
@@ -0,0 +1,302 @@
+use std::{collections::VecDeque, path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use axum::{
+    body::Bytes,
+    extract::{Request, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    fs::OpenOptions,
+    io::AsyncWriteExt,
+    sync::{Mutex, OnceCell},
+};
+
+use crate::{cards, config::CONFIG, session};
+
+/// One WebDriver command and chromedriver's answer, a line of a recording.
+#[derive(Serialize, Deserialize, Debug)]
+struct Exchange {
+    method: String,
+    path: String,
+    request: Value,
+    status: u16,
+    response: Value,
+}
+
+enum Proxy {
+    Record {
+        upstream: String,
+        client: reqwest::Client,
+        file: Mutex<tokio::fs::File>,
+    },
+    Replay {
+        exchanges: Mutex<VecDeque<Exchange>>,
+    },
+}
+
+static ENDPOINT: OnceCell<String> = OnceCell::const_new();
+
+// Bodies are JSON except for the odd empty GET
+fn body_value(bytes: &[u8]) -> Value {
+    serde_json::from_slice(bytes).unwrap_or_else(|_| {
+        if bytes.is_empty() {
+            Value::Null
+        } else {
+            Value::String(String::from_utf8_lossy(bytes).into_owned())
+        }
+    })
+}
+
+/// Where flows send WebDriver commands. That's the browser's driver unless `WEBDRIVER_RECORD`
+/// (commands pass through and are appended to the file) or `WEBDRIVER_REPLAY` (answers come from a
+/// recording, no browser needed) point them at a local proxy.
+pub async fn webdriver_url() -> Result<String> {
+    if CONFIG.webdriver_record.is_none() && CONFIG.webdriver_replay.is_none() {
+        return Ok(session::driver_url().to_string());
+    }
+
+    // the first session sets the proxy up, the recording is only opened or read then
+    ENDPOINT
+        .get_or_try_init(|| async { serve(proxy().await?).await })
+        .await
+        .cloned()
+}
+
+async fn proxy() -> Result<Proxy> {
+    Ok(match (&CONFIG.webdriver_record, &CONFIG.webdriver_replay) {
+        (Some(_), Some(_)) => anyhow::bail!("WEBDRIVER_RECORD and WEBDRIVER_REPLAY both set"),
+        (Some(path), _) => Proxy::Record {
+            upstream: session::driver_url().trim_end_matches('/').to_string(),
+            client: reqwest::Client::new(),
+            file: Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .with_context(|| format!("failed to open {}", path.display()))?,
+            ),
+        },
+        (None, Some(path)) => Proxy::Replay {
+            exchanges: Mutex::new(load(path).await?),
+        },
+        (None, None) => anyhow::bail!("neither WEBDRIVER_RECORD nor WEBDRIVER_REPLAY is set"),
+    })
+}
+
+// Serves `proxy` on a local port, returning its URL
+async fn serve(proxy: Proxy) -> Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let app = Router::new().fallback(forward).with_state(Arc::new(proxy));
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    tracing::info!("webdriver commands go through {}", url);
+
+    Ok(url)
+}
+
+async fn load(path: &Path) -> Result<VecDeque<Exchange>> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).context("malformed recording"))
+        .collect()
+}
+
+// WebDriver error body, so a diverging replay fails the flow like a browser error would
+fn webdriver_error(message: String) -> Response {
+    tracing::warn!("webdriver proxy: {}", message);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(json!({ "value": { "error": "unknown error", "message": message } })),
+    )
+        .into_response()
+}
+
+async fn forward(State(proxy): State<Arc<Proxy>>, req: Request) -> Response {
+    let method = req.method().to_string();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |path| path.as_str())
+        .to_string();
+    let body: Bytes = match axum::body::to_bytes(req.into_body(), usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => return webdriver_error(err.to_string()),
+    };
+
+    match proxy.as_ref() {
+        Proxy::Record {
+            upstream,
+            client,
+            file,
+        } => {
+            let sent = client
+                .request(
+                    method.parse().unwrap_or(reqwest::Method::GET),
+                    format!("{}{}", upstream, path),
+                )
+                .header("content-type", "application/json")
+                .body(body.to_vec())
+                .send()
+                .await;
+            let response = match sent {
+                Ok(response) => response,
+                Err(err) => return webdriver_error(err.to_string()),
+            };
+            let status = response.status().as_u16();
+            let response = match response.bytes().await {
+                Ok(bytes) => body_value(&bytes),
+                Err(err) => return webdriver_error(err.to_string()),
+            };
+
+            let exchange = Exchange {
+                method,
+                path,
+                request: body_value(&body),
+                status,
+                response,
+            };
+            // typed card details stay out of the file
+            if let Ok(line) = serde_json::to_string(&exchange) {
+                let mut line = cards::redact(&line);
+                line.push('\n');
+                if let Err(err) = file.lock().await.write_all(line.as_bytes()).await {
+                    tracing::warn!("failed to write recording: {}", err);
+                }
+            }
+
+            let status = StatusCode::from_u16(exchange.status).unwrap_or(StatusCode::BAD_GATEWAY);
+            (status, Json(exchange.response)).into_response()
+        }
+        Proxy::Replay { exchanges } => {
+            let Some(exchange) = exchanges.lock().await.pop_front() else {
+                return webdriver_error(format!("recording ended before {} {}", method, path));
+            };
+            if exchange.method != method || exchange.path != path {
+                return webdriver_error(format!(
+                    "replay diverged: recorded {} {}, got {} {}",
+                    exchange.method, exchange.path, method, path
+                ));
+            }
+
+            let status = StatusCode::from_u16(exchange.status).unwrap_or(StatusCode::BAD_GATEWAY);
+            (status, Json(exchange.response)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use thirtyfour::{DesiredCapabilities, WebDriver};
+
+    use super::*;
+
+    const FIXTURE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/webdriver/search.jsonl"
+    );
+
+    async fn replay() -> String {
+        let exchanges = load(Path::new(FIXTURE)).await.unwrap();
+        serve(Proxy::Replay {
+            exchanges: Mutex::new(exchanges),
+        })
+        .await
+        .unwrap()
+    }
+
+    async fn error_message(response: reqwest::Response) -> String {
+        assert_eq!(response.status(), 500);
+        let body: Value = response.json().await.unwrap();
+        body["value"]["message"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn replays_a_recorded_session() {
+        let url = replay().await;
+        let driver = WebDriver::new(&url, DesiredCapabilities::chrome())
+            .await
+            .unwrap();
+
+        driver.goto("https://redacted/search").await.unwrap();
+        assert_eq!(driver.title().await.unwrap(), "Search");
+        driver.quit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn fails_a_command_the_recording_does_not_expect() {
+        let url = replay().await;
+        let response = reqwest::get(format!("{}/session/abc/title", url))
+            .await
+            .unwrap();
+
+        let message = error_message(response).await;
+        assert!(message.starts_with("replay diverged"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn fails_once_the_recording_ended() {
+        let url = replay().await;
+        let driver = WebDriver::new(&url, DesiredCapabilities::chrome())
+            .await
+            .unwrap();
+        driver.goto("https://redacted/search").await.unwrap();
+        driver.title().await.unwrap();
+        driver.quit().await.unwrap();
+
+        let response = reqwest::get(format!("{}/session/abc/title", url))
+            .await
+            .unwrap();
+        let message = error_message(response).await;
+        assert!(message.starts_with("recording ended"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn records_what_it_forwards() {
+        let upstream = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_url = format!("http://{}", upstream.local_addr().unwrap());
+        let app = Router::new().fallback(|| async { Json(json!({ "value": "Search" })) });
+        tokio::spawn(async move { axum::serve(upstream, app).await });
+
+        let path = std::env::temp_dir().join(format!("recording-{}.jsonl", uuid::Uuid::new_v4()));
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .unwrap();
+        let url = serve(Proxy::Record {
+            upstream: upstream_url,
+            client: reqwest::Client::new(),
+            file: Mutex::new(file),
+        })
+        .await
+        .unwrap();
+
+        let response: Value = reqwest::get(format!("{}/session/abc/title", url))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(response["value"], "Search");
+
+        let recorded = load(&path).await.unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].method, "GET");
+        assert_eq!(recorded[0].path, "/session/abc/title");
+        assert_eq!(recorded[0].status, 200);
+        assert_eq!(recorded[0].response, json!({ "value": "Search" }));
+    }
+}
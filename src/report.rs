@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+use dump_core::{models::CorporationData, report::parse_profile_report};
+use serde::{Deserialize, Serialize};
+
+use crate::{artifacts::artifacts, config::CONFIG, corporations, storage};
+
+const REPORTS: &str = "reports";
+
+/// Extracts the text layer of a report PDF and parses it.
+pub async fn parse_profile_report_pdf(bytes: Vec<u8>) -> Result<CorporationData> {
+    // pdf-extract is synchronous and panics on some malformed files
+    let text = tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&bytes))
+        .await
+        .context("failed to read PDF")??;
+
+    Ok(parse_profile_report(&text))
+}
+
+/// Artifact key of the stored copy of document `id`.
+pub fn document_key(id: &str) -> String {
+    format!("documents/{}.pdf", id)
+}
+
+/// A parsed report, kept with the document it was parsed from so an erasure of the corporation
+/// finds both.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StoredReport {
+    pub document_id: String,
+    pub corporation_id: Option<String>,
+    pub data: CorporationData,
+}
+
+/// Stores the report parsed from document `document_id`. A soft-deleted corporation is purged
+/// first, like a scrape of it is, so the report doesn't end up next to the data being erased.
+/// Director addresses are cut down under `MINIMIZE_PII`, as in stored scrapes.
+pub async fn store(document_id: &str, mut data: CorporationData) -> Result<()> {
+    if CONFIG.minimize_pii {
+        data.minimize_pii();
+    }
+    let corporation_id = data
+        .corp_details
+        .iter()
+        .flatten()
+        .find_map(|row| row.get("Ontario Corporation Number"))
+        .cloned();
+    if let Some(corporation_id) = &corporation_id {
+        corporations::purge_if_deleted(corporation_id).await?;
+    }
+
+    let report = StoredReport {
+        document_id: document_id.to_string(),
+        corporation_id,
+        data,
+    };
+    storage::save(REPORTS, document_id, &report).await
+}
+
+/// Deletes the reports of the corporations `ids` and the documents they were parsed from,
+/// returning how many. Reports stored before they named their document are left to their
+/// retention.
+pub async fn purge(ids: &[String]) -> Result<usize> {
+    let mut purged = 0;
+    for report in storage::storage().list(REPORTS).await? {
+        let Ok(report) = serde_json::from_value::<StoredReport>(report.value) else {
+            continue;
+        };
+        if !report
+            .corporation_id
+            .as_ref()
+            .is_some_and(|id| ids.contains(id))
+        {
+            continue;
+        }
+        artifacts()
+            .delete(&document_key(&report.document_id))
+            .await?;
+        storage::delete(REPORTS, &report.document_id).await?;
+        purged += 1;
+    }
+
+    Ok(purged)
+}
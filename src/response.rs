@@ -0,0 +1,331 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use dump_core::patterns::HTML_LANG;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{compat, config::CONFIG, errors::AppError, handler::Freshness, retry::Attempt};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+/// Where federal profile data came from, see `handler::fetch_corporation`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Scrape,
+    OpenData,
+}
+
+struct RequestContext {
+    request_id: Uuid,
+    // of the `traceparent` header, with `TRACE_CONTEXT`
+    trace_id: Option<String>,
+    started: Instant,
+    attempts: AtomicU32,
+    // Tries of the flow that failed, see `retry`
+    failed_attempts: Mutex<Vec<Attempt>>,
+    cache: Mutex<CacheStatus>,
+    // The cached result is past its freshness and being refreshed
+    stale: AtomicBool,
+    source: Mutex<Option<Source>>,
+    // Language of the scraped pages, sent as Content-Language
+    language: Mutex<Option<String>>,
+    warnings: Mutex<Vec<Warning>>,
+    // Last page fetched for extraction, kept so a panic can be debugged against it
+    page: Mutex<Option<Page>>,
+    // Answer in the legacy shapes, see `compat`
+    legacy: bool,
+    // Set by handlers on top of the envelope
+    headers: Mutex<Vec<(&'static str, String)>>,
+}
+
+/// Something about the request the client should know, although it was answered.
+#[derive(Serialize, Debug, Clone)]
+pub struct Warning {
+    pub code: &'static str,
+    // request field the warning is about
+    pub field: &'static str,
+    pub message: String,
+}
+
+pub struct Page {
+    pub url: String,
+    pub html: String,
+}
+
+tokio::task_local! {
+    static CONTEXT: RequestContext;
+}
+
+/// Runs the request inside a fresh context the envelope metadata is collected in.
+pub async fn context(req: Request, next: Next) -> Response {
+    let request_id = Uuid::new_v4();
+    let context = RequestContext::new(
+        request_id,
+        trace_id(req.headers()),
+        compat::requested(req.headers()),
+    );
+
+    let (mut response, headers, language) = CONTEXT
+        .scope(context, async {
+            let response = next.run(req).await;
+            let headers = CONTEXT.with(|c| std::mem::take(&mut *c.headers.lock().unwrap()));
+            let language = CONTEXT.with(|c| c.language.lock().unwrap().take());
+            (response, headers, language)
+        })
+        .await;
+    if let Some(value) = language.and_then(|language| HeaderValue::from_str(&language).ok()) {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_LANGUAGE, value);
+    }
+    for (name, value) in headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&request_id.to_string()) {
+        response.headers_mut().insert("x-request-id", value);
+    }
+
+    response
+}
+
+/// Runs a job's flow outside of a request, e.g. on a worker, in a context of its own so what the
+/// flow records, like the page language, still ends up in its envelope.
+pub async fn detached<F: Future>(flow: F) -> F::Output {
+    CONTEXT
+        .scope(RequestContext::new(Uuid::new_v4(), None, false), flow)
+        .await
+}
+
+impl RequestContext {
+    fn new(request_id: Uuid, trace_id: Option<String>, legacy: bool) -> Self {
+        RequestContext {
+            request_id,
+            trace_id,
+            started: Instant::now(),
+            attempts: AtomicU32::new(0),
+            failed_attempts: Mutex::default(),
+            cache: Mutex::new(CacheStatus::Miss),
+            stale: AtomicBool::new(false),
+            source: Mutex::default(),
+            language: Mutex::default(),
+            warnings: Mutex::default(),
+            page: Mutex::default(),
+            legacy,
+            headers: Mutex::default(),
+        }
+    }
+}
+
+// `00-{trace id}-{parent id}-{flags}`, an all-zero trace id is invalid
+fn trace_id(headers: &HeaderMap) -> Option<String> {
+    if !CONFIG.trace_context {
+        return None;
+    }
+    let traceparent = headers.get("traceparent")?.to_str().ok()?;
+    let trace_id = traceparent.split('-').nth(1)?.to_ascii_lowercase();
+    let valid = trace_id.len() == 32
+        && trace_id.chars().all(|c| c.is_ascii_hexdigit())
+        && trace_id.chars().any(|c| c != '0');
+
+    valid.then_some(trace_id)
+}
+
+/// Trace id of the current request and the link to it in the tracing UI, see
+/// `TRACE_URL_TEMPLATE`.
+pub fn trace() -> (Option<String>, Option<String>) {
+    let trace_id = CONTEXT.try_with(|c| c.trace_id.clone()).ok().flatten();
+    let trace_url = trace_id.as_ref().and_then(|trace_id| {
+        CONFIG
+            .trace_url_template
+            .as_ref()
+            .map(|template| template.replace("{trace_id}", trace_id))
+    });
+
+    (trace_id, trace_url)
+}
+
+/// Whether the current request asked for the legacy shapes, see `compat`.
+pub fn legacy() -> bool {
+    CONTEXT.try_with(|c| c.legacy).unwrap_or(false)
+}
+
+pub fn request_id() -> Option<Uuid> {
+    CONTEXT.try_with(|c| c.request_id).ok()
+}
+
+/// Counts one (re)try of the current request's flow.
+pub fn record_attempt() {
+    let _ = CONTEXT.try_with(|c| c.attempts.fetch_add(1, Ordering::Relaxed));
+}
+
+/// Attempts recorded so far by the current request, `None` outside of one.
+pub fn attempts() -> Option<u32> {
+    CONTEXT
+        .try_with(|c| c.attempts.load(Ordering::Relaxed).max(1))
+        .ok()
+}
+
+pub fn record_failed_attempt(attempt: Attempt) {
+    let _ = CONTEXT.try_with(|c| c.failed_attempts.lock().unwrap().push(attempt));
+}
+
+/// Failed tries of the current request's flow so far, oldest first.
+pub fn failed_attempts() -> Vec<Attempt> {
+    CONTEXT
+        .try_with(|c| c.failed_attempts.lock().unwrap().clone())
+        .unwrap_or_default()
+}
+
+/// Marks the current request as answered from cache.
+pub fn record_cache_hit(stale: bool) {
+    let _ = CONTEXT.try_with(|c| {
+        *c.cache.lock().unwrap() = CacheStatus::Hit;
+        c.stale.store(stale, Ordering::Relaxed);
+    });
+}
+
+pub fn record_source(source: Source) {
+    let _ = CONTEXT.try_with(|c| *c.source.lock().unwrap() = Some(source));
+}
+
+/// Adds a warning to the current request's envelope.
+pub fn warn(warning: Warning) {
+    let _ = CONTEXT.try_with(|c| c.warnings.lock().unwrap().push(warning));
+}
+
+/// Adds a header to the current request's response.
+pub fn set_header(name: &'static str, value: impl ToString) {
+    let _ = CONTEXT.try_with(|c| c.headers.lock().unwrap().push((name, value.to_string())));
+}
+
+/// Records the language of the page the response's data was scraped from, e.g. "fr-CA".
+pub fn record_language(language: &str) {
+    let _ = CONTEXT.try_with(|c| *c.language.lock().unwrap() = Some(language.to_string()));
+}
+
+/// Remembers the page the current request is extracting from, and records its language.
+pub fn record_page(url: &str, html: &str) {
+    if let Some(language) = HTML_LANG.captures(html).and_then(|found| found.get(1)) {
+        record_language(language.as_str());
+    }
+    let _ = CONTEXT.try_with(|c| {
+        *c.page.lock().unwrap() = Some(Page {
+            url: url.to_string(),
+            html: html.to_string(),
+        })
+    });
+}
+
+pub fn take_page() -> Option<Page> {
+    CONTEXT
+        .try_with(|c| c.page.lock().unwrap().take())
+        .ok()
+        .flatten()
+}
+
+/// Body of every successful API response: the payload plus request metadata.
+#[derive(Serialize, Debug)]
+pub struct Envelope<T> {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub data: T,
+    pub request_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_url: Option<String>,
+    pub duration_ms: u64,
+    pub attempts: u32,
+    pub cache: CacheStatus,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+    // of the scraped pages, also sent as Content-Language
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    // when the data was scraped, set by the handlers answering from a cache or storage, so clients
+    // can tell stored data from live data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scraped_at: Option<DateTime<Utc>>,
+    // by section, of differential scrapes, see `handler::differential_corporation`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub freshness: Option<BTreeMap<String, Freshness>>,
+    pub warnings: Vec<Warning>,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(status: StatusCode, data: T) -> Self {
+        let (request_id, duration_ms, attempts, cache, stale, source, language, warnings) = CONTEXT
+            .try_with(|c| {
+                (
+                    Some(c.request_id),
+                    c.started.elapsed().as_millis() as u64,
+                    c.attempts.load(Ordering::Relaxed).max(1),
+                    *c.cache.lock().unwrap(),
+                    c.stale.load(Ordering::Relaxed),
+                    *c.source.lock().unwrap(),
+                    c.language.lock().unwrap().clone(),
+                    c.warnings.lock().unwrap().clone(),
+                )
+            })
+            .unwrap_or((None, 0, 1, CacheStatus::Miss, false, None, None, vec![]));
+        let (trace_id, trace_url) = trace();
+
+        Envelope {
+            status,
+            data,
+            request_id,
+            trace_id,
+            trace_url,
+            duration_ms,
+            attempts,
+            cache,
+            stale,
+            source,
+            language,
+            scraped_at: None,
+            freshness: None,
+            warnings,
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Envelope<T> {
+    fn into_response(self) -> Response {
+        if !legacy() {
+            return (self.status, Json(self)).into_response();
+        }
+
+        let mut body = match serde_json::to_value(&self) {
+            Ok(body) => body,
+            Err(err) => return AppError::from(err).into_response(),
+        };
+        compat::downgrade(&mut body);
+        (self.status, Json(body)).into_response()
+    }
+}
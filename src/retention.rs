@@ -0,0 +1,121 @@
+use std::time::Duration as StdDuration;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    artifacts::artifacts,
+    config::CONFIG,
+    corporations, report,
+    storage::{self, storage},
+};
+
+const ERASURES: &str = "erasures";
+
+// Artifacts of each retention, by key prefix: step screenshots, fetched documents and offloaded
+// responses are raw, delivered orders structured
+const RAW_ARTIFACTS: [&str; 3] = ["jobs/", "documents/", "responses/"];
+const STRUCTURED_ARTIFACTS: [&str; 1] = ["orders/"];
+
+/// How long documents of `collection` are kept, none for the collections no policy covers.
+///
+/// Raw data, what was captured while scraping, is kept `RETENTION_RAW_DAYS`. The structured
+/// data extracted from it is kept `RETENTION_STRUCTURED_DAYS`. A changed policy applies to
+/// documents saved from then on.
+pub fn retention(collection: &str) -> Option<Duration> {
+    let days = match collection {
+        "audit" | "job_logs" => CONFIG.retention_raw_days,
+        "corporations" | "corporation-history" | "changes" | "reports" | "deliveries" => {
+            CONFIG.retention_structured_days
+        }
+        // the snapshots of a corporation, `corporation-history:{id}`
+        _ if collection.starts_with("corporation-history:") => CONFIG.retention_structured_days,
+        // the name index, `corporation-names:{prefix}`, goes with the records it points to
+        _ if collection.starts_with("corporation-names:") => CONFIG.retention_structured_days,
+        _ => return None,
+    };
+
+    Some(Duration::days(days as i64))
+}
+
+/// A deletion request for a corporation's data, kept as the record that it was honoured.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Erasure {
+    pub corporation_id: String,
+    pub requested_by: String,
+    pub requested_at: DateTime<Utc>,
+    // once the purger deleted the data
+    pub purged_at: Option<DateTime<Utc>>,
+}
+
+impl Erasure {
+    /// Soft deletes the corporation, the next purge deletes it for good. None when no data of it
+    /// is stored.
+    pub async fn request(corporation_id: &str, requested_by: &str) -> Result<Option<Erasure>> {
+        let soft_deleted = corporations::soft_delete(corporation_id).await?;
+        // the reports of a corporation that was never scraped have no record to hide, so they go
+        // right away
+        let purged = !soft_deleted && report::purge(&[corporation_id.to_string()]).await? > 0;
+        if !soft_deleted && !purged {
+            return Ok(None);
+        }
+
+        let now = Utc::now();
+        let erasure = Erasure {
+            corporation_id: corporation_id.to_string(),
+            requested_by: requested_by.to_string(),
+            requested_at: now,
+            purged_at: purged.then_some(now),
+        };
+        storage::keep(ERASURES, corporation_id, &erasure).await?;
+
+        Ok(Some(erasure))
+    }
+}
+
+/// Every `RETENTION_PURGE_INTERVAL_SECS`, deletes the soft-deleted corporations and the data
+/// past its retention.
+pub fn spawn_purger() {
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(StdDuration::from_secs(CONFIG.retention_purge_interval_secs)).await;
+            if let Err(err) = purge().await {
+                tracing::warn!("failed to purge stored data: {:#}", err);
+            }
+        }
+    });
+}
+
+async fn purge() -> Result<()> {
+    let erased = corporations::purge_deleted().await?;
+    for id in &erased {
+        if let Some(mut erasure) = storage::load::<Erasure>(ERASURES, id).await? {
+            erasure.purged_at = Some(Utc::now());
+            storage::keep(ERASURES, id, &erasure).await?;
+        }
+    }
+
+    let expired = storage().purge_expired().await?;
+
+    let mut artifacts_purged = 0;
+    let raw_before = Utc::now() - Duration::days(CONFIG.retention_raw_days as i64);
+    for prefix in RAW_ARTIFACTS {
+        artifacts_purged += artifacts().purge(prefix, raw_before).await?;
+    }
+    let structured_before = Utc::now() - Duration::days(CONFIG.retention_structured_days as i64);
+    for prefix in STRUCTURED_ARTIFACTS {
+        artifacts_purged += artifacts().purge(prefix, structured_before).await?;
+    }
+
+    if !erased.is_empty() || expired > 0 || artifacts_purged > 0 {
+        tracing::info!(
+            "purged {} deleted corporations, {} expired documents and {} artifacts",
+            erased.len(),
+            expired,
+            artifacts_purged
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::{future::Future, time::Duration};
+
+use rand::Rng;
+use serde::Serialize;
+use tryhard::RetryPolicy;
+
+use crate::{errors::AppError, response};
+
+// Tries of a flow after the first one
+const RETRIES: u32 = 10;
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// A failed try of the current request's flow, listed with its final error.
+#[derive(Serialize, Debug, Clone)]
+pub struct Attempt {
+    pub attempt: u32,
+    pub error_class: &'static str,
+    // slept before the next try, none after the last one
+    pub backoff_ms: Option<u64>,
+}
+
+// Exponential with full jitter, so flows that failed together don't all retry at the same time
+fn backoff(attempt: u32, err: &AppError) -> RetryPolicy {
+    let error_class = err.class();
+    if !err.is_retryable() {
+        tracing::warn!(
+            attempt,
+            error_class,
+            "attempt failed, not retrying: {}",
+            err
+        );
+        response::record_failed_attempt(Attempt {
+            attempt,
+            error_class,
+            backoff_ms: None,
+        });
+        return RetryPolicy::Break;
+    }
+
+    let cap = BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_DELAY);
+    let delay = rand::thread_rng().gen_range(Duration::ZERO..=cap);
+    let backoff_ms = delay.as_millis() as u64;
+    tracing::warn!(
+        attempt,
+        error_class,
+        backoff_ms,
+        "attempt failed, retrying: {}",
+        err
+    );
+    response::record_failed_attempt(Attempt {
+        attempt,
+        error_class,
+        backoff_ms: Some(backoff_ms),
+    });
+
+    RetryPolicy::Delay(delay)
+}
+
+/// Runs a browser flow until it succeeds, it fails with an error retrying can't fix or it has
+/// been tried `RETRIES` more times. Tries are counted for the response and each failed one is
+/// logged with its error class and the backoff chosen.
+pub async fn retry<T, F, Fut>(mut flow: F) -> Result<T, AppError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AppError>>,
+{
+    let result = tryhard::retry_fn(|| {
+        response::record_attempt();
+        flow()
+    })
+    .retries(RETRIES)
+    .custom_backoff(backoff)
+    .await;
+
+    // the last failure doesn't go through `backoff`
+    if let Err(err) = &result {
+        if err.is_retryable() {
+            let attempt = response::attempts().unwrap_or(RETRIES + 1);
+            tracing::warn!(
+                attempt,
+                error_class = err.class(),
+                "out of retries: {}",
+                err
+            );
+            response::record_failed_attempt(Attempt {
+                attempt,
+                error_class: err.class(),
+                backoff_ms: None,
+            });
+        }
+    }
+
+    result
+}
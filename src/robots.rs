@@ -0,0 +1,208 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use reqwest::Url;
+
+use crate::{
+    config::{RobotsTxt, CONFIG},
+    errors::{AppError, ErrorKind},
+    politeness,
+};
+
+// How long a host's robots.txt is trusted before it's fetched again
+const TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct Fetched {
+    at: Instant,
+    rules: Arc<Rules>,
+}
+
+// Rules of each host
+static CACHE: Lazy<Mutex<HashMap<String, Fetched>>> = Lazy::new(Default::default);
+
+#[derive(Debug)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+// User-agent lines and the rules that follow them
+#[derive(Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+/// The Allow and Disallow lines of the groups that apply to this scraper.
+#[derive(Debug, Default)]
+struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    // Groups naming the scraper's product token, or `*` without any
+    fn parse(robots_txt: &str) -> Rules {
+        let agent = env!("CARGO_PKG_NAME").to_lowercase();
+        let mut groups: Vec<Group> = vec![];
+        let mut in_rules = false;
+
+        for line in robots_txt.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    // agents listed after rules start the next group
+                    if in_rules || groups.is_empty() {
+                        groups.push(Group::default());
+                        in_rules = false;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_lowercase());
+                    }
+                }
+                key @ ("allow" | "disallow") => {
+                    in_rules = true;
+                    // an empty Disallow allows everything, like no line at all
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.rules.push(Rule {
+                            allow: key == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let names = |group: &Group, name: &str| group.agents.iter().any(|named| named == name);
+        let name = match groups.iter().any(|group| names(group, &agent)) {
+            true => agent.as_str(),
+            false => "*",
+        };
+        let rules = groups
+            .into_iter()
+            .filter(|group| names(group, name))
+            .flat_map(|group| group.rules)
+            .collect();
+
+        Rules { rules }
+    }
+
+    // The longest matching rule decides, Allow on a tie
+    fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+// `*` matches any characters and a trailing `$` the end of the path
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
+
+async fn rules(provider: &str, url: &Url) -> Result<Arc<Rules>, reqwest::Error> {
+    let origin = url.origin().ascii_serialization();
+    if let Some(fetched) = CACHE.lock().unwrap().get(&origin) {
+        if fetched.at.elapsed() < TTL {
+            return Ok(fetched.rules.clone());
+        }
+    }
+
+    politeness::pace(provider).await;
+    let response = politeness::client(provider)
+        .get(format!("{}/robots.txt", origin))
+        .send()
+        .await?;
+    let rules = match response.status() {
+        status if status.is_success() => Rules::parse(&response.text().await?),
+        // no robots.txt, or one nobody may read, restricts nothing
+        status if status.is_client_error() => Rules::default(),
+        _ => return Err(response.error_for_status().unwrap_err()),
+    };
+    let rules = Arc::new(rules);
+    CACHE.lock().unwrap().insert(
+        origin,
+        Fetched {
+            at: Instant::now(),
+            rules: rules.clone(),
+        },
+    );
+
+    Ok(rules)
+}
+
+/// Fails with 403 when the robots.txt of `url`'s host disallows scraping it, unless
+/// `ROBOTS_TXT=ignore`. A robots.txt that can't be fetched is logged and doesn't hold up the
+/// scrape.
+pub async fn check(provider: &str, url: &str) -> Result<(), AppError> {
+    if CONFIG.robots_txt == RobotsTxt::Ignore {
+        return Ok(());
+    }
+    let url = Url::parse(url)?;
+
+    let rules = match rules(provider, &url).await {
+        Ok(rules) => rules,
+        Err(err) => {
+            tracing::warn!(
+                "failed to read the robots.txt of {}: {}",
+                url.origin().ascii_serialization(),
+                err
+            );
+            return Ok(());
+        }
+    };
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    if !rules.allows(&path) {
+        return Err(ErrorKind::Forbidden(format!(
+            "The robots.txt of {} disallows scraping {}",
+            url.host_str().unwrap_or_default(),
+            url.path()
+        ))
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Like `check`, for the fetches of background tasks and checks that answer no request.
+pub async fn ensure(provider: &str, url: &str) -> anyhow::Result<()> {
+    check(provider, url)
+        .await
+        .map_err(|err| anyhow::anyhow!("{}", err))
+}
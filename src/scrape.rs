@@ -0,0 +1,198 @@
+use anyhow::Result;
+use dump_core::models::CorporationSection;
+use serde_json::Value;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{
+    config::{OutputFormat, ScrapeTarget},
+    handler,
+};
+
+// Longer cells are cut in tables, which are meant for a terminal
+const MAX_CELL_WIDTH: usize = 60;
+
+/// The `scrape` subcommands. Prints what the matching API endpoint answers, in `output`.
+pub async fn run(target: &ScrapeTarget, output: OutputFormat) -> Result<()> {
+    let value = match target {
+        ScrapeTarget::Search { keyword, limit } => {
+            let found = handler::search_corporations(keyword, *limit)
+                .await
+                .map_err(|err| anyhow::anyhow!("{}", err))?;
+            serde_json::to_value(found)?
+        }
+        ScrapeTarget::Corporation { id, sections } => {
+            let sections = match sections {
+                Some(sections) => {
+                    handler::parse_sections(sections).map_err(|err| anyhow::anyhow!("{}", err))?
+                }
+                None => CorporationSection::ALL.to_vec(),
+            };
+            let response = handler::fetch_corporation(id, &sections, None)
+                .await
+                .map_err(|err| anyhow::anyhow!("{}", err))?;
+            serde_json::to_value(response.data)?
+        }
+    };
+
+    match output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&value)?),
+        OutputFormat::Csv => print!("{}", csv(&rows(&value))),
+        OutputFormat::Table => print!("{}", table(&rows(&value))),
+    }
+
+    Ok(())
+}
+
+struct Rows {
+    columns: Vec<String>,
+    cells: Vec<Vec<String>>,
+}
+
+// Nested fields are named by their path, e.g. `corp_details.0.Corporate Name`
+fn flatten(path: String, value: &Value, fields: &mut Vec<(String, String)>) {
+    let join = |key: &dyn std::fmt::Display| match path.as_str() {
+        "" => key.to_string(),
+        path => format!("{}.{}", path, key),
+    };
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(join(key), value, fields);
+            }
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                flatten(join(&i), value, fields);
+            }
+        }
+        Value::Null => fields.push((path, String::new())),
+        Value::String(text) => fields.push((path, text.clone())),
+        value => fields.push((path, value.to_string())),
+    }
+}
+
+// A list is a row per item with a column per field, anything else a row per field
+fn rows(value: &Value) -> Rows {
+    let Value::Array(items) = value else {
+        let mut fields = vec![];
+        flatten(String::new(), value, &mut fields);
+        return Rows {
+            columns: vec!["field".to_string(), "value".to_string()],
+            cells: fields
+                .into_iter()
+                .map(|(field, value)| vec![field, value])
+                .collect(),
+        };
+    };
+
+    let items: Vec<Vec<(String, String)>> = items
+        .iter()
+        .map(|item| {
+            let mut fields = vec![];
+            flatten(String::new(), item, &mut fields);
+            fields
+        })
+        .collect();
+    let mut columns: Vec<String> = vec![];
+    for (field, _) in items.iter().flatten() {
+        if !columns.contains(field) {
+            columns.push(field.clone());
+        }
+    }
+    let cells = items
+        .iter()
+        .map(|fields| {
+            columns
+                .iter()
+                .map(|column| {
+                    fields
+                        .iter()
+                        .find(|(field, _)| field == column)
+                        .map(|(_, value)| value.clone())
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .collect();
+
+    Rows { columns, cells }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv(rows: &Rows) -> String {
+    std::iter::once(&rows.columns)
+        .chain(&rows.cells)
+        .map(|row| {
+            let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+            fields.join(",") + "\r\n"
+        })
+        .collect()
+}
+
+// On a single line and at most `MAX_CELL_WIDTH` columns wide
+fn cell(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.width() <= MAX_CELL_WIDTH {
+        return text;
+    }
+
+    let mut cut = String::new();
+    let mut width = 0;
+    for c in text.chars() {
+        width += c.width().unwrap_or(0);
+        if width > MAX_CELL_WIDTH - 1 {
+            break;
+        }
+        cut.push(c);
+    }
+    cut + "…"
+}
+
+fn table(rows: &Rows) -> String {
+    let columns: Vec<String> = rows.columns.iter().map(|column| cell(column)).collect();
+    let cells: Vec<Vec<String>> = rows
+        .cells
+        .iter()
+        .map(|row| row.iter().map(|text| cell(text)).collect())
+        .collect();
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|i| {
+            std::iter::once(&columns)
+                .chain(&cells)
+                .map(|row| row[i].width())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let line = |row: &[String]| {
+        let padded: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(text, width)| format!(" {}{} ", text, " ".repeat(width - text.width())))
+            .collect();
+        format!("|{}|\n", padded.join("|"))
+    };
+    let border = {
+        let dashes: Vec<String> = widths.iter().map(|width| "-".repeat(width + 2)).collect();
+        format!("+{}+\n", dashes.join("+"))
+    };
+
+    let mut table = border.clone();
+    table.push_str(&line(&columns));
+    table.push_str(&border);
+    for row in &cells {
+        table.push_str(&line(row));
+    }
+    table.push_str(&border);
+    table.push_str(&format!("{} rows\n", cells.len()));
+
+    table
+}
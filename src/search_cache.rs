@@ -0,0 +1,92 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::CONFIG;
+
+struct Entry {
+    data: Value,
+    fetched: Instant,
+    scraped_at: DateTime<Utc>,
+}
+
+static ENTRIES: Lazy<Mutex<HashMap<String, Entry>>> = Lazy::new(Default::default);
+// Keys a background refresh is running for, so a popular query only refreshes once
+static REFRESHING: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+
+pub struct Cached {
+    pub data: Value,
+    pub scraped_at: DateTime<Utc>,
+    // older than `SEARCH_CACHE_TTL_SECS` but still within `SEARCH_CACHE_STALE_SECS`
+    pub stale: bool,
+}
+
+/// Search params as cached: the query word trimmed, lowercased and with single spaces, so
+/// "Acme  Foods" and "acme foods" share an entry. Also the params a refresh searches with.
+pub fn normalize<P: Serialize>(params: &P) -> serde_json::Result<Value> {
+    let mut params = serde_json::to_value(params)?;
+    if let Some(Value::String(query)) = params.get_mut("query_word") {
+        *query = query
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+    }
+
+    Ok(params)
+}
+
+pub fn key(params: &Value) -> String {
+    params.to_string()
+}
+
+pub fn get(key: &str) -> Option<Cached> {
+    let mut entries = ENTRIES.lock().unwrap();
+    let age = entries.get(key)?.fetched.elapsed();
+    if age > Duration::from_secs(CONFIG.search_cache_stale_secs) {
+        entries.remove(key);
+        return None;
+    }
+
+    let entry = &entries[key];
+    Some(Cached {
+        data: entry.data.clone(),
+        scraped_at: entry.scraped_at,
+        stale: age > Duration::from_secs(CONFIG.search_cache_ttl_secs),
+    })
+}
+
+/// Caches the data of a search that just finished, returns when it did.
+pub fn put(key: String, data: Value) -> DateTime<Utc> {
+    let scraped_at = Utc::now();
+    let mut entries = ENTRIES.lock().unwrap();
+    // drop what can't be served anymore instead of growing with every query ever searched
+    let max_age = Duration::from_secs(CONFIG.search_cache_stale_secs);
+    entries.retain(|_, entry| entry.fetched.elapsed() <= max_age);
+    entries.insert(
+        key,
+        Entry {
+            data,
+            fetched: Instant::now(),
+            scraped_at,
+        },
+    );
+
+    scraped_at
+}
+
+/// Claims the refresh of `key`, false if one is already running.
+pub fn start_refresh(key: &str) -> bool {
+    REFRESHING.lock().unwrap().insert(key.to_string())
+}
+
+pub fn finish_refresh(key: &str) {
+    REFRESHING.lock().unwrap().remove(key);
+}
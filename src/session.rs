@@ -0,0 +1,552 @@
+use std::ops::Deref;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::json;
+use thirtyfour::{cookie::SameSite, extensions::cdp::ChromeDevTools, prelude::*};
+
+#[cfg(feature = "cdp")]
+use crate::cdp;
+use crate::{
+    chaos,
+    config::{Browser, CONFIG},
+    cookies,
+    flags::{self, Flag},
+    politeness, pool, recording,
+};
+
+/// Browser session the flows drive, over WebDriver or, with the `cdp` feature, the DevTools
+/// protocol. Elements are always located by XPath.
+#[async_trait]
+pub trait Driver: Send + Sync {
+    async fn goto(&self, url: &str) -> WebDriverResult<()>;
+    async fn current_url(&self) -> WebDriverResult<String>;
+    async fn title(&self) -> WebDriverResult<String>;
+    async fn add_cookie(&self, cookie: &cookies::Cookie) -> WebDriverResult<()>;
+    async fn get_cookies(&self) -> WebDriverResult<Vec<cookies::Cookie>>;
+    async fn delete_all_cookies(&self) -> WebDriverResult<()>;
+    async fn screenshot_png(&self) -> WebDriverResult<Vec<u8>>;
+    // the viewport at `THUMBNAIL_SCALE`, full size where the browser can't scale screenshots
+    async fn thumbnail_png(&self) -> WebDriverResult<Vec<u8>>;
+    async fn find_all(&self, xpath: &str) -> WebDriverResult<Vec<Box<dyn Element>>>;
+    async fn quit(self: Box<Self>) -> WebDriverResult<()>;
+}
+
+#[async_trait]
+pub trait Element: Send + Sync {
+    async fn click(&self) -> WebDriverResult<()>;
+    // dispatched from script, reaches elements something else covers
+    async fn js_click(&self) -> WebDriverResult<()>;
+    // centered rather than aligned to the top, where sticky headers sit
+    async fn scroll_into_view(&self) -> WebDriverResult<()>;
+    async fn send_keys(&self, text: &str) -> WebDriverResult<()>;
+    async fn clear(&self) -> WebDriverResult<()>;
+    // live `value` property of a form field
+    async fn value(&self) -> WebDriverResult<Option<String>>;
+    // live DOM property, e.g. the resolved `href` of a link
+    async fn property(&self, name: &str) -> WebDriverResult<Option<String>>;
+    async fn text(&self) -> WebDriverResult<String>;
+    async fn is_displayed(&self) -> WebDriverResult<bool>;
+}
+
+pub type Session = Box<dyn Driver>;
+
+pub const THUMBNAIL_SCALE: f64 = 0.25;
+
+/// WebDriver server of the configured browser.
+pub fn driver_url() -> &'static str {
+    #[cfg(feature = "firefox")]
+    if CONFIG.browser == Browser::Firefox {
+        return &CONFIG.geckodriver_url;
+    }
+    &CONFIG.chromedriver_url
+}
+
+/// Whether sessions run Chrome through chromedriver, the default.
+pub fn is_chromedriver() -> bool {
+    match CONFIG.browser {
+        Browser::Chrome => true,
+        #[cfg(feature = "firefox")]
+        Browser::Firefox => false,
+        #[cfg(feature = "cdp")]
+        Browser::ChromeCdp => !flags::enabled(Flag::Cdp),
+    }
+}
+
+/// Chrome arguments of the `stealth` flag, which hide what sites tell automated browsers by.
+pub fn stealth_args() -> &'static [&'static str] {
+    if flags::enabled(Flag::Stealth) {
+        &[
+            "--disable-blink-features=AutomationControlled",
+            "--disable-infobars",
+            "--window-size=1366,768",
+        ]
+    } else {
+        &[]
+    }
+}
+
+fn chrome_capabilities() -> WebDriverResult<Capabilities> {
+    let mut caps = DesiredCapabilities::chrome();
+    caps.set_ignore_certificate_errors()?;
+    caps.add_chrome_arg("--disable-dev-tools")?;
+    caps.add_chrome_arg("--user-data-dir=/tmp/user-data")?;
+    for arg in stealth_args() {
+        caps.add_chrome_arg(arg)?;
+    }
+    if let Some(user_agent) = politeness::user_agent(cookies::REGISTRY) {
+        caps.add_chrome_arg(&format!("--user-agent={}", user_agent))?;
+    }
+    if flags::enabled(Flag::Stealth) {
+        caps.add_chrome_option("excludeSwitches", ["enable-automation"])?;
+        caps.add_chrome_option("useAutomationExtension", false)?;
+    }
+    #[cfg(any(feature = "lambda", feature = "ecs", feature = "headless"))]
+    {
+        caps.set_disable_dev_shm_usage()?;
+        caps.set_disable_gpu()?;
+        caps.set_disable_web_security()?;
+        caps.set_headless()?;
+        caps.set_no_sandbox()?;
+        caps.add_chrome_arg("--no-zygote")?;
+        caps.add_chrome_arg("--single-process")?;
+    }
+
+    Ok(caps.into())
+}
+
+#[cfg(feature = "firefox")]
+fn firefox_capabilities() -> WebDriverResult<Capabilities> {
+    use thirtyfour::CapabilitiesHelper;
+
+    let mut firefox = DesiredCapabilities::firefox();
+    if let Some(binary) = &CONFIG.firefox_binary {
+        firefox.set_firefox_binary(binary)?;
+    }
+    if let Some(user_agent) = politeness::user_agent(cookies::REGISTRY) {
+        let mut preferences = thirtyfour::common::capabilities::firefox::FirefoxPreferences::new();
+        preferences.set_user_agent(user_agent)?;
+        firefox.set_preferences(preferences)?;
+    }
+    #[cfg(any(feature = "lambda", feature = "ecs", feature = "headless"))]
+    firefox.set_headless()?;
+
+    let mut caps = Capabilities::from(firefox);
+    caps.add("acceptInsecureCerts", true)?;
+
+    Ok(caps)
+}
+
+/// A flow's session that is quit when dropped, so flows that fail halfway or whose request is
+/// aborted don't leak it. Flows still `quit` it themselves where they can wait for it.
+pub struct SessionGuard {
+    session: Option<Session>,
+}
+
+impl SessionGuard {
+    pub async fn quit(mut self) -> WebDriverResult<()> {
+        match self.session.take() {
+            Some(session) => session.quit().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Deref for SessionGuard {
+    type Target = dyn Driver;
+
+    fn deref(&self) -> &Self::Target {
+        self.session
+            .as_deref()
+            .expect("the session is only taken by quit")
+    }
+}
+
+// Drop can't wait, so the quit runs on its own task. Outside a runtime, e.g. while it shuts
+// down, the driver reaps the session once it times out
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let Some(session) = self.session.take() else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        runtime.spawn(async move {
+            match session.quit().await {
+                Ok(()) => tracing::debug!("quit the session of a dropped flow"),
+                Err(err) => tracing::warn!("failed to quit the session of a dropped flow: {}", err),
+            }
+        });
+    }
+}
+
+/// Session for a flow, from the warm pool when `BROWSER_POOL_SIZE` is set. Quitting it returns
+/// it to the pool.
+pub async fn start() -> WebDriverResult<SessionGuard> {
+    // every browser flow is on the registry
+    let session = politeness::wrap(cookies::REGISTRY, async {
+        if pool::enabled() {
+            pool::checkout().await
+        } else {
+            launch().await
+        }
+    })
+    .await?;
+
+    Ok(SessionGuard {
+        session: Some(chaos::wrap(session)),
+    })
+}
+
+/// Starts a new session of the configured browser.
+pub async fn launch() -> WebDriverResult<Session> {
+    let caps = match CONFIG.browser {
+        Browser::Chrome => chrome_capabilities()?,
+        #[cfg(feature = "firefox")]
+        Browser::Firefox => firefox_capabilities()?,
+        #[cfg(feature = "cdp")]
+        Browser::ChromeCdp if flags::enabled(Flag::Cdp) => {
+            return Ok(Box::new(cdp::CdpSession::start().await?))
+        }
+        #[cfg(feature = "cdp")]
+        Browser::ChromeCdp => chrome_capabilities()?,
+    };
+
+    let url = recording::webdriver_url()
+        .await
+        .map_err(|err| WebDriverError::CustomError(err.to_string()))?;
+    let driver = WebDriver::new(&url, caps).await?;
+
+    Ok(Box::new(WebDriverSession(driver)))
+}
+
+struct WebDriverSession(WebDriver);
+
+impl WebDriverSession {
+    // WebDriver has no scaled screenshots, Chrome's DevTools do
+    async fn scaled_screenshot(&self) -> WebDriverResult<Vec<u8>> {
+        let devtools = ChromeDevTools::new(self.0.handle.clone());
+        let metrics = devtools.execute_cdp("Page.getLayoutMetrics").await?;
+        let viewport = &metrics["cssLayoutViewport"];
+        let screenshot = devtools
+            .execute_cdp_with_params(
+                "Page.captureScreenshot",
+                json!({
+                    "format": "png",
+                    "clip": {
+                        "x": viewport["pageX"],
+                        "y": viewport["pageY"],
+                        "width": viewport["clientWidth"],
+                        "height": viewport["clientHeight"],
+                        "scale": THUMBNAIL_SCALE,
+                    },
+                }),
+            )
+            .await?;
+        let data = screenshot["data"]
+            .as_str()
+            .ok_or_else(|| WebDriverError::CustomError("no screenshot data".to_string()))?;
+
+        STANDARD
+            .decode(data)
+            .map_err(|err| WebDriverError::CustomError(err.to_string()))
+    }
+}
+
+#[async_trait]
+impl Driver for WebDriverSession {
+    async fn goto(&self, url: &str) -> WebDriverResult<()> {
+        self.0.goto(url).await
+    }
+
+    async fn current_url(&self) -> WebDriverResult<String> {
+        Ok(self.0.current_url().await?.to_string())
+    }
+
+    async fn title(&self) -> WebDriverResult<String> {
+        self.0.title().await
+    }
+
+    async fn add_cookie(&self, cookie: &cookies::Cookie) -> WebDriverResult<()> {
+        let mut webdriver_cookie = Cookie::new(cookie.name.clone(), cookie.value.clone());
+        webdriver_cookie.set_domain(cookie.domain.clone());
+        webdriver_cookie.set_path(cookie.path.clone());
+        webdriver_cookie.set_same_site(Some(match cookie.same_site {
+            cookies::SameSite::Strict => SameSite::Strict,
+            cookies::SameSite::Lax => SameSite::Lax,
+            cookies::SameSite::None => SameSite::None,
+        }));
+        self.0.add_cookie(webdriver_cookie).await
+    }
+
+    async fn get_cookies(&self) -> WebDriverResult<Vec<cookies::Cookie>> {
+        let cookies = self.0.get_all_cookies().await?;
+
+        Ok(cookies
+            .into_iter()
+            .map(|cookie| cookies::Cookie {
+                name: cookie.name().to_string(),
+                value: cookie.value().to_string(),
+                domain: cookie.domain().unwrap_or_default().to_string(),
+                path: cookie.path().unwrap_or("/").to_string(),
+                same_site: match cookie.same_site() {
+                    Some(SameSite::Strict) => cookies::SameSite::Strict,
+                    Some(SameSite::None) => cookies::SameSite::None,
+                    _ => cookies::SameSite::Lax,
+                },
+            })
+            .collect())
+    }
+
+    async fn delete_all_cookies(&self) -> WebDriverResult<()> {
+        self.0.delete_all_cookies().await
+    }
+
+    async fn screenshot_png(&self) -> WebDriverResult<Vec<u8>> {
+        self.0.screenshot_as_png().await
+    }
+
+    async fn thumbnail_png(&self) -> WebDriverResult<Vec<u8>> {
+        if !is_chromedriver() {
+            return self.screenshot_png().await;
+        }
+        match self.scaled_screenshot().await {
+            Ok(png) => Ok(png),
+            // e.g. a grid that doesn't forward DevTools commands
+            Err(err) => {
+                tracing::debug!("falling back to a full screenshot: {}", err);
+                self.screenshot_png().await
+            }
+        }
+    }
+
+    async fn find_all(&self, xpath: &str) -> WebDriverResult<Vec<Box<dyn Element>>> {
+        let elements = self.0.find_all(By::XPath(xpath)).await?;
+
+        Ok(elements
+            .into_iter()
+            .map(|element| {
+                Box::new(WebDriverElement {
+                    driver: self.0.clone(),
+                    element,
+                }) as Box<dyn Element>
+            })
+            .collect())
+    }
+
+    async fn quit(self: Box<Self>) -> WebDriverResult<()> {
+        self.0.quit().await
+    }
+}
+
+struct WebDriverElement {
+    driver: WebDriver,
+    element: WebElement,
+}
+
+#[async_trait]
+impl Element for WebDriverElement {
+    async fn click(&self) -> WebDriverResult<()> {
+        self.element.click().await
+    }
+
+    async fn js_click(&self) -> WebDriverResult<()> {
+        self.driver
+            .execute("arguments[0].click();", vec![self.element.to_json()?])
+            .await?;
+
+        Ok(())
+    }
+
+    async fn scroll_into_view(&self) -> WebDriverResult<()> {
+        self.driver
+            .execute(
+                "arguments[0].scrollIntoView({block: 'center', inline: 'nearest'});",
+                vec![self.element.to_json()?],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn send_keys(&self, text: &str) -> WebDriverResult<()> {
+        self.element.send_keys(text).await
+    }
+
+    async fn clear(&self) -> WebDriverResult<()> {
+        self.element.clear().await
+    }
+
+    async fn value(&self) -> WebDriverResult<Option<String>> {
+        self.element.prop("value").await
+    }
+
+    async fn property(&self, name: &str) -> WebDriverResult<Option<String>> {
+        self.element.prop(name).await
+    }
+
+    async fn text(&self) -> WebDriverResult<String> {
+        self.element.text().await
+    }
+
+    async fn is_displayed(&self) -> WebDriverResult<bool> {
+        self.element.is_displayed().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::flow::{self, Step, Vars};
+
+    // Records the commands it's sent, the pages it's sent to fail
+    struct MockDriver {
+        commands: Arc<Mutex<Vec<String>>>,
+        failing: Option<&'static str>,
+    }
+
+    impl MockDriver {
+        fn record(&self, command: String) {
+            self.commands.lock().unwrap().push(command);
+        }
+    }
+
+    #[async_trait]
+    impl Driver for MockDriver {
+        async fn goto(&self, url: &str) -> WebDriverResult<()> {
+            self.record(format!("goto {}", url));
+            if self.failing == Some(url) {
+                return Err(WebDriverError::CustomError(format!("{} is down", url)));
+            }
+            Ok(())
+        }
+
+        async fn current_url(&self) -> WebDriverResult<String> {
+            Ok("about:blank".to_string())
+        }
+
+        async fn title(&self) -> WebDriverResult<String> {
+            Ok(String::new())
+        }
+
+        async fn add_cookie(&self, _cookie: &cookies::Cookie) -> WebDriverResult<()> {
+            Ok(())
+        }
+
+        async fn get_cookies(&self) -> WebDriverResult<Vec<cookies::Cookie>> {
+            Ok(vec![])
+        }
+
+        async fn delete_all_cookies(&self) -> WebDriverResult<()> {
+            Ok(())
+        }
+
+        async fn screenshot_png(&self) -> WebDriverResult<Vec<u8>> {
+            Ok(vec![])
+        }
+
+        async fn thumbnail_png(&self) -> WebDriverResult<Vec<u8>> {
+            Ok(vec![])
+        }
+
+        async fn find_all(&self, _xpath: &str) -> WebDriverResult<Vec<Box<dyn Element>>> {
+            Ok(vec![])
+        }
+
+        async fn quit(self: Box<Self>) -> WebDriverResult<()> {
+            self.record("quit".to_string());
+            Ok(())
+        }
+    }
+
+    fn guard(failing: Option<&'static str>) -> (SessionGuard, Arc<Mutex<Vec<String>>>) {
+        let commands = Arc::new(Mutex::new(vec![]));
+        let driver = MockDriver {
+            commands: commands.clone(),
+            failing,
+        };
+
+        (
+            SessionGuard {
+                session: Some(Box::new(driver)),
+            },
+            commands,
+        )
+    }
+
+    fn goto(url: &str) -> Step {
+        Step::Goto {
+            url: url.to_string(),
+        }
+    }
+
+    // Waits up to a second for the commands to end with `command`, the quit of a dropped guard
+    // runs on a task of its own
+    async fn wait_for(commands: &Mutex<Vec<String>>, command: &str) {
+        for _ in 0..100 {
+            if commands.lock().unwrap().last().map(String::as_str) == Some(command) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("no {} in {:?}", command, commands.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_flow_stops_and_quits_its_session() {
+        let (session, commands) = guard(None);
+        let steps = [
+            goto("https://redacted/search"),
+            Step::Sleep { secs: 60 },
+            goto("https://redacted/products"),
+        ];
+        let running =
+            tokio::spawn(async move { flow::run(&*session, &steps, &Vars::default()).await });
+
+        wait_for(&commands, "goto https://redacted/search").await;
+        running.abort();
+        assert!(running.await.unwrap_err().is_cancelled());
+
+        wait_for(&commands, "quit").await;
+        assert_eq!(
+            *commands.lock().unwrap(),
+            ["goto https://redacted/search", "quit"]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_failed_flow_quits_its_session() {
+        let (session, commands) = guard(Some("https://redacted/search"));
+        let steps = [
+            goto("https://redacted/search"),
+            goto("https://redacted/products"),
+        ];
+
+        let failed = flow::run(&*session, &steps, &Vars::default()).await;
+        assert!(failed.is_err());
+        drop(session);
+
+        wait_for(&commands, "quit").await;
+        assert_eq!(
+            *commands.lock().unwrap(),
+            ["goto https://redacted/search", "quit"]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_quit_session_is_not_quit_again() {
+        let (session, commands) = guard(None);
+
+        session.quit().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(*commands.lock().unwrap(), ["quit"]);
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use once_cell::sync::Lazy;
+use redis::AsyncCommands;
+use serde::Serialize;
+
+use crate::{alerts, config::CONFIG, load_shed, lock::redis_connection};
+
+// Queue waits of the workers as `{unix ms}:{wait ms}`, newest first, so `api` instances see them
+const QUEUE_WAITS_KEY: &str = "slo:queue-waits";
+const MAX_SAMPLES: usize = 10_000;
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Sample {
+    at: Instant,
+    ms: u64,
+    ok: bool,
+}
+
+static ROUTES: Lazy<Mutex<HashMap<String, VecDeque<Sample>>>> = Lazy::new(Default::default);
+// Queue waits seen by this process, all there is without redis
+static QUEUE_WAITS: Lazy<Mutex<VecDeque<Sample>>> = Lazy::new(Default::default);
+static LAST_ALERT: Lazy<Mutex<Option<Instant>>> = Lazy::new(Default::default);
+
+fn window() -> Duration {
+    Duration::from_secs(CONFIG.slo_window_secs)
+}
+
+fn push(samples: &mut VecDeque<Sample>, sample: Sample) {
+    samples.push_front(sample);
+    while samples
+        .back()
+        .is_some_and(|oldest| oldest.at.elapsed() > window())
+        || samples.len() > MAX_SAMPLES
+    {
+        samples.pop_back();
+    }
+}
+
+/// Times every routed request end to end, a 5xx answer is a failure.
+pub async fn track(req: Request, next: Next) -> Response {
+    let route = match req.extensions().get::<MatchedPath>() {
+        Some(path) => format!("{} {}", req.method(), path.as_str()),
+        None => format!("{} {}", req.method(), req.uri().path()),
+    };
+    let started = Instant::now();
+
+    let response = next.run(req).await;
+    let sample = Sample {
+        at: Instant::now(),
+        ms: started.elapsed().as_millis() as u64,
+        ok: !response.status().is_server_error(),
+    };
+    push(ROUTES.lock().unwrap().entry(route).or_default(), sample);
+
+    response
+}
+
+/// How long a job waited in the queue before a worker took it.
+pub async fn record_queue_wait(wait: Duration) {
+    let ms = wait.as_millis() as u64;
+    push(
+        &mut QUEUE_WAITS.lock().unwrap(),
+        Sample {
+            at: Instant::now(),
+            ms,
+            ok: true,
+        },
+    );
+
+    let Some(url) = &CONFIG.redis_url else {
+        return;
+    };
+    let shared = async {
+        let mut conn = redis_connection(url).await?;
+        let sample = format!("{}:{}", Utc::now().timestamp_millis(), ms);
+        conn.lpush::<_, _, ()>(QUEUE_WAITS_KEY, sample).await?;
+        conn.ltrim::<_, ()>(QUEUE_WAITS_KEY, 0, MAX_SAMPLES as isize - 1)
+            .await?;
+        anyhow::Ok(())
+    };
+    if let Err(err) = shared.await {
+        tracing::warn!("failed to record the queue wait: {}", err);
+    }
+}
+
+// Shared waits within the window, or this process's own when redis doesn't answer
+async fn queue_waits() -> Vec<u64> {
+    if let Some(url) = &CONFIG.redis_url {
+        let shared = async {
+            let mut conn = redis_connection(url).await?;
+            anyhow::Ok(
+                conn.lrange::<_, Vec<String>>(QUEUE_WAITS_KEY, 0, -1)
+                    .await?,
+            )
+        };
+        match shared.await {
+            Ok(samples) => {
+                let since = Utc::now().timestamp_millis() - window().as_millis() as i64;
+                return samples
+                    .iter()
+                    .filter_map(|sample| {
+                        let (at, ms) = sample.split_once(':')?;
+                        (at.parse::<i64>().ok()? >= since).then(|| ms.parse().ok())?
+                    })
+                    .collect();
+            }
+            Err(err) => tracing::warn!("failed to read the queue waits: {}", err),
+        }
+    }
+
+    QUEUE_WAITS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|sample| sample.at.elapsed() <= window())
+        .map(|sample| sample.ms)
+        .collect()
+}
+
+// Nearest rank, `None` without samples
+fn p95(mut ms: Vec<u64>) -> Option<u64> {
+    if ms.is_empty() {
+        return None;
+    }
+    ms.sort_unstable();
+    let rank = (ms.len() * 95).div_ceil(100);
+    Some(ms[rank.max(1) - 1])
+}
+
+#[derive(Serialize, Debug)]
+pub struct RouteSlo {
+    pub requests: usize,
+    pub success_rate: f64,
+    pub p95_ms: u64,
+}
+
+/// Service levels over the last `SLO_WINDOW_SECS`, the `/api/admin/slo` response.
+#[derive(Serialize, Debug)]
+pub struct Slo {
+    pub window_secs: u64,
+    // `None` without queued jobs in the window
+    pub queue_wait_p95_ms: Option<u64>,
+    // of the routes that drive a browser or fetch from the registry
+    pub scrape_p95_ms: Option<u64>,
+    pub routes: BTreeMap<String, RouteSlo>,
+    // thresholds crossed, empty when the service is within its objectives
+    pub breaches: Vec<String>,
+}
+
+pub async fn summary() -> Slo {
+    let mut routes = BTreeMap::new();
+    let mut scrape_ms = vec![];
+    for (route, samples) in ROUTES.lock().unwrap().iter() {
+        let live: Vec<&Sample> = samples
+            .iter()
+            .filter(|sample| sample.at.elapsed() <= window())
+            .collect();
+        if live.is_empty() {
+            continue;
+        }
+        let ms: Vec<u64> = live.iter().map(|sample| sample.ms).collect();
+        // `GET /api/corporation/:id` -> `/api/corporation/:id`
+        let path = route
+            .split_once(' ')
+            .map_or(route.as_str(), |(_, path)| path);
+        if load_shed::is_scrape(path) {
+            scrape_ms.extend(&ms);
+        }
+        routes.insert(
+            route.clone(),
+            RouteSlo {
+                requests: live.len(),
+                success_rate: live.iter().filter(|sample| sample.ok).count() as f64
+                    / live.len() as f64,
+                p95_ms: p95(ms).unwrap_or_default(),
+            },
+        );
+    }
+
+    let mut slo = Slo {
+        window_secs: CONFIG.slo_window_secs,
+        queue_wait_p95_ms: p95(queue_waits().await),
+        scrape_p95_ms: p95(scrape_ms),
+        routes,
+        breaches: vec![],
+    };
+    slo.breaches = breaches(&slo);
+
+    slo
+}
+
+fn breaches(slo: &Slo) -> Vec<String> {
+    let mut breaches = vec![];
+    if let Some(ms) = slo.queue_wait_p95_ms {
+        if ms > CONFIG.slo_queue_wait_p95_secs * 1000 {
+            breaches.push(format!(
+                "P95 queue wait {}s is over {}s",
+                ms / 1000,
+                CONFIG.slo_queue_wait_p95_secs
+            ));
+        }
+    }
+    if let Some(ms) = slo.scrape_p95_ms {
+        if ms > CONFIG.slo_scrape_p95_secs * 1000 {
+            breaches.push(format!(
+                "P95 scrape time {}s is over {}s",
+                ms / 1000,
+                CONFIG.slo_scrape_p95_secs
+            ));
+        }
+    }
+    for (route, route_slo) in &slo.routes {
+        // a single failed request on a quiet route isn't a trend
+        if route_slo.requests >= CONFIG.alert_min_requests
+            && route_slo.success_rate < CONFIG.slo_success_rate
+        {
+            breaches.push(format!(
+                "{} succeeded {:.1}% of the time, below {:.1}%",
+                route,
+                route_slo.success_rate * 100.0,
+                CONFIG.slo_success_rate * 100.0
+            ));
+        }
+    }
+
+    breaches
+}
+
+/// Logs the summary every minute under the `slo` target, for log based metrics, and alerts on
+/// Slack when an objective is missed, at most once a window.
+pub fn spawn_publisher() {
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(PUBLISH_INTERVAL).await;
+            let slo = summary().await;
+            match serde_json::to_string(&slo) {
+                Ok(json) => tracing::info!(target: "slo", "{}", json),
+                Err(err) => tracing::warn!("failed to serialize the SLO summary: {}", err),
+            }
+
+            if slo.breaches.is_empty() {
+                continue;
+            }
+            let mut last_alert = LAST_ALERT.lock().unwrap();
+            if last_alert.is_some_and(|at| at.elapsed() < window()) {
+                continue;
+            }
+            *last_alert = Some(Instant::now());
+            drop(last_alert);
+            alerts::send(format!("SLO missed: {}", slo.breaches.join("; ")));
+        }
+    });
+}
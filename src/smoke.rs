@@ -0,0 +1,164 @@
+use std::future::Future;
+
+use anyhow::Result;
+use dump_core::models::{CorporationData, CorporationSection};
+use serde::Serialize;
+
+use crate::handler;
+
+#[derive(Serialize, Debug)]
+struct Check {
+    name: String,
+    passed: bool,
+    detail: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+struct Report {
+    company: String,
+    checks: Vec<Check>,
+}
+
+impl Report {
+    fn check(&mut self, name: &str, passed: bool, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name: name.to_string(),
+            passed,
+            detail: detail.into(),
+        });
+    }
+
+    fn passed(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    fn print(&self, json: bool) -> Result<()> {
+        if json {
+            println!("{}", serde_json::to_string_pretty(self)?);
+            return Ok(());
+        }
+
+        println!("smoke test for '{}'", self.company);
+        for check in &self.checks {
+            let mark = if check.passed { "ok  " } else { "FAIL" };
+            println!("  [{}] {}: {}", mark, check.name, check.detail);
+        }
+        Ok(())
+    }
+}
+
+// Runs a step on its own task so a panic in the extraction code fails the step, not the run
+async fn step<T, F>(future: F) -> std::result::Result<T, String>
+where
+    T: Send + 'static,
+    F: Future<Output = std::result::Result<T, String>> + Send + 'static,
+{
+    match tokio::spawn(future).await {
+        Ok(result) => result,
+        Err(err) => Err(format!("panicked: {}", err)),
+    }
+}
+
+fn check_profile(report: &mut Report, data: &CorporationData) {
+    let name = data.corporate_name().filter(|name| !name.is_empty());
+    report.check(
+        "corporate name",
+        name.is_some(),
+        name.unwrap_or("missing").to_string(),
+    );
+
+    let address = data.address_details.as_deref().unwrap_or_default();
+    report.check(
+        "registered office",
+        !address.is_empty(),
+        if address.is_empty() {
+            "missing"
+        } else {
+            address
+        },
+    );
+
+    let directors = data
+        .directors()
+        .map_or(0, |directors| directors.current.len());
+    report.check("directors", directors > 0, format!("{} listed", directors));
+
+    let filings = data
+        .annual_filings_details
+        .as_ref()
+        .map_or(0, |filings| filings.status_by_year.len());
+    report.check("annual filings", filings > 0, format!("{} years", filings));
+
+    let history = data
+        .corp_history_details
+        .as_ref()
+        .map_or(0, |history| history.len());
+    report.check(
+        "corporate history",
+        history > 0,
+        format!("{} tables", history),
+    );
+}
+
+/// The `smoke-test` subcommand. Prints a report of every check and fails when one didn't pass.
+pub async fn run(company: &str, json: bool) -> Result<()> {
+    let mut report = Report {
+        company: company.to_string(),
+        ..Default::default()
+    };
+
+    let name = company.to_string();
+    let search = step(async move {
+        handler::search_corporations(&name, Some(1))
+            .await
+            .map_err(|err| err.to_string())
+    })
+    .await;
+
+    let number = match search {
+        Ok(results) => {
+            let first = results.first();
+            let number = first.and_then(|row| row.get("corporation_number")).cloned();
+            report.check(
+                "search",
+                number.is_some(),
+                match first {
+                    Some(row) => format!(
+                        "found {} ({})",
+                        row.get("business_name").map_or("?", String::as_str),
+                        number.as_deref().unwrap_or("no corporation number")
+                    ),
+                    None => "no results".to_string(),
+                },
+            );
+            number
+        }
+        Err(err) => {
+            report.check("search", false, err);
+            None
+        }
+    };
+
+    if let Some(number) = number {
+        let profile = step(async move {
+            handler::fetch_corporation(&number, &CorporationSection::ALL, None)
+                .await
+                .map(|envelope| envelope.data)
+                .map_err(|err| err.to_string())
+        })
+        .await;
+
+        match profile {
+            Ok(data) => {
+                report.check("profile", true, "scraped");
+                check_profile(&mut report, &data);
+            }
+            Err(err) => report.check("profile", false, err),
+        }
+    }
+
+    report.print(json)?;
+    anyhow::ensure!(report.passed(), "smoke test failed");
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{types::AttributeValue, Client};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use super::{Document, Metadata, Storage};
+
+/// Single-table layout: `pk` holds the collection, `sk` the document id, `data` the JSON
+/// document and `updated_at` when it was put. `expires_at` is meant to be the table's TTL
+/// attribute. TTL deletion is lazy, so expired items are also filtered out on read. Times are
+/// epoch seconds.
+pub struct DynamoDbStorage {
+    client: Client,
+    table: String,
+}
+
+impl DynamoDbStorage {
+    pub async fn new(table: &str) -> Self {
+        let config = aws_config::load_from_env().await;
+
+        DynamoDbStorage {
+            client: Client::new(&config),
+            table: table.to_string(),
+        }
+    }
+}
+
+fn timestamp(item: &std::collections::HashMap<String, AttributeValue>, name: &str) -> Option<i64> {
+    item.get(name)
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<i64>().ok())
+}
+
+fn parse_item(
+    item: &std::collections::HashMap<String, AttributeValue>,
+    now: i64,
+) -> Result<Option<Document>> {
+    let expires_at = timestamp(item, "expires_at").unwrap_or(i64::MAX);
+    if expires_at <= now {
+        return Ok(None);
+    }
+
+    let id = item
+        .get("sk")
+        .and_then(|v| v.as_s().ok())
+        .context("item without sk attribute")?;
+    let data = item
+        .get("data")
+        .and_then(|v| v.as_s().ok())
+        .context("item without data attribute")?;
+
+    Ok(Some(Document {
+        id: id.clone(),
+        value: serde_json::from_str(data)?,
+        metadata: Metadata {
+            expires_at: DateTime::from_timestamp(expires_at, 0).unwrap_or(DateTime::<Utc>::MAX_UTC),
+            updated_at: timestamp(item, "updated_at")
+                .and_then(|updated_at| DateTime::from_timestamp(updated_at, 0)),
+        },
+    }))
+}
+
+#[async_trait]
+impl Storage for DynamoDbStorage {
+    async fn put(
+        &self,
+        collection: &str,
+        id: &str,
+        value: Value,
+        metadata: Metadata,
+    ) -> Result<()> {
+        let mut put = self
+            .client
+            .put_item()
+            .table_name(&self.table)
+            .item("pk", AttributeValue::S(collection.to_string()))
+            .item("sk", AttributeValue::S(id.to_string()))
+            .item("data", AttributeValue::S(value.to_string()))
+            .item(
+                "expires_at",
+                AttributeValue::N(metadata.expires_at.timestamp().to_string()),
+            );
+        if let Some(updated_at) = metadata.updated_at {
+            put = put.item(
+                "updated_at",
+                AttributeValue::N(updated_at.timestamp().to_string()),
+            );
+        }
+        put.send().await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, collection: &str, id: &str) -> Result<Option<Document>> {
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key("pk", AttributeValue::S(collection.to_string()))
+            .key("sk", AttributeValue::S(id.to_string()))
+            .send()
+            .await?;
+
+        match output.item() {
+            Some(item) => parse_item(item, Utc::now().timestamp()),
+            None => Ok(None),
+        }
+    }
+
+    async fn list(&self, collection: &str) -> Result<Vec<Document>> {
+        let now = Utc::now().timestamp();
+        let mut documents = Vec::new();
+        let mut pages = self
+            .client
+            .query()
+            .table_name(&self.table)
+            .key_condition_expression("pk = :pk")
+            .expression_attribute_values(":pk", AttributeValue::S(collection.to_string()))
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            for item in page?.items() {
+                documents.extend(parse_item(item, now)?);
+            }
+        }
+
+        Ok(documents)
+    }
+
+    async fn delete(&self, collection: &str, id: &str) -> Result<()> {
+        self.client
+            .delete_item()
+            .table_name(&self.table)
+            .key("pk", AttributeValue::S(collection.to_string()))
+            .key("sk", AttributeValue::S(id.to_string()))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
+    // TTL deletion can lag by days, expired items are deleted here instead of waiting on it
+    async fn purge_expired(&self) -> Result<usize> {
+        let now = Utc::now().timestamp();
+        let mut purged = 0;
+        let mut pages = self
+            .client
+            .scan()
+            .table_name(&self.table)
+            .filter_expression("expires_at <= :now")
+            .projection_expression("pk, sk")
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            for item in page?.items() {
+                let (Some(pk), Some(sk)) = (item.get("pk"), item.get("sk")) else {
+                    continue;
+                };
+                self.client
+                    .delete_item()
+                    .table_name(&self.table)
+                    .key("pk", pk.clone())
+                    .key("sk", sk.clone())
+                    .send()
+                    .await?;
+                purged += 1;
+            }
+        }
+
+        Ok(purged)
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{Document, Metadata, Storage};
+
+/// Storage in a local directory, one subdirectory per collection, so development instances keep
+/// their jobs and scrapes across restarts. Not shared between hosts. Each document is a JSON
+/// file of it and its metadata.
+pub struct FilesystemStorage {
+    root: PathBuf,
+}
+
+// Collections and ids as file names: anything but ASCII letters, digits, `-` and `_` is
+// percent-encoded, which also keeps separators and dots from reaching outside the directory
+fn file_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+impl FilesystemStorage {
+    pub fn new(root: &Path) -> Self {
+        FilesystemStorage {
+            root: root.to_path_buf(),
+        }
+    }
+
+    fn collection(&self, collection: &str) -> PathBuf {
+        self.root.join(file_name(collection))
+    }
+
+    fn path(&self, collection: &str, id: &str) -> PathBuf {
+        self.collection(collection)
+            .join(format!("{}.json", file_name(id)))
+    }
+
+    async fn read(&self, path: &Path) -> Result<Option<Document>> {
+        match tokio::fs::read(path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("malformed document file {}", path.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // Document files in `dir`, none when nothing was stored in it yet
+    async fn files(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let mut files = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                files.push(path);
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+#[async_trait]
+impl Storage for FilesystemStorage {
+    async fn put(
+        &self,
+        collection: &str,
+        id: &str,
+        value: Value,
+        metadata: Metadata,
+    ) -> Result<()> {
+        let path = self.path(collection, id);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let document = Document {
+            id: id.to_string(),
+            value,
+            metadata,
+        };
+
+        // written next to it and renamed, so readers never see half a document. Named per write,
+        // so writers of the same document don't rename each other's half-written file.
+        let partial = path.with_extension(format!("json.{}.partial", Uuid::new_v4()));
+        let written = async {
+            tokio::fs::write(&partial, serde_json::to_vec(&document)?).await?;
+            anyhow::Ok(tokio::fs::rename(&partial, &path).await?)
+        }
+        .await;
+        if written.is_err() {
+            let _ = tokio::fs::remove_file(&partial).await;
+        }
+        written.with_context(|| format!("failed to store {}", path.display()))
+    }
+
+    async fn get(&self, collection: &str, id: &str) -> Result<Option<Document>> {
+        let document = self.read(&self.path(collection, id)).await?;
+
+        Ok(document.filter(|document| document.metadata.expires_at > Utc::now()))
+    }
+
+    async fn list(&self, collection: &str) -> Result<Vec<Document>> {
+        let now = Utc::now();
+        let mut documents = vec![];
+        for path in self.files(&self.collection(collection)).await? {
+            // deleted since the directory was read
+            if let Some(document) = self.read(&path).await? {
+                if document.metadata.expires_at > now {
+                    documents.push(document);
+                }
+            }
+        }
+        documents.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(documents)
+    }
+
+    async fn delete(&self, collection: &str, id: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(collection, id)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn purge_expired(&self) -> Result<usize> {
+        let now = Utc::now();
+        let mut purged = 0;
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            for path in self.files(&entry.path()).await? {
+                let expired = self
+                    .read(&path)
+                    .await?
+                    .is_some_and(|document| document.metadata.expires_at <= now);
+                if expired {
+                    tokio::fs::remove_file(&path).await?;
+                    purged += 1;
+                }
+            }
+        }
+
+        Ok(purged)
+    }
+}
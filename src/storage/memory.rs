@@ -0,0 +1,84 @@
+use std::{collections::BTreeMap, sync::RwLock};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use super::{Document, Metadata, Storage};
+
+type Documents = BTreeMap<(String, String), (Value, Metadata)>;
+
+/// Process-local storage, the default for development and single-instance deployments.
+#[derive(Default)]
+pub struct MemoryStorage {
+    documents: RwLock<Documents>,
+}
+
+fn live(metadata: &Metadata, now: DateTime<Utc>) -> bool {
+    metadata.expires_at > now
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn put(
+        &self,
+        collection: &str,
+        id: &str,
+        value: Value,
+        metadata: Metadata,
+    ) -> Result<()> {
+        let mut documents = self.documents.write().unwrap();
+        let now = Utc::now();
+        documents.retain(|_, (_, metadata)| live(metadata, now));
+        documents.insert((collection.to_string(), id.to_string()), (value, metadata));
+
+        Ok(())
+    }
+
+    async fn get(&self, collection: &str, id: &str) -> Result<Option<Document>> {
+        let documents = self.documents.read().unwrap();
+        let document = documents
+            .get(&(collection.to_string(), id.to_string()))
+            .filter(|(_, metadata)| live(metadata, Utc::now()))
+            .map(|(value, metadata)| Document {
+                id: id.to_string(),
+                value: value.clone(),
+                metadata: *metadata,
+            });
+
+        Ok(document)
+    }
+
+    async fn list(&self, collection: &str) -> Result<Vec<Document>> {
+        let documents = self.documents.read().unwrap();
+        let now = Utc::now();
+        let listed = documents
+            .iter()
+            .filter(|((c, _), (_, metadata))| c == collection && live(metadata, now))
+            .map(|((_, id), (value, metadata))| Document {
+                id: id.clone(),
+                value: value.clone(),
+                metadata: *metadata,
+            })
+            .collect();
+
+        Ok(listed)
+    }
+
+    async fn delete(&self, collection: &str, id: &str) -> Result<()> {
+        let mut documents = self.documents.write().unwrap();
+        documents.remove(&(collection.to_string(), id.to_string()));
+
+        Ok(())
+    }
+
+    async fn purge_expired(&self) -> Result<usize> {
+        let mut documents = self.documents.write().unwrap();
+        let before = documents.len();
+        let now = Utc::now();
+        documents.retain(|_, (_, metadata)| live(metadata, now));
+
+        Ok(before - documents.len())
+    }
+}
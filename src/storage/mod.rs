@@ -0,0 +1,139 @@
+mod dynamodb;
+mod filesystem;
+mod memory;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::OnceCell;
+
+use crate::{
+    config::{StorageBackend, CONFIG},
+    retention,
+};
+
+/// What's kept about a document besides its value.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Metadata {
+    // backends may drop the document after it
+    pub expires_at: DateTime<Utc>,
+    // when the document was last put, none for documents stored before it was kept
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Metadata {
+    /// Of a document put now, kept until `expires_at`.
+    pub fn expiring(expires_at: DateTime<Utc>) -> Self {
+        Metadata {
+            expires_at,
+            updated_at: Some(Utc::now()),
+        }
+    }
+}
+
+/// A stored document with its metadata.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Document {
+    pub id: String,
+    pub value: Value,
+    #[serde(flatten)]
+    pub metadata: Metadata,
+}
+
+/// Document store for job records and scrape results.
+///
+/// Documents are grouped into collections and addressed by id. Every document carries its
+/// `Metadata`, with an expiry after which backends may drop it. Artifacts are blobs with a
+/// content type instead and go to `artifacts::ArtifactStore`.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn put(&self, collection: &str, id: &str, value: Value, metadata: Metadata)
+        -> Result<()>;
+    async fn get(&self, collection: &str, id: &str) -> Result<Option<Document>>;
+    /// Every live document of the collection, ordered by id.
+    async fn list(&self, collection: &str) -> Result<Vec<Document>>;
+    /// Drops the document, if there is one.
+    async fn delete(&self, collection: &str, id: &str) -> Result<()>;
+    /// Drops every expired document backends haven't dropped yet, returning how many.
+    async fn purge_expired(&self) -> Result<usize>;
+}
+
+static STORAGE: OnceCell<Box<dyn Storage>> = OnceCell::const_new();
+
+pub async fn init() -> Result<()> {
+    let storage: Box<dyn Storage> = match CONFIG.storage_backend {
+        StorageBackend::Memory => Box::new(memory::MemoryStorage::default()),
+        StorageBackend::Filesystem => {
+            Box::new(filesystem::FilesystemStorage::new(&CONFIG.storage_dir))
+        }
+        StorageBackend::Dynamodb => {
+            Box::new(dynamodb::DynamoDbStorage::new(&CONFIG.dynamodb_table).await)
+        }
+    };
+    STORAGE
+        .set(storage)
+        .map_err(|_| anyhow::anyhow!("storage already initialized"))
+}
+
+pub fn storage() -> &'static dyn Storage {
+    STORAGE.get().expect("storage not initialized").as_ref()
+}
+
+/// Saves the document until the retention of its collection ends, `STORAGE_TTL_SECS` for the
+/// collections no retention policy covers.
+pub async fn save<T: Serialize>(collection: &str, id: &str, value: &T) -> Result<()> {
+    let expires_at = match retention::retention(collection) {
+        Some(retention) => Utc::now() + retention,
+        None => Utc::now() + Duration::seconds(CONFIG.storage_ttl_secs as i64),
+    };
+    storage()
+        .put(
+            collection,
+            id,
+            serde_json::to_value(value)?,
+            Metadata::expiring(expires_at),
+        )
+        .await
+}
+
+/// Like `save`, but the document never expires.
+pub async fn keep<T: Serialize>(collection: &str, id: &str, value: &T) -> Result<()> {
+    storage()
+        .put(
+            collection,
+            id,
+            serde_json::to_value(value)?,
+            Metadata::expiring(DateTime::<Utc>::MAX_UTC),
+        )
+        .await
+}
+
+pub async fn delete(collection: &str, id: &str) -> Result<()> {
+    storage().delete(collection, id).await
+}
+
+pub async fn load<T: DeserializeOwned>(collection: &str, id: &str) -> Result<Option<T>> {
+    storage()
+        .get(collection, id)
+        .await?
+        .map(|document| {
+            serde_json::from_value(document.value)
+                .with_context(|| format!("malformed document {}/{}", collection, id))
+        })
+        .transpose()
+}
+
+pub async fn load_all<T: DeserializeOwned>(collection: &str) -> Result<Vec<T>> {
+    storage()
+        .list(collection)
+        .await?
+        .into_iter()
+        .map(|document| {
+            serde_json::from_value(document.value)
+                .with_context(|| format!("malformed document in {}", collection))
+        })
+        .collect()
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    artifacts::artifacts,
+    audit, cards,
+    dead_letter::{self, DeadLetter},
+    flow,
+    jobs::{Job, JobStatus},
+    txlog,
+};
+
+/// Where a job's runs stand: its own timeline, its dead letter and the jobs it was requeued from
+/// and as.
+#[derive(Serialize, Debug)]
+struct Attempts {
+    status: JobStatus,
+    created_at: DateTime<Utc>,
+    started_at: Option<DateTime<Utc>>,
+    updated_at: DateTime<Utc>,
+    error: Option<String>,
+    dead_letter: Option<DeadLetter>,
+    // oldest first
+    retried_from: Vec<Uuid>,
+    retried_as: Vec<Uuid>,
+}
+
+#[derive(Serialize, Debug)]
+struct Manifest {
+    job_id: Uuid,
+    generated_at: DateTime<Utc>,
+    files: Vec<String>,
+    // screenshots and snapshots the log names whose artifacts are gone, e.g. purged
+    missing: Vec<String>,
+    // screenshots of the payment pages, which may show the card details typed in. Their HTML
+    // snapshots are included, those have the card details masked
+    withheld: Vec<String>,
+}
+
+// Params as the job got them, without card details, passwords and tokens
+fn sanitized(params: &Value) -> Value {
+    let mut params = params.clone();
+    audit::scrub(&mut params);
+
+    let redacted = cards::redact(&params.to_string());
+    serde_json::from_str(&redacted).unwrap_or(params)
+}
+
+async fn attempts(job: &Job) -> Result<Attempts> {
+    let letters = dead_letter::list().await?;
+    let requeued: HashMap<Uuid, Uuid> = letters
+        .iter()
+        .filter_map(|letter| Some((letter.job.id, letter.retried_as?)))
+        .collect();
+
+    let mut retried_from = vec![];
+    let mut current = job.id;
+    while let Some((&from, _)) = requeued.iter().find(|(_, &as_)| as_ == current) {
+        if retried_from.contains(&from) {
+            break;
+        }
+        retried_from.insert(0, from);
+        current = from;
+    }
+    let mut retried_as = vec![];
+    let mut current = job.id;
+    while let Some(&next) = requeued.get(&current) {
+        if retried_as.contains(&next) {
+            break;
+        }
+        retried_as.push(next);
+        current = next;
+    }
+
+    Ok(Attempts {
+        status: job.status,
+        created_at: job.created_at,
+        started_at: job.started_at,
+        updated_at: job.updated_at,
+        error: job.error.clone(),
+        dead_letter: letters.into_iter().find(|letter| letter.job.id == job.id),
+        retried_from,
+        retried_as,
+    })
+}
+
+/// Zip of everything known about a job, to attach to a bug report for the registry operator:
+/// `job.json`, `params.json` with secrets scrubbed, `log.json` with the browser's steps,
+/// `attempts.json`, and the `screenshots/` and `html/` snapshots the log took, except the
+/// screenshots of payment pages.
+pub async fn assemble(job: &Job) -> Result<Vec<u8>> {
+    let mut zip = Zip::default();
+    let mut missing = vec![];
+    let mut withheld = vec![];
+
+    let params = sanitized(&job.params);
+    let mut shown = job.clone();
+    shown.params = params.clone();
+    zip.add("job.json", &serde_json::to_vec_pretty(&shown)?);
+    zip.add("params.json", &serde_json::to_vec_pretty(&params)?);
+    zip.add(
+        "attempts.json",
+        &serde_json::to_vec_pretty(&attempts(job).await?)?,
+    );
+
+    if let Some(log) = txlog::find(job.id).await? {
+        zip.add("log.json", &serde_json::to_vec_pretty(&log)?);
+        for entry in &log.entries {
+            let on_payment_page = entry.url.as_deref().is_some_and(flow::is_payment_host);
+            if entry.screenshot.is_some() && on_payment_page {
+                withheld.push(format!("screenshots/{}.png", entry.seq));
+            }
+            let files = [
+                (
+                    entry.screenshot.is_some() && !on_payment_page,
+                    txlog::screenshot_key(job.id, entry.seq),
+                    format!("screenshots/{}.png", entry.seq),
+                ),
+                (
+                    entry.html.is_some(),
+                    txlog::html_key(job.id, entry.seq),
+                    format!("html/{}.html", entry.seq),
+                ),
+            ];
+            for (_, key, name) in files.into_iter().filter(|(taken, _, _)| *taken) {
+                match artifacts().get(&key).await? {
+                    Some(bytes) => zip.add(&name, &bytes),
+                    None => missing.push(name),
+                }
+            }
+        }
+    }
+
+    let manifest = Manifest {
+        job_id: job.id,
+        generated_at: Utc::now(),
+        files: zip.names(),
+        missing,
+        withheld,
+    };
+    zip.add("manifest.json", &serde_json::to_vec_pretty(&manifest)?);
+
+    Ok(zip.finish())
+}
+
+struct ZipEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Just enough of the zip format for small bundles: stored entries, no compression or zip64.
+#[derive(Default)]
+struct Zip {
+    bytes: Vec<u8>,
+    entries: Vec<ZipEntry>,
+}
+
+// MS-DOS time and date of now, what zip headers carry
+fn dos_time() -> (u16, u16) {
+    let now = Utc::now();
+    let time = (now.hour() << 11) | (now.minute() << 5) | (now.second() / 2);
+    let date = (((now.year() - 1980).max(0) as u32) << 9) | (now.month() << 5) | now.day();
+
+    (time as u16, date as u16)
+}
+
+impl Zip {
+    fn add(&mut self, name: &str, data: &[u8]) {
+        let (time, date) = dos_time();
+        let entry = ZipEntry {
+            name: name.to_string(),
+            crc: crc32fast::hash(data),
+            size: data.len() as u32,
+            offset: self.bytes.len() as u32,
+        };
+
+        let b = &mut self.bytes;
+        b.extend(0x04034b50u32.to_le_bytes());
+        b.extend(20u16.to_le_bytes()); // version needed
+        b.extend(0x0800u16.to_le_bytes()); // UTF-8 names
+        b.extend(0u16.to_le_bytes()); // stored
+        b.extend(time.to_le_bytes());
+        b.extend(date.to_le_bytes());
+        b.extend(entry.crc.to_le_bytes());
+        b.extend(entry.size.to_le_bytes());
+        b.extend(entry.size.to_le_bytes());
+        b.extend((name.len() as u16).to_le_bytes());
+        b.extend(0u16.to_le_bytes()); // extra field
+        b.extend(name.as_bytes());
+        b.extend(data);
+
+        self.entries.push(entry);
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let (time, date) = dos_time();
+        let directory_offset = self.bytes.len() as u32;
+
+        let b = &mut self.bytes;
+        for entry in &self.entries {
+            b.extend(0x02014b50u32.to_le_bytes());
+            b.extend(20u16.to_le_bytes()); // version made by
+            b.extend(20u16.to_le_bytes()); // version needed
+            b.extend(0x0800u16.to_le_bytes());
+            b.extend(0u16.to_le_bytes());
+            b.extend(time.to_le_bytes());
+            b.extend(date.to_le_bytes());
+            b.extend(entry.crc.to_le_bytes());
+            b.extend(entry.size.to_le_bytes());
+            b.extend(entry.size.to_le_bytes());
+            b.extend((entry.name.len() as u16).to_le_bytes());
+            b.extend([0u8; 12]); // extra, comment, disk, attributes
+            b.extend(entry.offset.to_le_bytes());
+            b.extend(entry.name.as_bytes());
+        }
+        let directory_size = b.len() as u32 - directory_offset;
+
+        b.extend(0x06054b50u32.to_le_bytes());
+        b.extend([0u8; 4]); // disk numbers
+        b.extend((self.entries.len() as u16).to_le_bytes());
+        b.extend((self.entries.len() as u16).to_le_bytes());
+        b.extend(directory_size.to_le_bytes());
+        b.extend(directory_offset.to_le_bytes());
+        b.extend(0u16.to_le_bytes()); // comment
+
+        self.bytes
+    }
+}
@@ -0,0 +1,58 @@
+use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig,
+};
+
+use crate::config::CONFIG;
+
+fn certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("failed to open {}", path.display()))?,
+    );
+    rustls_pemfile::certs(&mut reader)
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("failed to read certificates from {}", path.display()))
+}
+
+fn private_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("failed to open {}", path.display()))?,
+    );
+    rustls_pemfile::private_key(&mut reader)?
+        .with_context(|| format!("no private key in {}", path.display()))
+}
+
+/// Listener TLS settings, `None` to serve plain HTTP. With `TLS_CLIENT_CA` set every client must
+/// present a certificate signed by it.
+pub fn config() -> Result<Option<RustlsConfig>> {
+    let (Some(cert), Some(key)) = (&CONFIG.tls_cert, &CONFIG.tls_key) else {
+        return Ok(None);
+    };
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match &CONFIG.tls_client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in certs(ca)? {
+                roots.add(cert)?;
+            }
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server = builder.with_single_cert(certs(cert)?, private_key(key)?)?;
+    server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Some(RustlsConfig::from_config(Arc::new(server))))
+}
@@ -0,0 +1,237 @@
+use std::{fmt::Display, future::Future, sync::Mutex};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thirtyfour::prelude::WebDriverResult;
+use uuid::Uuid;
+
+use crate::{artifacts::artifacts, cards, session::Driver, storage};
+
+const COLLECTION: &str = "job_logs";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Navigate,
+    Click,
+    // typed values aren't kept, only the field
+    Input,
+    Submit,
+    Screenshot,
+    // a flow step finished, see `TraceLevel::Steps`
+    Step,
+    Error,
+}
+
+/// How much of a job's flow its log keeps, `trace_level` of the job.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceLevel {
+    // browser actions, with screenshots at key points only
+    #[default]
+    Checkpoints,
+    // every step of the flow too, each with a thumbnail of the page it left, a storyboard of the
+    // job
+    Steps,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    pub seq: usize,
+    pub at: DateTime<Utc>,
+    pub action: Action,
+    pub target: String,
+    // page the browser was on once the action completed
+    pub url: Option<String>,
+    // artifact location of the page screenshot, taken at key points only
+    pub screenshot: Option<String>,
+    // ... and of the page's HTML, with card details masked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+}
+
+/// Ordered record of what the browser did during a payment job, kept as evidence of the order.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionLog {
+    pub job_id: Uuid,
+    #[serde(default)]
+    pub trace_level: TraceLevel,
+    pub entries: Vec<Entry>,
+}
+
+tokio::task_local! {
+    static LOG: Mutex<TransactionLog>;
+}
+
+/// Runs a job's flow with its transaction log recording. A failure of the flow is logged as its
+/// last entry.
+pub async fn scope<T, E, F>(job_id: Uuid, trace_level: TraceLevel, flow: F) -> Result<T, E>
+where
+    E: Display,
+    F: Future<Output = Result<T, E>>,
+{
+    let log = Mutex::new(TransactionLog {
+        job_id,
+        trace_level,
+        entries: vec![],
+    });
+
+    LOG.scope(log, async {
+        let result = flow.await;
+        if let Err(err) = &result {
+            push(None, Action::Error, &err.to_string(), None).await;
+        }
+        result
+    })
+    .await
+}
+
+/// Appends an entry to the current job's log, if any.
+pub async fn record(driver: &dyn Driver, action: Action, target: &str) {
+    if LOG.try_with(|_| ()).is_err() {
+        return;
+    }
+    push(Some(driver), action, target, None).await;
+}
+
+/// Appends an entry with a screenshot and the HTML of the page.
+pub async fn checkpoint(driver: &dyn Driver, action: Action, target: &str) {
+    let Ok(job_id) = LOG.try_with(|log| log.lock().unwrap().job_id) else {
+        return;
+    };
+
+    let screenshot = match driver.screenshot_png().await {
+        Ok(png) => store(job_id, &png).await,
+        Err(err) => {
+            tracing::warn!("failed to take screenshot: {}", err);
+            None
+        }
+    };
+    let html = match snapshot(driver).await {
+        Ok(html) => store_html(job_id, &html).await,
+        Err(err) => {
+            tracing::warn!("failed to snapshot the page: {}", err);
+            None
+        }
+    };
+
+    push_entry(Some(driver), action, target, screenshot, html).await;
+}
+
+async fn snapshot(driver: &dyn Driver) -> WebDriverResult<String> {
+    let mut html = String::new();
+    if let Some(root) = driver.find_all("/html").await?.into_iter().next() {
+        html = root.property("outerHTML").await?.unwrap_or_default();
+    }
+
+    Ok(cards::redact(&html))
+}
+
+/// Key of the screenshot of the entry `seq` of the job's log.
+pub fn screenshot_key(job_id: Uuid, seq: usize) -> String {
+    format!("jobs/{}/{}.png", job_id, seq)
+}
+
+/// Key of the HTML snapshot of the entry `seq` of the job's log.
+pub fn html_key(job_id: Uuid, seq: usize) -> String {
+    format!("jobs/{}/{}.html", job_id, seq)
+}
+
+fn next_seq() -> usize {
+    LOG.try_with(|log| log.lock().unwrap().entries.len())
+        .unwrap_or_default()
+}
+
+async fn store_html(job_id: Uuid, html: &str) -> Option<String> {
+    artifacts()
+        .put(&html_key(job_id, next_seq()), html.as_bytes(), "text/html")
+        .await
+        .inspect_err(|err| tracing::warn!("failed to store page snapshot: {}", err))
+        .ok()
+}
+
+/// Appends a finished flow step with a thumbnail of the page, when the job traces its steps.
+pub async fn step(driver: &dyn Driver, target: &str) {
+    let Ok((job_id, trace_level)) = LOG.try_with(|log| {
+        let log = log.lock().unwrap();
+        (log.job_id, log.trace_level)
+    }) else {
+        return;
+    };
+    if trace_level != TraceLevel::Steps {
+        return;
+    }
+
+    let thumbnail = match driver.thumbnail_png().await {
+        Ok(png) => store(job_id, &png).await,
+        Err(err) => {
+            tracing::warn!("failed to take thumbnail: {}", err);
+            None
+        }
+    };
+
+    push(Some(driver), Action::Step, target, thumbnail).await;
+}
+
+// Stored under the seq of the entry it's about to be attached to
+async fn store(job_id: Uuid, png: &[u8]) -> Option<String> {
+    artifacts()
+        .put(&screenshot_key(job_id, next_seq()), png, "image/png")
+        .await
+        .inspect_err(|err| tracing::warn!("failed to store screenshot: {}", err))
+        .ok()
+}
+
+async fn push(
+    driver: Option<&dyn Driver>,
+    action: Action,
+    target: &str,
+    screenshot: Option<String>,
+) {
+    push_entry(driver, action, target, screenshot, None).await
+}
+
+// The log is saved after every entry so it survives a crash mid-flow
+async fn push_entry(
+    driver: Option<&dyn Driver>,
+    action: Action,
+    target: &str,
+    screenshot: Option<String>,
+    html: Option<String>,
+) {
+    let url = match driver {
+        Some(driver) => driver.current_url().await.ok(),
+        None => None,
+    };
+
+    let Ok(log) = LOG.try_with(|log| {
+        let mut log = log.lock().unwrap();
+        let seq = log.entries.len();
+        log.entries.push(Entry {
+            seq,
+            at: Utc::now(),
+            action,
+            target: target.to_string(),
+            url,
+            screenshot,
+            html,
+        });
+        log.clone()
+    }) else {
+        return;
+    };
+
+    if let Err(err) = storage::save(COLLECTION, &log.job_id.to_string(), &log).await {
+        tracing::warn!(
+            "failed to save transaction log of job {}: {}",
+            log.job_id,
+            err
+        );
+    }
+}
+
+pub async fn find(job_id: Uuid) -> Result<Option<TransactionLog>> {
+    storage::load(COLLECTION, &job_id.to_string()).await
+}
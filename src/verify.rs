@@ -0,0 +1,162 @@
+use std::{future::Future, sync::RwLock, time::Duration};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dump_core::patterns::COL_SM_12;
+use once_cell::sync::Lazy;
+use scraper::Html;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::{
+    browser,
+    config::{Mode, CONFIG},
+    cookies,
+    handler::{FEDERAL_SEARCH_URL, REGISTRY_SEARCH_URL},
+    maintenance, politeness, robots, session,
+};
+
+// How long a provider gets to load its entry page and show the element
+const CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+// Field of the registry search every flow starts from
+const REGISTRY_SEARCH_INPUT: &str = "//input[@name='QueryString']";
+
+/// Outcome of the read-only check of one provider.
+#[derive(Serialize, Debug, Clone)]
+pub struct ProviderCheck {
+    pub provider: &'static str,
+    pub url: &'static str,
+    pub passed: bool,
+    pub detail: String,
+    pub duration_ms: u64,
+}
+
+/// Whether the instance should get traffic, see `--verify`.
+#[derive(Serialize, Debug, Clone)]
+pub struct Readiness {
+    pub ready: bool,
+    // none while the checks are still running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified_at: Option<DateTime<Utc>>,
+    pub checks: Vec<ProviderCheck>,
+}
+
+struct Verified {
+    at: DateTime<Utc>,
+    checks: Vec<ProviderCheck>,
+}
+
+// Of the startup checks, none until they finished
+static VERIFIED: Lazy<RwLock<Option<Verified>>> = Lazy::new(Default::default);
+
+/// With `--verify`, checks every provider the instance scrapes in the background: loads its
+/// entry page and resolves an element every flow needs. `/readyz` answers 503 until they passed.
+pub fn spawn() {
+    if !CONFIG.verify {
+        return;
+    }
+
+    tokio::spawn(async {
+        let checks = run().await;
+        for check in &checks {
+            if check.passed {
+                tracing::info!("verified {}: {}", check.provider, check.detail);
+            } else {
+                tracing::error!("{} failed verification: {}", check.provider, check.detail);
+            }
+        }
+        *VERIFIED.write().unwrap() = Some(Verified {
+            at: Utc::now(),
+            checks,
+        });
+    });
+}
+
+pub fn readiness() -> Readiness {
+    if !CONFIG.verify {
+        return Readiness {
+            ready: true,
+            verified_at: None,
+            checks: vec![],
+        };
+    }
+
+    match VERIFIED.read().unwrap().as_ref() {
+        Some(verified) => Readiness {
+            ready: verified.checks.iter().all(|check| check.passed),
+            verified_at: Some(verified.at),
+            checks: verified.checks.clone(),
+        },
+        None => Readiness {
+            ready: false,
+            verified_at: None,
+            checks: vec![],
+        },
+    }
+}
+
+async fn run() -> Vec<ProviderCheck> {
+    let mut checks = vec![];
+    // `api` instances hand the browser flows to workers
+    if CONFIG.mode != Mode::Api {
+        checks.push(check(cookies::REGISTRY, REGISTRY_SEARCH_URL, registry()).await);
+    }
+    checks.push(check(maintenance::FEDERAL, FEDERAL_SEARCH_URL, federal()).await);
+
+    checks
+}
+
+async fn check(
+    provider: &'static str,
+    url: &'static str,
+    probe: impl Future<Output = Result<String>>,
+) -> ProviderCheck {
+    let started = Instant::now();
+    let (passed, detail) = match tokio::time::timeout(CHECK_TIMEOUT, probe).await {
+        Ok(Ok(detail)) => (true, detail),
+        Ok(Err(err)) => (false, err.to_string()),
+        Err(_) => (false, format!("timed out after {:?}", CHECK_TIMEOUT)),
+    };
+
+    ProviderCheck {
+        provider,
+        url,
+        passed,
+        detail,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+// The search page in a browser session, down to its query field
+async fn registry() -> Result<String> {
+    let driver = session::start().await?;
+    let found = async {
+        driver.goto(REGISTRY_SEARCH_URL).await?;
+        browser::exists(&*driver, REGISTRY_SEARCH_INPUT, CHECK_TIMEOUT).await
+    }
+    .await;
+    driver.quit().await?;
+
+    anyhow::ensure!(found?, "the search page has no {}", REGISTRY_SEARCH_INPUT);
+    Ok("search page shows the query field".to_string())
+}
+
+// The search page over HTTP, like the profile and search scrapes fetch it
+async fn federal() -> Result<String> {
+    robots::ensure(maintenance::FEDERAL, FEDERAL_SEARCH_URL).await?;
+    politeness::pace(maintenance::FEDERAL).await;
+    let html = politeness::client(maintenance::FEDERAL)
+        .get(FEDERAL_SEARCH_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let found = Html::parse_document(&html)
+        .select(&COL_SM_12)
+        .next()
+        .is_some();
+    anyhow::ensure!(found, "the search page has no div.col-sm-12 layout");
+    Ok("search page has the expected layout".to_string())
+}
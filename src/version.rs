@@ -0,0 +1,125 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dump_core::patterns;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{Mode, CONFIG},
+    flow,
+};
+
+// Both would start a server of their own, the first one started never returns
+#[cfg(all(feature = "lambda", feature = "ecs"))]
+compile_error!(
+    "the `lambda` and `ecs` features are mutually exclusive, build with `--features lambda` for \
+     AWS Lambda or `--features ecs` for a container"
+);
+
+/// How the binary was built.
+#[derive(Serialize, Debug)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    // set by `build.rs`, `unknown` where neither git nor GIT_SHA was there
+    pub git_sha: &'static str,
+    pub built_at: Option<DateTime<Utc>>,
+    pub profile: &'static str,
+    pub features: Vec<&'static str>,
+    // what serves the API: `axum` listens on PORT, `lambda` takes Lambda invocations
+    pub server: &'static str,
+    // digest of the selectors and regexes scrapes and flows go by, changes with any of them
+    pub selectors: String,
+}
+
+fn selectors_digest() -> String {
+    let mut hasher = Sha256::new();
+    for (name, source) in patterns::sources().chain(&flow::SELECTORS) {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(source.as_bytes());
+        hasher.update([0]);
+    }
+    let digest = hasher.finalize();
+
+    digest[..6]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn build_info() -> BuildInfo {
+    let features = [
+        ("lambda", cfg!(feature = "lambda")),
+        ("ecs", cfg!(feature = "ecs")),
+        ("headless", cfg!(feature = "headless")),
+        ("firefox", cfg!(feature = "firefox")),
+        ("cdp", cfg!(feature = "cdp")),
+    ];
+
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        built_at: env!("BUILD_TIMESTAMP")
+            .parse()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+        profile: if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+        features: features
+            .into_iter()
+            .filter_map(|(feature, enabled)| enabled.then_some(feature))
+            .collect(),
+        server: if cfg!(feature = "lambda") {
+            "lambda"
+        } else if cfg!(any(debug_assertions, feature = "ecs")) {
+            "axum"
+        } else {
+            "none"
+        },
+        selectors: selectors_digest(),
+    }
+}
+
+/// Fails on runtime settings the build can't serve.
+pub fn check() -> Result<()> {
+    let info = build_info();
+    anyhow::ensure!(
+        info.server != "none",
+        "release builds serve nothing without a server, build with `--features ecs` or \
+         `--features lambda`"
+    );
+    // invocations only last as long as a request
+    anyhow::ensure!(
+        !(info.server == "lambda" && CONFIG.mode == Mode::Worker),
+        "workers can't run on Lambda, run them from an `ecs` build or use MODE=api"
+    );
+
+    Ok(())
+}
+
+/// `--print-build-info`.
+pub fn print() {
+    let info = build_info();
+    println!(
+        "{} {} ({})",
+        env!("CARGO_PKG_NAME"),
+        info.version,
+        info.profile
+    );
+    println!(
+        "features: {}",
+        match info.features.is_empty() {
+            true => "none".to_string(),
+            false => info.features.join(", "),
+        }
+    );
+    println!("server: {}", info.server);
+    println!("commit: {}", info.git_sha);
+    if let Some(built_at) = info.built_at {
+        println!("built: {}", built_at.to_rfc3339());
+    }
+    println!("selectors: {}", info.selectors);
+}
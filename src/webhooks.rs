@@ -0,0 +1,607 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use once_cell::sync::OnceCell;
+use rand::RngCore;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{
+    config::CONFIG,
+    corporations::ChangeKind,
+    jobs::{Job, JobKind, JobStatus},
+    storage,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+// Subscribers registered through the API, the configured ones only live in WEBHOOK_SUBSCRIBERS
+const SUBSCRIBERS: &str = "webhook-subscribers";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    #[serde(rename = "job.completed")]
+    JobCompleted,
+    #[serde(rename = "payment.succeeded")]
+    PaymentSucceeded,
+    #[serde(rename = "corporation.changed")]
+    CorporationChanged,
+    #[serde(rename = "corporation.status_changed")]
+    CorporationStatusChanged,
+    // only ever sent by `POST /api/webhooks/test`
+    #[serde(rename = "webhook.test")]
+    WebhookTest,
+}
+
+/// How often a failed delivery is tried again: `max_attempts` in all, waiting `backoff_secs`
+/// before the second and twice as long before each one after.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_backoff_secs")]
+    pub backoff_secs: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_backoff_secs() -> u64 {
+    30
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: default_max_attempts(),
+            backoff_secs: default_backoff_secs(),
+        }
+    }
+}
+
+/// An endpoint events are posted to, signed with its `secret`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Subscriber {
+    pub id: String,
+    pub url: String,
+    // only shown when the subscriber is registered
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub secret: String,
+    // the event types it gets, every one if empty
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    // API client that registered it, whose job events it gets. None for the configured ones,
+    // which get every client's
+    #[serde(default)]
+    pub client: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl Subscriber {
+    /// A subscriber registered by `client`, with a random secret unless one is given.
+    pub fn new(
+        client: &str,
+        url: String,
+        secret: Option<String>,
+        events: Vec<EventKind>,
+        retry: RetryPolicy,
+    ) -> Subscriber {
+        Subscriber {
+            id: Uuid::new_v4().to_string(),
+            url,
+            secret: secret.unwrap_or_else(generate_secret),
+            events,
+            retry,
+            client: Some(client.to_string()),
+            created_at: Some(Utc::now()),
+        }
+    }
+
+    pub fn redacted(mut self) -> Subscriber {
+        self.secret = String::new();
+        self
+    }
+
+    fn wants(&self, event: &Event) -> bool {
+        let kind = self.events.is_empty() || self.events.contains(&event.kind);
+        // other clients' jobs are none of its business
+        let client = match (&self.client, &event.client) {
+            (Some(subscriber), Some(client)) => subscriber == client,
+            _ => true,
+        };
+        kind && client
+    }
+
+    pub async fn find(id: &str) -> Result<Option<Subscriber>> {
+        if let Some(configured) = configured().iter().find(|subscriber| subscriber.id == id) {
+            return Ok(Some(configured.clone()));
+        }
+        storage::load(SUBSCRIBERS, id).await
+    }
+
+    /// The configured subscribers and those registered through the API.
+    pub async fn all() -> Result<Vec<Subscriber>> {
+        let mut subscribers = configured().to_vec();
+        subscribers.extend(storage::load_all::<Subscriber>(SUBSCRIBERS).await?);
+
+        Ok(subscribers)
+    }
+
+    // kept until deleted, unlike the records of the store
+    pub async fn save(&self) -> Result<()> {
+        storage::keep(SUBSCRIBERS, &self.id, self).await
+    }
+
+    pub async fn delete(&self) -> Result<()> {
+        storage::delete(SUBSCRIBERS, &self.id).await
+    }
+}
+
+fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+static CONFIGURED: OnceCell<Vec<Subscriber>> = OnceCell::new();
+
+/// Loads `WEBHOOK_SUBSCRIBERS`, a JSON list of subscribers, e.g. `[{"id": "crm", "url":
+/// "https://crm.example.com/hooks/registry", "secret": "...", "events": ["corporation.changed"]}]`.
+pub fn load() -> Result<()> {
+    let mut subscribers: Vec<Subscriber> = vec![];
+    if let Some(path) = &CONFIG.webhook_subscribers {
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        subscribers = serde_json::from_str(&file)
+            .with_context(|| format!("invalid webhook subscribers in {}", path.display()))?;
+    }
+    for subscriber in &mut subscribers {
+        anyhow::ensure!(
+            !subscriber.secret.is_empty(),
+            "webhook subscriber {} has no secret",
+            subscriber.id
+        );
+        subscriber.client = None;
+    }
+
+    CONFIGURED
+        .set(subscribers)
+        .map_err(|_| anyhow::anyhow!("webhook subscribers already loaded"))
+}
+
+fn configured() -> &'static [Subscriber] {
+    CONFIGURED.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+/// Body of every webhook.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Event {
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub kind: EventKind,
+    pub created_at: DateTime<Utc>,
+    pub data: Value,
+    // client whose job the event is about, only its subscribers get it
+    #[serde(skip)]
+    pub client: Option<String>,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, data: Value) -> Event {
+        Event {
+            id: Uuid::new_v4(),
+            kind,
+            created_at: Utc::now(),
+            data,
+            client: None,
+        }
+    }
+}
+
+/// `v1=` and the hex HMAC-SHA256 under `secret` of `{id}.{timestamp}.{body}`, what
+/// `Webhook-Signature` carries.
+pub fn signature(secret: &str, id: Uuid, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(format!("{}.{}.", id, timestamp).as_bytes());
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("v1={}", hex)
+}
+
+/// How a subscriber answered a delivery.
+#[derive(Serialize, Debug)]
+pub struct Delivery {
+    pub event_id: Uuid,
+    pub subscriber: String,
+    pub timestamp: i64,
+    pub signature: String,
+    pub status: u16,
+}
+
+/// One try at delivering an event, kept per subscriber so it can debug its receiver.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeliveryAttempt {
+    pub event_id: Uuid,
+    pub event_type: EventKind,
+    // 1 for the first try
+    pub attempt: u32,
+    pub attempted_at: DateTime<Utc>,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl DeliveryAttempt {
+    fn collection(subscriber: &str) -> String {
+        format!("webhook-deliveries:{}", subscriber)
+    }
+
+    async fn record(&self, subscriber: &str) {
+        // zero padded so ids sort like the attempts were made
+        let id = format!(
+            "{:020}-{}-{}",
+            self.attempted_at.timestamp_micros(),
+            self.event_id.simple(),
+            self.attempt
+        );
+        if let Err(err) = storage::save(&DeliveryAttempt::collection(subscriber), &id, self).await {
+            tracing::warn!("failed to log the delivery of {}: {}", self.event_id, err);
+        }
+    }
+
+    /// Delivery attempts to the subscriber, oldest first.
+    pub async fn of(subscriber: &str) -> Result<Vec<DeliveryAttempt>> {
+        storage::load_all(&DeliveryAttempt::collection(subscriber)).await
+    }
+}
+
+// Loopback, private, link-local (the cloud metadata endpoints among them) and other addresses
+// that don't lead out to the internet
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // shared address space of carrier-grade NAT
+        || (a == 100 && (64..128).contains(&b))
+        || a == 0)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // unique local fc00::/7 and link-local fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// Checks a subscriber url can be delivered to: it's https and its host is on the internet, not
+/// an address of ours. Hosts are resolved, so names pointing inside are refused too.
+pub async fn check_url(url: &str) -> Result<SocketAddr> {
+    let url = Url::parse(url)?;
+    anyhow::ensure!(url.scheme() == "https", "webhooks are only sent over https");
+    let host = url.host_str().context("no host")?;
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("failed to resolve {}", host))?
+        .collect();
+    anyhow::ensure!(!addrs.is_empty(), "{} doesn't resolve", host);
+    if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        anyhow::bail!("{} resolves to {}, which isn't public", host, addr.ip());
+    }
+
+    Ok(addrs[0])
+}
+
+async fn post(subscriber: &Subscriber, event: &Event) -> Result<Delivery> {
+    let body = serde_json::to_vec(event)?;
+    let timestamp = Utc::now().timestamp();
+    let signature = signature(&subscriber.secret, event.id, timestamp, &body);
+
+    // checked again on every delivery, as the name may point elsewhere since it was registered.
+    // The request goes to the address checked, and redirects aren't followed anywhere else.
+    let addr = check_url(&subscriber.url).await?;
+    let url = Url::parse(&subscriber.url)?;
+    let client = reqwest::Client::builder()
+        .resolve(url.host_str().unwrap_or_default(), addr)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let response = client
+        .post(url)
+        .timeout(TIMEOUT)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("webhook-id", event.id.to_string())
+        .header("webhook-timestamp", timestamp.to_string())
+        .header("webhook-signature", &signature)
+        .body(body)
+        .send()
+        .await?;
+
+    Ok(Delivery {
+        event_id: event.id,
+        subscriber: subscriber.id.clone(),
+        timestamp,
+        signature,
+        status: response.status().as_u16(),
+    })
+}
+
+/// Posts `event` to the subscriber and logs the attempt. It's signed so the receiver can tell it
+/// came from us: the `Webhook-Signature` covers the `Webhook-Id` and `Webhook-Timestamp` too, so
+/// receivers that reject old timestamps and ids they've seen are safe from replays.
+pub async fn deliver(subscriber: &Subscriber, event: &Event, attempt: u32) -> Result<Delivery> {
+    let started = Instant::now();
+    let attempted_at = Utc::now();
+    let delivery = post(subscriber, event).await;
+
+    DeliveryAttempt {
+        event_id: event.id,
+        event_type: event.kind,
+        attempt,
+        attempted_at,
+        status: delivery.as_ref().ok().map(|delivery| delivery.status),
+        error: delivery.as_ref().err().map(|err| err.to_string()),
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+    .record(&subscriber.id)
+    .await;
+
+    delivery
+}
+
+const OUTBOX: &str = "webhook-outbox";
+// How long an entry being delivered is left alone by other dispatchers, longer than a delivery
+// may take
+const LEASE_SECS: i64 = 60;
+const MAX_BACKOFF_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    // not delivered yet, retried at `next_attempt_at`
+    Pending,
+    Delivered,
+    // the retry policy gave up, or the subscriber is gone
+    Failed,
+}
+
+/// An event owed to a subscriber. Entries are saved before anything is sent, so deliveries
+/// interrupted by a restart are tried again by the next dispatcher. Receivers may see an event
+/// more than once and should drop `Webhook-Id`s they've seen.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutboxEntry {
+    pub id: String,
+    pub event: Event,
+    pub subscriber: String,
+    pub status: OutboxStatus,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_status: Option<u16>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl OutboxEntry {
+    async fn save(&self) -> Result<()> {
+        storage::save(OUTBOX, &self.id, self).await
+    }
+
+    /// Every entry of the outbox, oldest first.
+    pub async fn all() -> Result<Vec<OutboxEntry>> {
+        storage::load_all(OUTBOX).await
+    }
+
+    /// Where the delivery of the event to each of its subscribers stands.
+    pub async fn of_event(event_id: Uuid) -> Result<Vec<OutboxEntry>> {
+        let entries = OutboxEntry::all().await?;
+
+        Ok(entries
+            .into_iter()
+            .filter(|entry| entry.event.id == event_id)
+            .collect())
+    }
+
+    fn leased(&mut self) {
+        self.next_attempt_at = Utc::now() + chrono::Duration::seconds(LEASE_SECS);
+        self.updated_at = Utc::now();
+    }
+
+    // One more attempt, then done or due again after the subscriber's backoff, doubled with each
+    // failed attempt
+    async fn attempt(mut self) -> Result<()> {
+        let Some(subscriber) = Subscriber::find(&self.subscriber).await? else {
+            self.status = OutboxStatus::Failed;
+            self.last_error = Some("the subscriber was deleted".to_string());
+            self.updated_at = Utc::now();
+            return self.save().await;
+        };
+
+        self.attempts += 1;
+        let delivery = deliver(&subscriber, &self.event, self.attempts).await;
+        self.last_status = delivery.as_ref().ok().map(|delivery| delivery.status);
+        self.last_error = match &delivery {
+            Ok(delivery) if (200..300).contains(&delivery.status) => None,
+            Ok(delivery) => Some(format!("answered {}", delivery.status)),
+            Err(err) => Some(err.to_string()),
+        };
+        self.updated_at = Utc::now();
+
+        if self.last_error.is_none() {
+            self.status = OutboxStatus::Delivered;
+        } else if self.attempts >= subscriber.retry.max_attempts {
+            self.status = OutboxStatus::Failed;
+            tracing::warn!(
+                "gave up sending event {} to webhook subscriber {}: {}",
+                self.event.id,
+                subscriber.id,
+                self.last_error.as_deref().unwrap_or_default()
+            );
+        } else {
+            let backoff = subscriber.retry.backoff_secs << (self.attempts - 1).min(20);
+            self.next_attempt_at =
+                self.updated_at + chrono::Duration::seconds(backoff.min(MAX_BACKOFF_SECS) as i64);
+        }
+
+        self.save().await
+    }
+}
+
+/// Queues `event` for every subscriber that wants it and sends it right away, in the background.
+pub fn publish(event: Event) {
+    tokio::spawn(async move {
+        if let Err(err) = enqueue(event).await {
+            tracing::error!("failed to queue a webhook event: {}", err);
+        }
+    });
+}
+
+async fn enqueue(event: Event) -> Result<()> {
+    for subscriber in Subscriber::all().await? {
+        if !subscriber.wants(&event) {
+            continue;
+        }
+        let now = Utc::now();
+        let mut entry = OutboxEntry {
+            // zero padded so the outbox lists oldest first
+            id: format!(
+                "{:020}-{}-{}",
+                now.timestamp_micros(),
+                event.id.simple(),
+                subscriber.id
+            ),
+            event: event.clone(),
+            subscriber: subscriber.id,
+            status: OutboxStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_status: None,
+            last_error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        entry.leased();
+        entry.save().await?;
+
+        tokio::spawn(async move {
+            if let Err(err) = entry.attempt().await {
+                tracing::warn!("failed to update the webhook outbox: {}", err);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Every 10 seconds, sends the pending outbox entries that are due.
+pub fn spawn_dispatcher() {
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            if let Err(err) = dispatch_due().await {
+                tracing::warn!("failed to dispatch the webhook outbox: {}", err);
+            }
+        }
+    });
+}
+
+async fn dispatch_due() -> Result<()> {
+    let now = Utc::now();
+    let due = OutboxEntry::all()
+        .await?
+        .into_iter()
+        .filter(|entry| entry.status == OutboxStatus::Pending && entry.next_attempt_at <= now);
+
+    for mut entry in due {
+        entry.leased();
+        entry.save().await?;
+        entry.attempt().await?;
+    }
+
+    Ok(())
+}
+
+/// Tells the job's client it finished, and that the purchase went through for payment jobs.
+pub fn job_completed(job: &Job) {
+    let mut completed = Event::new(
+        EventKind::JobCompleted,
+        json!({
+            "job_id": job.id,
+            "kind": job.kind,
+            "status": job.status,
+            "error": job.error,
+        }),
+    );
+    completed.client = Some(job.client.clone());
+    publish(completed);
+
+    if job.kind == JobKind::PaymentPage && job.status == JobStatus::Succeeded {
+        let mut succeeded = Event::new(
+            EventKind::PaymentSucceeded,
+            json!({
+                "job_id": job.id,
+                "company": job.params.get("selected_company"),
+                "product": job.params.get("search_product"),
+            }),
+        );
+        succeeded.client = Some(job.client.clone());
+        publish(succeeded);
+    }
+}
+
+/// A stored corporation was created or some of its sections changed, see `corporations::Change`.
+pub fn corporation_changed(id: &str, kind: ChangeKind, sections: &[String]) {
+    publish(Event::new(
+        EventKind::CorporationChanged,
+        json!({
+            "corporation_id": id,
+            "kind": kind,
+            "sections": sections,
+        }),
+    ));
+}
+
+/// Tells the subscribers a monitored corporation's status changed, e.g. from Active to
+/// Dissolved.
+pub fn corporation_status_changed(id: &str, previous: &str, current: &str) {
+    publish(Event::new(
+        EventKind::CorporationStatusChanged,
+        json!({
+            "corporation_id": id,
+            "previous_status": previous,
+            "status": current,
+        }),
+    ));
+}
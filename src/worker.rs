@@ -0,0 +1,93 @@
+use std::{panic::AssertUnwindSafe, sync::Arc};
+
+use anyhow::Result;
+use futures::FutureExt;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::{
+    capacity, config::CONFIG, handler, jobs::Job, lock, off_peak, panics, queue, response,
+};
+
+/// Consumes the job queue, running up to `WORKER_CONCURRENCY` browser flows at a time.
+pub async fn run() -> Result<()> {
+    println!(
+        "🚀 worker consuming jobs, {} at a time",
+        CONFIG.worker_concurrency
+    );
+    let slots = Arc::new(Semaphore::new(CONFIG.worker_concurrency));
+    capacity::spawn_heartbeat(slots.clone());
+    off_peak::spawn_releaser();
+
+    loop {
+        let slot = slots.clone().acquire_owned().await?;
+        let popped = match queue::pop(5.0).await {
+            Ok(Some(popped)) => popped,
+            Ok(None) => continue,
+            Err(err) => {
+                tracing::error!("failed to read the job queue: {}", err);
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+
+        let id = popped.id;
+        tokio::spawn(async move {
+            if let Err(err) = process(popped).await {
+                tracing::error!("job {} failed to run: {}", id, err);
+            }
+            drop(slot);
+        });
+    }
+}
+
+// The client's running count taken by `queue::pop`, given back when dropped, so a job that
+// panicked or failed early doesn't hold it
+struct Running(String);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let client = std::mem::take(&mut self.0);
+        tokio::spawn(async move {
+            if let Err(err) = queue::finished(&client).await {
+                tracing::warn!("failed to release a running job of {}: {}", client, err);
+            }
+        });
+    }
+}
+
+async fn process(popped: queue::Popped) -> Result<()> {
+    let _running = popped.client.map(Running);
+    run_job(popped.id).await
+}
+
+async fn run_job(id: Uuid) -> Result<()> {
+    let Some(job) = Job::find(id).await? else {
+        tracing::warn!("job {} was queued but no record exists", id);
+        return Ok(());
+    };
+    let job = job.run().await?;
+    let lock = handler::job_lock_key(&job);
+
+    // a panicking flow fails its job rather than leaving it running
+    let executed = AssertUnwindSafe(response::detached(handler::execute(&job)))
+        .catch_unwind()
+        .await
+        .unwrap_or_else(|panic| Err(panics::failure(panic)));
+    let finished = match executed {
+        Ok(envelope) => {
+            job.succeed(envelope.status.as_u16(), envelope.language, envelope.data)
+                .await
+        }
+        Err(err) => handler::fail_job(job, &err).await,
+    };
+    // the payment lock was handed over to the job when it was queued
+    if let Some(key) = lock {
+        if let Err(err) = lock::release(&key, id).await {
+            tracing::warn!("failed to release {}: {}", key, err);
+        }
+    }
+    finished?;
+
+    Ok(())
+}